@@ -0,0 +1,17 @@
+#version 150
+// the bright parts of the screen glow over their surroundings
+in vec2 v_tex_coords;
+out vec4 out_color;
+uniform sampler2D scene;
+uniform vec2 resolution;
+void main() {
+    vec2 texel = 2.0 / resolution;
+    vec3 glow = vec3(0.0);
+    for (int x = -4; x <= 4; x++) {
+        for (int y = -4; y <= 4; y++) {
+            vec3 color = texture(scene, v_tex_coords + vec2(x, y) * texel).rgb;
+            glow += max(color - vec3(0.6), vec3(0.0));
+        }
+    }
+    out_color = vec4(texture(scene, v_tex_coords).rgb + glow / 40.0, 1.0);
+}
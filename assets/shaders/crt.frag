@@ -0,0 +1,14 @@
+#version 150
+// scanlines and vignette of a cathode ray tube
+in vec2 v_tex_coords;
+out vec4 out_color;
+uniform sampler2D scene;
+uniform vec2 resolution;
+uniform float time;
+void main() {
+    vec3 color = texture(scene, v_tex_coords).rgb;
+    float scanline = 0.85 + 0.15 * sin((v_tex_coords.y * resolution.y + time * 10.0) * 3.14159);
+    vec2 center = v_tex_coords * 2.0 - 1.0;
+    float vignette = 1.0 - 0.3 * dot(center, center);
+    out_color = vec4(color * scanline * vignette, 1.0);
+}
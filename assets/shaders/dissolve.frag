@@ -0,0 +1,15 @@
+#version 150
+// the shape is eaten by noise, param is the fraction dissolved
+in vec4 v_color;
+in vec2 v_position;
+in float v_param;
+out vec4 out_color;
+uniform float time;
+void main() {
+    vec2 cell = floor(v_position * 8.0) + floor(time * 4.0);
+    float noise = fract(sin(dot(cell, vec2(12.9898, 78.233))) * 43758.5453);
+    if (noise < v_param) {
+        discard;
+    }
+    out_color = v_color;
+}
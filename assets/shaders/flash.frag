@@ -0,0 +1,8 @@
+#version 150
+// the color is mixed with white, param is the intensity
+in vec4 v_color;
+in float v_param;
+out vec4 out_color;
+void main() {
+    out_color = vec4(mix(v_color.rgb, vec3(1.0), clamp(v_param, 0.0, 1.0)), v_color.a);
+}
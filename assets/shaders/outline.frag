@@ -0,0 +1,15 @@
+#version 150
+// the edge of the shape is drawn in the inverse color, param is its width in [0,1]
+in vec4 v_color;
+in vec2 v_position;
+in float v_param;
+out vec4 out_color;
+uniform bool circle;
+void main() {
+    float distance = circle ? length(v_position) : max(abs(v_position.x), abs(v_position.y));
+    if (distance > 1.0 - v_param) {
+        out_color = vec4(vec3(1.0) - v_color.rgb, v_color.a);
+    } else {
+        out_color = v_color;
+    }
+}
@@ -0,0 +1,451 @@
+//! abilities defined in abilities.definitions of the configuration as compositions
+//! of effects, with the pools of resources (stamina, mana) spent by them.
+//!
+//! an ability is used only if its cooldown is over and its pool holds its cost, both
+//! checked before anything is spent. pools regenerate at their rate once their delay
+//! after the last cost is over.
+//!
+//! casters use the abilities of their slots, on input for players and when their
+//! target is in range for monsters. the effects apply once the cast time is over, in
+//! the direction of the force of the caster.
+
+use app;
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use components::*;
+use resource::*;
+use specs::Join;
+use combat::{Damage, DamageType};
+use progression::{self, Modifier};
+use projectile;
+use melee::MeleeAttack;
+use rope::Rope;
+use std::f32::consts::PI;
+
+pub type VecAbilityEffect = Vec<AbilityEffect>;
+pub type VecModifier = Vec<Modifier>;
+
+/// * max: value of the pool when full
+/// * regen: value regenerated per second
+/// * regen_delay: time after a cost before the pool regenerates
+pub struct PoolDefinition {
+    pub name: String,
+    pub max: f32,
+    pub regen: f32,
+    pub regen_delay: f32,
+}
+impl_from_into_toml_for_struct!( PoolDefinition {
+    name: String,
+    max: f32,
+    regen: f32,
+    regen_delay: f32,
+});
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum EffectType {
+    /// fire a projectile of the profile name
+    Projectile,
+    /// dash the caster over distance amount
+    Dash,
+    /// damage of amount the entities in radius around the caster, less with the distance
+    /// by abilities.blast_falloff and behind walls
+    Blast,
+    /// apply the status name to the caster
+    Status,
+    /// spawn the entity name at radius in front of the caster
+    Summon,
+    /// swing a melee attack in front of the caster of range radius and of half
+    /// opening amount in degrees, at most 90
+    Melee,
+    /// cast a rope of length at most radius in front of the caster, anchored to walls and
+    /// entities of abilities.rope_mask. a roped caster reels the rope of amount instead,
+    /// or releases it if amount is 0
+    Rope,
+}
+impl_from_into_toml_for_enum!(EffectType {
+    Projectile,
+    Dash,
+    Blast,
+    Status,
+    Summon,
+    Melee,
+    Rope,
+});
+
+/// an effect of an ability, the fields unused by its type are ignored
+pub struct AbilityEffect {
+    pub effect: EffectType,
+    pub name: String,
+    pub amount: f32,
+    pub radius: f32,
+}
+impl_from_into_toml_for_struct!( AbilityEffect {
+    effect: EffectType,
+    name: String,
+    amount: f32,
+    radius: f32,
+});
+
+/// * cooldown: time after a use before the ability can be used again
+/// * pool: name of the pool the cost is spent from, empty for no cost
+/// * cost: value spent from the pool at each use
+/// * cast_time: time between the use and the effects
+/// * range: distance to its target a monster uses the ability at
+pub struct AbilityDefinition {
+    pub name: String,
+    pub cooldown: f32,
+    pub pool: String,
+    pub cost: f32,
+    pub cast_time: f32,
+    pub range: f32,
+    pub effects: VecAbilityEffect,
+}
+impl_from_into_toml_for_struct!( AbilityDefinition {
+    name: String,
+    cooldown: f32,
+    pool: String,
+    cost: f32,
+    cast_time: f32,
+    range: f32,
+    effects: VecAbilityEffect,
+});
+
+/// the modifiers apply to the attributes of the entity during duration,
+/// an invulnerable entity takes no damage
+pub struct StatusDefinition {
+    pub name: String,
+    pub duration: f32,
+    pub invulnerable: bool,
+    pub modifiers: VecModifier,
+}
+impl_from_into_toml_for_struct!( StatusDefinition {
+    name: String,
+    duration: f32,
+    invulnerable: bool,
+    modifiers: VecModifier,
+});
+
+/// the index of the ability named name
+pub fn ability(name: &str) -> Option<usize> {
+    config.abilities.definitions.iter().position(|a| a.name == name)
+}
+
+/// the index of the pool named name
+pub fn pool(name: &str) -> Option<usize> {
+    config.abilities.pools.iter().position(|p| p.name == name)
+}
+
+/// the index of the status named name
+pub fn status(name: &str) -> Option<usize> {
+    config.abilities.statuses.iter().position(|s| s.name == name)
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum AbilityError {
+    /// the remaining time of the cooldown
+    Cooldown(f32),
+    /// the value missing in the pool
+    Depleted(f32),
+}
+
+/// the pools and cooldowns of an entity, indexed as the definitions
+pub struct Abilities {
+    pools: Vec<f32>,
+    regen_delays: Vec<f32>,
+    cooldowns: Vec<f32>,
+}
+impl specs::Component for Abilities {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Abilities {
+    /// pools are full and abilities ready
+    pub fn new() -> Self {
+        Abilities {
+            pools: config.abilities.pools.iter().map(|p| p.max).collect(),
+            regen_delays: vec![0.; config.abilities.pools.len()],
+            cooldowns: vec![0.; config.abilities.definitions.len()],
+        }
+    }
+
+    /// whether the ability can be used now
+    pub fn check(&self, ability: usize) -> Result<(),AbilityError> {
+        let definition = &config.abilities.definitions[ability];
+        if self.cooldowns[ability] > 0. {
+            return Err(AbilityError::Cooldown(self.cooldowns[ability]));
+        }
+        if let Some(pool) = pool(&definition.pool) {
+            if self.pools[pool] < definition.cost {
+                return Err(AbilityError::Depleted(definition.cost - self.pools[pool]));
+            }
+        }
+        Ok(())
+    }
+
+    /// spend the cost and start the cooldown if the ability can be used
+    pub fn try_use(&mut self, ability: usize) -> Result<(),AbilityError> {
+        try!(self.check(ability));
+        let definition = &config.abilities.definitions[ability];
+        if let Some(pool) = pool(&definition.pool) {
+            self.pools[pool] -= definition.cost;
+            self.regen_delays[pool] = config.abilities.pools[pool].regen_delay;
+        }
+        self.start_cooldown(ability);
+        Ok(())
+    }
+
+    /// start the cooldown without using the ability
+    pub fn start_cooldown(&mut self, ability: usize) {
+        self.cooldowns[ability] = config.abilities.definitions[ability].cooldown;
+    }
+
+    /// the remaining time of the cooldown
+    pub fn cooldown(&self, ability: usize) -> f32 {
+        self.cooldowns[ability]
+    }
+
+    /// the remaining fraction of the cooldown, from 1 when just used to 0 when ready
+    pub fn cooldown_ratio(&self, ability: usize) -> f32 {
+        let cooldown = config.abilities.definitions[ability].cooldown;
+        if cooldown > 0. { self.cooldowns[ability]/cooldown } else { 0. }
+    }
+
+    pub fn pool(&self, pool: usize) -> f32 {
+        self.pools[pool]
+    }
+
+    /// the fraction of the pool that is filled
+    pub fn pool_ratio(&self, pool: usize) -> f32 {
+        self.pools[pool]/config.abilities.pools[pool].max
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for cooldown in &mut self.cooldowns {
+            *cooldown = (*cooldown - dt).max(0.);
+        }
+        for (i, definition) in config.abilities.pools.iter().enumerate() {
+            if self.regen_delays[i] > 0. {
+                self.regen_delays[i] -= dt;
+            } else {
+                self.pools[i] = (self.pools[i] + definition.regen*dt).min(definition.max);
+            }
+        }
+    }
+}
+
+/// the statuses applied to an entity with their remaining time
+pub struct Statuses {
+    active: Vec<(usize,f32)>,
+}
+impl specs::Component for Statuses {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Statuses {
+    pub fn new() -> Self {
+        Statuses {
+            active: vec!(),
+        }
+    }
+
+    /// apply the status for its duration, a status already applied is refreshed
+    pub fn apply(&mut self, status: usize) {
+        let duration = config.abilities.statuses[status].duration;
+        self.active.retain(|&(s, _)| s != status);
+        self.active.push((status, duration));
+    }
+
+    pub fn is_active(&self, status: usize) -> bool {
+        self.active.iter().any(|&(s, _)| s == status)
+    }
+
+    /// whether an active status is invulnerable
+    pub fn is_invulnerable(&self) -> bool {
+        self.active.iter().any(|&(s, _)| config.abilities.statuses[s].invulnerable)
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for &mut (_, ref mut remaining) in &mut self.active {
+            *remaining -= dt;
+        }
+        self.active.retain(|&(_, remaining)| remaining > 0.);
+    }
+}
+
+/// an entity using the abilities of its slots
+pub struct Caster {
+    slots: Vec<usize>,
+    /// the slot to use at next update
+    pub requested: Option<usize>,
+    /// the ability cast and the remaining cast time
+    casting: Option<(usize,f32)>,
+}
+impl specs::Component for Caster {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Caster {
+    pub fn new(names: &[String]) -> Self {
+        Caster {
+            slots: names.iter().map(|name| ability(name).expect("caster ability must be defined in abilities.definitions")).collect(),
+            requested: None,
+            casting: None,
+        }
+    }
+
+    pub fn slots(&self) -> &[usize] {
+        &self.slots
+    }
+
+    /// the ability cast and the remaining cast time
+    pub fn casting(&self) -> Option<(usize,f32)> {
+        self.casting
+    }
+}
+
+/// update cooldowns, pools and statuses, start the requested casts and apply the
+/// effects of the casts that are over
+pub struct AbilitySystem;
+impl specs::System<app::UpdateContext> for AbilitySystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut abilities, mut casters, mut statuses, mut dashes, mut attacks, mut ropes, mut lives, states, forces, factions, attributes, physic_world, relations, entities) = arg.fetch(|world| {
+            (
+                world.write::<Abilities>(),
+                world.write::<Caster>(),
+                world.write::<Statuses>(),
+                world.write::<Dash>(),
+                world.write::<MeleeAttack>(),
+                world.write::<Rope>(),
+                world.write::<Life>(),
+                world.read::<PhysicState>(),
+                world.read::<PhysicForce>(),
+                world.read::<Faction>(),
+                world.read::<Attributes>(),
+                world.read_resource::<PhysicWorld>(),
+                world.read_resource::<Relations>(),
+                world.entities(),
+            )
+        });
+
+        for abilities in (&mut abilities).iter() {
+            abilities.update(context.dt);
+        }
+        for (statuses, entity) in (&mut statuses, &entities).iter() {
+            statuses.update(context.dt);
+            if let Some(life) = lives.get_mut(entity) {
+                life.set_invulnerable(statuses.is_invulnerable());
+            }
+        }
+
+        let mut casts = vec!();
+        for (caster, abilities, entity) in (&mut casters, &mut abilities, &entities).iter() {
+            if let Some(slot) = caster.requested.take() {
+                let ability = caster.slots.get(slot).cloned();
+                if let (None, Some(ability)) = (caster.casting, ability) {
+                    if abilities.try_use(ability).is_ok() {
+                        caster.casting = Some((ability, config.abilities.definitions[ability].cast_time));
+                    }
+                }
+            }
+            if let Some((ability, remaining)) = caster.casting {
+                let remaining = remaining - context.dt;
+                if remaining <= 0. {
+                    caster.casting = None;
+                    casts.push((entity, ability));
+                } else {
+                    caster.casting = Some((ability, remaining));
+                }
+            }
+        }
+
+        for (entity, ability) in casts {
+            let position = match states.get(entity) {
+                Some(state) => state.position,
+                None => continue,
+            };
+            let direction = forces.get(entity).map(|force| force.direction).unwrap_or(0.);
+            let front = |distance: f32| [position[0] + distance*direction.cos(), position[1] + distance*direction.sin()];
+
+            for effect in &config.abilities.definitions[ability].effects {
+                match effect.effect {
+                    EffectType::Projectile => {
+                        let profile = projectile::profile(&effect.name).expect("ability projectile must be a projectile profile");
+                        context.control_tx.send(app::Control::CreateProjectile(entity,front(config.abilities.projectile_offset),direction,profile)).unwrap();
+                    },
+                    EffectType::Dash => if let Some(dash) = dashes.get_mut(entity) {
+                        dash.start(direction, effect.amount);
+                    },
+                    EffectType::Blast => {
+                        let amount = effect.amount*progression::damage(attributes.get(entity));
+                        let victims = physic_world.explode(position, effect.radius, config.abilities.blast_falloff, config.abilities.blast_mask.val, config.entities.wall_group.val)
+                            .into_iter()
+                            .filter(|&(victim, intensity)| victim != entity && intensity > 0.
+                                    && lives.get(victim).is_some() && relations.harms(factions.get(entity), factions.get(victim)))
+                            .collect::<Vec<_>>();
+                        for (victim, intensity) in victims {
+                            let amount = amount*intensity;
+                            lives.get_mut(victim).unwrap().damage(amount, entity);
+                            let victim_position = states.get(victim).map(|s| s.position).unwrap_or(position);
+                            context.event_tx.send(app::GameEvent::Damaged(Damage::new(entity, victim, victim_position, amount, DamageType::Explosion))).unwrap();
+                        }
+                    },
+                    EffectType::Status => if let Some(statuses) = statuses.get_mut(entity) {
+                        statuses.apply(status(&effect.name).expect("ability status must be defined in abilities.statuses"));
+                    },
+                    EffectType::Summon => {
+                        let tile = front(effect.radius);
+                        context.control_tx.send(app::Control::Summon(effect.name.clone(),[tile[0].round() as isize, tile[1].round() as isize])).unwrap();
+                    },
+                    EffectType::Melee => {
+                        let mut attack = MeleeAttack::new(effect.radius, effect.amount*PI/180., config.abilities.melee_duration, config.abilities.melee_mask.val);
+                        attack.swing(direction);
+                        attacks.insert(entity, attack);
+                    },
+                    EffectType::Rope => {
+                        let released = match ropes.get_mut(entity) {
+                            Some(rope) => {
+                                rope.reel(effect.amount);
+                                effect.amount == 0.
+                            },
+                            None => {
+                                if let Some(rope) = Rope::cast(&physic_world, entity, position, direction, effect.radius, config.abilities.rope_mask.val) {
+                                    ropes.insert(entity, rope);
+                                }
+                                false
+                            },
+                        };
+                        if released {
+                            ropes.remove(entity);
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_abilities() {
+    let column = ability(&config.abilities.column).unwrap();
+    let mut abilities = Abilities::new();
+    assert_eq!(abilities.try_use(column), Ok(()));
+    assert!(abilities.try_use(column).is_err());
+    abilities.update(config.abilities.definitions[column].cooldown);
+    assert_eq!(abilities.check(column), Ok(()));
+
+    // a failed use spends nothing
+    if let Some(costly) = config.abilities.definitions.iter().position(|a| pool(&a.pool).is_some() && a.cost > 0.) {
+        let pool = pool(&config.abilities.definitions[costly].pool).unwrap();
+        abilities.pools[pool] = 0.;
+        assert!(abilities.try_use(costly).is_err());
+        assert_eq!(abilities.pool(pool), 0.);
+        assert_eq!(abilities.cooldown(costly), 0.);
+    }
+
+    let mut statuses = Statuses::new();
+    if !config.abilities.statuses.is_empty() {
+        statuses.apply(0);
+        assert!(statuses.is_active(0));
+        statuses.update(config.abilities.statuses[0].duration);
+        assert!(!statuses.is_active(0));
+    }
+}
@@ -0,0 +1,82 @@
+//! the accessibility options, saved in save.toml and changed in the menu:
+//! * colorblind: the hue colors are replaced by accessibility.colorblind_palette, told
+//!   apart with any kind of color blindness
+//! * shake: the scale of the intensity of the screen shake, 0 disables it
+//! * game_speed: the scale of the time of the game
+//! * hold_to_toggle: a direction pressed is held until pressed again
+//!
+//! the screen shake offsets the camera by a random amount proportional to the square of
+//! the trauma. the trauma is added by the damages to the players and by the explosions
+//! and decays over time.
+
+use config;
+use graphics::ColorsValue;
+use rand::{self, Rng};
+
+pub struct Accessibility {
+    pub shake: f32,
+    pub game_speed: f32,
+    pub hold_to_toggle: bool,
+    /// in [0,1]
+    trauma: f32,
+}
+impl Accessibility {
+    pub fn new() -> Self {
+        Accessibility {
+            shake: config.accessibility.shake,
+            game_speed: config.accessibility.game_speed,
+            hold_to_toggle: config.accessibility.hold_to_toggle,
+            trauma: 0.,
+        }
+    }
+
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.trauma = (self.trauma - config.accessibility.shake_decay*dt).max(0.);
+    }
+
+    /// the offset of the camera for the screen shake
+    pub fn shake_offset(&self) -> [f32;2] {
+        let amplitude = config.accessibility.shake_amplitude*self.shake*self.trauma.powi(2);
+        if amplitude <= 0. {
+            return [0., 0.];
+        }
+        // the shake is cosmetic, it must not draw from the world rng
+        let mut rng = rand::thread_rng();
+        [rng.gen_range(-amplitude, amplitude), rng.gen_range(-amplitude, amplitude)]
+    }
+}
+
+/// the colors with the hue colors replaced by accessibility.colorblind_palette
+pub fn colorblind_colors(colors: &ColorsValue) -> ColorsValue {
+    let palette = &config.accessibility.colorblind_palette;
+    let mut colors = colors.clone();
+    colors.yellow = palette[0];
+    colors.orange = palette[1];
+    colors.red = palette[2];
+    colors.magenta = palette[3];
+    colors.violet = palette[4];
+    colors.blue = palette[5];
+    colors.cyan = palette[6];
+    colors.green = palette[7];
+    colors
+}
+
+#[test]
+fn test_screen_shake() {
+    let mut accessibility = Accessibility::new();
+    accessibility.shake = 1.;
+    accessibility.add_trauma(2.);
+    assert_eq!(accessibility.trauma, 1.);
+    let offset = accessibility.shake_offset();
+    assert!(offset[0].abs() <= config.accessibility.shake_amplitude);
+
+    accessibility.shake = 0.;
+    assert_eq!(accessibility.shake_offset(), [0., 0.]);
+
+    accessibility.update(2./config.accessibility.shake_decay);
+    assert_eq!(accessibility.trauma, 0.);
+}
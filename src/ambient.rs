@@ -0,0 +1,254 @@
+//! ambient emitters placed by the level data: looping particles (dust, drips),
+//! looping positional sounds and flickering lights.
+//!
+//! each emitter is an instance of one of the prefabs of ambient.prefabs. emitters
+//! farther than ambient.cull_distance from the listener, which follows the camera,
+//! are culled: their particles are dropped and neither simulated nor drawn, and
+//! their sound isn't played.
+
+use app;
+use specs;
+use config;
+use baal;
+use rand::{self, Rng};
+use configuration::{self, FromToml};
+use toml;
+use components::*;
+use graphics::{self, Color, Layer};
+use specs::Join;
+use std::f32::consts::PI;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub type Array2F32 = [f32;2];
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum EmitterKind {
+    Particles,
+    Sound,
+    Light,
+}
+impl_from_into_toml_for_enum!(EmitterKind {
+    Particles,
+    Sound,
+    Light,
+});
+
+/// * name: the name the level data refer to
+/// * kind: particles, sound or light
+/// * color, layer: of the particles or the light
+/// * rate: particles spawned per second, or changes of intensity of the light per second
+/// * lifetime: of the particles
+/// * velocity: of the particles, downward for drips
+/// * spread: radius around the emitter the particles spawn in
+/// * radius: of the particles, or of the light at full intensity
+/// * flicker: the intensity of the light varies in [1 - flicker, 1]
+/// * sound: the persistent effect looping at the emitter
+pub struct AmbientPrefab {
+    pub name: String,
+    pub kind: EmitterKind,
+    pub color: Color,
+    pub layer: Layer,
+    pub rate: f32,
+    pub lifetime: f32,
+    pub velocity: Array2F32,
+    pub spread: f32,
+    pub radius: f32,
+    pub flicker: f32,
+    pub sound: usize,
+}
+impl_from_into_toml_for_struct!( AmbientPrefab {
+    name: String,
+    kind: EmitterKind,
+    color: Color,
+    layer: Layer,
+    rate: f32,
+    lifetime: f32,
+    velocity: Array2F32,
+    spread: f32,
+    radius: f32,
+    flicker: f32,
+    sound: usize,
+});
+
+/// an emitter of the prefab of name at position (map rows are negative)
+pub struct EmitterDefinition {
+    pub position: Array2F32,
+    pub prefab: String,
+}
+impl_from_into_toml_for_struct!( EmitterDefinition {
+    position: Array2F32,
+    prefab: String,
+});
+
+struct Particle {
+    position: [f32;2],
+    age: f32,
+}
+
+pub struct AmbientEmitter {
+    prefab: usize,
+    active: bool,
+    particles: Vec<Particle>,
+    /// time until the next particle or change of intensity
+    timer: f32,
+    intensity: f32,
+}
+impl specs::Component for AmbientEmitter {
+    type Storage = specs::VecStorage<Self>;
+}
+impl AmbientEmitter {
+    pub fn new(prefab: usize) -> Self {
+        AmbientEmitter {
+            prefab: prefab,
+            active: false,
+            particles: vec!(),
+            timer: 0.,
+            intensity: 1.,
+        }
+    }
+
+    pub fn prefab(&self) -> &'static AmbientPrefab {
+        &config.ambient.prefabs[self.prefab]
+    }
+
+    /// false if the emitter is culled
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn particles(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// age and move the particles, spawn new ones around position and flicker the light
+    fn update<R: Rng>(&mut self, position: [f32;2], dt: f32, rng: &mut R) {
+        let prefab = self.prefab();
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.position[0] += prefab.velocity[0]*dt;
+            particle.position[1] += prefab.velocity[1]*dt;
+        }
+        self.particles.retain(|p| p.age < prefab.lifetime);
+
+        if prefab.rate <= 0. {
+            return;
+        }
+        self.timer -= dt;
+        while self.timer <= 0. {
+            self.timer += 1./prefab.rate;
+            match prefab.kind {
+                EmitterKind::Particles => if self.particles.len() < config.ambient.max_particles {
+                    let distance = prefab.spread*rng.gen::<f32>().sqrt();
+                    let angle = 2.*PI*rng.gen::<f32>();
+                    self.particles.push(Particle {
+                        position: [position[0] + distance*angle.cos(), position[1] + distance*angle.sin()],
+                        age: 0.,
+                    });
+                },
+                EmitterKind::Light => self.intensity = 1. - prefab.flicker*rng.gen::<f32>(),
+                EmitterKind::Sound => (),
+            }
+        }
+    }
+
+    /// draw the first max_particles particles
+    pub fn draw(&self, position: [f32;2], max_particles: usize, frame: &mut graphics::Frame) {
+        if !self.active {
+            return;
+        }
+        let prefab = self.prefab();
+        match prefab.kind {
+            EmitterKind::Particles => for particle in self.particles.iter().take(max_particles) {
+                let p = particle.position;
+                if frame.is_visible(p[0],p[1],prefab.radius) {
+                    frame.draw_square(p[0],p[1],prefab.radius,prefab.layer,prefab.color);
+                }
+            },
+            EmitterKind::Light => {
+                let radius = prefab.radius*self.intensity;
+                if frame.is_visible(position[0],position[1],radius) {
+                    frame.draw_circle(position[0],position[1],radius,prefab.layer,prefab.color);
+                }
+            },
+            EmitterKind::Sound => (),
+        }
+    }
+}
+
+/// cull the emitters far from the listener and update the others
+pub struct AmbientSystem;
+impl specs::System<app::UpdateContext> for AmbientSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut emitters, states) = arg.fetch(|world| {
+            (
+                world.write::<AmbientEmitter>(),
+                world.read::<PhysicState>(),
+            )
+        });
+
+        // ambient emitters are cosmetic, they must not draw from the world rng
+        let mut rng = rand::thread_rng();
+        let listener = baal::effect::listener();
+        for (emitter, state) in (&mut emitters, &states).iter() {
+            let p = state.position;
+            let distance = ((p[0] - listener[0]).powi(2) + (p[1] - listener[1]).powi(2)).sqrt();
+            emitter.active = distance <= config.ambient.cull_distance;
+            if emitter.active {
+                emitter.update(p, context.dt, &mut rng);
+            } else {
+                emitter.particles.clear();
+            }
+        }
+    }
+}
+
+/// create the emitters defined in the file if it exists:
+/// a toml file with an array of tables `emitters`
+pub fn load(path: &Path, world: &mut specs::World) -> Result<(),String> {
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    let mut string = String::new();
+    try!(try!(fs::File::open(&path).map_err(|e| format!("open ambient failed: {}",e)))
+         .read_to_string(&mut string)
+         .map_err(|_| String::from("ambient invalid: not valid UTF-8")));
+
+    let mut parser = toml::Parser::new(&*string);
+    let table = try!(parser.parse().ok_or(format!("ambient {:?} invalid: toml parsing failed",path)));
+    let emitters: Vec<EmitterDefinition> = match table.get("emitters") {
+        Some(emitters) => try!(FromToml::from_toml(emitters).map_err(|e| format!("ambient {:?} invalid: emitters{}",path,e))),
+        None => vec!(),
+    };
+
+    for (i, emitter) in emitters.iter().enumerate() {
+        let prefab = try!(config.ambient.prefabs.iter().position(|p| p.name == emitter.prefab)
+            .ok_or(format!("ambient {:?} invalid: emitters[{}].prefab {} is not in ambient.prefabs",path,i,emitter.prefab)));
+        world.create_now()
+            .with::<PhysicState>(PhysicState::new(emitter.position))
+            .with::<AmbientEmitter>(AmbientEmitter::new(prefab))
+            .build();
+    }
+    Ok(())
+}
+
+#[test]
+fn test_ambient_emitter() {
+    use rng::WorldRng;
+
+    let mut rng = WorldRng::from_seed(1);
+    for (i, prefab) in config.ambient.prefabs.iter().enumerate() {
+        let mut emitter = AmbientEmitter::new(i);
+        for _ in 0..100 {
+            emitter.update([0.,0.], 0.1, &mut rng);
+        }
+        assert!(emitter.particles.len() <= config.ambient.max_particles);
+        match prefab.kind {
+            EmitterKind::Particles => assert!(!emitter.particles.is_empty()),
+            EmitterKind::Light => assert!(emitter.intensity >= 1. - prefab.flicker && emitter.intensity <= 1.),
+            EmitterKind::Sound => assert!(emitter.particles.is_empty()),
+        }
+    }
+}
@@ -9,19 +9,69 @@ use specs::Join;
 use levels;
 use systems::*;
 use components::*;
+use resource::{DayNight, Weather, Relations, WorldRng};
 use std::sync::mpsc;
 use baal;
 use std::rc::Rc;
 use std::sync::Arc;
 use entities;
+use inventory::Stack;
+use checkpoint::PlayerSnapshot;
+use stats::Stats;
+use sequence::{self, Sequence};
+use quest::Journal;
+use combat::{CombatLog, Damage};
+use task::{Task, Tasks};
+use inspector::Inspector;
+use memory;
+use freecam::{self, FreeCamera};
+use capture::Capture;
+use jobs::Jobs;
+use scene::SceneStack;
+use loading::Loading;
+use governor::{self, Governor};
+use profiles::{self, Profiles};
+use settings;
+use ambient::EmitterKind;
+use hotreload::Watcher;
+use telemetry::Telemetry;
+use locale::Locale;
+use accessibility::{self, Accessibility};
+use controls::{self, Controls, Rebind};
+use display::{self, Display, DisplayEvent};
+use shaders::Shaders;
+use palette;
+use lod::{RenderLod, RenderLodProfile, LodRepresentation};
+use feedback::HitFeedback;
+use conf;
+use shop::{self, ShopView, Trade};
+use worlds;
+use schedule::Schedule;
+use net::{self, Lockstep, NetError};
+use replication::{Server, Client};
+use life::Kind;
+use movement::MovementMode;
+use mount;
+use grab;
+use autotile;
+use boss;
+use decal::{Decals, DecalKind};
+use combat::DamageType;
 use std::fmt;
+use std::mem;
+use std::time::Instant;
 use gilrs;
+use rand;
 
 static HELP: &'static str = "
 use up,down,left,right or w,s,a,d to move
 
 use escape to go to or escape from menu
 
+use space or e to interact and to read the next line of a dialogue
+
+use escape to skip a sequence
+
 ";
 
 static CREDIT: &'static str = "
@@ -50,12 +100,95 @@ powered by rust language [rust-lang.org]
 pub struct Graphic {
     color: graphics::Color,
     layer: graphics::Layer,
+    /// the name of the material in shaders.materials
+    material: Option<&'static str>,
+    material_param: f32,
+    /// the time left of the flash of a hit
+    flash: f32,
+    /// the scale added by a hit
+    punch: f32,
+    /// the time left of the outline of a hit
+    outline: f32,
+    /// the index of the palette in palettes.definitions
+    palette: Option<usize>,
+    lod: Option<RenderLod>,
 }
 impl Graphic {
     pub fn new(color: graphics::Color, layer: graphics::Layer) -> Self {
         Graphic {
             color: color,
             layer: layer,
+            material: None,
+            material_param: 0.,
+            flash: 0.,
+            punch: 0.,
+            outline: 0.,
+            palette: None,
+            lod: None,
+        }
+    }
+    /// draw with the palette, none if empty
+    pub fn with_palette(mut self, palette: &str) -> Self {
+        self.palette = palette::palette(palette);
+        self
+    }
+    /// draw far with the render lod profile, none if empty
+    pub fn with_lod(mut self, lod: &str) -> Self {
+        self.lod = RenderLod::named(lod);
+        self
+    }
+    /// the render lod profile if far
+    fn far(&self) -> Option<&'static RenderLodProfile> {
+        self.lod.as_ref().and_then(|lod| if lod.far { Some(lod.profile()) } else { None })
+    }
+    /// draw with the material, the default one if empty
+    pub fn with_material(mut self, material: &'static str, param: f32) -> Self {
+        self.material = if material.is_empty() { None } else { Some(material) };
+        self.material_param = param;
+        self
+    }
+    pub fn set_color(&mut self, color: graphics::Color) {
+        self.color = color;
+    }
+    /// the color in the palette
+    fn swap(&self, color: graphics::Color) -> graphics::Color {
+        match self.palette {
+            Some(palette) => palette::swap(palette, color),
+            None => color,
+        }
+    }
+    fn color(&self) -> graphics::Color {
+        self.swap(self.color)
+    }
+    pub fn flash(&mut self) {
+        self.flash = config.shaders.flash_duration;
+    }
+    pub fn punch(&mut self, punch: f32) {
+        self.punch = self.punch.max(punch);
+    }
+    pub fn outline(&mut self, time: f32) {
+        self.outline = self.outline.max(time);
+    }
+    fn update(&mut self, dt: f32) {
+        self.flash = (self.flash - dt).max(0.);
+        self.punch = (self.punch - config.combat.hit_punch_decay*dt).max(0.);
+        self.outline = (self.outline - dt).max(0.);
+    }
+    /// the scale of the shape
+    fn scale(&self) -> f32 {
+        1. + self.punch
+    }
+    /// the material to draw with and its parameter
+    fn material(&self, shaders: &Shaders) -> (graphics::Material, f32) {
+        if self.flash > 0. {
+            (shaders.material(&*config.shaders.flash_material), self.flash / config.shaders.flash_duration)
+        } else if self.outline > 0. {
+            (shaders.material(&*config.shaders.outline_material), config.shaders.outline_width)
+        } else {
+            match self.material {
+                Some(material) => (shaders.material(material), self.material_param),
+                None => (graphics::Material::default(), 0.),
+            }
         }
     }
 }
@@ -73,6 +206,15 @@ pub enum Effect {
         layer: graphics::Layer,
         color: graphics::Color,
     },
+    /// text rising from position
+    Text {
+        position: [f32;2],
+        text: String,
+        persistance: f32,
+        color: graphics::Color,
+    },
+    /// applied to the entity when received
+    HitFeedback(HitFeedback),
 }
 impl Effect {
     fn next(self,dt: f32) -> Option<Effect> {
@@ -85,6 +227,16 @@ impl Effect {
                     None
                 }
             },
+            Effect::Text { position, text, mut persistance, color } => {
+                persistance -= dt;
+                if persistance > 0. {
+                    let position = [position[0], position[1] + config.combat.float_velocity*dt];
+                    Some(Effect::Text { position: position, text: text, persistance: persistance, color: color })
+                } else {
+                    None
+                }
+            },
+            Effect::HitFeedback(feedback) => feedback.next(dt).map(|feedback| Effect::HitFeedback(feedback)),
         }
     }
     fn draw(&self, frame: &mut graphics::Frame) {
@@ -100,6 +252,10 @@ impl Effect {
             } => {
                 frame.draw_line(o[0],o[1],a,le,t,la,co);
             },
+            &Effect::Text { position, ref text, persistance: _, color } => {
+                frame.draw_text(position[0],position[1],config.combat.float_scale,text,graphics::Layer::Ceil,color);
+            },
+            &Effect::HitFeedback(ref feedback) => feedback.draw(frame),
         }
     }
 }
@@ -107,23 +263,133 @@ impl Effect {
 pub enum Control {
     GotoLevel(levels::Level),
     ResetLevel,
+    Respawn,
     ResetGame,
     ResetCastle,
     CreateBall([f32;2],Arc<()>),
+    CreatePickup([f32;2],[f32;2],Stack),
+    TransferInventory(specs::Entity,specs::Entity),
+    /// the caster, the position, the heading and the projectile profile
+    CreateProjectile(specs::Entity,[f32;2],f32,usize),
+    /// an entity spawnable by sequences and its tile
+    Summon(String,[isize;2]),
+    /// the customer and the vendor
+    OpenShop(specs::Entity,specs::Entity),
+    /// the customer, the vendor and the item traded
+    Trade(specs::Entity,specs::Entity,usize,Trade),
+    /// the rider and the mount
+    Mount(specs::Entity,specs::Entity),
+    /// the boss and the tiles of the adds of its phase, summoned one after the other
+    SummonAdds(specs::Entity,Vec<[isize;2]>),
+    /// wall or open the gates of the arena
+    LockArena(specs::Entity),
+    UnlockArena(specs::Entity),
+}
+
+/// gameplay events sent by systems, the app drains them after each update
+pub enum GameEvent {
+    InventoryChanged(specs::Entity),
+    ItemPickedUp(specs::Entity,Stack),
+    InteractionPromptChanged(specs::Entity,Option<String>),
+    Interact(specs::Entity,specs::Entity),
+    CheckpointReached(specs::Entity,PlayerSnapshot),
+    Respawned(specs::Entity),
+    Died(specs::Entity,Kind,[f32;2]),
+    AchievementUnlocked(String),
+    /// quest and stage indices in the quests definitions
+    QuestStageCompleted(usize,usize),
+    QuestCompleted(usize),
+    EnteredLiquid(specs::Entity),
+    LeftLiquid(specs::Entity),
+    /// the trigger and the entity that starts or stops overlapping it
+    OverlapEnter(specs::Entity,specs::Entity),
+    OverlapExit(specs::Entity,specs::Entity),
+    /// the attacker, the target and the direction from the attacker to the target
+    MeleeHit(specs::Entity,specs::Entity,f32),
+    /// the ambient light crossed day_night.threshold
+    Dawn,
+    Dusk,
+    /// the tracked entity and the name of the region
+    RegionEntered(specs::Entity,String),
+    RegionExited(specs::Entity,String),
+    Damaged(Damage),
+    /// the last entity that damaged it, the entity killed and its kind
+    Killed(specs::Entity,specs::Entity,Kind),
+    /// the customer, the vendor, the stack traded and its price
+    Bought(specs::Entity,specs::Entity,Stack,usize),
+    Sold(specs::Entity,specs::Entity,Stack,usize),
+    DashStarted(specs::Entity),
+    DashEnded(specs::Entity),
+    MovementModeChanged(specs::Entity,MovementMode),
+    /// the grabber and the body
+    Grabbed(specs::Entity,specs::Entity),
+    Released(specs::Entity,specs::Entity),
+    Thrown(specs::Entity,specs::Entity),
+    /// the boss and its index in bosses.definitions
+    BossEncounterStarted(specs::Entity,usize),
+    /// the boss and the index of its phase
+    BossPhaseChanged(specs::Entity,usize),
+    /// the index of the boss in bosses.definitions and whether it is defeated
+    BossEncounterEnded(usize,bool),
+    /// the governor changed the quality to the level of quality.levels
+    QualityChanged(usize),
 }
 
 #[derive(Clone)]
 pub struct UpdateContext {
     pub effect_tx: mpsc::Sender<Effect>,
     pub control_tx: mpsc::Sender<Control>,
+    pub event_tx: mpsc::Sender<GameEvent>,
     pub dt: f32,
+    /// the factor of the physic lod distances of the quality level
+    pub ai_lod: f32,
 }
 
+/// the part of a job run on the main thread once it is done
+pub type Completion = Box<FnMut(&mut App) + Send>;
+
+/// the completion notifying the text
+fn notification(text: String) -> Completion {
+    Box::new(move |app: &mut App| app.notification = Some((text.clone(),config.stats.notification_time)))
+}
+
+/// add the system to the planner in the built-in phase, see schedule
+pub fn add_system<S: specs::System<UpdateContext> + 'static>(planner: &mut specs::Planner<UpdateContext>, system: S, name: &str, phase: &str) {
+    Schedule::new().add_system(planner, system, name, phase);
+}
+
+/// a callback run as a system with the dt of the update
+pub struct StepHook<F: FnMut(specs::RunArg, f32) + Send>(F);
+impl<F: FnMut(specs::RunArg, f32) + Send> specs::System<UpdateContext> for StepHook<F> {
+    fn run(&mut self, arg: specs::RunArg, context: UpdateContext) {
+        (self.0)(arg, context.dt);
+    }
+}
+
+/// run the hook before the physic step of each update
+#[allow(dead_code)]
+pub fn add_pre_step_hook<F: FnMut(specs::RunArg, f32) + Send + 'static>(planner: &mut specs::Planner<UpdateContext>, name: &str, hook: F) {
+    add_system(planner, StepHook(hook), name, "pre_step");
+}
+
+/// run the hook after the physic step of each update
+#[allow(dead_code)]
+pub fn add_post_step_hook<F: FnMut(specs::RunArg, f32) + Send + 'static>(planner: &mut specs::Planner<UpdateContext>, name: &str, hook: F) {
+    add_system(planner, StepHook(hook), name, "post_step");
+}
+
+/// the states of the scene stack: the game at the bottom, a menu for the main menu and
+/// each submenu over it, and the texts and controls over the menu that opened them.
+/// the loading of a level is over the game until the world is swapped in
 #[derive(PartialEq,Clone)]
 enum State {
     Game,
+    Loading,
+    /// the entry selected
     Menu(usize),
-    Text(usize,String),
+    Text(String),
+    /// the row selected and true if listening for a key
+    Controls(usize,bool),
 }
 
 struct MenuEntry {
@@ -149,6 +415,118 @@ impl MenuEntry {
     }
 }
 
+/// the entries of the display submenu
+fn display_menu() -> Vec<MenuEntry> {
+    vec!(
+        MenuEntry::new_button(
+            Box::new(|app| app.locale.format("window_mode", &[("value", &*app.locale.get(app.display.mode.name()))])),
+            Rc::new(Box::new(|app| {
+                app.display.cycle_mode();
+                app.save();
+            }))),
+        MenuEntry::new_left_right(
+            Box::new(|app| app.locale.format("resolution", &[("value", &*format!("{}x{}", app.display.resolution[0], app.display.resolution[1]))])),
+            Rc::new(Box::new(|app| {
+                app.display.cycle_resolution(-1);
+                app.save();
+            })),
+            Rc::new(Box::new(|app| {
+                app.display.cycle_resolution(1);
+                app.save();
+            }))),
+        MenuEntry::new_button(
+            Box::new(|app| app.locale.format("vsync", &[("value", &*app.locale.get(if app.display.vsync { "on" } else { "off" }))])),
+            Rc::new(Box::new(|app| {
+                app.display.toggle_vsync();
+                app.save();
+            }))),
+        MenuEntry::new_left_right(
+            Box::new(|app| app.locale.format("ui_scale", &[("value", &*((app.display.ui_scale*10.).round() as usize).to_string())])),
+            Rc::new(Box::new(|app| {
+                app.display.ui_scale = (app.display.ui_scale - 0.1).max(0.5);
+                app.graphics.set_ui_scale(app.display.ui_scale);
+                app.save();
+            })),
+            Rc::new(Box::new(|app| {
+                app.display.ui_scale = (app.display.ui_scale + 0.1).min(2.0);
+                app.graphics.set_ui_scale(app.display.ui_scale);
+                app.save();
+            }))),
+        MenuEntry::new_button(
+            Box::new(|app| app.locale.format("letterbox", &[("value", &*app.locale.get(if app.display.letterbox { "on" } else { "off" }))])),
+            Rc::new(Box::new(|app| {
+                app.display.letterbox = !app.display.letterbox;
+                app.graphics.set_letterbox(app.display.letterbox());
+                app.save();
+            }))),
+        MenuEntry::new_button(
+            Box::new(|app| app.locale.format("pixel_art", &[("value", &*app.locale.get(if app.graphics.pixel_art().is_some() { "on" } else { "off" }))])),
+            Rc::new(Box::new(|app| {
+                let pixel_art = if app.graphics.pixel_art().is_some() { None } else { Some(pixel_art()) };
+                match app.graphics.set_pixel_art(pixel_art) {
+                    Ok(()) => app.save(),
+                    Err(err) => app.notification = Some((format!("{}",err),config.stats.notification_time)),
+                }
+            }))),
+        MenuEntry::new_button(
+            Box::new(|app| app.locale.format("post_effects", &[("value", &*app.locale.get(if app.shaders.post_effects { "on" } else { "off" }))])),
+            Rc::new(Box::new(|app| {
+                app.shaders.post_effects = !app.shaders.post_effects;
+                app.graphics.set_post_passes(app.shaders.active_post_passes());
+                app.save();
+            }))),
+    )
+}
+
+fn pixel_art() -> graphics::PixelArt {
+    graphics::PixelArt {
+        resolution: config.graphics.pixel_art_resolution,
+        integer_scaling: config.graphics.pixel_art_integer_scaling,
+    }
+}
+
+/// the entries of the accessibility submenu
+fn accessibility_menu() -> Vec<MenuEntry> {
+    vec!(
+        MenuEntry::new_button(
+            Box::new(|app| app.locale.format("colorblind", &[("value", &*app.locale.get(if app.graphics.colorblind() { "on" } else { "off" }))])),
+            Rc::new(Box::new(|app| {
+                let colorblind = app.graphics.colorblind();
+                app.graphics.set_colorblind(!colorblind);
+                app.save();
+            }))),
+        MenuEntry::new_left_right(
+            Box::new(|app| app.locale.format("screen_shake", &[("value", &*((app.accessibility.shake*10.).round() as usize).to_string())])),
+            Rc::new(Box::new(|app| {
+                app.accessibility.shake = (app.accessibility.shake - 0.1).max(0.0);
+                app.save();
+            })),
+            Rc::new(Box::new(|app| {
+                app.accessibility.shake = (app.accessibility.shake + 0.1).min(1.0);
+                app.save();
+            }))),
+        MenuEntry::new_left_right(
+            Box::new(|app| app.locale.format("game_speed", &[("value", &*((app.accessibility.game_speed*10.).round() as usize).to_string())])),
+            Rc::new(Box::new(|app| {
+                app.accessibility.game_speed = (app.accessibility.game_speed - 0.1).max(0.1);
+                app.save();
+            })),
+            Rc::new(Box::new(|app| {
+                app.accessibility.game_speed = (app.accessibility.game_speed + 0.1).min(1.0);
+                app.save();
+            }))),
+        MenuEntry::new_button(
+            Box::new(|app| app.locale.format("hold_to_toggle", &[("value", &*app.locale.get(if app.accessibility.hold_to_toggle { "on" } else { "off" }))])),
+            Rc::new(Box::new(|app| {
+                app.accessibility.hold_to_toggle = !app.accessibility.hold_to_toggle;
+                // directions held by the previous mode are released
+                app.player_control_state = PlayerControlState::Keyboard(vec!());
+                app.update_player_control();
+                app.save();
+            }))),
+    )
+}
+
 enum PlayerControlState {
     Keyboard(Vec<Direction>),
     Joystick(f32,f32),
@@ -169,6 +547,21 @@ impl PlayerControlState {
             directions.retain(|&d| d != dir);
         }
     }
+    /// hold the direction until pressed again, the opposite direction is released
+    fn toggle_keyboard_dir(&mut self, dir: Direction) {
+        let held = match *self {
+            PlayerControlState::Keyboard(ref directions) => directions.contains(&dir),
+            PlayerControlState::Joystick(..) => false,
+        };
+        if held {
+            self.retain_keyboard_dir(dir);
+        } else {
+            if let &mut PlayerControlState::Keyboard(ref mut directions) = self {
+                directions.retain(|d| d.perpendicular(&dir));
+            }
+            self.push_keyboard_dir(dir);
+        }
+    }
     fn set_axis_x_state(&mut self, x: f32) {
         if let PlayerControlState::Keyboard(_) = *self {
             *self = PlayerControlState::Joystick(0.,0.);
@@ -187,6 +580,29 @@ impl PlayerControlState {
     }
 }
 
+/// the curtain of the transition between two states, it shrinks from the sides with
+/// the fraction of the transition left
+fn draw_transition(frame: &mut graphics::Frame, transition: Option<f32>) {
+    if let Some(left) = transition {
+        frame.draw_rectangle(0.,0.,config.menu.background_width*left,config.menu.background_height,
+                             graphics::Layer::BillBoard,config.menu.background_color);
+    }
+}
+
+fn push_inventory_text(inventory: &Inventory, text: &mut String) {
+    for stack in inventory.slots().iter().filter_map(|slot| slot.as_ref()) {
+        let name = config.items.definitions.get(stack.item).map(|d| &*d.name).unwrap_or("?");
+        text.push_str(&*format!("{} x{}  ",name,stack.count));
+    }
+}
+
+fn apply_input(force: &mut PhysicForce, input: net::Input) {
+    if input.intensity != 0. {
+        force.direction = input.direction;
+    }
+    force.intensity = input.intensity;
+}
+
 enum JoystickMenuState {
     Pressed(Direction,f32),
     Released,
@@ -196,9 +612,17 @@ pub struct App {
     difficulty: f32,
     menu: Vec<MenuEntry>,
     menu_interline: Vec<usize>,
+    /// the parent menus of the submenu shown with their interlines, the entries are the
+    /// ones of the menu states under it
+    menu_stack: Vec<(Vec<MenuEntry>,Vec<usize>)>,
     castles: Vec<levels::Castle>,
-    state: State,
+    states: SceneStack<State>,
     current_level: levels::Level,
+    /// the levels reachable from the current one built on worker threads
+    preloads: Vec<levels::Preload>,
+    /// the level entered built for the loading state
+    loading: Option<Loading>,
+    jobs: Jobs<Completion>,
     camera: graphics::Camera,
     graphics: graphics::Graphics,
     planner: specs::Planner<UpdateContext>,
@@ -209,6 +633,40 @@ pub struct App {
     effect_rx: mpsc::Receiver<Effect>,
     effect_storage: Vec<Effect>,
     effect_tx: mpsc::Sender<Effect>,
+    event_rx: mpsc::Receiver<GameEvent>,
+    event_tx: mpsc::Sender<GameEvent>,
+    inventory_hud: String,
+    interaction_prompt: Option<String>,
+    checkpoint: Option<PlayerSnapshot>,
+    stats: Stats,
+    notification: Option<(String,f32)>,
+    sequence: Option<Sequence>,
+    journal: Journal,
+    tasks: Tasks,
+    inspector: Inspector,
+    free_camera: Option<FreeCamera>,
+    /// save the next frame rendered as a screenshot
+    screenshot_requested: bool,
+    capture: Capture,
+    watcher: Watcher,
+    shop: Option<ShopView>,
+    combat_log: CombatLog,
+    telemetry: Telemetry,
+    governor: Governor,
+    profiles: Profiles,
+    locale: Locale,
+    accessibility: Accessibility,
+    controls: Controls,
+    display: Display,
+    shaders: Shaders,
+    decals: Decals,
+    net: Option<Lockstep>,
+    server: Option<Server>,
+    client: Option<Client>,
+    action_requested: bool,
+    player_immersed: bool,
+    /// the effect volume to restore once the sounds are not muffled anymore
+    muffled_volume: Option<f32>,
     focus: bool,
     pub quit: bool,
 }
@@ -216,6 +674,8 @@ pub struct App {
 pub enum AppError {
     InitGraphics(graphics::GraphicsCreationError),
     LevelCreation(String),
+    LoadStats(String),
+    LoadLocale(String),
 }
 
 impl fmt::Display for AppError {
@@ -224,6 +684,8 @@ impl fmt::Display for AppError {
         match *self {
             InitGraphics(ref e) => write!(fmt,"graphics init failed: {}",e),
             LevelCreation(ref s) =>write!(fmt,"level creation error: {}",s),
+            LoadStats(ref s) =>write!(fmt,"stats loading error: {}",s),
+            LoadLocale(ref s) =>write!(fmt,"locale loading error: {}",s),
         }
     }
 }
@@ -231,25 +693,29 @@ impl fmt::Display for AppError {
 impl App {
     pub fn new<F: glium::backend::Facade>(facade: &F, castles: Vec<levels::Castle>) -> Result<App,AppError> {
         // init graphics
-        let graphics = try!(graphics::Graphics::new(facade, graphics::GraphicsSetting {
-            colors: graphics::ColorsValue {
-                base03: config.graphics.base03,
-                base02: config.graphics.base02,
-                base01: config.graphics.base01,
-                base00: config.graphics.base00,
-                base0: config.graphics.base0,
-                base1: config.graphics.base1,
-                base2: config.graphics.base2,
-                base3: config.graphics.base3,
-                yellow: config.graphics.yellow,
-                orange: config.graphics.orange,
-                red: config.graphics.red,
-                magenta: config.graphics.magenta,
-                violet: config.graphics.violet,
-                blue: config.graphics.blue,
-                cyan: config.graphics.cyan,
-                green: config.graphics.green,
-            },
+        let colors = graphics::ColorsValue {
+            base03: config.graphics.base03,
+            base02: config.graphics.base02,
+            base01: config.graphics.base01,
+            base00: config.graphics.base00,
+            base0: config.graphics.base0,
+            base1: config.graphics.base1,
+            base2: config.graphics.base2,
+            base3: config.graphics.base3,
+            yellow: config.graphics.yellow,
+            orange: config.graphics.orange,
+            red: config.graphics.red,
+            magenta: config.graphics.magenta,
+            violet: config.graphics.violet,
+            blue: config.graphics.blue,
+            cyan: config.graphics.cyan,
+            green: config.graphics.green,
+        };
+        let display = Display::new();
+        let mut graphics = try!(graphics::Graphics::new(facade, graphics::GraphicsSetting {
+            colorblind_colors: accessibility::colorblind_colors(&colors),
+            colorblind: config.accessibility.colorblind,
+            colors: colors,
             mode: match &*config.graphics.mode {
                 "light" => graphics::Mode::Light,
                 "dark" => graphics::Mode::Dark,
@@ -259,40 +725,27 @@ impl App {
             circle_precision: config.graphics.circle_precision,
             font: config.graphics.font_file.val.clone(),
             billboard_font_scale: config.graphics.billboard_font_scale,
+            ui_scale: display.ui_scale,
+            letterbox: display.letterbox(),
+            pixel_art: if config.graphics.pixel_art { Some(pixel_art()) } else { None },
         }).map_err(|e| AppError::InitGraphics(e)));
+        let shaders = Shaders::load(&mut graphics);
+
+        // load stats
+        let stats = try!(Stats::load().map_err(|e| AppError::LoadStats(e)));
+
+        // load locale
+        let locale = try!(Locale::load().map_err(|e| AppError::LoadLocale(e)));
+        let controls = Controls::load(&config.controls.profile);
+        for (key, action, other) in controls.conflicts() {
+            println!("WARNING: controls {}: key {} is bound to {} and {}", controls.profile(), key, action.name(), other.name());
+        }
 
         // init camera
         let camera = graphics::Camera::new(0.0, 0.0, config.camera.zoom);
 
         // init world
-        let mut world = specs::World::new();
-        world.register::<PlayerControl>();
-        world.register::<TowardPlayerControl>();
-        world.register::<MonsterControl>();
-
-        world.register::<PhysicState>();
-        world.register::<PhysicForce>();
-        world.register::<PhysicType>();
-        world.register::<PhysicDynamic>();
-        world.register::<PhysicStatic>();
-        world.register::<PhysicTrigger>();
-        world.register::<GridSquare>();
-
-        world.register::<Graphic>();
-
-        world.register::<Life>();
-        world.register::<Killer>();
-        world.register::<Ball>();
-        world.register::<Column>();
-
-        world.register::<Portal>();
-
-        world.register::<FixedCameraText>();
-        world.register::<Text>();
-        world.register::<FixedCamera>();
-
-        world.register::<DynPersistentSnd>();
-        world.register::<StaticPersistentSnd>();
+        let mut world = worlds::new_world();
 
         // check levels
         let check_level = match &*config.levels.check_level {
@@ -320,6 +773,8 @@ impl App {
                         };
                         try!(levels::load_level(&level, &castles, &mut world)
                                 .map_err(|e| AppError::LevelCreation(format!("load level {}.{}.{} failed: {}",castle.name,dungeon.name,r,e))));
+                        try!(sequence::load(&level, &castles)
+                                .map_err(|e| AppError::LevelCreation(format!("load sequence {}.{}.{} failed: {}",castle.name,dungeon.name,r,e))));
                     }
                 }
             }
@@ -333,28 +788,58 @@ impl App {
 
         // init planner
         let mut planner = specs::Planner::new(world,config.general.number_of_thread);
-        planner.add_system(PhysicSystem, "physic", 10);
-        planner.add_system(PlayerSystem::default(), "player", 5);
-        planner.add_system(MonsterSystem, "monster", 5);
-        planner.add_system(TowardPlayerSystem, "toward_player", 5);
-        planner.add_system(KillerSystem, "killer", 5);
-        planner.add_system(BallSystem, "ball", 5);
-        planner.add_system(PortalSystem, "portal", 5);
-        planner.add_system(ColumnSystem, "column", 5);
-        planner.add_system(LifeSystem, "life", 1);
-        planner.add_system(PersistentSndSystem::default(), "life", 2);
+        let schedule = Schedule::new();
+        schedule.add_system(&mut planner, PhysicSystem, "physic", "physic");
+        #[cfg(feature = "validate")]
+        schedule.add_system(&mut planner, ValidateSystem, "validate", "validate");
+        schedule.add_system(&mut planner, PlayerSystem::default(), "player", "control");
+        schedule.add_system(&mut planner, MonsterSystem, "monster", "control");
+        schedule.add_system(&mut planner, TowardPlayerSystem, "toward_player", "control");
+        schedule.add_system(&mut planner, KillerSystem, "killer", "gameplay");
+        schedule.add_system(&mut planner, BallSystem, "ball", "gameplay");
+        schedule.add_system(&mut planner, PortalSystem, "portal", "gameplay");
+        schedule.add_system(&mut planner, ColumnSystem, "column", "gameplay");
+        schedule.add_system(&mut planner, PickupSystem, "pickup", "gameplay");
+        schedule.add_system(&mut planner, InteractionSystem, "interaction", "gameplay");
+        schedule.add_system(&mut planner, HighlightSystem, "highlight", "follow");
+        schedule.add_system(&mut planner, CheckpointSystem, "checkpoint", "gameplay");
+        schedule.add_system(&mut planner, LiquidSystem::default(), "liquid", "gameplay");
+        schedule.add_system(&mut planner, ForceZoneSystem, "force_zone", "gameplay");
+        schedule.add_system(&mut planner, ProjectileFieldSystem::default(), "projectile_field", "gameplay");
+        schedule.add_system(&mut planner, UpdateLodSystem, "update_lod", "gameplay");
+        schedule.add_system(&mut planner, MeleeSystem, "melee", "gameplay");
+        schedule.add_system(&mut planner, HazardSystem::default(), "hazard", "gameplay");
+        schedule.add_system(&mut planner, BossSystem, "boss", "gameplay");
+        schedule.add_system(&mut planner, RegionSystem, "region", "gameplay");
+        schedule.add_system(&mut planner, ThreatSystem, "threat", "gameplay");
+        schedule.add_system(&mut planner, MotionSystem, "motion", "gameplay");
+        schedule.add_system(&mut planner, ProgressionSystem, "progression", "gameplay");
+        schedule.add_system(&mut planner, AbilitySystem, "ability", "gameplay");
+        schedule.add_system(&mut planner, DashSystem, "dash", "gameplay");
+        schedule.add_system(&mut planner, MovementSystem, "movement", "gameplay");
+        schedule.add_system(&mut planner, GrabSystem, "grab", "follow");
+        schedule.add_system(&mut planner, MountSystem, "mount", "follow");
+        schedule.add_system(&mut planner, AutotileSystem, "autotile", "gameplay");
+        schedule.add_system(&mut planner, RopeSystem, "rope", "follow");
+        schedule.add_system(&mut planner, PatrolSystem, "patrol", "control");
+        schedule.add_system(&mut planner, DayNightSystem, "day_night", "gameplay");
+        schedule.add_system(&mut planner, WeatherSystem::default(), "weather", "gameplay");
+        schedule.add_system(&mut planner, AmbientSystem, "ambient", "gameplay");
+        schedule.add_system(&mut planner, LifeSystem, "life", "life");
+        schedule.add_system(&mut planner, PersistentSndSystem::default(), "persistent_snd", "sound");
 
         let (effect_tx, effect_rx) = mpsc::channel();
         let (control_tx, control_rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
 
         // create menu
-        let menu_interline = vec!(0,1,4,7,9,11);
+        let menu_interline = vec!(0,1,4,7,13,15);
         let menu = vec!(
             MenuEntry::new_button(
-                Box::new(|_| "continue".into()),
+                Box::new(|app| app.locale.get("continue")),
                 Rc::new(Box::new(|app| app.goto_state_game()))),
             MenuEntry::new_left_right(
-                Box::new(|app| format!("difficulty: {}",((app.difficulty*10.).round() as usize))),
+                Box::new(|app| app.locale.format("difficulty", &[("value", &*((app.difficulty*10.).round() as usize).to_string())])),
                 Rc::new(Box::new(|app| {
                     app.difficulty = (app.difficulty - 0.1).max(0.1);
                     app.save();
@@ -364,22 +849,22 @@ impl App {
                     app.save();
                 }))),
             MenuEntry::new_button(
-                Box::new(|_| "restart room".into()),
+                Box::new(|app| app.locale.get("restart_room")),
                 Rc::new(Box::new(|app| {
                     app.control_tx.send(Control::ResetLevel).unwrap();
                 }))),
             MenuEntry::new_button(
-                Box::new(|_| "restart castle".into()),
+                Box::new(|app| app.locale.get("restart_castle")),
                 Rc::new(Box::new(|app| {
                     app.control_tx.send(Control::ResetCastle).unwrap();
                 }))),
             MenuEntry::new_button(
-                Box::new(|_| "restart game".into()),
+                Box::new(|app| app.locale.get("restart_game")),
                 Rc::new(Box::new(|app| {
                     app.control_tx.send(Control::ResetGame).unwrap();
                 }))),
             MenuEntry::new_left_right(
-                Box::new(|_| format!("global volume: {}",(baal::global_volume()*10.).round() as usize)),
+                Box::new(|app| app.locale.format("global_volume", &[("value", &*((baal::global_volume()*10.).round() as usize).to_string())])),
                 Rc::new(Box::new(|app| {
                     baal::set_global_volume((baal::global_volume()-0.1).max(0.0));
                     app.save();
@@ -389,7 +874,7 @@ impl App {
                     app.save();
                 }))),
             MenuEntry::new_left_right(
-                Box::new(|_| format!("music volume: {}",(baal::music::volume()*10.).round() as usize)),
+                Box::new(|app| app.locale.format("music_volume", &[("value", &*((baal::music::volume()*10.).round() as usize).to_string())])),
                 Rc::new(Box::new(|app| {
                     baal::music::set_volume((baal::music::volume()-0.1).max(0.0));
                     app.save()
@@ -399,7 +884,7 @@ impl App {
                     app.save();
                 }))),
             MenuEntry::new_left_right(
-                Box::new(|_| format!("effects volume: {}",(baal::effect::volume()*10.).round() as usize)),
+                Box::new(|app| app.locale.format("effects_volume", &[("value", &*((baal::effect::volume()*10.).round() as usize).to_string())])),
                 Rc::new(Box::new(|app| {
                     baal::effect::set_volume((baal::effect::volume()-0.1).max(0.0));
                     app.save()
@@ -409,16 +894,16 @@ impl App {
                     app.save();
                 }))),
             MenuEntry::new_button(
-                Box::new(|app| format!("theme: {}", match app.graphics.mode() {
-                    graphics::Mode::Dark => "dark",
-                    graphics::Mode::Light => "light",
-                })),
+                Box::new(|app| app.locale.format("theme", &[("value", &*app.locale.get(match app.graphics.mode() {
+                    graphics::Mode::Dark => "theme_dark",
+                    graphics::Mode::Light => "theme_light",
+                }))])),
                 Rc::new(Box::new(|app| {
                     app.graphics.toggle_mode();
                     app.save();
                 }))),
             MenuEntry::new_left_right(
-                Box::new(|app| format!("luminosity: {}", (app.graphics.luminosity()*10.).round() as usize)),
+                Box::new(|app| app.locale.format("luminosity", &[("value", &*((app.graphics.luminosity()*10.).round() as usize).to_string())])),
                 Rc::new(Box::new(|app| {
                     let l = app.graphics.luminosity();
                     app.graphics.set_luminosity((l-0.1).max(0.1));
@@ -429,17 +914,55 @@ impl App {
                     app.graphics.set_luminosity((l+0.1).min(1.0));
                     app.save();
                 }))),
+            MenuEntry::new_left_right(
+                Box::new(|app| app.locale.format("language", &[("value", app.locale.language())])),
+                Rc::new(Box::new(|app| {
+                    app.locale.cycle(-1);
+                    app.save();
+                })),
+                Rc::new(Box::new(|app| {
+                    app.locale.cycle(1);
+                    app.save();
+                }))),
+            MenuEntry::new_left_right(
+                Box::new(|app| {
+                    let name = app.profiles.current();
+                    if name == &**profiles::SESSION {
+                        app.locale.format("profile", &[("value", name)])
+                    } else {
+                        app.locale.format("profile_next_start", &[("value", name)])
+                    }
+                }),
+                Rc::new(Box::new(|app| app.cycle_profile(-1))),
+                Rc::new(Box::new(|app| app.cycle_profile(1)))),
+            MenuEntry::new_button(
+                Box::new(|app| app.locale.get("display")),
+                Rc::new(Box::new(|app| app.open_submenu(display_menu(), vec!())))),
             MenuEntry::new_button(
-                Box::new(|_| "help".into()),
-                Rc::new(Box::new(|app| app.goto_state_text(HELP.into())))),
+                Box::new(|app| app.locale.get("accessibility")),
+                Rc::new(Box::new(|app| app.open_submenu(accessibility_menu(), vec!())))),
+            MenuEntry::new_button(
+                Box::new(|app| app.locale.get("controls")),
+                Rc::new(Box::new(|app| {
+                    app.push_state(State::Controls(0,false));
+                }))),
+            MenuEntry::new_button(
+                Box::new(|app| app.locale.get("help")),
+                Rc::new(Box::new(|app| {
+                    let text = app.locale.get_or("help_text", HELP);
+                    app.goto_state_text(text)
+                }))),
             // MenuEntry::new_button(
             //     Box::new(|_| "donate".into()),
             //     Rc::new(Box::new(|app| app.goto_state_text(DONATE.into())))),
             MenuEntry::new_button(
-                Box::new(|_| "credit".into()),
-                Rc::new(Box::new(|app| app.goto_state_text(CREDIT.into())))),
+                Box::new(|app| app.locale.get("credit")),
+                Rc::new(Box::new(|app| {
+                    let text = app.locale.get_or("credit_text", CREDIT);
+                    app.goto_state_text(text)
+                }))),
             MenuEntry::new_button(
-                Box::new(|_| "quit".into()),
+                Box::new(|app| app.locale.get("quit")),
                 Rc::new(Box::new(|app| app.quit = true))),
             );
 
@@ -447,9 +970,13 @@ impl App {
             difficulty: config.general.difficulty,
             menu_interline: menu_interline,
             menu: menu,
-            state: State::Game,
+            menu_stack: vec!(),
+            states: SceneStack::new(State::Game),
             castles: castles,
             current_level: level,
+            preloads: vec!(),
+            loading: None,
+            jobs: Jobs::new(config.general.job_threads),
             joystick_menu_state: JoystickMenuState::Released,
             effect_storage: Vec::new(),
             camera: camera,
@@ -460,6 +987,38 @@ impl App {
             effect_tx: effect_tx,
             control_rx: control_rx,
             control_tx: control_tx,
+            event_rx: event_rx,
+            event_tx: event_tx,
+            inventory_hud: String::new(),
+            interaction_prompt: None,
+            checkpoint: None,
+            stats: stats,
+            notification: None,
+            sequence: None,
+            journal: Journal::new(),
+            tasks: Tasks::new(),
+            inspector: Inspector::new(),
+            free_camera: None,
+            screenshot_requested: false,
+            capture: Capture::new(),
+            watcher: Watcher::new(),
+            shop: None,
+            combat_log: CombatLog::new(),
+            telemetry: Telemetry::new(),
+            governor: Governor::new(),
+            profiles: Profiles::load(&settings::dir()),
+            locale: locale,
+            accessibility: Accessibility::new(),
+            controls: controls,
+            display: display,
+            shaders: shaders,
+            decals: Decals::new(),
+            net: None,
+            server: None,
+            client: None,
+            action_requested: false,
+            player_immersed: false,
+            muffled_volume: None,
             focus: true,
             quit: false,
         })
@@ -471,10 +1030,23 @@ impl App {
 
         let result =  conf::save(conf::Save {
             difficulty: self.difficulty,
+            language: self.locale.language().into(),
             global_volume: baal::music::volume(),
-            effect_volume: baal::effect::volume(),
+            effect_volume: self.muffled_volume.unwrap_or(baal::effect::volume()),
             music_volume: baal::effect::volume(),
             luminosity: self.graphics.luminosity(),
+            colorblind: self.graphics.colorblind(),
+            shake: self.accessibility.shake,
+            game_speed: self.accessibility.game_speed,
+            hold_to_toggle: self.accessibility.hold_to_toggle,
+            profile: self.controls.profile().into(),
+            dimension: self.display.resolution,
+            vsync: self.display.vsync,
+            window_mode: self.display.mode.name().into(),
+            ui_scale: self.display.ui_scale,
+            letterbox: self.display.letterbox,
+            pixel_art: self.graphics.pixel_art().is_some(),
+            post_effects: self.shaders.post_effects,
             mode: match self.graphics.mode() {
                 graphics::Mode::Light => "light".into(),
                 graphics::Mode::Dark => "dark".into(),
@@ -484,21 +1056,21 @@ impl App {
             writeln!(&mut std::io::stderr(), "ERROR failed to save save_file: {}", err).unwrap();
         }
     }
-    fn update_player_control(&mut self) {
+    /// the input of the local player, the direction is meaningless if the intensity is null
+    fn player_input(&self) -> net::Input {
         use std::f32::consts::PI;
 
-        let world = self.planner.mut_world();
+        let mut input = net::Input::none();
+        input.action = self.action_requested;
+
+        if self.sequence.as_ref().map(|s| s.input_locked()).unwrap_or(false) {
+            return input;
+        }
 
         match self.player_control_state {
             PlayerControlState::Joystick(x,y) => {
-                let angle = y.atan2(x);
-                let intensity = (x.powi(2)+y.powi(2)).sqrt();
-                let characters = world.read::<PlayerControl>();
-                let mut forces = world.write::<PhysicForce>();
-                for (_, force) in (&characters, &mut forces).iter() {
-                    force.direction = angle;
-                    force.intensity = intensity;
-                }
+                input.direction = y.atan2(x);
+                input.intensity = (x.powi(2)+y.powi(2)).sqrt();
             },
             PlayerControlState::Keyboard(ref directions) => {
                 if let Some(dir) = directions.last() {
@@ -510,7 +1082,7 @@ impl App {
                         }
                     }
 
-                    let angle = match dir {
+                    input.direction = match dir {
                         &Direction::Up => {
                             match last_perpendicular {
                                 Some(&Direction::Left) => 3.*PI/4.,
@@ -540,53 +1112,168 @@ impl App {
                             }
                         },
                     };
-
-                    let characters = world.read::<PlayerControl>();
-                    let mut forces = world.write::<PhysicForce>();
-                    for (_, force) in (&characters, &mut forces).iter() {
-                        force.direction = angle;
-                        force.intensity = 1.;
-                    }
-                } else {
-                    let characters = world.read::<PlayerControl>();
-                    let mut forces = world.write::<PhysicForce>();
-                    for (_, force) in (&characters, &mut forces).iter() {
-                        force.intensity = 0.;
-                    }
+                    input.intensity = 1.;
                 }
             },
         }
+        input
     }
-    pub fn goto_state_menu(&mut self) {
-        baal::effect::short::stop_all();
-        baal::effect::persistent::clear_positions_for_all();
-        baal::effect::persistent::update_volume_for_all();
+    fn update_player_control(&mut self) {
+        // with lockstep the inputs are applied on each frame
+        if self.net.is_some() { return }
 
-        match self.state {
-            State::Game => self.state = State::Menu(0),
-            State::Menu(_) => (),
-            State::Text(entry,_) => self.state = State::Menu(entry),
+        let input = self.player_input();
+        let clients = self.server.as_ref().map(|s| s.client_entities()).unwrap_or(vec!());
+        let world = self.planner.mut_world();
+        let characters = world.read::<PlayerControl>();
+        let mut forces = world.write::<PhysicForce>();
+        for (_, force, entity) in (&characters, &mut forces, &world.entities()).iter() {
+            if !clients.contains(&entity) {
+                apply_input(force, input);
+            }
+        }
+    }
+    fn networked(&self) -> bool {
+        self.net.is_some() || self.server.is_some() || self.client.is_some()
+    }
+    /// push the state over the top one
+    fn push_state(&mut self, state: State) {
+        let left = self.states.top().clone();
+        self.state_left(&left);
+        self.states.push(state);
+        let entered = self.states.top().clone();
+        self.state_entered(&entered);
+    }
+    /// pop the top state, false if it is the game
+    fn pop_state(&mut self) -> bool {
+        let left = match self.states.pop() {
+            Some(left) => left,
+            None => return false,
+        };
+        if let State::Menu(_) = left {
+            // the parent menu of the submenu left
+            if let Some((menu, interline)) = self.menu_stack.pop() {
+                self.menu = menu;
+                self.menu_interline = interline;
+            }
+        }
+        self.state_left(&left);
+        let entered = self.states.top().clone();
+        self.state_entered(&entered);
+        true
+    }
+    /// the hook of the state shown, on a push or when the state over it is popped
+    fn state_entered(&mut self, state: &State) {
+        match *state {
+            State::Game => {
+                self.joystick_menu_state = JoystickMenuState::Released;
+                reset_static_persistent_snd(self.planner.mut_world());
+                let immersed = self.player_immersed;
+                self.muffle_sounds(immersed);
+            },
+            State::Text(_) => baal::effect::pause(),
+            State::Loading | State::Menu(_) | State::Controls(_,_) => (),
+        }
+    }
+    /// the hook of the state hidden, on a pop or when a state is pushed over it
+    fn state_left(&mut self, state: &State) {
+        match *state {
+            State::Game | State::Text(_) => {
+                baal::effect::short::stop_all();
+                baal::effect::persistent::clear_positions_for_all();
+                baal::effect::persistent::update_volume_for_all();
+
+                // the volume is set in the menu without attenuation
+                self.muffle_sounds(false);
+            },
+            State::Loading | State::Menu(_) | State::Controls(_,_) => (),
+        }
+    }
+    pub fn goto_state_menu(&mut self) {
+        loop {
+            let top = self.states.top().clone();
+            match top {
+                State::Game => return self.push_state(State::Menu(0)),
+                State::Menu(_) | State::Loading => return,
+                State::Text(_) | State::Controls(_,_) => { self.pop_state(); },
+            }
+        }
+    }
+    /// show the entries instead of the menu until escape
+    fn open_submenu(&mut self, menu: Vec<MenuEntry>, interline: Vec<usize>) {
+        let menu_shown = if let State::Menu(_) = *self.states.top() { true } else { false };
+        if menu_shown {
+            let parent = mem::replace(&mut self.menu, menu);
+            let parent_interline = mem::replace(&mut self.menu_interline, interline);
+            self.menu_stack.push((parent, parent_interline));
+            self.push_state(State::Menu(0));
         }
     }
     pub fn goto_state_game(&mut self) {
-        self.joystick_menu_state = JoystickMenuState::Released;
-        reset_static_persistent_snd(self.planner.mut_world());
-
-        self.state = State::Game;
+        // the game is entered once the level is loaded
+        if let State::Loading = *self.states.top() {
+            return;
+        }
+        let mut popped = false;
+        while self.pop_state() {
+            popped = true;
+        }
+        // the game is entered again in the world of the level loaded
+        if !popped {
+            self.state_entered(&State::Game);
+        }
     }
     pub fn goto_state_text(&mut self, text: String) {
-        baal::effect::pause();
-
-        match self.state {
-            State::Game => self.state = State::Text(0,text),
-            State::Text(entry,_) | State::Menu(entry) => self.state = State::Text(entry,text),
-        }
+        self.goto_state_menu();
+        self.push_state(State::Text(text));
     }
     pub fn goto_level(&mut self, level: levels::Level) {
         while let Ok(_) = self.control_rx.try_recv() {}
         while let Ok(_) = self.effect_rx.try_recv() {}
+        while let Ok(_) = self.event_rx.try_recv() {}
+
+        let preload = self.preloads.iter().position(|p| p.level == level).map(|i| self.preloads.remove(i));
+        // the other preloads are abandoned
+        self.preloads.clear();
+
+        // peers must build the same world at the same time
+        if config.levels.loading_screen && !self.networked() {
+            let preload = match preload {
+                Some(preload) => preload,
+                None => levels::Preload::start(level, self.castles.clone(), &self.jobs),
+            };
+            self.goto_state_game();
+            self.loading = Some(Loading::new(preload, rand::random()));
+            self.push_state(State::Loading);
+            return;
+        }
 
-        if let Some(e) = levels::load_level(&level,&self.castles,self.planner.mut_world()).err() {
+        let result = match preload {
+            Some(preload) => preload.wait().map(|world| {
+                levels::play_level_sounds(&level,&self.castles);
+                self.swap_world(world);
+            }),
+            None => levels::load_level(&level,&self.castles,self.planner.mut_world()),
+        };
+        self.level_loaded(level, result);
+    }
+    /// swap in the world of the loading level once it is built and enter the game
+    fn update_loading(&mut self, dt: f32) {
+        let world = match self.loading.as_mut().and_then(|loading| loading.update(dt)) {
+            Some(world) => world,
+            None => return,
+        };
+        let level = self.loading.take().unwrap().level().clone();
+        let result = world.map(|world| {
+            levels::play_level_sounds(&level,&self.castles);
+            self.swap_world(world);
+        });
+        self.level_loaded(level, result);
+        self.pop_state();
+    }
+    /// set up the level whose world is swapped in
+    fn level_loaded(&mut self, level: levels::Level, result: Result<(),levels::LoadLevelError>) {
+        if let Some(e) = result.err() {
             let level_name = match level {
                 levels::Level::Room { castle: c, dungeon: d, room: r } => format!("room (castle: {:?}, dungeon: {:?}, room: {:?})",
                 self.castles.get(c),
@@ -599,8 +1286,614 @@ impl App {
             panic!(format!("ERROR: failed to load level {}: {}",level_name,e));
         }
 
+        self.tasks.clear();
+        self.player_immersed = false;
+        self.muffle_sounds(false);
+
+        // sequences can be skipped locally so they are not played with lockstep
+        self.sequence = match sequence::load(&level,&self.castles) {
+            Ok(sequence) => if self.networked() { None } else { sequence },
+            // a sequence being edited may be invalid when the level is hot reloaded
+            Err(e) => {
+                use std;
+                use std::io::Write;
+                writeln!(&mut std::io::stderr(), "ERROR failed to load sequence: {}", e).unwrap();
+                None
+            },
+        };
+
+        if self.net.is_some() {
+            let world = self.planner.mut_world();
+            let host = (&world.read::<PlayerControl>(), &world.read::<PhysicState>(), &world.entities()).iter().next().map(|(_,s,e)| (e,s.position));
+            if let Some((host, position)) = host {
+                world.write::<Peer>().insert(host, Peer { index: 0 });
+                let peer = entities::add_peer_character(world, position);
+                world.write::<Peer>().insert(peer, Peer { index: 1 });
+            }
+        }
+        if let Some(ref mut server) = self.server {
+            server.reset_ids();
+            server.spawn_clients(self.planner.mut_world(), true);
+        }
+        if let Some(ref mut client) = self.client {
+            client.reset();
+        }
+        if let levels::Level::Room { castle, dungeon, room } = level {
+            let name = self.castles[castle].dungeons[dungeon].rooms[room].clone();
+            for event in self.journal.progress("reach", &*name, 1.) {
+                self.event_tx.send(event).unwrap();
+            }
+        }
+
         self.current_level = level;
         self.update_player_control();
+        self.update_inventory_hud();
+        self.interaction_prompt = None;
+        self.shop = None;
+        self.decals.clear();
+        self.checkpoint = None;
+        self.graphics.set_grading(graphics::Grading::default(), 0.);
+        self.start_preloads();
+
+        self.telemetry.level_started(self.current_level.name(&self.castles));
+
+        let mut files = levels::level_files(&self.current_level,&self.castles);
+        files.push(conf::FILE.into());
+        self.watcher.watch(files);
+    }
+    /// check the changed files and rebuild the level if one of its files changed,
+    /// a level or a configuration invalid is reported and not applied
+    fn hot_reload(&mut self, changed: Vec<::std::path::PathBuf>) {
+        let level = self.current_level.clone();
+        let message = if changed.iter().any(|file| file.as_path() == ::std::path::Path::new(conf::FILE)) {
+            match conf::check() {
+                Ok(()) => format!("{} changed: restart to apply",conf::FILE),
+                Err(err) => err,
+            }
+        } else {
+            let result = levels::build_level(&level,&self.castles,&mut worlds::new_world(),&mut |_| ())
+                .map_err(|e| format!("{}",e))
+                .and_then(|_| sequence::load(&level,&self.castles).map(|_| ()));
+            match result {
+                Ok(()) => {
+                    self.goto_level(level);
+                    format!("reloaded: {}",changed[0].display())
+                },
+                Err(err) => format!("reload failed: {}",err),
+            }
+        };
+        self.notification = Some((message,config.stats.notification_time));
+    }
+    /// replace the world by the one of a preloaded level,
+    /// the time of day and the weather go on
+    fn swap_world(&mut self, world: specs::World) {
+        let previous = ::std::mem::replace(self.planner.mut_world(), world);
+        let world = self.planner.mut_world();
+        ::std::mem::swap(&mut *world.write_resource::<DayNight>(), &mut *previous.write_resource::<DayNight>());
+        ::std::mem::swap(&mut *world.write_resource::<Weather>(), &mut *previous.write_resource::<Weather>());
+        ::std::mem::swap(&mut *world.write_resource::<Relations>(), &mut *previous.write_resource::<Relations>());
+        // the draws go on from the ones of the previous world instead of restarting from the seed
+        ::std::mem::swap(&mut *world.write_resource::<WorldRng>(), &mut *previous.write_resource::<WorldRng>());
+    }
+    /// build the destinations of the portals of the level by jobs,
+    /// not in network games as peers must build the same world at the same time
+    fn start_preloads(&mut self) {
+        if !config.levels.preload || self.networked() {
+            return;
+        }
+        let mut destinations: Vec<levels::Level> = vec!();
+        for portal in (&self.planner.mut_world().read::<Portal>()).iter() {
+            let destination = portal.destination();
+            if *destination != self.current_level && !destinations.contains(destination) {
+                destinations.push(destination.clone());
+            }
+        }
+        for destination in destinations {
+            self.preloads.push(levels::Preload::start(destination, self.castles.clone(), &self.jobs));
+        }
+    }
+    /// export the damages to a csv file for balance analysis
+    /// the builder of the window if the display settings need it to be rebuilt
+    pub fn take_window_rebuild(&mut self) -> Option<Result<glutin::WindowBuilder<'static>,String>> {
+        self.display.take_rebuild()
+    }
+    /// the graphics read the size of the framebuffer each frame, the settings are kept
+    /// up to date with the window
+    pub fn display_event(&mut self, event: DisplayEvent) {
+        match event {
+            DisplayEvent::Rebuilt => self.save(),
+            DisplayEvent::Failed(err) => self.notification = Some((err,config.stats.notification_time)),
+            DisplayEvent::Resized(width,height) => if self.display.mode == display::WindowMode::Windowed {
+                self.display.resolution = [width,height];
+            },
+        }
+    }
+    pub fn export_combat_log(&mut self, path: &::std::path::Path) -> ::std::io::Result<()> {
+        self.combat_log.export(path)
+    }
+    pub fn export_telemetry(&mut self, path: &::std::path::Path) -> ::std::io::Result<()> {
+        self.telemetry.export(path)
+    }
+    /// start lockstep with a peer from the entry
+    pub fn set_net(&mut self, net: Lockstep) {
+        self.net = Some(net);
+        self.journal = Journal::new();
+        self.goto_level(levels::Level::Entry);
+        self.goto_state_game();
+    }
+    /// serve the game to clients from the entry
+    pub fn set_server(&mut self, server: Server) {
+        self.server = Some(server);
+        self.goto_level(levels::Level::Entry);
+        self.goto_state_game();
+    }
+    /// play the game simulated by the server
+    pub fn set_client(&mut self, client: Client) {
+        self.client = Some(client);
+        self.goto_level(levels::Level::Entry);
+        self.goto_state_game();
+    }
+    fn disconnect(&mut self, error: NetError) {
+        self.net = None;
+        self.server = None;
+        self.client = None;
+        self.notification = Some((format!("net: {}",error),config.stats.notification_time));
+        self.update_player_control();
+    }
+    /// exchange inputs with the peer and apply the ones of the current frame,
+    /// return false if the frame must wait for the peer inputs
+    fn step_lockstep(&mut self) -> bool {
+        let result = if let Some(ref mut net) = self.net {
+            net.receive().map(|_| net.inputs())
+        } else {
+            return true;
+        };
+
+        match result {
+            Ok(Some(inputs)) => {
+                let world = self.planner.mut_world();
+                let characters = world.read::<PlayerControl>();
+                let peers = world.read::<Peer>();
+                let mut forces = world.write::<PhysicForce>();
+                let mut interactors = world.write::<Interactor>();
+                // by peer, the characters are reordered when one dies
+                for (_, peer, force, entity) in (&characters, &peers, &mut forces, &world.entities()).iter() {
+                    let input = match inputs.get(peer.index) {
+                        Some(input) => input,
+                        None => continue,
+                    };
+                    apply_input(force, *input);
+                    if input.action {
+                        if let Some(interactor) = interactors.get_mut(entity) {
+                            interactor.requested = true;
+                        }
+                    }
+                }
+                true
+            },
+            Ok(None) => {
+                if let Some(e) = self.net.as_mut().unwrap().send().err() {
+                    self.disconnect(e);
+                }
+                false
+            },
+            Err(e) => {
+                self.disconnect(e);
+                true
+            },
+        }
+    }
+    /// receive inputs of clients or snapshots of the server
+    fn receive_replication(&mut self) {
+        let mut error = None;
+        if let Some(ref mut server) = self.server {
+            let world = self.planner.mut_world();
+            error = server.receive(world).err();
+            server.spawn_clients(world, false);
+            server.apply_inputs(world);
+        }
+
+        let mut level = None;
+        if let Some(ref mut client) = self.client {
+            match client.receive() {
+                Ok(l) => level = l,
+                Err(e) => error = Some(e),
+            }
+        }
+        if let Some(level) = level {
+            if level != self.current_level {
+                self.goto_level(level);
+            }
+        }
+
+        let input = self.player_input();
+        if let Some(ref mut client) = self.client {
+            self.action_requested = false;
+            if let Some(e) = client.send_input(input).err() {
+                error = Some(e);
+            }
+        }
+
+        if let Some(e) = error {
+            self.disconnect(e);
+        }
+    }
+    /// send snapshots to clients or apply the ones of the server
+    fn send_replication(&mut self, dt: f32) {
+        let mut error = None;
+        if let Some(ref mut server) = self.server {
+            error = server.update(dt, self.planner.mut_world(), &self.current_level).err();
+        }
+        if let Some(ref mut client) = self.client {
+            client.apply(dt, self.planner.mut_world());
+        }
+        if let Some(e) = error {
+            self.disconnect(e);
+        }
+    }
+    /// schedule the local input and go to next frame
+    fn end_lockstep_frame(&mut self) {
+        let input = self.player_input();
+        self.action_requested = false;
+
+        let result = if let Some(ref mut net) = self.net {
+            let hash = net::world_hash(self.planner.mut_world());
+            net.push_input(input);
+            net.advance(hash).and_then(|_| net.send())
+        } else {
+            return;
+        };
+
+        if let Some(e) = result.err() {
+            self.disconnect(e);
+        }
+    }
+    fn update_sequence(&mut self, dt: f32) {
+        if let Some(mut sequence) = self.sequence.take() {
+            let was_locked = sequence.input_locked();
+            sequence.update(dt, self.planner.mut_world(), [self.camera.x,self.camera.y]);
+            let locked = sequence.input_locked();
+
+            if !sequence.is_finished() {
+                self.sequence = Some(sequence);
+            }
+            if locked != was_locked {
+                self.update_player_control();
+            }
+        }
+    }
+    fn skip_sequence(&mut self) {
+        if let Some(mut sequence) = self.sequence.take() {
+            sequence.skip(self.planner.mut_world());
+            self.update_player_control();
+        }
+    }
+    fn update_inventory_hud(&mut self) {
+        let world = self.planner.mut_world();
+        let characters = world.read::<PlayerControl>();
+        let inventories = world.read::<Inventory>();
+
+        self.inventory_hud.clear();
+        for (_, inventory) in (&characters, &inventories).iter() {
+            push_inventory_text(inventory, &mut self.inventory_hud);
+        }
+    }
+    /// the views to render with the character they follow:
+    /// one for each local character if split screen is enabled
+    fn views(&mut self) -> Vec<(graphics::View, Option<specs::Entity>)> {
+        let shake = if self.free_camera.is_none() { self.accessibility.shake_offset() } else { [0., 0.] };
+        let world = self.planner.mut_world();
+        let characters = world.read::<PlayerControl>();
+        let fixed_cameras = world.read::<FixedCamera>();
+        let states = world.read::<PhysicState>();
+        let peers = world.read::<Peer>();
+
+        let clients = self.server.as_ref().map(|s| s.client_entities()).unwrap_or(vec!());
+        let locals: Vec<(specs::Entity,[f32;2])> = (&characters, &states, &world.entities()).iter()
+            .filter(|&(_,_,e)| !clients.contains(&e))
+            .map(|(_,s,e)| (e,s.position))
+            .collect();
+        let fixed_camera = fixed_cameras.iter().next().is_some();
+        let sequence_camera = self.sequence.as_ref().and_then(|s| s.camera());
+
+        if config.graphics.split_screen && locals.len() > 1 && !fixed_camera && sequence_camera.is_none() && self.free_camera.is_none() {
+            let cameras = locals.iter().map(|&(_,p)| graphics::Camera::new(p[0] + shake[0], p[1] + shake[1], self.camera.zoom)).collect();
+            return graphics::View::split(cameras).into_iter().zip(locals.iter().map(|&(e,_)| Some(e))).collect();
+        }
+
+        let local_index = self.net.as_ref().map(|n| n.local_index());
+        for &(entity, position) in &locals {
+            if local_index.map(|l| peers.get(entity).map(|p| p.index) == Some(l)).unwrap_or(true) {
+                self.camera.x = position[0];
+                self.camera.y = position[1];
+            }
+        }
+        if fixed_camera {
+            self.camera.x = 0.;
+            self.camera.y = 0.;
+        }
+        if let Some(camera) = sequence_camera {
+            self.camera.x = camera[0];
+            self.camera.y = camera[1];
+        }
+        if let Some(ref camera) = self.free_camera {
+            self.camera.x = camera.position[0];
+            self.camera.y = camera.position[1];
+        }
+        let mut camera = self.camera.clone();
+        camera.x += shake[0];
+        camera.y += shake[1];
+        vec!((graphics::View::full(camera), None))
+    }
+    pub fn save_stats(&self) {
+        use std;
+        use std::io::Write;
+
+        if let Some(err) = self.stats.save().err() {
+            writeln!(&mut std::io::stderr(), "ERROR failed to save stats file: {}", err).unwrap();
+        }
+    }
+    fn handle_event(&mut self, event: GameEvent) {
+        let is_player = match event {
+            GameEvent::InventoryChanged(entity)
+                | GameEvent::ItemPickedUp(entity,_)
+                | GameEvent::InteractionPromptChanged(entity,_)
+                | GameEvent::Interact(entity,_)
+                | GameEvent::CheckpointReached(entity,_)
+                | GameEvent::Respawned(entity)
+                | GameEvent::Died(entity,_,_)
+                | GameEvent::EnteredLiquid(entity)
+                | GameEvent::LeftLiquid(entity)
+                | GameEvent::OverlapEnter(entity,_)
+                | GameEvent::OverlapExit(entity,_)
+                | GameEvent::MeleeHit(entity,_,_)
+                | GameEvent::RegionEntered(entity,_)
+                | GameEvent::RegionExited(entity,_)
+                | GameEvent::Killed(entity,_,_)
+                | GameEvent::Bought(entity,_,_,_)
+                | GameEvent::Sold(entity,_,_,_)
+                | GameEvent::DashStarted(entity)
+                | GameEvent::DashEnded(entity)
+                | GameEvent::MovementModeChanged(entity,_)
+                | GameEvent::Grabbed(entity,_)
+                | GameEvent::Released(entity,_)
+                | GameEvent::Thrown(entity,_) => self.planner.mut_world().read::<PlayerControl>().get(entity).is_some(),
+            GameEvent::Damaged(damage) => self.planner.mut_world().read::<PlayerControl>().get(damage.source).is_some(),
+            GameEvent::AchievementUnlocked(_)
+                | GameEvent::QuestStageCompleted(_,_)
+                | GameEvent::QuestCompleted(_)
+                | GameEvent::Dawn
+                | GameEvent::Dusk
+                | GameEvent::BossEncounterStarted(_,_)
+                | GameEvent::BossPhaseChanged(_,_)
+                | GameEvent::BossEncounterEnded(_,_)
+                | GameEvent::QualityChanged(_) => false,
+        };
+        self.inspector.record(&event);
+        let achievements = {
+            let players = self.planner.mut_world().read::<PlayerControl>();
+            self.stats.handle(&event, |entity| players.get(entity).is_some())
+        };
+        for achievement in achievements {
+            self.event_tx.send(GameEvent::AchievementUnlocked(achievement)).unwrap();
+        }
+        for quest_event in self.journal.handle(&event,is_player) {
+            self.event_tx.send(quest_event).unwrap();
+        }
+
+        match event {
+            GameEvent::InventoryChanged(_) => {
+                if is_player {
+                    self.update_inventory_hud();
+                }
+            },
+            GameEvent::InteractionPromptChanged(entity,prompt) => {
+                if is_player {
+                    self.interaction_prompt = prompt;
+                }
+                if self.shop.as_ref().map(|shop| shop.customer == entity).unwrap_or(false) {
+                    self.shop = None;
+                }
+            },
+            GameEvent::Bought(_,_,_,_) | GameEvent::Sold(_,_,_,_) => if is_player {
+                baal::effect::short::play_on_listener(config.shop.trade_snd);
+            },
+            GameEvent::AchievementUnlocked(name) => {
+                baal::effect::short::play_on_listener(config.stats.unlock_snd);
+                self.notification = Some((self.locale.format("achievement_unlocked", &[("name", &*self.locale.get(&name))]),config.stats.notification_time));
+                self.save_stats();
+                if config.capture.on_achievement {
+                    self.export_capture();
+                }
+            },
+            GameEvent::CheckpointReached(_,snapshot) => self.checkpoint = Some(snapshot),
+            GameEvent::QuestCompleted(quest) => {
+                let definition = &config.quests.definitions[quest];
+                baal::effect::short::play_on_listener(config.quests.complete_snd);
+                self.notification = Some((self.locale.format("quest_completed", &[("name", &*self.locale.get(&definition.name))]),config.stats.notification_time));
+                if definition.reward_count > 0 {
+                    self.give_reward(Stack { item: definition.reward_item, count: definition.reward_count });
+                }
+            },
+            GameEvent::Damaged(damage) => {
+                self.combat_log.record(damage);
+                self.telemetry.damage(&damage);
+                let world = self.planner.mut_world();
+                if damage.typ == DamageType::Explosion {
+                    self.decals.stamp(DecalKind::Scorch, damage.position);
+                    self.accessibility.add_trauma(config.accessibility.shake_explosion);
+                }
+                if world.read::<PlayerControl>().get(damage.target).is_some() {
+                    self.accessibility.add_trauma(config.accessibility.shake_damage);
+                }
+                // blood splashes away from the source
+                let source = world.read::<PhysicState>().get(damage.source).map(|s| s.position).unwrap_or(damage.position);
+                let angle = (damage.position[1] - source[1]).atan2(damage.position[0] - source[0]);
+                self.decals.stamp_on_surface(world, DecalKind::Blood, damage.position, &[angle]);
+                self.effect_tx.send(Effect::HitFeedback(HitFeedback::of_damage(&damage, angle))).unwrap();
+            },
+            GameEvent::Killed(killer,_,kind) => {
+                let xp = match kind {
+                    Kind::Character => config.progression.character_xp,
+                    Kind::Monster => config.progression.monster_xp,
+                    Kind::Ball => config.progression.ball_xp,
+                };
+                let level = self.planner.mut_world().write::<Experience>().get_mut(killer)
+                    .and_then(|experience| if experience.gain(xp) { Some(experience.level) } else { None });
+                if let (Some(level), true) = (level, is_player) {
+                    baal::effect::short::play_on_listener(config.progression.level_up_snd);
+                    self.notification = Some((self.locale.format("level_up", &[("level", &*level.to_string())]),config.stats.notification_time));
+                }
+            },
+            GameEvent::BossEncounterStarted(_,_) => {
+                if baal::music::index().is_some() {
+                    baal::music::play(boss::MUSIC);
+                }
+            },
+            GameEvent::BossEncounterEnded(definition,defeated) => {
+                levels::play_level_music(&self.current_level,&self.castles);
+                if defeated {
+                    let name = &config.bosses.definitions[definition].name;
+                    self.notification = Some((self.locale.format("boss_defeated", &[("name", &*self.locale.get(name))]),config.stats.notification_time));
+                }
+            },
+            GameEvent::QualityChanged(level) => {
+                let name = &config.quality.levels[level].name;
+                self.notification = Some((self.locale.format("quality_changed", &[("name", &*self.locale.get(name))]),config.stats.notification_time));
+            },
+            GameEvent::RegionEntered(_,name) => if is_player {
+                self.graphics.set_grading(self.shaders.region_grading(Some(&*name)), config.shaders.lut_transition);
+                self.notification = Some((self.locale.get(&name),config.stats.notification_time));
+            },
+            GameEvent::RegionExited(_,_) => if is_player {
+                self.graphics.set_grading(self.shaders.region_grading(None), config.shaders.lut_transition);
+            },
+            GameEvent::EnteredLiquid(_) => if is_player {
+                self.player_immersed = true;
+                self.muffle_sounds(true);
+            },
+            GameEvent::LeftLiquid(_) => if is_player {
+                self.player_immersed = false;
+                self.muffle_sounds(false);
+            },
+            GameEvent::Died(entity,kind,position) => {
+                // the references to the dead entity are invalidated
+                {
+                    let world = self.planner.mut_world();
+                    for monster in (&mut world.write::<MonsterControl>()).iter() {
+                        monster.invalidate(entity);
+                    }
+                    for motion in (&mut world.write::<Motion>()).iter() {
+                        motion.target.invalidate(entity);
+                    }
+                }
+                self.telemetry.death(kind, position);
+                if is_player {
+                    self.player_immersed = false;
+                    self.muffle_sounds(false);
+                }
+                match kind {
+                    Kind::Ball => {
+                        use std::f32::consts::PI;
+                        let world = self.planner.mut_world();
+                        self.decals.stamp_on_surface(world, DecalKind::Hole, position, &[0., PI/2., PI, -PI/2.]);
+                    },
+                    Kind::Character | Kind::Monster => self.decals.stamp(DecalKind::Blood, position),
+                }
+            },
+            GameEvent::ItemPickedUp(_,_)
+                | GameEvent::Interact(_,_)
+                | GameEvent::Respawned(_)
+                | GameEvent::QuestStageCompleted(_,_)
+                | GameEvent::OverlapEnter(_,_)
+                | GameEvent::OverlapExit(_,_)
+                | GameEvent::MeleeHit(_,_,_)
+                | GameEvent::DashStarted(_)
+                | GameEvent::DashEnded(_)
+                | GameEvent::MovementModeChanged(_,_)
+                | GameEvent::Grabbed(_,_)
+                | GameEvent::Released(_,_)
+                | GameEvent::Thrown(_,_)
+                | GameEvent::BossPhaseChanged(_,_)
+                | GameEvent::Dawn
+                | GameEvent::Dusk => (),
+        }
+    }
+    /// attenuate the effects while the player is in a liquid
+    fn muffle_sounds(&mut self, muffle: bool) {
+        match (muffle, self.muffled_volume) {
+            (true, None) => {
+                let volume = baal::effect::volume();
+                self.muffled_volume = Some(volume);
+                baal::effect::set_volume(volume*config.entities.liquid_sound_attenuation);
+            },
+            (false, Some(volume)) => {
+                baal::effect::set_volume(volume);
+                self.muffled_volume = None;
+            },
+            _ => (),
+        }
+    }
+    /// trade an item at the price of the vendor and send the Bought or Sold event,
+    /// notify the player of the reason of a failed trade
+    fn trade(&mut self, customer: specs::Entity, vendor: specs::Entity, item: usize, trade: Trade) {
+        let result = {
+            let world = self.planner.mut_world();
+            let mut wallets = world.write::<Wallet>();
+            let mut inventories = world.write::<Inventory>();
+            let entry = world.read::<Vendor>().get(vendor)
+                .and_then(|vendor| config.shop.tables[vendor.table].entries.iter().find(|entry| entry.item == item));
+
+            // the stock is taken out of the storage to borrow both inventories
+            match (entry, wallets.get_mut(customer), inventories.remove(vendor)) {
+                (Some(entry), Some(wallet), Some(mut stock)) => {
+                    let result = inventories.get_mut(customer)
+                        .ok_or(shop::ShopError::InventoryFull)
+                        .and_then(|inventory| shop::trade(trade, entry, wallet, &mut stock, inventory));
+                    inventories.insert(vendor, stock);
+                    result
+                },
+                (_, _, stock) => {
+                    if let Some(stock) = stock {
+                        inventories.insert(vendor, stock);
+                    }
+                    Err(shop::ShopError::NotTraded)
+                },
+            }
+        };
+
+        let is_player = self.planner.mut_world().read::<PlayerControl>().get(customer).is_some();
+        match result {
+            Ok(price) => {
+                let stack = Stack { item: item, count: 1 };
+                let event = match trade {
+                    Trade::Buy => GameEvent::Bought(customer,vendor,stack,price),
+                    Trade::Sell => GameEvent::Sold(customer,vendor,stack,price),
+                };
+                self.event_tx.send(event).unwrap();
+                self.update_inventory_hud();
+            },
+            Err(err) => if is_player {
+                self.notification = Some((self.locale.get(err.message()),config.stats.notification_time));
+            },
+        }
+    }
+    /// insert the stack in the player inventory and drop what doesn't fit
+    fn give_reward(&mut self, stack: Stack) {
+        {
+            let world = self.planner.mut_world();
+            let characters = world.read::<PlayerControl>();
+            let states = world.read::<PhysicState>();
+            let mut inventories = world.write::<Inventory>();
+
+            for (_, state, inventory) in (&characters, &states, &mut inventories).iter() {
+                let left = inventory.insert(stack);
+                if left != 0 {
+                    self.control_tx.send(Control::CreatePickup(state.position,[0.,0.],Stack { item: stack.item, count: left })).unwrap();
+                }
+            }
+        }
+        self.update_inventory_hud();
     }
     pub fn focused(&mut self, focus: bool) {
         self.focus = focus;
@@ -616,18 +1909,79 @@ impl App {
     pub fn update(&mut self, args: event_loop::UpdateArgs) {
         if !self.focus { return }
 
-        match self.state {
+        for mut completion in self.jobs.drain() {
+            completion(self);
+        }
+        self.states.update(args.dt as f32);
+
+        let state = self.states.top().clone();
+        match state {
             State::Game => {
+                if let Some(ref mut camera) = self.free_camera {
+                    camera.update(args.dt as f32);
+                    if camera.is_paused() { return }
+                }
+                if !self.step_lockstep() { return }
+                self.receive_replication();
+
+                let dt = if self.net.is_some() {
+                    1. / config.event_loop.ups as f32
+                } else {
+                    args.dt as f32 * self.difficulty * self.accessibility.game_speed
+                };
                 let context = UpdateContext {
-                    dt: args.dt as f32 * self.difficulty,
+                    dt: dt,
                     effect_tx: self.effect_tx.clone(),
                     control_tx: self.control_tx.clone(),
+                    event_tx: self.event_tx.clone(),
+                    // peers must step the same entities
+                    ai_lod: if self.networked() { 1. } else { self.governor.level().ai_lod },
                 };
 
+                self.capture.update(args.dt as f32);
+                self.accessibility.update(args.dt as f32);
+                self.telemetry.update(dt);
+                // peers must build the same world at the same time
+                if !self.networked() {
+                    let changed = self.watcher.update(args.dt as f32);
+                    if !changed.is_empty() {
+                        self.hot_reload(changed);
+                        return;
+                    }
+                }
+                self.update_sequence(args.dt as f32);
+                self.tasks.update(dt, self.planner.mut_world());
+
+                let start = Instant::now();
                 self.planner.dispatch(context);
                 self.planner.wait();
+                self.governor.measure("update", governor::seconds(start.elapsed()));
+                if let Some(level) = self.governor.update(args.dt as f32) {
+                    self.event_tx.send(GameEvent::QualityChanged(level)).unwrap();
+                }
+                self.decals.update(dt);
+                {
+                    let mut graphics = self.planner.mut_world().write::<Graphic>();
+                    for graphic in (&mut graphics).iter() {
+                        graphic.update(dt);
+                    }
+                }
+
+                for achievement in self.stats.add("time", args.dt as f32) {
+                    self.event_tx.send(GameEvent::AchievementUnlocked(achievement)).unwrap();
+                }
+                for event in self.journal.update(args.dt as f32) {
+                    self.event_tx.send(event).unwrap();
+                }
+                self.notification = self.notification.take().and_then(|(text,time)| {
+                    if time > args.dt as f32 { Some((text,time - args.dt as f32)) } else { None }
+                });
+
+                self.end_lockstep_frame();
+                self.send_replication(dt);
             },
-            State::Menu(_) | State::Text(_,_) => {
+            State::Loading => self.update_loading(args.dt as f32),
+            State::Menu(_) | State::Text(_) | State::Controls(_,_) => {
                 let dir = if let JoystickMenuState::Pressed(dir, ref mut time) = self.joystick_menu_state {
                     if *time <= 0. {
                         *time = config.joystick.time_to_repeat;
@@ -644,13 +1998,38 @@ impl App {
             },
         }
         while let Ok(control) = self.control_rx.try_recv() {
+            // the server decides of the level
+            if self.client.is_some() {
+                match control {
+                    Control::GotoLevel(_)
+                        | Control::ResetLevel
+                        | Control::Respawn
+                        | Control::ResetCastle
+                        | Control::ResetGame => continue,
+                    _ => (),
+                }
+            }
             match control {
-                Control::GotoLevel(level) => self.goto_level(level),
+                Control::GotoLevel(level) => {
+                    self.telemetry.level_completed();
+                    self.goto_level(level);
+                },
                 Control::ResetLevel => {
                     let level = self.current_level.clone();
                     self.goto_level(level);
                     self.goto_state_game();
                 }
+                Control::Respawn => {
+                    let snapshot = self.checkpoint.as_ref().map(|snapshot| snapshot.with_penalty());
+                    if let Some(snapshot) = snapshot {
+                        let entity = entities::respawn_character(self.planner.mut_world(),&snapshot);
+                        self.event_tx.send(GameEvent::Respawned(entity)).unwrap();
+                        self.update_player_control();
+                        self.update_inventory_hud();
+                    } else {
+                        self.control_tx.send(Control::ResetLevel).unwrap();
+                    }
+                }
                 Control::ResetCastle => {
                     let level = match self.current_level {
                         levels::Level::Room { castle, dungeon: _, room: _ } => levels::Level::Corridor { castle: castle },
@@ -661,93 +2040,355 @@ impl App {
                     self.goto_state_game();
                 }
                 Control::ResetGame => {
+                    self.journal = Journal::new();
                     self.goto_level(levels::Level::Entry);
                     self.goto_state_game();
                 }
                 Control::CreateBall(pos,arc) => entities::add_ball(self.planner.mut_world(),pos,arc),
+                Control::CreatePickup(pos,velocity,stack) => entities::add_pickup(self.planner.mut_world(),pos,velocity,stack),
+                Control::TransferInventory(from,to) => {
+                    {
+                        let world = self.planner.mut_world();
+                        let mut inventories = world.write::<Inventory>();
+
+                        let stacks = inventories.get_mut(from).map(|i| i.drain()).unwrap_or(vec!());
+                        let mut left = vec!();
+                        if let Some(inventory) = inventories.get_mut(to) {
+                            for stack in stacks {
+                                let count = inventory.insert(stack);
+                                if count != 0 {
+                                    left.push(Stack { item: stack.item, count: count });
+                                }
+                            }
+                        } else {
+                            left = stacks;
+                        }
+                        if let Some(inventory) = inventories.get_mut(from) {
+                            for stack in left {
+                                inventory.insert(stack);
+                            }
+                        }
+                    }
+                    self.update_inventory_hud();
+                },
+                Control::CreateProjectile(caster,pos,heading,profile) => entities::add_projectile(self.planner.mut_world(),caster,pos,heading,profile),
+                Control::Summon(entity,pos) => sequence::spawn_now(self.planner.mut_world(),&*entity,pos),
+                Control::SummonAdds(boss,tiles) => {
+                    let mut task = Task::new();
+                    for tile in tiles {
+                        task = task
+                            .then(move |world| sequence::spawn_now(world,"monster",tile))
+                            .wait_seconds(config.bosses.add_interval);
+                    }
+                    self.tasks.spawn_for(boss,task);
+                },
+                Control::OpenShop(customer,vendor) => {
+                    if self.planner.mut_world().read::<PlayerControl>().get(customer).is_some() {
+                        self.shop = Some(ShopView::new(customer,vendor));
+                    }
+                },
+                Control::Trade(customer,vendor,item,trade) => self.trade(customer,vendor,item,trade),
+                Control::Mount(rider,mount) => {
+                    mount::mount(self.planner.mut_world(),rider,mount);
+                },
+                Control::LockArena(arena) => boss::lock(self.planner.mut_world(),arena),
+                Control::UnlockArena(arena) => boss::unlock(self.planner.mut_world(),arena),
             }
         }
+        while let Ok(event) = self.event_rx.try_recv() {
+            self.handle_event(event);
+        }
+        for aggregate in self.combat_log.end_frame() {
+            self.effect_tx.send(Effect::Text {
+                position: aggregate.position,
+                text: format!("{}",aggregate.amount),
+                persistance: config.combat.float_persistance,
+                color: if aggregate.crit { config.combat.crit_color } else { config.combat.float_color },
+            }).unwrap();
+        }
     }
     pub fn render(&mut self, args: event_loop::RenderArgs) {
         let dt = 1. / config.event_loop.max_fps as f32;
+        let transition = self.states.transition();
 
-        match self.state {
+        let state = self.states.top().clone();
+        match state {
             State::Game => {
+                let start = Instant::now();
+                let quality = self.governor.level();
+                let governor_text = self.governor.text();
+                let views = self.views();
+                let render_time = self.client.as_ref().map(|c| c.render_time());
                 let world = self.planner.mut_world();
 
-                // update camera
-                {
-                    let characters = world.read::<PlayerControl>();
-                    let fixed_cameras = world.read::<FixedCamera>();
-                    let states = world.read::<PhysicState>();
+                let stats = self.graphics.stats();
+                let inspected = self.inspector.entity();
+                let damages_text = self.inspector.damages_text(&self.combat_log);
+                let inspector_text = self.inspector.text(world)
+                    .map(|text| format!("{}{}draw batches: {} instances: {}\n{}", text, damages_text, stats.batches, stats.instances, governor_text));
+                let hud = self.free_camera.as_ref().map(|c| c.hud()).unwrap_or(true);
+                let shop_text = self.shop.as_ref().map(|shop| shop.text(world, &self.locale));
 
-                    for (_, state) in (&characters, &states).iter() {
-                        self.camera.x = state.position[0];
-                        self.camera.y = state.position[1];
-                    }
-                    if fixed_cameras.iter().next().is_some() {
-                        self.camera.x = 0.;
-                        self.camera.y = 0.;
-                    }
+                if world.has_resource::<DayNight>() {
+                    self.graphics.set_tint(world.read_resource::<DayNight>().tint());
                 }
-
                 let mut frame = graphics::Frame::new(&mut self.graphics, args.frame, &self.camera);
 
-                // draw entities
+                let mut effects = self.effect_storage.drain(..).collect::<Vec<Effect>>();
+                while let Ok(effect) = self.effect_rx.try_recv() {
+                    match effect {
+                        Effect::HitFeedback(mut feedback) => {
+                            feedback.particles = quality.particles(feedback.particles);
+                            feedback.trigger(world.write::<Graphic>().get_mut(feedback.entity));
+                            effects.push(Effect::HitFeedback(feedback));
+                        },
+                        effect => effects.push(effect),
+                    }
+                }
+
+                // render lods from the distance to the nearest view, zoomed out views are farther
                 {
                     let states = world.read::<PhysicState>();
-                    let fixed_camera_texts = world.read::<FixedCameraText>();
-                    let texts = world.read::<Text>();
-                    let types = world.read::<PhysicType>();
-                    let graphics = world.read::<Graphic>();
-                    let squares = world.read::<GridSquare>();
-
-                    for (square, graphic) in (&squares, &graphics).iter() {
-                        let p = square.position;
-                        frame.draw_square(p[0],p[1],0.5,graphic.layer,graphic.color);
+                    let mut graphics = world.write::<Graphic>();
+                    for (graphic, state) in (&mut graphics, &states).iter() {
+                        if let Some(ref mut lod) = graphic.lod {
+                            let distance = views.iter()
+                                .map(|&(ref view, _)| {
+                                    let camera = &view.camera;
+                                    ((camera.x - state.position[0]).powi(2) + (camera.y - state.position[1]).powi(2)).sqrt()
+                                        * config.camera.zoom / camera.zoom
+                                })
+                                .fold(::std::f32::MAX, f32::min);
+                            lod.update(distance);
+                        }
                     }
+                }
 
-                    for (state, typ, graphic) in (&states, &types, &graphics).iter() {
-                        let x = state.position[0];
-                        let y = state.position[1];
-                        match typ.shape {
-                            Shape::Circle(radius) => frame.draw_circle(x,y,radius,graphic.layer,graphic.color),
-                            Shape::Square(radius) => frame.draw_square(x,y,radius,graphic.layer,graphic.color),
+                for &(ref view, character) in &views {
+                    frame.set_view(view);
+                    let camera = &view.camera;
+
+                    // draw entities
+                    {
+                        let states = world.read::<PhysicState>();
+                        let fixed_camera_texts = world.read::<FixedCameraText>();
+                        let texts = world.read::<Text>();
+                        let types = world.read::<PhysicType>();
+                        let graphics = world.read::<Graphic>();
+                        let squares = world.read::<GridSquare>();
+                        let inventories = world.read::<Inventory>();
+                        let interpolateds = world.read::<Interpolated>();
+                        let autotiles = world.read::<Autotile>();
+                        let highlights = world.read::<Highlight>();
+                        let abilities = world.read::<Abilities>();
+                        let casters = world.read::<Caster>();
+                        let entities = world.entities();
+
+                        for (square, graphic) in (&squares, &graphics).iter() {
+                            let p = square.position;
+                            if frame.is_visible(p[0],p[1],0.5) {
+                                let (material, param) = graphic.material(&self.shaders);
+                                frame.set_material(material, param);
+                                frame.draw_square(p[0],p[1],0.5,graphic.layer,graphic.color());
+                            }
+                        }
+
+                        let position = |entity: specs::Entity, state: &PhysicState| render_time
+                            .and_then(|time| interpolateds.get(entity).and_then(|i| i.position(time)))
+                            .unwrap_or(state.position);
+
+                        // outlines of the highlighted entities, under them
+                        frame.set_material(graphics::Material::default(), 0.);
+                        let selection = inspected.map(|entity| (entity, Highlight::selection()));
+                        let highlighted = (&highlights, &entities).iter()
+                            .map(|(highlight, entity)| (entity, highlight))
+                            .chain(selection.as_ref().map(|&(entity, ref highlight)| (entity, highlight)));
+                        for (entity, highlight) in highlighted {
+                            let (state, typ, graphic) = match (states.get(entity), types.get(entity), graphics.get(entity)) {
+                                (Some(state), Some(typ), Some(graphic)) => (state, typ, graphic),
+                                _ => continue,
+                            };
+                            if graphic.far().is_some() {
+                                continue;
+                            }
+                            let p = position(entity, state);
+                            let scale = graphic.scale();
+                            match typ.shape {
+                                Shape::Circle(radius) => if frame.is_visible(p[0],p[1],radius*scale + highlight.thickness) {
+                                    frame.draw_circle(p[0],p[1],radius*scale + highlight.thickness,graphic.layer,highlight.color)
+                                },
+                                Shape::Square(radius) => if frame.is_visible(p[0],p[1],radius*scale + highlight.thickness) {
+                                    frame.draw_square(p[0],p[1],radius*scale + highlight.thickness,graphic.layer,highlight.color)
+                                },
+                            }
+                        }
+
+                        for (state, typ, graphic, entity) in (&states, &types, &graphics, &entities).iter() {
+                            let position = position(entity, state);
+                            let x = position[0];
+                            let y = position[1];
+                            let (material, param) = graphic.material(&self.shaders);
+                            frame.set_material(material, param);
+                            if let Some(autotile) = autotiles.get(entity) {
+                                if frame.is_visible(x,y,0.5) {
+                                    for &(rect, edge) in &autotile::pieces(autotile.mask(), config.entities.wall_edge) {
+                                        let color = graphic.swap(if edge { config.entities.wall_edge_color } else { graphic.color });
+                                        frame.draw_rectangle(x + rect[0],y + rect[1],rect[2],rect[3],graphic.layer,color);
+                                    }
+                                }
+                                continue;
+                            }
+                            if let Some(lod) = graphic.far() {
+                                if lod.representation == LodRepresentation::Dot && frame.is_visible(x,y,lod.dot_size) {
+                                    frame.set_material(graphics::Material::default(), 0.);
+                                    frame.draw_square(x,y,lod.dot_size,graphic.layer,graphic.color());
+                                }
+                                continue;
+                            }
+                            let scale = graphic.scale();
+                            match typ.shape {
+                                Shape::Circle(radius) => if frame.is_visible(x,y,radius*scale) {
+                                    frame.draw_circle(x,y,radius*scale,graphic.layer,graphic.color())
+                                },
+                                Shape::Square(radius) => if frame.is_visible(x,y,radius*scale) {
+                                    frame.draw_square(x,y,radius*scale,graphic.layer,graphic.color())
+                                },
+                            }
+                        }
+                        frame.set_material(graphics::Material::default(), 0.);
+
+                        // throw arcs of the bodies held by the players
+                        for (_, entity) in (&world.read::<PlayerControl>(), &entities).iter() {
+                            for p in grab::throw_arc(world, entity) {
+                                if frame.is_visible(p[0],p[1],config.grab.arc_dot_size) {
+                                    frame.draw_square(p[0],p[1],config.grab.arc_dot_size,config.grab.arc_layer,config.grab.arc_color);
+                                }
+                            }
+                        }
+
+                        if config.text.right > config.text.left {
+                            for text in fixed_camera_texts.iter() {
+                                for (y,text_line) in (config.text.bottom+3..config.text.top+1).rev().zip(text.string.lines()) {
+                                    frame.draw_text(config.text.left as f32, y as f32, config.graphics.font_scale, text_line,graphics::Layer::Floor, config.entities.text_color);
+                                }
+                            }
+                        }
+
+                        for text in texts.iter() {
+                            frame.draw_text(text.x, text.y, text.scale, &*text.string, graphics::Layer::Floor, config.entities.text_color);
+                        }
+
+                        if hud {
+                            // the hud of a split view is the one of its character
+                            let mut inventory_hud = String::new();
+                            let inventory_hud = match character.and_then(|c| inventories.get(c)) {
+                                Some(inventory) => {
+                                    push_inventory_text(inventory, &mut inventory_hud);
+                                    &inventory_hud
+                                },
+                                None => &self.inventory_hud,
+                            };
+                            if !inventory_hud.is_empty() {
+                                let p = config.graphics.hud_position;
+                                frame.draw_text(camera.x + p[0], camera.y + p[1], config.graphics.font_scale, &*inventory_hud, graphics::Layer::Floor, config.entities.text_color);
+                            }
+
+                            // bars of the pools then of the cooldowns of the abilities of the player
+                            let owner = character.or_else(|| (&world.read::<PlayerControl>(), &entities).iter().next().map(|(_, e)| e));
+                            if let (Some(owner_abilities), Some(caster)) = (owner.and_then(|o| abilities.get(o)), owner.and_then(|o| casters.get(o))) {
+                                let ratios = (0..config.abilities.pools.len()).map(|pool| owner_abilities.pool_ratio(pool))
+                                    .chain(caster.slots().iter().map(|&ability| 1. - owner_abilities.cooldown_ratio(ability)));
+                                let size = config.abilities.hud_size;
+                                for (i, ratio) in ratios.enumerate() {
+                                    let p = [config.abilities.hud_position[0], config.abilities.hud_position[1] + i as f32*config.abilities.hud_spacing];
+                                    let width = size[0]*ratio.max(0.).min(1.);
+                                    frame.draw_rectangle(camera.x + p[0], camera.y + p[1], size[0], size[1], graphics::Layer::Floor, config.abilities.hud_background_color);
+                                    frame.draw_rectangle(camera.x + p[0] - (size[0] - width)/2., camera.y + p[1], width, size[1], config.abilities.hud_layer, config.abilities.hud_color);
+                                }
+                            }
+
+                            if let Some((ref notification,_)) = self.notification {
+                                let p = config.stats.notification_position;
+                                frame.draw_text(camera.x + p[0], camera.y + p[1], config.graphics.font_scale, &*notification, graphics::Layer::Floor, config.entities.text_color);
+                            }
+
+                            if let Some((name, fraction)) = boss::engaged(world) {
+                                let p = config.bosses.bar_position;
+                                let size = config.bosses.bar_size;
+                                let width = size[0]*fraction.min(1.);
+                                frame.draw_rectangle(camera.x + p[0], camera.y + p[1], size[0], size[1], graphics::Layer::Floor, config.bosses.bar_background_color);
+                                frame.draw_rectangle(camera.x + p[0] - (size[0] - width)/2., camera.y + p[1], width, size[1], config.bosses.bar_layer, config.bosses.bar_color);
+                                frame.draw_text(camera.x + p[0] - size[0]/2., camera.y + p[1] + size[1], config.graphics.font_scale, &*self.locale.get(name), graphics::Layer::Floor, config.entities.text_color);
+                            }
+
+                            if let Some(ref prompt) = self.interaction_prompt {
+                                let p = config.graphics.prompt_position;
+                                frame.draw_text(camera.x + p[0], camera.y + p[1], config.graphics.font_scale, &*prompt, graphics::Layer::Floor, config.entities.text_color);
+                            }
+
+                            let p = config.graphics.journal_position;
+                            for (i,entry) in self.journal.entries().iter().enumerate() {
+                                let y = camera.y + p[1] - i as f32 * config.graphics.journal_interline;
+                                frame.draw_text(camera.x + p[0], y, config.graphics.font_scale, &*entry, graphics::Layer::Floor, config.entities.text_color);
+                            }
+
+                            if let Some(ref text) = inspector_text {
+                                let p = config.graphics.inspector_position;
+                                for (i,line) in text.lines().enumerate() {
+                                    let y = camera.y + p[1] - i as f32 * config.graphics.journal_interline;
+                                    frame.draw_text(camera.x + p[0], y, config.graphics.font_scale, line, graphics::Layer::Floor, config.entities.text_color);
+                                }
+                            }
+
+                            if let Some(ref text) = shop_text {
+                                let p = config.graphics.shop_position;
+                                for (i,line) in text.lines().enumerate() {
+                                    let y = camera.y + p[1] - i as f32 * config.graphics.journal_interline;
+                                    frame.draw_text(camera.x + p[0], y, config.graphics.font_scale, line, graphics::Layer::Floor, config.entities.text_color);
+                                }
+                            }
+
+                            if let Some((speaker,portrait,line)) = self.sequence.as_ref().and_then(|s| s.dialogue()) {
+                                let p = config.graphics.dialogue_position;
+                                frame.draw_square(camera.x + p[0], camera.y + p[1], 0.5, config.graphics.portrait_layer, portrait);
+                                frame.draw_text(camera.x + p[0] + 1., camera.y + p[1] + 0.5, config.graphics.font_scale, &*self.locale.get(speaker), graphics::Layer::Floor, config.entities.text_color);
+                                frame.draw_text(camera.x + p[0] + 1., camera.y + p[1] - 0.5, config.graphics.font_scale, &*self.locale.get(line), graphics::Layer::Floor, config.entities.text_color);
+                            }
                         }
                     }
 
-                    if config.text.right > config.text.left {
-                        for text in fixed_camera_texts.iter() {
-                            for (y,text_line) in (config.text.bottom+3..config.text.top+1).rev().zip(text.string.lines()) {
-                                frame.draw_text(config.text.left as f32, y as f32, config.graphics.font_scale, text_line,graphics::Layer::Floor, config.entities.text_color);
+                    self.decals.draw(&mut frame);
+
+                    // draw ambient emitters
+                    {
+                        let states = world.read::<PhysicState>();
+                        let emitters = world.read::<AmbientEmitter>();
+                        let mut lights = 0;
+                        for (emitter, state) in (&emitters, &states).iter() {
+                            if emitter.is_active() && emitter.prefab().kind == EmitterKind::Light {
+                                if lights >= quality.lights { continue }
+                                lights += 1;
                             }
+                            emitter.draw(state.position, quality.particles(config.ambient.max_particles), &mut frame);
                         }
                     }
 
-                    for text in texts.iter() {
-                        frame.draw_text(text.x, text.y, text.scale, &*text.string, graphics::Layer::Floor, config.entities.text_color);
+                    // draw effects
+                    for effect in &effects {
+                        effect.draw(&mut frame);
                     }
                 }
 
-                // draw effects
                 //TODO draw effects: do not next if pause
-                for effect in &self.effect_storage {
-                    effect.draw(&mut frame);
-                }
-                let old_effect_storage = self.effect_storage.drain(..).collect::<Vec<Effect>>();;
-                for effect in old_effect_storage {
+                for effect in effects {
                     if let Some(effect) = effect.next(dt) {
                         self.effect_storage.push(effect)
                     }
                 }
 
-                while let Ok(effect) = self.effect_rx.try_recv() {
-                    effect.draw(&mut frame);
-                    if let Some(effect) = effect.next(dt) {
-                        self.effect_storage.push(effect);
-                    }
-                }
-
+                // the swap waits for the vsync, only the drawing is measured
+                self.governor.measure("render", governor::seconds(start.elapsed()));
+                draw_transition(&mut frame, transition);
                 frame.finish().unwrap();
             },
             State::Menu(entry) => {
@@ -767,24 +2408,154 @@ impl App {
                         menu.push('\n');
                     }
                 }
+                self.graphics.set_tint([1.,1.,1.]);
+                let mut frame = graphics::Frame::new(&mut self.graphics, args.frame, &self.camera);
+                frame.draw_billboard_centered_text(&*cursor,config.menu.cursor_color);
+                frame.draw_billboard_centered_text(&*menu,config.menu.entry_color);
+                draw_transition(&mut frame, transition);
+                frame.finish().unwrap();
+            }
+            State::Controls(row,listening) => {
+                let mut menu = String::new();
+                let mut cursor = String::new();
+                let mut rows = self.controls_rows(if listening { Some(row) } else { None });
+                // the result of the last binding
+                if let Some((ref text,_)) = self.notification {
+                    rows.push(String::new());
+                    rows.push(text.clone());
+                }
+                for (index, text) in rows.iter().enumerate() {
+                    cursor.push_str(if index == row { "<<                     >>\n" } else { "\n" });
+                    menu.push_str(text);
+                    menu.push('\n');
+                }
+                self.graphics.set_tint([1.,1.,1.]);
                 let mut frame = graphics::Frame::new(&mut self.graphics, args.frame, &self.camera);
                 frame.draw_billboard_centered_text(&*cursor,config.menu.cursor_color);
                 frame.draw_billboard_centered_text(&*menu,config.menu.entry_color);
+                draw_transition(&mut frame, transition);
                 frame.finish().unwrap();
             }
-            State::Text(_,ref text) => {
+            State::Text(ref text) => {
+                self.graphics.set_tint([1.,1.,1.]);
                 let mut frame = graphics::Frame::new(&mut self.graphics, args.frame, &self.camera);
                 frame.draw_rectangle(0.,0.,25.0,18.0,graphics::Layer::BillBoard,config.menu.background_color);
                 frame.draw_billboard_centered_text(&*text,config.menu.entry_color);
+                draw_transition(&mut frame, transition);
+                frame.finish().unwrap();
+            }
+            State::Loading => {
+                let loading = self.loading.as_ref().expect("loading state must have a level loading");
+                let title = self.locale.format("loading", &[("level", &*loading.level().name(&self.castles))]);
+                let tip = loading.tip().map(|tip| self.locale.get(tip)).unwrap_or(String::new());
+                self.graphics.set_tint([1.,1.,1.]);
+                let mut frame = graphics::Frame::new(&mut self.graphics, args.frame, &self.camera);
+                loading.draw(&*title, &*tip, &mut frame);
+                draw_transition(&mut frame, transition);
                 frame.finish().unwrap();
             }
         }
 
+        if let State::Game = *self.states.top() {
+            if self.capture.is_due() {
+                let (pixels, width, height) = self.graphics.read_front_buffer();
+                self.capture.push(&pixels, width, height);
+            }
+        }
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            let (pixels, width, height) = self.graphics.read_front_buffer();
+            let spawned = self.jobs.spawn_completed(move || notification(match freecam::save_screenshot(&pixels, width, height) {
+                Ok(path) => format!("screenshot saved: {}",path.display()),
+                Err(err) => err,
+            }));
+            if let Err(err) = spawned {
+                self.notification = Some((err,config.stats.notification_time));
+            }
+        }
+
+    }
+    /// the rows of the controls screen: the profile, the actions and the reset
+    fn controls_rows(&self, listening: Option<usize>) -> Vec<String> {
+        let mut rows = vec!(self.locale.format("controls_profile", &[("value", &*self.locale.get(self.controls.profile()))]));
+        for (i, &(action, ref keys)) in self.controls.bindings().iter().enumerate() {
+            let name = self.locale.get(&*format!("action_{}", action.name()));
+            rows.push(if listening == Some(i + 1) {
+                self.locale.format("controls_listen", &[("action", &*name)])
+            } else {
+                let keys: Vec<String> = keys.iter().map(|&k| controls::key_name(k)).collect();
+                self.locale.format("controls_entry", &[("action", &*name), ("keys", &*keys.join(", "))])
+            });
+        }
+        rows.push(self.locale.get("controls_reset"));
+        rows
+    }
+    /// change the profile or listen for the key of the action or reset the keys
+    fn controls_row_pressed(&mut self, row: usize, offset: isize) {
+        let bindings = self.controls.bindings().len();
+        if row == 0 {
+            let profiles = config.controls.profiles.len() as isize;
+            let index = config.controls.profiles.iter().position(|p| p == self.controls.profile()).unwrap_or(0) as isize;
+            let profile = &config.controls.profiles[((index + offset)%profiles + profiles) as usize % profiles as usize];
+            self.controls = Controls::load(profile);
+            self.save();
+        } else if row <= bindings {
+            self.states.replace(State::Controls(row,true));
+        } else {
+            self.controls = Controls::new(self.controls.profile());
+            self.save_controls();
+        }
+    }
+    /// bind the key to the action of the row listening
+    fn rebind(&mut self, row: usize, key: u8) {
+        self.states.replace(State::Controls(row,false));
+        let action = self.controls.bindings()[row - 1].0;
+        let name = controls::key_name(key);
+        self.notification = match self.controls.bind(action, key) {
+            Rebind::Bound => None,
+            Rebind::Conflict(other) => Some((self.locale.format("controls_conflict", &[
+                ("key", &*name),
+                ("action", &*self.locale.get(&*format!("action_{}", other.name()))),
+            ]),config.stats.notification_time)),
+            Rebind::Reserved => Some((self.locale.format("controls_reserved", &[("key", &*name)]),config.stats.notification_time)),
+        };
+        self.save_controls();
+    }
+    /// select the profile used from the next start
+    fn cycle_profile(&mut self, offset: isize) {
+        if let Err(err) = self.profiles.cycle(offset) {
+            self.notification = Some((err,config.stats.notification_time));
+        }
+    }
+    fn save_controls(&mut self) {
+        if let Err(err) = self.controls.save() {
+            self.notification = Some((err,config.stats.notification_time));
+        }
     }
     pub fn dir_pressed(&mut self, direction: Direction) {
         use std::ops::Rem;
 
-        match self.state {
+        let state = self.states.top().clone();
+        match state {
+            State::Game if self.free_camera.is_some() => self.free_camera.as_mut().unwrap().push_dir(direction),
+            State::Game if self.shop.is_some() => {
+                baal::effect::short::play_on_listener(config.menu.clic_snd);
+                let world = self.planner.mut_world();
+                let shop = self.shop.as_mut().unwrap();
+                match direction {
+                    Direction::Up => shop.select(world, -1),
+                    Direction::Down => shop.select(world, 1),
+                    Direction::Right | Direction::Left => if let Some(item) = shop.selected(world) {
+                        let trade = if direction == Direction::Right { Trade::Buy } else { Trade::Sell };
+                        self.control_tx.send(Control::Trade(shop.customer,shop.vendor,item,trade)).unwrap();
+                    },
+                }
+            },
+            State::Game if self.accessibility.hold_to_toggle => {
+                self.player_control_state.toggle_keyboard_dir(direction);
+                self.update_player_control();
+            },
             State::Game => {
                 self.player_control_state.push_keyboard_dir(direction);
                 self.update_player_control();
@@ -792,20 +2563,34 @@ impl App {
             State::Menu(entry) => {
                 baal::effect::short::play_on_listener(config.menu.clic_snd);
                 match direction {
-                    Direction::Up => self.state = State::Menu(if entry == 0 { self.menu.len()-1 } else { entry-1 }),
-                    Direction::Down => self.state = State::Menu((entry+1).rem(self.menu.len())),
+                    Direction::Up => { self.states.replace(State::Menu(if entry == 0 { self.menu.len()-1 } else { entry-1 })); },
+                    Direction::Down => { self.states.replace(State::Menu((entry+1).rem(self.menu.len()))); },
                     Direction::Right => (*self.menu[entry].right.clone())(self),
                     Direction::Left => (*self.menu[entry].left.clone())(self),
                 }
             }
-            State::Text(entry,_) => {
+            State::Text(_) => {
+                baal::effect::short::play_on_listener(config.menu.clic_snd);
+                self.pop_state();
+            }
+            State::Loading | State::Controls(_,true) => (),
+            State::Controls(row,false) => {
                 baal::effect::short::play_on_listener(config.menu.clic_snd);
-                self.state = State::Menu(entry)
+                let rows = self.controls.bindings().len() + 2;
+                match direction {
+                    Direction::Up => { self.states.replace(State::Controls(if row == 0 { rows-1 } else { row-1 },false)); },
+                    Direction::Down => { self.states.replace(State::Controls((row+1).rem(rows),false)); },
+                    Direction::Right => self.controls_row_pressed(row,1),
+                    Direction::Left => self.controls_row_pressed(row,-1),
+                }
             }
         }
     }
     pub fn dir_released(&mut self, direction: Direction) {
-        match self.state {
+        let state = self.states.top().clone();
+        match state {
+            State::Game if self.free_camera.is_some() => self.free_camera.as_mut().unwrap().retain_dir(direction),
+            State::Game if self.accessibility.hold_to_toggle => (),
             State::Game => {
                 self.player_control_state.retain_keyboard_dir(direction);
                 self.update_player_control();
@@ -813,35 +2598,168 @@ impl App {
             _ => (),
         }
     }
+    pub fn action_pressed(&mut self) {
+        let state = self.states.top().clone();
+        match state {
+            State::Game if self.sequence.as_ref().map(|s| s.dialogue().is_some()).unwrap_or(false) => {
+                self.sequence.as_mut().unwrap().advance();
+            },
+            State::Game if self.net.is_some() || self.client.is_some() => self.action_requested = true,
+            State::Game => {
+                let riders = {
+                    let world = self.planner.mut_world();
+                    let characters = world.read::<PlayerControl>();
+                    let riders = world.read::<Rider>();
+                    let mut interactors = world.write::<Interactor>();
+                    let mut seated = vec!();
+                    for (_, interactor, entity) in (&characters, &mut interactors, &world.entities()).iter() {
+                        if riders.get(entity).is_some() {
+                            seated.push(entity);
+                        } else {
+                            interactor.requested = true;
+                        }
+                    }
+                    seated
+                };
+                for rider in riders {
+                    if !mount::dismount(self.planner.mut_world(),rider) {
+                        self.notification = Some((self.locale.get("no_room_to_dismount"),config.stats.notification_time));
+                    }
+                }
+            },
+            State::Controls(row,false) => self.controls_row_pressed(row,1),
+            State::Loading | State::Menu(_) | State::Text(_) | State::Controls(_,true) => (),
+        }
+    }
     pub fn escape_pressed(&mut self) {
         baal::effect::short::play_on_listener(config.menu.clic_snd);
-        match self.state {
-            State::Game | State::Text(_,_) => self.goto_state_menu(),
-            State::Menu(_) => self.goto_state_game(),
+        let state = self.states.top().clone();
+        match state {
+            State::Game if self.inspector.is_open() => self.inspector.close(),
+            State::Game if self.free_camera.is_some() => self.free_camera = None,
+            State::Game if self.shop.is_some() => self.shop = None,
+            State::Game if self.sequence.is_some() => self.skip_sequence(),
+            State::Game | State::Text(_) | State::Controls(_,false) => self.goto_state_menu(),
+            State::Controls(row,true) => { self.states.replace(State::Controls(row,false)); },
+            // back to the parent menu or the game
+            State::Menu(_) => { self.pop_state(); },
+            State::Loading => (),
         }
     }
     pub fn key_pressed(&mut self, key: u8) {
-        if config.keys.up.contains(&key) {
-            self.dir_pressed(Direction::Up);
-        } else if config.keys.down.contains(&key) {
-            self.dir_pressed(Direction::Down);
-        } else if config.keys.left.contains(&key) {
-            self.dir_pressed(Direction::Left);
-        } else if config.keys.right.contains(&key) {
-            self.dir_pressed(Direction::Right);
-        } else if config.keys.escape.contains(&key) {
-            self.escape_pressed()
+        use controls::Action;
+
+        let action = self.controls.action(key);
+        let top = self.states.top().clone();
+        if let State::Controls(row,true) = top {
+            // the escape keys cancel the listening
+            if action != Some(Action::Escape) {
+                self.rebind(row,key);
+                return;
+            }
+        }
+        let game = if let State::Game = *self.states.top() { true } else { false };
+        match (action, game) {
+            (Some(Action::Up), _) => self.dir_pressed(Direction::Up),
+            (Some(Action::Down), _) => self.dir_pressed(Direction::Down),
+            (Some(Action::Left), _) => self.dir_pressed(Direction::Left),
+            (Some(Action::Right), _) => self.dir_pressed(Direction::Right),
+            (Some(Action::Escape), _) => self.escape_pressed(),
+            (Some(Action::Action), _) => self.action_pressed(),
+            (Some(Action::Ability(slot)), true) => self.ability_pressed(slot),
+            (Some(Action::Grab), true) => self.grab_pressed(false),
+            (Some(Action::Throw), true) => self.grab_pressed(true),
+            (Some(Action::Screenshot), true) => self.screenshot_requested = true,
+            (Some(Action::Capture), true) => self.export_capture(),
+            (None, true) => {
+                self.free_camera_key_pressed(key);
+                self.inspector_key_pressed(key)
+            },
+            _ => (),
+        }
+    }
+    /// request the players to use the ability of the slot
+    fn ability_pressed(&mut self, slot: usize) {
+        let world = self.planner.mut_world();
+        let characters = world.read::<PlayerControl>();
+        let mut casters = world.write::<Caster>();
+        for (_, caster) in (&characters, &mut casters).iter() {
+            caster.requested = Some(slot);
+        }
+    }
+    /// request the players to grab or release a body, or to throw it
+    fn grab_pressed(&mut self, throw: bool) {
+        let world = self.planner.mut_world();
+        let characters = world.read::<PlayerControl>();
+        let mut grabbers = world.write::<Grabber>();
+        for (_, grabber) in (&characters, &mut grabbers).iter() {
+            if throw {
+                grabber.throw_requested = true;
+            } else {
+                grabber.requested = true;
+            }
+        }
+    }
+    /// export the recent gameplay as a gif by a job
+    fn export_capture(&mut self) {
+        let capture = self.capture.clone();
+        let spawned = self.jobs.spawn_completed(move || notification(match capture.export() {
+            Ok(path) => format!("capture saved: {}",path.display()),
+            Err(err) => err,
+        }));
+        if let Err(err) = spawned {
+            self.notification = Some((err,config.stats.notification_time));
+        }
+    }
+    /// toggle the free camera, and the pause and the hud while it is on
+    fn free_camera_key_pressed(&mut self, key: u8) {
+        let networked = self.networked();
+        if config.keys.free_camera.contains(&key) {
+            if self.free_camera.take().is_none() {
+                // the players stop while the directions fly the camera
+                self.player_control_state = PlayerControlState::Keyboard(vec!());
+                self.update_player_control();
+                self.free_camera = Some(FreeCamera::new([self.camera.x, self.camera.y]));
+            }
+        } else if let Some(ref mut camera) = self.free_camera {
+            if config.keys.free_camera_pause.contains(&key) {
+                // the peers of a networked game can't be paused
+                if !networked {
+                    camera.toggle_pause();
+                }
+            } else if config.keys.free_camera_hud.contains(&key) {
+                camera.toggle_hud();
+            }
+        }
+    }
+    fn inspector_key_pressed(&mut self, key: u8) {
+        let world = self.planner.mut_world();
+        if config.keys.inspector.contains(&key) {
+            self.inspector.pick(world, [self.camera.x, self.camera.y]);
+        } else if config.keys.inspector_next.contains(&key) {
+            self.inspector.next(world);
+        } else if config.keys.inspector_field.contains(&key) {
+            self.inspector.select_next_field();
+        } else if config.keys.inspector_decrease.contains(&key) {
+            self.inspector.tweak(world, -1.);
+        } else if config.keys.inspector_increase.contains(&key) {
+            self.inspector.tweak(world, 1.);
+        } else if config.keys.memory_report.contains(&key) {
+            println!("{}", memory::report(world, &[
+                ("effects", self.effect_storage.len()),
+                ("decals", self.decals.len()),
+            ]));
         }
     }
     pub fn key_released(&mut self, key: u8) {
-        if config.keys.up.contains(&key) {
-            self.dir_released(Direction::Up);
-        } else if config.keys.down.contains(&key) {
-            self.dir_released(Direction::Down);
-        } else if config.keys.left.contains(&key) {
-            self.dir_released(Direction::Left);
-        } else if config.keys.right.contains(&key) {
-            self.dir_released(Direction::Right);
+        use controls::Action;
+
+        match self.controls.action(key) {
+            Some(Action::Up) => self.dir_released(Direction::Up),
+            Some(Action::Down) => self.dir_released(Direction::Down),
+            Some(Action::Left) => self.dir_released(Direction::Left),
+            Some(Action::Right) => self.dir_released(Direction::Right),
+            _ => (),
         }
     }
     pub fn button_pressed(&mut self, button: gilrs::Button) {
@@ -852,6 +2770,7 @@ impl App {
             North | DPadUp => self.dir_pressed(Direction::Up),
             West | DPadLeft => self.dir_pressed(Direction::Left),
             Select => self.escape_pressed(),
+            LeftTrigger | RightTrigger => self.action_pressed(),
             _ => (),
         }
     }
@@ -902,7 +2821,8 @@ impl App {
             return;
         }
 
-        match self.state {
+        let state = self.states.top().clone();
+        match state {
             State::Game => {
                 if axis.is_horizontal() {
                     self.player_control_state.set_axis_x_state(pos);
@@ -912,7 +2832,8 @@ impl App {
                     self.update_player_control();
                 }
             },
-            State::Text(_,_) | State::Menu(_) => {
+            State::Loading => (),
+            State::Text(_) | State::Menu(_) | State::Controls(_,_) => {
                 match self.joystick_menu_state {
                     JoystickMenuState::Released => {
                         if pos.abs() >= config.joystick.press_epsilon {
@@ -936,3 +2857,33 @@ impl App {
         }
     }
 }
+
+#[test]
+fn test_step_hooks() {
+    use std::sync::{Arc, Mutex};
+    use trace;
+
+    let mut world = trace::new_world();
+    let body = trace::add_body(&mut world, [0.5, 0.5]);
+    let mut planner = specs::Planner::new(world, 1);
+    add_system(&mut planner, PhysicSystem, "physic", "physic");
+    add_pre_step_hook(&mut planner, "push", move |arg, _| {
+        let mut forces = arg.fetch(|world| world.write::<PhysicForce>());
+        forces.get_mut(body).unwrap().intensity = 1.;
+    });
+    let positions = Arc::new(Mutex::new(vec!()));
+    let recorded = positions.clone();
+    add_post_step_hook(&mut planner, "record", move |arg, dt| {
+        let states = arg.fetch(|world| world.read::<PhysicState>());
+        recorded.lock().unwrap().push((states.get(body).unwrap().position[0], dt));
+    });
+
+    for _ in 0..10 {
+        planner.dispatch(trace::new_context());
+        planner.wait();
+    }
+    let positions = positions.lock().unwrap();
+    assert_eq!(positions.len(), 10);
+    assert!(positions.iter().all(|&(_, dt)| dt == 1. / config.event_loop.ups as f32));
+    assert!(positions.windows(2).all(|w| w[1].0 > w[0].0));
+}
@@ -0,0 +1,251 @@
+//! auto-tiling of the walls: each wall is drawn as one of the 16 tiles given by the
+//! walls on its four sides, with an edge of entities.wall_edge on the sides open to
+//! the floor so wall masses are outlined instead of drawn as separate squares.
+//!
+//! the tiles are computed for the new walls and recomputed when walls are moved or
+//! removed, only for the cells around the change. the cells of the walls are kept in
+//! the AutotileCells resource of the world, replaced when a level is built.
+
+use app;
+use specs;
+use components::*;
+use resource::*;
+use specs::Join;
+use std::collections::{HashMap, HashSet};
+
+pub const UP: u8 = 1;
+pub const RIGHT: u8 = 2;
+pub const DOWN: u8 = 4;
+pub const LEFT: u8 = 8;
+
+/// the tile of a wall: the sides with a wall next to it
+pub struct Autotile {
+    mask: u8,
+    /// the cell the mask is computed for, none for a new wall
+    cell: Option<[isize;2]>,
+}
+impl specs::Component for Autotile {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Autotile {
+    pub fn new() -> Self {
+        Autotile {
+            mask: 0,
+            cell: None,
+        }
+    }
+
+    pub fn mask(&self) -> u8 {
+        self.mask
+    }
+}
+
+fn cell_of(position: [f32;2]) -> [isize;2] {
+    [position[0].round() as isize, position[1].round() as isize]
+}
+
+fn neighbors(cell: [isize;2]) -> [([isize;2],u8);4] {
+    [
+        ([cell[0], cell[1] + 1], UP),
+        ([cell[0] + 1, cell[1]], RIGHT),
+        ([cell[0], cell[1] - 1], DOWN),
+        ([cell[0] - 1, cell[1]], LEFT),
+    ]
+}
+
+/// the sides of cell with a solid cell next to it
+pub fn mask_of<F: Fn([isize;2]) -> bool>(cell: [isize;2], solid: F) -> u8 {
+    neighbors(cell).iter()
+        .filter(|&&(neighbor, _)| solid(neighbor))
+        .fold(0, |mask, &(_, side)| mask | side)
+}
+
+/// the cells of the walls of the world
+pub struct AutotileCells {
+    cells: HashMap<specs::Entity,[isize;2]>,
+    /// the walls in each cell
+    solid: HashMap<[isize;2],usize>,
+}
+impl AutotileCells {
+    pub fn new() -> Self {
+        AutotileCells {
+            cells: HashMap::new(),
+            solid: HashMap::new(),
+        }
+    }
+
+    pub fn is_solid(&self, cell: [isize;2]) -> bool {
+        self.solid.get(&cell).cloned().unwrap_or(0) > 0
+    }
+
+    fn add(&mut self, cell: [isize;2], dirty: &mut HashSet<[isize;2]>) {
+        *self.solid.entry(cell).or_insert(0) += 1;
+        dirty_around(cell, dirty);
+    }
+
+    fn remove(&mut self, cell: [isize;2], dirty: &mut HashSet<[isize;2]>) {
+        let empty = match self.solid.get_mut(&cell) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            },
+            None => false,
+        };
+        if empty {
+            self.solid.remove(&cell);
+        }
+        dirty_around(cell, dirty);
+    }
+
+    /// the wall is at the cell, the cells whose tile changes are added to dirty
+    pub fn set(&mut self, entity: specs::Entity, cell: [isize;2], dirty: &mut HashSet<[isize;2]>) {
+        match self.cells.insert(entity, cell) {
+            Some(previous) if previous == cell => return,
+            Some(previous) => self.remove(previous, dirty),
+            None => (),
+        }
+        self.add(cell, dirty);
+    }
+
+    /// forget the walls not in alive, the cells whose tile changes are added to dirty
+    pub fn retain(&mut self, alive: &HashSet<specs::Entity>, dirty: &mut HashSet<[isize;2]>) {
+        let removed = self.cells.iter()
+            .filter(|&(entity, _)| !alive.contains(entity))
+            .map(|(&entity, &cell)| (entity, cell))
+            .collect::<Vec<_>>();
+        for (entity, cell) in removed {
+            self.cells.remove(&entity);
+            self.remove(cell, dirty);
+        }
+    }
+
+    pub fn contains(&self, entity: specs::Entity) -> bool {
+        self.cells.contains_key(&entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+fn dirty_around(cell: [isize;2], dirty: &mut HashSet<[isize;2]>) {
+    dirty.insert(cell);
+    for &(neighbor, _) in &neighbors(cell) {
+        dirty.insert(neighbor);
+    }
+}
+
+/// the nine pieces of the tile of mask, as rectangles [x, y, width, height] centered
+/// relative to the center of a cell of size 1, with whether they are part of the edge.
+/// a corner is part of the edge if one of its sides is open
+pub fn pieces(mask: u8, edge: f32) -> [([f32;4],bool);9] {
+    let open = |side: u8| mask & side == 0;
+    let inner = 1. - 2.*edge;
+    let offset = 0.5 - edge/2.;
+    [
+        ([0., 0., inner, inner], false),
+        ([0., offset, inner, edge], open(UP)),
+        ([offset, 0., edge, inner], open(RIGHT)),
+        ([0., -offset, inner, edge], open(DOWN)),
+        ([-offset, 0., edge, inner], open(LEFT)),
+        ([offset, offset, edge, edge], open(UP) || open(RIGHT)),
+        ([offset, -offset, edge, edge], open(DOWN) || open(RIGHT)),
+        ([-offset, -offset, edge, edge], open(DOWN) || open(LEFT)),
+        ([-offset, offset, edge, edge], open(UP) || open(LEFT)),
+    ]
+}
+
+/// compute the tiles of the new walls and recompute the tiles around the walls moved
+/// or removed since last update
+pub struct AutotileSystem;
+impl specs::System<app::UpdateContext> for AutotileSystem {
+    fn run(&mut self, arg: specs::RunArg, _context: app::UpdateContext) {
+        let (mut autotiles, mut cells, states, entities) = arg.fetch(|world| {
+            (
+                world.write::<Autotile>(),
+                world.write_resource::<AutotileCells>(),
+                world.read::<PhysicState>(),
+                world.entities(),
+            )
+        });
+
+        let mut dirty = HashSet::new();
+        let mut walls = 0;
+        for (autotile, state, entity) in (&autotiles, &states, &entities).iter() {
+            walls += 1;
+            let cell = cell_of(state.position);
+            if autotile.cell != Some(cell) {
+                cells.set(entity, cell, &mut dirty);
+            }
+        }
+        // walls were removed
+        if cells.len() > walls {
+            let alive = (&autotiles, &entities).iter().map(|(_, entity)| entity).collect::<HashSet<_>>();
+            cells.retain(&alive, &mut dirty);
+        }
+        // walls were moved from another world with their tile
+        if cells.len() < walls {
+            for (_, state, entity) in (&autotiles, &states, &entities).iter() {
+                if !cells.contains(entity) {
+                    cells.set(entity, cell_of(state.position), &mut dirty);
+                }
+            }
+        }
+
+        if !dirty.is_empty() {
+            for (autotile, state) in (&mut autotiles, &states).iter() {
+                let cell = cell_of(state.position);
+                if dirty.contains(&cell) || autotile.cell != Some(cell) {
+                    autotile.mask = mask_of(cell, |c| cells.is_solid(c));
+                    autotile.cell = Some(cell);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_autotile() {
+    let solid = [[0,0],[1,0],[0,-1]].iter().cloned().collect::<HashSet<_>>();
+    assert_eq!(mask_of([0,0], |c| solid.contains(&c)), RIGHT | DOWN);
+    assert_eq!(mask_of([1,0], |c| solid.contains(&c)), LEFT);
+    assert_eq!(mask_of([5,5], |c| solid.contains(&c)), 0);
+
+    let pieces = pieces(RIGHT | DOWN, 0.1);
+    assert_eq!(pieces.iter().filter(|&&(_, edge)| edge).count(), 5);
+    // the inner corner between the right and down walls is not an edge
+    assert!(!pieces[6].1);
+    let area: f32 = pieces.iter().map(|&(rect, _)| rect[2]*rect[3]).sum();
+    assert!((area - 1.).abs() < 1e-5);
+}
+
+#[test]
+fn test_autotile_cells() {
+    let mut world = specs::World::new();
+    let a = world.create_now().build();
+    let b = world.create_now().build();
+    let mut cells = AutotileCells::new();
+    let mut dirty = HashSet::new();
+    cells.set(a, [0,0], &mut dirty);
+    cells.set(b, [1,0], &mut dirty);
+    assert_eq!(mask_of([0,0], |c| cells.is_solid(c)), RIGHT);
+    assert!(dirty.contains(&[0,0]) && dirty.contains(&[2,0]));
+
+    // a wall unchanged doesn't dirty its cell
+    dirty.clear();
+    cells.set(a, [0,0], &mut dirty);
+    assert!(dirty.is_empty());
+
+    // the walls of the same level in a new world are all new
+    let mut rebuilt = AutotileCells::new();
+    rebuilt.set(a, [0,0], &mut dirty);
+    rebuilt.set(b, [1,0], &mut dirty);
+    assert!(dirty.contains(&[0,0]) && dirty.contains(&[1,0]));
+
+    dirty.clear();
+    cells.retain(&[a].iter().cloned().collect(), &mut dirty);
+    assert_eq!(cells.len(), 1);
+    assert!(!cells.is_solid([1,0]));
+    assert!(dirty.contains(&[0,0]));
+    assert_eq!(mask_of([0,0], |c| cells.is_solid(c)), 0);
+}
@@ -0,0 +1,261 @@
+//! stress scenes run without window nor audio: `cargo run --release --features bench -- --bench`
+//!
+//! each scene is simulated for a number of frames and the time spent in each
+//! subsystem is reported so regressions of the physic world can be measured.
+//!
+//! the occupancy of the cells is reported too, with `--heatmap` it is also exported
+//! to heatmap_<scene>.csv and heatmap_<scene>.png to tune physic.unit.
+
+use app;
+use specs;
+use config;
+use time;
+use physic;
+use components::*;
+use systems::PhysicSystem;
+use heatmap::Heatmap;
+use rand::Rng;
+use rng::WorldRng;
+use std::sync::mpsc;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+const FRAMES: usize = 300;
+const BODIES: usize = 2000;
+const RAYCASTS: usize = 500;
+const CITY_SIZE: isize = 200;
+
+/// time spent in each subsystem in nanoseconds, one sample per frame
+struct Report {
+    timings: BTreeMap<&'static str,Vec<u64>>,
+    heatmap: Heatmap,
+}
+
+impl Report {
+    fn new() -> Self {
+        Report {
+            timings: BTreeMap::new(),
+            heatmap: Heatmap::new(),
+        }
+    }
+
+    fn measure<T, F: FnOnce() -> T>(&mut self, subsystem: &'static str, f: F) -> T {
+        let start = time::precise_time_ns();
+        let result = f();
+        self.timings.entry(subsystem).or_insert(vec!()).push(time::precise_time_ns() - start);
+        result
+    }
+
+    fn print(&self, scene: &str) {
+        println!("{}:", scene);
+        for (subsystem, timings) in &self.timings {
+            let total: u64 = timings.iter().sum();
+            let max = timings.iter().cloned().max().unwrap_or(0);
+            println!("  {:<14} mean {:>8.3} ms  max {:>8.3} ms  ({} samples)",
+                     subsystem,
+                     total as f64 / timings.len().max(1) as f64 / 1e6,
+                     max as f64 / 1e6,
+                     timings.len());
+        }
+        println!("  cell occupancy (unit {}):", config.physic.unit);
+        print!("{}", self.heatmap.distribution());
+
+        if env::args().any(|arg| arg == "--heatmap") {
+            let name = scene.split(" (").next().unwrap().replace(" ", "_");
+            let csv = format!("heatmap_{}.csv", name);
+            let png = format!("heatmap_{}.png", name);
+            fs::File::create(&csv).and_then(|mut file| self.heatmap.write_csv(&mut file))
+                .unwrap_or_else(|e| println!("  export of {} failed: {}", csv, e));
+            match fs::File::create(&png) {
+                Ok(file) => self.heatmap.write_png(file)
+                    .unwrap_or_else(|e| println!("  export of {} failed: {}", png, e)),
+                Err(e) => println!("  export of {} failed: {}", png, e),
+            }
+        }
+    }
+}
+
+fn new_world() -> specs::World {
+    let mut world = specs::World::new();
+    world.register::<PhysicState>();
+    world.register::<PhysicType>();
+    world.register::<PhysicForce>();
+    world.register::<PhysicDynamic>();
+    world.register::<PhysicStatic>();
+    world.register::<PhysicTrigger>();
+    world.register::<PhysicTeleported>();
+    world.register::<UpdateLod>();
+    world.register::<Movement>();
+    world.add_resource(physic::PhysicWorld::new());
+    world
+}
+
+fn new_context() -> app::UpdateContext {
+    let (effect_tx, _) = mpsc::channel();
+    let (control_tx, _) = mpsc::channel();
+    let (event_tx, _) = mpsc::channel();
+    app::UpdateContext {
+        effect_tx: effect_tx,
+        control_tx: control_tx,
+        event_tx: event_tx,
+        dt: 1. / config.event_loop.ups as f32,
+        ai_lod: 1.,
+    }
+}
+
+/// walls all around a square of size
+fn add_border(world: &mut specs::World, size: isize) {
+    for i in -1..size+1 {
+        add_wall(world, [i, -1]);
+        add_wall(world, [i, size]);
+        add_wall(world, [-1, i]);
+        add_wall(world, [size, i]);
+    }
+}
+
+fn add_wall(world: &mut specs::World, pos: [isize;2]) {
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(
+                config.entities.wall_group.val,
+                config.entities.wall_mask.val,
+                Shape::Square(config.entities.wall_radius)))
+        .build();
+}
+
+fn add_body(world: &mut specs::World, pos: [f32;2], direction: f32) {
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicDynamic>(PhysicDynamic)
+        .with::<PhysicType>(PhysicType::new_movable(
+                config.entities.monster_group.val,
+                config.entities.monster_mask.val,
+                Shape::Circle(config.entities.monster_radius),
+                CollisionBehavior::Bounce,
+                config.entities.monster_velocity,
+                config.entities.monster_time,
+                config.entities.monster_weight))
+        .with::<PhysicForce>(PhysicForce { direction: direction, intensity: 1. })
+        .build();
+}
+
+fn fill(world: &mut specs::World, report: &mut Report) {
+    let mut physic_world = world.write_resource::<physic::PhysicWorld>();
+    report.measure("fill", || physic_world.fill(&world));
+}
+
+/// run the physic system for FRAMES frames and call frame after each one
+fn simulate<F: FnMut(&mut specs::World, &mut Report)>(world: specs::World, report: &mut Report, mut frame: F) {
+    let mut planner = specs::Planner::new(world, config.general.number_of_thread);
+    app::add_system(&mut planner, PhysicSystem, "physic", "physic");
+
+    for _ in 0..FRAMES {
+        report.measure("physic", || {
+            planner.dispatch(new_context());
+            planner.wait();
+        });
+        report.heatmap.sample(&planner.mut_world().read_resource::<physic::PhysicWorld>());
+        frame(planner.mut_world(), report);
+    }
+}
+
+/// BODIES dynamic bodies bouncing in a closed room
+fn bouncing_bodies() {
+    use std::f32::consts::PI;
+
+    // seeded so runs are comparable
+    let mut rng = WorldRng::new();
+    let mut report = Report::new();
+    let mut world = new_world();
+    let size = (BODIES as f32).sqrt() as isize * 2;
+
+    add_border(&mut world, size);
+    for _ in 0..BODIES {
+        let pos = [rng.gen_range(0., size as f32), rng.gen_range(0., size as f32)];
+        add_body(&mut world, pos, rng.gen_range(-PI, PI));
+    }
+    fill(&mut world, &mut report);
+
+    simulate(world, &mut report, |_, _| ());
+    report.print(&*format!("bouncing bodies ({})", BODIES));
+}
+
+/// RAYCASTS raycasts each frame among bodies in a closed room,
+/// also against walls only to measure the static fast path
+fn raycasts() {
+    use std::f32::consts::PI;
+
+    let mut rng = WorldRng::new();
+    let mut report = Report::new();
+    let mut world = new_world();
+    let size = (BODIES as f32).sqrt() as isize * 2;
+
+    add_border(&mut world, size);
+    for _ in 0..BODIES / 4 {
+        let pos = [rng.gen_range(0., size as f32), rng.gen_range(0., size as f32)];
+        add_body(&mut world, pos, rng.gen_range(-PI, PI));
+    }
+    fill(&mut world, &mut report);
+
+    simulate(world, &mut report, |world, report| {
+        let physic_world = world.read_resource::<physic::PhysicWorld>();
+        let rays: Vec<Ray> = (0..RAYCASTS).map(|_| Ray {
+            origin: [rng.gen_range(0., size as f32), rng.gen_range(0., size as f32)],
+            angle: rng.gen_range(-PI, PI),
+            length: size as f32,
+            mask: !0,
+        }).collect();
+
+        report.measure("raycast", || {
+            for ray in &rays {
+                physic_world.raycast(ray, &mut |_| false);
+            }
+        });
+        let static_only = RaycastOptions { static_only: true, .. RaycastOptions::default() };
+        report.measure("raycast static", || {
+            for ray in &rays {
+                physic_world.raycast_with(ray, &static_only, &mut |_| false);
+            }
+        });
+    });
+    report.print(&*format!("raycasts ({} per frame)", RAYCASTS));
+}
+
+/// a city of CITY_SIZE*CITY_SIZE blocks with streets crossed by a few bodies
+fn static_city() {
+    use std::f32::consts::PI;
+
+    let mut rng = WorldRng::new();
+    let mut report = Report::new();
+    let mut world = new_world();
+
+    add_border(&mut world, CITY_SIZE);
+    for x in 0..CITY_SIZE {
+        for y in 0..CITY_SIZE {
+            if x % 4 != 0 && y % 4 != 0 {
+                add_wall(&mut world, [x, y]);
+            }
+        }
+    }
+    for x in 0..CITY_SIZE / 4 {
+        for y in 0..CITY_SIZE / 4 {
+            add_body(&mut world, [x as f32 * 4. + 0.5, y as f32 * 4. + 0.5], rng.gen_range(-PI, PI));
+        }
+    }
+    fill(&mut world, &mut report);
+
+    simulate(world, &mut report, |world, report| {
+        if rng.gen_weighted_bool(60) {
+            fill(world, report);
+        }
+    });
+    report.print(&*format!("static city ({}x{})", CITY_SIZE, CITY_SIZE));
+}
+
+pub fn run() {
+    bouncing_bodies();
+    raycasts();
+    static_city();
+}
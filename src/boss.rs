@@ -0,0 +1,449 @@
+//! boss encounters: a boss waits in its arena until a player enters it, then the
+//! gates of the arena are walled and the boss fights through the phases of its
+//! definition in bosses.definitions.
+//!
+//! a phase starts when the health of the boss falls to its threshold: it sets the
+//! behavior, the speed and the ability of the boss and spawns adds at the adds tiles of
+//! the arena, one every bosses.add_interval seconds while the boss lives. the gates open when the boss dies or when no player is left in the
+//! arena, and the end of the encounter is sent for quests and music.
+
+use app;
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use entities;
+use components::*;
+use resource::PhysicWorld;
+use physic::IntoGrid;
+use graphics::Color;
+use specs::Join;
+use ability;
+use progression::{Modifier, Source, Stat};
+use std::f32::consts::PI;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub type Array2Isize = [isize;2];
+pub type VecArray2Isize = Vec<[isize;2]>;
+pub type VecPhaseDefinition = Vec<PhaseDefinition>;
+
+/// the boss music is pushed right after levels.entry_music
+pub const MUSIC: usize = 1;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum BossBehavior {
+    Chase,
+    Strafe,
+    Retreat,
+}
+impl_from_into_toml_for_enum!(BossBehavior {
+    Chase,
+    Strafe,
+    Retreat,
+});
+
+/// * threshold: the phase starts when the health of the boss falls to this fraction
+///   of its maximum
+/// * behavior: chase the target, strafe around it at range or retreat to range
+/// * range: distance kept from the target by strafe and retreat
+/// * speed: factor of the speed of the boss
+/// * ability: ability used on the target when ready and in range, empty for none
+/// * adds: monsters spawned at the adds tiles of the arena when the phase starts
+pub struct PhaseDefinition {
+    pub threshold: f32,
+    pub behavior: BossBehavior,
+    pub range: f32,
+    pub speed: f32,
+    pub ability: String,
+    pub adds: usize,
+}
+impl_from_into_toml_for_struct!( PhaseDefinition {
+    threshold: f32,
+    behavior: BossBehavior,
+    range: f32,
+    speed: f32,
+    ability: String,
+    adds: usize,
+});
+
+/// * name: shown on the health bar, the target of `defeat` quest objectives
+/// * health, damage: base attributes of the boss
+/// * phases: by decreasing threshold, the first one is started with the encounter
+pub struct BossDefinition {
+    pub name: String,
+    pub health: f32,
+    pub damage: f32,
+    pub radius: f32,
+    pub color: Color,
+    pub phases: VecPhaseDefinition,
+}
+impl_from_into_toml_for_struct!( BossDefinition {
+    name: String,
+    health: f32,
+    damage: f32,
+    radius: f32,
+    color: Color,
+    phases: VecPhaseDefinition,
+});
+
+/// the index of the boss of name in bosses.definitions
+pub fn boss(name: &str) -> Option<usize> {
+    config.bosses.definitions.iter().position(|b| b.name == name)
+}
+
+/// an arena of the level data, tiles are in map coordinates (rows are negative):
+/// * boss: name of the boss in bosses.definitions
+/// * spawn: tile of the boss
+/// * position, size: the rectangle a player enters to start the encounter
+/// * gates: tiles walled during the encounter
+/// * adds: tiles the adds of the phases spawn at, one after the other
+pub struct ArenaDefinition {
+    pub boss: String,
+    pub spawn: Array2Isize,
+    pub position: Array2Isize,
+    pub size: Array2Isize,
+    pub gates: VecArray2Isize,
+    pub adds: VecArray2Isize,
+}
+impl_from_into_toml_for_struct!( ArenaDefinition {
+    boss: String,
+    spawn: Array2Isize,
+    position: Array2Isize,
+    size: Array2Isize,
+    gates: VecArray2Isize,
+    adds: VecArray2Isize,
+});
+
+pub struct Boss {
+    pub definition: usize,
+    phase: usize,
+}
+impl specs::Component for Boss {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Boss {
+    pub fn new(definition: usize) -> Self {
+        Boss {
+            definition: definition,
+            phase: 0,
+        }
+    }
+
+    pub fn definition(&self) -> &'static BossDefinition {
+        &config.bosses.definitions[self.definition]
+    }
+
+    pub fn phase(&self) -> usize {
+        self.phase
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ArenaState {
+    /// waiting for a player
+    Idle,
+    /// the gates are walled
+    Locked,
+    /// the boss is dead
+    Cleared,
+}
+
+pub struct Arena {
+    boss: Option<specs::Entity>,
+    definition: usize,
+    position: [isize;2],
+    size: [isize;2],
+    gates: Vec<[isize;2]>,
+    adds: Vec<[isize;2]>,
+    /// the adds tile of the next add
+    next_add: usize,
+    state: ArenaState,
+}
+impl specs::Component for Arena {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Arena {
+    /// tiles are centred on integer positions
+    fn contains(&self, position: [f32;2]) -> bool {
+        let x = position[0].round() as isize;
+        let y = position[1].round() as isize;
+        x >= self.position[0] && x < self.position[0] + self.size[0]
+            && y <= self.position[1] && y > self.position[1] - self.size[1]
+    }
+
+    pub fn boss(&self) -> Option<specs::Entity> {
+        self.boss
+    }
+}
+
+/// a wall of the gates of an arena
+#[derive(Clone,Default)]
+pub struct ArenaGate;
+impl specs::Component for ArenaGate {
+    type Storage = specs::NullStorage<Self>;
+}
+
+/// the phase of definition at the fraction of health left
+fn phase_at(definition: &BossDefinition, fraction: f32) -> usize {
+    definition.phases.iter().filter(|p| fraction <= p.threshold).count().max(1) - 1
+}
+
+/// the direction and intensity of the force of a boss at position with behavior
+/// toward target
+fn steer(behavior: BossBehavior, range: f32, position: [f32;2], target: [f32;2]) -> (f32, f32) {
+    let angle = (target[1] - position[1]).atan2(target[0] - position[0]);
+    let distance = ((target[0] - position[0]).powi(2) + (target[1] - position[1]).powi(2)).sqrt();
+    match behavior {
+        BossBehavior::Chase => (angle, 1.),
+        BossBehavior::Retreat => if distance < range {
+            (angle + PI, 1.)
+        } else {
+            (angle, 0.)
+        },
+        BossBehavior::Strafe => {
+            // turn around the target, leaning toward the circle of radius range
+            let lean = if range > 0. { ((distance - range)/range).max(-1.).min(1.)*PI/4. } else { 0. };
+            (angle + PI/2. - lean, 1.)
+        },
+    }
+}
+
+/// wall the gates of the arena
+pub fn lock(world: &mut specs::World, arena: specs::Entity) {
+    let gates = match world.read::<Arena>().get(arena) {
+        Some(arena) => arena.gates.clone(),
+        None => return,
+    };
+    for gate in gates {
+        entities::add_gate(world, gate);
+    }
+    world.write_resource::<PhysicWorld>().fill(world);
+}
+
+/// remove the walls of the gates of the arena
+pub fn unlock(world: &mut specs::World, arena: specs::Entity) {
+    let walls = {
+        let arenas = world.read::<Arena>();
+        let gates = world.read::<ArenaGate>();
+        let states = world.read::<PhysicState>();
+        let arena = match arenas.get(arena) {
+            Some(arena) => arena,
+            None => return,
+        };
+        (&gates, &states, &world.entities()).iter()
+            .filter(|&(_, state, _)| arena.gates.iter().any(|gate| gate.into_grid() == state.position))
+            .map(|(_, _, entity)| entity)
+            .collect::<Vec<_>>()
+    };
+    for wall in walls {
+        world.delete_now(wall);
+    }
+    world.write_resource::<PhysicWorld>().fill(world);
+}
+
+/// the boss of the encounter in progress and the fraction of its health left
+pub fn engaged(world: &specs::World) -> Option<(&'static str, f32)> {
+    let arenas = world.read::<Arena>();
+    let bosses = world.read::<Boss>();
+    let lives = world.read::<Life>();
+    for arena in arenas.iter().filter(|arena| arena.state == ArenaState::Locked) {
+        if let Some((boss, life)) = arena.boss.and_then(|boss| bosses.get(boss).and_then(|b| lives.get(boss).map(|l| (b, l)))) {
+            let fraction = if life.max_health() > 0. { life.health()/life.max_health() } else { 0. };
+            return Some((&*boss.definition().name, fraction.max(0.)));
+        }
+    }
+    None
+}
+
+/// start and end the encounters, advance the phases and control the bosses engaged
+pub struct BossSystem;
+impl specs::System<app::UpdateContext> for BossSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut arenas, mut bosses, mut forces, mut casters, mut attributes, abilities, lives, players, states, entities) = arg.fetch(|world| {
+            (
+                world.write::<Arena>(),
+                world.write::<Boss>(),
+                world.write::<PhysicForce>(),
+                world.write::<Caster>(),
+                world.write::<Attributes>(),
+                world.read::<Abilities>(),
+                world.read::<Life>(),
+                world.read::<PlayerControl>(),
+                world.read::<PhysicState>(),
+                world.entities(),
+            )
+        });
+
+        let player_positions: Vec<[f32;2]> = (&players, &states).iter().map(|(_, state)| state.position).collect();
+
+        for (arena, arena_entity) in (&mut arenas, &entities).iter() {
+            let player_inside = player_positions.iter().any(|&p| arena.contains(p));
+            let boss_alive = arena.boss.and_then(|boss| lives.get(boss)).map(|life| life.is_alive()).unwrap_or(false);
+            let mut spawn = 0;
+
+            match arena.state {
+                ArenaState::Idle => if player_inside && boss_alive {
+                    arena.state = ArenaState::Locked;
+                    context.control_tx.send(app::Control::LockArena(arena_entity)).unwrap();
+                    let boss = arena.boss.unwrap();
+                    if let Some(boss_component) = bosses.get_mut(boss) {
+                        spawn = boss_component.definition().phases[boss_component.phase].adds;
+                    }
+                    context.event_tx.send(app::GameEvent::BossEncounterStarted(boss,arena.definition)).unwrap();
+                },
+                ArenaState::Locked => if !boss_alive || !player_inside {
+                    arena.state = if boss_alive { ArenaState::Idle } else { ArenaState::Cleared };
+                    context.control_tx.send(app::Control::UnlockArena(arena_entity)).unwrap();
+                    context.event_tx.send(app::GameEvent::BossEncounterEnded(arena.definition,!boss_alive)).unwrap();
+                    if let Some(force) = arena.boss.and_then(|boss| forces.get_mut(boss)) {
+                        force.intensity = 0.;
+                    }
+                },
+                ArenaState::Cleared => (),
+            }
+            if arena.state != ArenaState::Locked {
+                continue;
+            }
+
+            let boss_entity = arena.boss.unwrap();
+            let boss = match bosses.get_mut(boss_entity) {
+                Some(boss) => boss,
+                None => continue,
+            };
+            let definition = boss.definition();
+            let position = states.get(boss_entity).expect("boss expect state component").position;
+
+            let life = lives.get(boss_entity).unwrap();
+            let fraction = if life.max_health() > 0. { life.health()/life.max_health() } else { 0. };
+            let phase = phase_at(definition, fraction).max(boss.phase);
+            if phase != boss.phase {
+                boss.phase = phase;
+                spawn += definition.phases[phase].adds;
+                context.event_tx.send(app::GameEvent::BossPhaseChanged(boss_entity,phase)).unwrap();
+            }
+            let phase = &definition.phases[boss.phase];
+
+            let mut tiles = vec!();
+            for _ in 0..spawn {
+                if arena.adds.is_empty() {
+                    break;
+                }
+                tiles.push(arena.adds[arena.next_add % arena.adds.len()]);
+                arena.next_add += 1;
+            }
+            if !tiles.is_empty() {
+                context.control_tx.send(app::Control::SummonAdds(boss_entity,tiles)).unwrap();
+            }
+
+            if let Some(attributes) = attributes.get_mut(boss_entity) {
+                attributes.set_modifiers(Source::Boss, vec!(Modifier {
+                    stat: Stat::Speed,
+                    add: 0.,
+                    multiply: phase.speed,
+                }));
+            }
+
+            // the nearest player in the arena is the target
+            let target = player_positions.iter()
+                .filter(|&&p| arena.contains(p))
+                .map(|&p| (p, (p[0] - position[0]).powi(2) + (p[1] - position[1]).powi(2)))
+                .fold(None, |nearest: Option<([f32;2],f32)>, (p, d)| if nearest.map(|(_, n)| d < n).unwrap_or(true) { Some((p, d)) } else { nearest });
+            let (target, distance) = match target {
+                Some((target, d)) => (target, d.sqrt()),
+                None => continue,
+            };
+
+            if let Some(force) = forces.get_mut(boss_entity) {
+                let (direction, intensity) = steer(phase.behavior, phase.range, position, target);
+                force.direction = direction;
+                force.intensity = intensity;
+            }
+
+            if let (Some(caster), Some(abilities), Some(ability)) = (casters.get_mut(boss_entity), abilities.get(boss_entity), ability::ability(&phase.ability)) {
+                if distance <= config.abilities.definitions[ability].range && abilities.check(ability).is_ok() {
+                    caster.requested = caster.slots().iter().position(|&a| a == ability);
+                }
+            }
+        }
+    }
+}
+
+/// create the arenas and their bosses defined in the file if it exists:
+/// a toml file with an array of tables `arenas`
+pub fn load(path: &Path, world: &mut specs::World) -> Result<(),String> {
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    let mut string = String::new();
+    try!(try!(fs::File::open(&path).map_err(|e| format!("open bosses failed: {}",e)))
+         .read_to_string(&mut string)
+         .map_err(|_| String::from("bosses invalid: not valid UTF-8")));
+
+    let mut parser = toml::Parser::new(&*string);
+    let table = try!(parser.parse().ok_or(format!("bosses {:?} invalid: toml parsing failed",path)));
+    let arenas: Vec<ArenaDefinition> = match table.get("arenas") {
+        Some(arenas) => try!(FromToml::from_toml(arenas).map_err(|e| format!("bosses {:?} invalid: arenas{}",path,e))),
+        None => vec!(),
+    };
+
+    for (i, arena) in arenas.into_iter().enumerate() {
+        let definition = try!(boss(&arena.boss)
+            .ok_or(format!("bosses {:?} invalid: arenas[{}].boss {} is not in bosses.definitions",path,i,arena.boss)));
+        if arena.size[0] <= 0 || arena.size[1] <= 0 {
+            return Err(format!("bosses {:?} invalid: arenas[{}].size must be positive",path,i));
+        }
+        let boss = entities::add_boss(world, arena.spawn, definition);
+        world.create_now()
+            .with::<Arena>(Arena {
+                boss: Some(boss),
+                definition: definition,
+                position: arena.position,
+                size: arena.size,
+                gates: arena.gates,
+                adds: arena.adds,
+                next_add: 0,
+                state: ArenaState::Idle,
+            })
+            .build();
+    }
+    Ok(())
+}
+
+#[test]
+fn test_boss_phases() {
+    for definition in &config.bosses.definitions {
+        assert_eq!(phase_at(definition, 1.), 0);
+        assert_eq!(phase_at(definition, 0.), definition.phases.len() - 1);
+    }
+
+    let (direction, intensity) = steer(BossBehavior::Retreat, 3., [0.,0.], [1.,0.]);
+    assert!((direction - PI).abs() < 1e-6 && intensity == 1.);
+    assert_eq!(steer(BossBehavior::Retreat, 3., [0.,0.], [5.,0.]).1, 0.);
+    // strafing on the circle goes along the tangent
+    assert!((steer(BossBehavior::Strafe, 3., [0.,0.], [3.,0.]).0 - PI/2.).abs() < 1e-6);
+}
+
+#[test]
+fn test_arena_contains() {
+    let arena = Arena {
+        boss: None,
+        definition: 0,
+        position: [0, 0],
+        size: [3, 2],
+        gates: vec!(),
+        adds: vec!(),
+        next_add: 0,
+        state: ArenaState::Idle,
+    };
+    assert!(arena.contains([0., 0.]));
+    assert!(arena.contains([2., -1.]));
+    assert!(arena.contains([-0.49, 0.49]));
+    assert!(arena.contains([2.49, -1.49]));
+    assert!(!arena.contains([-0.51, 0.]));
+    assert!(!arena.contains([0., 0.51]));
+    assert!(!arena.contains([2.51, -1.]));
+    assert!(!arena.contains([0., -1.51]));
+}
@@ -0,0 +1,24 @@
+//! budget of the expensive queries of a frame, like the target lookups of the monsters:
+//! the queries due are submitted with a priority and only the ones of highest priority
+//! within the budget are run, the others stay due and are submitted again next frame.
+//!
+//! the budget is a number of queries rather than a time so that the peers of a
+//! networked game run the same queries each frame. a query waiting should raise its
+//! priority with its wait so none starves.
+
+use std::cmp::Ordering;
+
+/// the queries of highest priority, at most budget, in the order they were submitted
+/// when their priority is equal
+pub fn take<T>(mut queries: Vec<(T,f32)>, budget: usize) -> Vec<T> {
+    // the sort is stable
+    queries.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    queries.into_iter().take(budget).map(|(query, _)| query).collect()
+}
+
+#[test]
+fn test_take() {
+    assert_eq!(take(vec!(("a", 0.), ("b", 2.), ("c", 1.), ("d", 2.)), 3), vec!("b", "d", "c"));
+    assert_eq!(take(vec!(("a", 0.)), 3), vec!("a"));
+    assert!(take(vec!(("a", 0.)), 0).is_empty());
+}
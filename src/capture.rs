@@ -0,0 +1,242 @@
+//! the capture keeps the last capture.seconds of play, capture.fps frames per second
+//! downscaled by capture.scale, and exports them as an animated gif in capture.dir
+//! for bug reports and highlights.
+//!
+//! frames are quantized to a fixed palette of 256 colors (3 bits of red and green, 2
+//! bits of blue) when captured so the buffer stays small and the export only encodes.
+
+use config;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Clone)]
+struct Frame {
+    /// indices in the palette, rows from top to bottom
+    indices: Vec<u8>,
+    width: u16,
+    height: u16,
+}
+
+#[derive(Clone)]
+pub struct Capture {
+    frames: VecDeque<Frame>,
+    /// time until the next frame is due
+    timer: f32,
+    due: bool,
+}
+impl Capture {
+    pub fn new() -> Self {
+        Capture {
+            frames: VecDeque::new(),
+            timer: 0.,
+            due: false,
+        }
+    }
+
+    fn capacity() -> usize {
+        (config.capture.seconds*config.capture.fps).ceil() as usize
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if !config.capture.enabled {
+            return;
+        }
+        self.timer -= dt;
+        if self.timer <= 0. {
+            self.timer = (self.timer + 1./config.capture.fps).max(0.);
+            self.due = true;
+        }
+    }
+
+    /// true if a frame must be pushed
+    pub fn is_due(&self) -> bool {
+        self.due
+    }
+
+    /// push the rgba pixels of a frame, rows from bottom to top
+    pub fn push(&mut self, pixels: &[u8], width: u32, height: u32) {
+        self.due = false;
+        let scale = config.capture.scale.max(1) as u32;
+        let frame_width = (width/scale).max(1).min(u16::max_value() as u32);
+        let frame_height = (height/scale).max(1).min(u16::max_value() as u32);
+
+        let mut indices = Vec::with_capacity((frame_width*frame_height) as usize);
+        for y in (0..frame_height).rev() {
+            for x in 0..frame_width {
+                let i = (((y*scale).min(height - 1)*width + (x*scale).min(width - 1))*4) as usize;
+                indices.push(quantize(pixels[i], pixels[i+1], pixels[i+2]));
+            }
+        }
+
+        // a resize of the window drops the frames of the previous size
+        if self.frames.back().map(|f| f.width as u32 != frame_width || f.height as u32 != frame_height).unwrap_or(false) {
+            self.frames.clear();
+        }
+        while self.frames.len() >= Capture::capacity() {
+            if self.frames.pop_front().is_none() {
+                return;
+            }
+        }
+        self.frames.push_back(Frame {
+            indices: indices,
+            width: frame_width as u16,
+            height: frame_height as u16,
+        });
+    }
+
+    /// write the frames as a gif in capture.dir named after the first index not taken
+    pub fn export(&self) -> Result<PathBuf, String> {
+        let (width, height) = match self.frames.front() {
+            Some(frame) => (frame.width, frame.height),
+            None => return Err("capture is empty".into()),
+        };
+        let dir = PathBuf::from(&*config.capture.dir.val);
+        try!(fs::create_dir_all(&dir).map_err(|e| format!("create capture directory failed: {}",e)));
+        let path = (0..).map(|i| dir.join(format!("capture_{}.gif",i)))
+            .find(|path| !path.exists())
+            .unwrap();
+
+        let file = try!(fs::File::create(&path).map_err(|e| format!("create capture failed: {}",e)));
+        let delay = (100./config.capture.fps).round() as u16;
+        try!(self.write_gif(io::BufWriter::new(file), width, height, delay).map_err(|e| format!("write capture failed: {}",e)));
+        Ok(path)
+    }
+
+    fn write_gif<W: Write>(&self, mut writer: W, width: u16, height: u16, delay: u16) -> io::Result<()> {
+        try!(writer.write_all(b"GIF89a"));
+        // logical screen with a global color table of 256 colors
+        try!(write_u16(&mut writer, width));
+        try!(write_u16(&mut writer, height));
+        try!(writer.write_all(&[0xf7, 0, 0]));
+        for i in 0..256 {
+            try!(writer.write_all(&palette(i as u8)));
+        }
+        // loop forever
+        try!(writer.write_all(&[0x21, 0xff, 0x0b]));
+        try!(writer.write_all(b"NETSCAPE2.0"));
+        try!(writer.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00]));
+
+        for frame in &self.frames {
+            // graphic control extension with the delay in hundredths of second
+            try!(writer.write_all(&[0x21, 0xf9, 0x04, 0x00]));
+            try!(write_u16(&mut writer, delay));
+            try!(writer.write_all(&[0x00, 0x00]));
+            // image descriptor of the whole screen
+            try!(writer.write_all(&[0x2c, 0, 0, 0, 0]));
+            try!(write_u16(&mut writer, frame.width));
+            try!(write_u16(&mut writer, frame.height));
+            try!(writer.write_all(&[0x00, 8]));
+            for block in lzw(&frame.indices).chunks(255) {
+                try!(writer.write_all(&[block.len() as u8]));
+                try!(writer.write_all(block));
+            }
+            try!(writer.write_all(&[0x00]));
+        }
+        writer.write_all(&[0x3b])
+    }
+}
+
+fn write_u16<W: Write>(writer: &mut W, value: u16) -> io::Result<()> {
+    writer.write_all(&[(value & 0xff) as u8, (value >> 8) as u8])
+}
+
+/// the index of the color in the palette
+fn quantize(r: u8, g: u8, b: u8) -> u8 {
+    (r & 0xe0) | ((g & 0xe0) >> 3) | (b >> 6)
+}
+
+/// the color of the index in the palette
+fn palette(index: u8) -> [u8;3] {
+    let r = (index >> 5) as u32;
+    let g = ((index >> 2) & 7) as u32;
+    let b = (index & 3) as u32;
+    [(r*255/7) as u8, (g*255/7) as u8, (b*255/3) as u8]
+}
+
+/// the lzw compression of indices of 8 bits as in gif
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    const CLEAR: u32 = 256;
+    const END: u32 = 257;
+    const MAX: u32 = 4096;
+
+    let mut output = vec!();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut write = |code: u32, size: u32, output: &mut Vec<u8>| {
+        buffer |= code << bits;
+        bits += size;
+        while bits >= 8 {
+            output.push((buffer & 0xff) as u8);
+            buffer >>= 8;
+            bits -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u32,u8),u32> = HashMap::new();
+    let mut next = END + 1;
+    let mut size = 9;
+    write(CLEAR, size, &mut output);
+
+    let mut prefix: Option<u32> = None;
+    for &index in indices {
+        let code = match prefix {
+            None => {
+                prefix = Some(index as u32);
+                continue;
+            },
+            Some(code) => code,
+        };
+        if let Some(&extended) = table.get(&(code, index)) {
+            prefix = Some(extended);
+            continue;
+        }
+        write(code, size, &mut output);
+        if next < MAX {
+            table.insert((code, index), next);
+            next += 1;
+            if next > 1 << size && size < 12 {
+                size += 1;
+            }
+        } else {
+            write(CLEAR, size, &mut output);
+            table.clear();
+            next = END + 1;
+            size = 9;
+        }
+        prefix = Some(index as u32);
+    }
+    if let Some(code) = prefix {
+        write(code, size, &mut output);
+        // the decoder adds an entry for the last code before reading the end
+        if next < MAX && next + 1 > 1 << size && size < 12 {
+            size += 1;
+        }
+    }
+    write(END, size, &mut output);
+    // flush the bits of the last byte
+    write(0, 7, &mut output);
+    output
+}
+
+#[test]
+fn test_capture() {
+    assert_eq!(quantize(255, 255, 255), 255);
+    assert_eq!(palette(255), [255, 255, 255]);
+    assert_eq!(palette(quantize(0, 0, 0)), [0, 0, 0]);
+
+    let scale = config.capture.scale.max(1) as u32;
+    let pixels = vec![128u8; (4*scale*3*scale*4) as usize];
+    let mut capture = Capture::new();
+    for _ in 0..Capture::capacity() + 2 {
+        capture.push(&pixels, 4*scale, 3*scale);
+    }
+    assert_eq!(capture.frames.len(), Capture::capacity());
+    assert_eq!(capture.frames[0].indices.len(), 12);
+
+    let mut gif = vec!();
+    capture.write_gif(&mut gif, 4, 3, 10).unwrap();
+    assert_eq!(&gif[..6], b"GIF89a");
+    assert_eq!(gif.last(), Some(&0x3b));
+}
@@ -0,0 +1,119 @@
+use app;
+use specs;
+use config;
+use components::*;
+use inventory::Stack;
+use specs::Join;
+use baal;
+use utils::Into3D;
+
+/// the state of the player saved when a checkpoint is reached
+#[derive(Clone)]
+pub struct PlayerSnapshot {
+    pub position: [f32;2],
+    pub inventory: Inventory,
+    pub xp: f32,
+    pub money: usize,
+    /// the part of the maximum health left
+    pub health: f32,
+}
+impl PlayerSnapshot {
+    pub fn new(state: &PhysicState, life: Option<&Life>, inventory: Option<&Inventory>, experience: Option<&Experience>, wallet: Option<&Wallet>) -> Self {
+        PlayerSnapshot {
+            position: state.position,
+            health: life.map(|l| l.health()/l.max_health()).unwrap_or(1.),
+            inventory: inventory.cloned().unwrap_or(Inventory::new(config.entities.char_inventory_size)),
+            xp: experience.map(|e| e.xp).unwrap_or(0.),
+            money: wallet.map(|w| w.money).unwrap_or(0),
+        }
+    }
+
+    /// the snapshot with the fraction of each stack defined by the checkpoint penalty
+    /// removed, the snapshot itself is kept so the penalty doesn't compound
+    pub fn with_penalty(&self) -> Self {
+        let mut snapshot = self.clone();
+        for stack in snapshot.inventory.drain() {
+            let lost = (stack.count as f32 * config.entities.checkpoint_item_penalty).floor() as usize;
+            if stack.count > lost {
+                snapshot.inventory.insert(Stack {
+                    item: stack.item,
+                    count: stack.count - lost,
+                });
+            }
+        }
+        snapshot
+    }
+}
+
+pub struct Checkpoint {
+    activated: bool,
+}
+impl specs::Component for Checkpoint {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Checkpoint {
+    pub fn new() -> Self {
+        Checkpoint {
+            activated: false,
+        }
+    }
+}
+
+pub struct CheckpointSystem;
+impl specs::System<app::UpdateContext> for CheckpointSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut checkpoints, mut graphics, mut lives, grid_squares, players, states, inventories, experiences, wallets, entities) = arg.fetch(|world| {
+            (
+                world.write::<Checkpoint>(),
+                world.write::<Graphic>(),
+                world.write::<Life>(),
+                world.read::<GridSquare>(),
+                world.read::<PlayerControl>(),
+                world.read::<PhysicState>(),
+                world.read::<Inventory>(),
+                world.read::<Experience>(),
+                world.read::<Wallet>(),
+                world.entities(),
+            )
+        });
+
+        for (_, state, player) in (&players, &states, &entities).iter() {
+            for (checkpoint, square, entity) in (&mut checkpoints, &grid_squares, &entities).iter() {
+                if checkpoint.activated { continue }
+
+                let pos = square.position;
+                if (state.position[0] - pos[0]).powi(2) + (state.position[1] - pos[1]).powi(2) < config.entities.checkpoint_radius.powi(2) {
+                    checkpoint.activated = true;
+                    if let Some(graphic) = graphics.get_mut(entity) {
+                        graphic.set_color(config.entities.checkpoint_active_color);
+                    }
+                    baal::effect::short::play(config.entities.checkpoint_snd,pos.into_3d());
+
+                    let mut snapshot = PlayerSnapshot::new(state,lives.get(player),inventories.get(player),experiences.get(player),wallets.get(player));
+                    snapshot.position = pos;
+                    if let Some(life) = lives.get_mut(player) {
+                        life.set_respawns(true);
+                    }
+                    context.event_tx.send(app::GameEvent::CheckpointReached(player,snapshot)).unwrap();
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_penalty_does_not_compound() {
+    let mut inventory = Inventory::new(config.entities.char_inventory_size);
+    inventory.insert(Stack { item: 0, count: 8 });
+    let snapshot = PlayerSnapshot {
+        position: [0., 0.],
+        inventory: inventory,
+        xp: 0.,
+        money: 0,
+        health: 1.,
+    };
+    let lost = (8. * config.entities.checkpoint_item_penalty).floor() as usize;
+    assert_eq!(snapshot.with_penalty().inventory.count(0), 8 - lost);
+    assert_eq!(snapshot.with_penalty().inventory.count(0), 8 - lost);
+    assert_eq!(snapshot.inventory.count(0), 8);
+}
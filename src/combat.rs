@@ -0,0 +1,166 @@
+//! combat log of every damage dealt: the damages of a frame are aggregated by target
+//! for the floating numbers, the last combat.history_size are kept to be queried and
+//! all of them can be exported as csv with `--combat-log <file>` for balance analysis.
+
+use specs;
+use config;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum DamageType {
+    /// touched by a killer
+    Contact,
+    Melee,
+    /// proximity detonation of a projectile
+    Explosion,
+    /// passed through by a dash
+    Dash,
+    /// standing over lava, spikes or another hazard
+    Hazard,
+}
+impl DamageType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DamageType::Contact => "contact",
+            DamageType::Melee => "melee",
+            DamageType::Explosion => "explosion",
+            DamageType::Dash => "dash",
+            DamageType::Hazard => "hazard",
+        }
+    }
+}
+
+/// no hit is critical for now
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Damage {
+    pub source: specs::Entity,
+    pub target: specs::Entity,
+    /// position of the target when hit
+    pub position: [f32;2],
+    pub amount: f32,
+    pub typ: DamageType,
+    pub crit: bool,
+}
+
+impl Damage {
+    pub fn new(source: specs::Entity, target: specs::Entity, position: [f32;2], amount: f32, typ: DamageType) -> Self {
+        Damage {
+            source: source,
+            target: target,
+            position: position,
+            amount: amount,
+            typ: typ,
+            crit: false,
+        }
+    }
+}
+
+/// the damages dealt to a target during a frame
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Aggregate {
+    pub target: specs::Entity,
+    pub position: [f32;2],
+    pub amount: f32,
+    pub crit: bool,
+}
+
+pub struct CombatLog {
+    frame: usize,
+    /// damages of the frame
+    pending: Vec<Damage>,
+    /// last damages with their frame, oldest first
+    history: VecDeque<(usize,Damage)>,
+    export: Option<BufWriter<File>>,
+}
+
+impl CombatLog {
+    pub fn new() -> Self {
+        CombatLog {
+            frame: 0,
+            pending: vec!(),
+            history: VecDeque::new(),
+            export: None,
+        }
+    }
+
+    /// write every damage recorded from now on to the file
+    pub fn export(&mut self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(try!(File::create(path)));
+        try!(writeln!(writer, "frame,source,target,amount,type,crit"));
+        self.export = Some(writer);
+        Ok(())
+    }
+
+    pub fn record(&mut self, damage: Damage) {
+        if let Some(ref mut writer) = self.export {
+            let result = writeln!(writer, "{},{},{},{},{},{}", self.frame, damage.source.get_id(), damage.target.get_id(),
+                                  damage.amount, damage.typ.name(), damage.crit);
+            if let Err(err) = result {
+                println!("WARNING: combat log export failed: {}", err);
+            }
+        }
+        self.pending.push(damage);
+        self.history.push_back((self.frame, damage));
+        while self.history.len() > config.combat.history_size {
+            self.history.pop_front();
+        }
+    }
+
+    /// the damages of the frame summed by target in order of first hit
+    pub fn end_frame(&mut self) -> Vec<Aggregate> {
+        let mut aggregates: Vec<Aggregate> = vec!();
+        let mut indices = HashMap::new();
+        for damage in self.pending.drain(..) {
+            let i = *indices.entry(damage.target).or_insert_with(|| {
+                aggregates.push(Aggregate { target: damage.target, position: damage.position, amount: 0., crit: false });
+                aggregates.len() - 1
+            });
+            aggregates[i].position = damage.position;
+            aggregates[i].amount += damage.amount;
+            aggregates[i].crit |= damage.crit;
+        }
+        self.frame += 1;
+        aggregates
+    }
+
+    /// the damages kept of the last frames that match filter, most recent first
+    pub fn query<F: Fn(&Damage) -> bool>(&self, frames: usize, filter: F) -> Vec<&Damage> {
+        self.history.iter().rev()
+            .take_while(|&&(frame, _)| frame + frames >= self.frame)
+            .map(|&(_, ref damage)| damage)
+            .filter(|damage| filter(*damage))
+            .collect()
+    }
+
+    /// the damages dealt or taken by entity, most recent first
+    pub fn involving(&self, entity: specs::Entity) -> Vec<&Damage> {
+        self.query(self.frame, |damage| damage.source == entity || damage.target == entity)
+    }
+}
+
+#[test]
+fn test_combat_log() {
+    let mut world = specs::World::new();
+    let a = world.create_now().build();
+    let b = world.create_now().build();
+
+    let mut log = CombatLog::new();
+    log.record(Damage::new(a, b, [0.,0.], 1., DamageType::Contact));
+    log.record(Damage::new(b, a, [1.,0.], 1., DamageType::Melee));
+    log.record(Damage::new(a, b, [2.,0.], 2., DamageType::Explosion));
+    let aggregates = log.end_frame();
+    assert_eq!(aggregates.len(), 2);
+    assert_eq!(aggregates[0].target, b);
+    assert_eq!(aggregates[0].amount, 3.);
+    assert_eq!(aggregates[0].position, [2.,0.]);
+    assert_eq!(log.end_frame(), vec!());
+
+    log.record(Damage::new(b, a, [1.,0.], 1., DamageType::Melee));
+    log.end_frame();
+    assert_eq!(log.query(1, |_| true).len(), 1);
+    assert_eq!(log.query(3, |d| d.typ == DamageType::Melee).len(), 2);
+    assert_eq!(log.involving(a).len(), 4);
+}
@@ -3,43 +3,507 @@ use toml;
 use std;
 
 use levels as levelss;
+use inventory;
+use stats;
+use quest;
+use weather;
+use faction;
+use projectile;
+use loot;
+use progression;
+use shop;
+use ability;
+use movement;
+use ambient;
+use hazard;
+use boss;
+use palette;
+use lod;
+use governor;
+use ids::IdExhaustion;
+use savegame;
+use settings;
+use sequence;
 use graphics::{ Color, Layer };
 
 pub type VecVecStringPath = Vec<VecStringPath>;
 pub type Dimension = [u32;2];
+pub type VecDimension = Vec<[u32;2]>;
 pub type Array4F32 = [f32;4];
+pub type VecArray4F32 = Vec<[f32;4]>;
 pub type Array4F64 = [f64;4];
 pub type VecF32 = Vec<f32>;
 pub type VecU8 = Vec<u8>;
+pub type VecString = Vec<String>;
 pub type Dungeons = Vec<levelss::Dungeon>;
 pub type Array3U8 = [u8;3];
+pub type Array2F32 = [f32;2];
+pub type Array3F32 = [f32;3];
+pub type VecItemDefinition = Vec<inventory::ItemDefinition>;
+pub type VecAchievementDefinition = Vec<stats::AchievementDefinition>;
+pub type VecQuestDefinition = Vec<quest::QuestDefinition>;
+pub type VecWeatherDefinition = Vec<weather::WeatherDefinition>;
+pub type VecVecRelation = Vec<Vec<faction::Relation>>;
+pub type VecMotionProfile = Vec<projectile::MotionProfile>;
+pub type VecLootTable = Vec<loot::LootTable>;
+pub type VecModifier = Vec<progression::Modifier>;
+pub type VecPriceTable = Vec<shop::PriceTable>;
+pub type VecPoolDefinition = Vec<ability::PoolDefinition>;
+pub type VecAbilityDefinition = Vec<ability::AbilityDefinition>;
+pub type VecStatusDefinition = Vec<ability::StatusDefinition>;
+pub type VecModeDefinition = Vec<movement::ModeDefinition>;
+pub type VecAmbientPrefab = Vec<ambient::AmbientPrefab>;
+pub type VecHazardDefinition = Vec<hazard::HazardDefinition>;
+pub type VecBossDefinition = Vec<boss::BossDefinition>;
+pub type VecPaletteDefinition = Vec<palette::PaletteDefinition>;
+pub type VecArray2String = Vec<[String;2]>;
+pub type VecRenderLodProfile = Vec<lod::RenderLodProfile>;
+pub type VecQualityLevel = Vec<governor::QualityLevel>;
 
 fn config_constraint(conf: &Config) -> Result<(),String> {
     if conf.keys.up.len() == 0
        || conf.keys.down.len() == 0
        || conf.keys.left.len() == 0
-       || conf.keys.right.len() == 0 {
+       || conf.keys.right.len() == 0
+       || conf.keys.action.len() == 0 {
            return Err("ERROR: configuration file invalid: keys mustn't be empty".into());
     }
 
+    if conf.levels.loading_tip_time <= 0. {
+        return Err("ERROR: configuration file invalid: levels.loading_tip_time must be strictly positive".into());
+    }
+
+    if conf.menu.transition_time < 0. {
+        return Err("ERROR: configuration file invalid: menu.transition_time must be positive".into());
+    }
+
     // assert persistent snd and static snd doesn't overlap
     if conf.entities.monster_persistent_snd == conf.entities.laser_persistent_snd {
         return Err("ERROR: configuration file invalid: monster_persistent_snd and laser_persistent_snd must be different".into());
     }
 
+    // assert items are defined and stackable
+    if conf.items.definitions.len() == 0 {
+        return Err("ERROR: configuration file invalid: items definitions mustn't be empty".into());
+    }
+    if conf.items.definitions.iter().any(|d| d.max_stack == 0) {
+        return Err("ERROR: configuration file invalid: items max_stack must be greater than 0".into());
+    }
+
+    // assert lockstep packets are not empty and hashes are exchanged
+    if conf.net.redundancy == 0 || conf.net.redundancy > 50 {
+        return Err("ERROR: configuration file invalid: net redundancy must be between 1 and 50".into());
+    }
+    if conf.replication.tick_rate <= 0. {
+        return Err("ERROR: configuration file invalid: replication tick_rate must be positive".into());
+    }
+    if conf.net.hash_period == 0 {
+        return Err("ERROR: configuration file invalid: net hash_period must be greater than 0".into());
+    }
+
+    // assert group names are unique and match the groups bitflags
+    if conf.entities.groups.len() > 32 {
+        return Err("ERROR: configuration file invalid: entities groups must be at most 32".into());
+    }
+    for (i, name) in conf.entities.groups.iter().enumerate() {
+        if conf.entities.groups[..i].contains(name) {
+            return Err(format!("ERROR: configuration file invalid: entities group {} is defined twice",name));
+        }
+    }
+    let named_groups = [
+        ("char", conf.entities.char_group.val),
+        ("monster", conf.entities.monster_group.val),
+        ("ball", conf.entities.ball_group.val),
+        ("laser", conf.entities.laser_group.val),
+        ("column", conf.entities.column_group.val),
+        ("wall", conf.entities.wall_group.val),
+        ("pickup", conf.entities.pickup_group.val),
+        ("chest", conf.entities.chest_group.val),
+        ("liquid", conf.entities.liquid_group.val),
+        ("zone", conf.entities.zone_group.val),
+        ("occluder", conf.entities.occluder_group.val),
+        ("crate", conf.entities.crate_group.val),
+        ("hazard", conf.entities.hazard_group.val),
+    ];
+    for &(name, group) in &named_groups {
+        if conf.entities.groups.iter().position(|g| g == name).map(|i| 1 << i) != Some(group) {
+            return Err(format!("ERROR: configuration file invalid: entities {}_group must be the group named {} in groups",name,name));
+        }
+    }
+
+    // assert wall tiles have a center
+    if conf.entities.wall_edge < 0. || conf.entities.wall_edge >= 0.5 {
+        return Err("ERROR: configuration file invalid: entities wall_edge must be in [0, 0.5)".into());
+    }
+
+    // assert dawn and dusk happen
+    if conf.day_night.period <= 0. {
+        return Err("ERROR: configuration file invalid: day_night period must be positive".into());
+    }
+    if conf.day_night.threshold <= conf.day_night.night_ambient || conf.day_night.threshold > 1. {
+        return Err("ERROR: configuration file invalid: day_night threshold must be greater than night_ambient and at most 1".into());
+    }
+
+    // assert weather states are unique and play existing sounds
+    if !conf.weather.states.iter().any(|s| s.name == conf.weather.initial) {
+        return Err(format!("ERROR: configuration file invalid: weather initial {} must be a weather state",conf.weather.initial));
+    }
+    for (i, state) in conf.weather.states.iter().enumerate() {
+        if conf.weather.states[..i].iter().any(|s| s.name == state.name) {
+            return Err(format!("ERROR: configuration file invalid: weather state {} is defined twice",state.name));
+        }
+        if state.ambient_snds.iter().any(|&snd| snd >= conf.audio.persistent_effects.len()) {
+            return Err(format!("ERROR: configuration file invalid: weather state {} ambient_snds must be persistent effects",state.name));
+        }
+    }
+
+    // assert each faction has a relation toward every faction
+    if conf.factions.names.len() == 0 {
+        return Err("ERROR: configuration file invalid: factions names mustn't be empty".into());
+    }
+    for name in ["player","monster"].iter() {
+        if !conf.factions.names.iter().any(|n| n == name) {
+            return Err(format!("ERROR: configuration file invalid: factions names must contain {}",name));
+        }
+    }
+    if conf.factions.relations.len() != conf.factions.names.len()
+       || conf.factions.relations.iter().any(|r| r.len() != conf.factions.names.len()) {
+        return Err("ERROR: configuration file invalid: factions relations must be a square matrix of the size of names".into());
+    }
+
+    // assert threat is forgotten
+    if conf.targeting.threat_decay <= 0. {
+        return Err("ERROR: configuration file invalid: targeting threat_decay must be positive".into());
+    }
+    if conf.targeting.vision_radius <= 0. {
+        return Err("ERROR: configuration file invalid: targeting vision_radius must be positive".into());
+    }
+
+    // assert projectile profiles are unique and their acceleration is a curve over time
+    for (i, profile) in conf.projectiles.profiles.iter().enumerate() {
+        if conf.projectiles.profiles[..i].iter().any(|p| p.name == profile.name) {
+            return Err(format!("ERROR: configuration file invalid: projectile profile {} is defined twice",profile.name));
+        }
+        if profile.acceleration.windows(2).any(|w| w[0][0] >= w[1][0]) {
+            return Err(format!("ERROR: configuration file invalid: projectile profile {} acceleration times must be increasing",profile.name));
+        }
+    }
+    if !conf.projectiles.profiles.iter().any(|p| p.name == conf.projectiles.column_profile) {
+        return Err(format!("ERROR: configuration file invalid: projectiles column_profile {} must be a profile",conf.projectiles.column_profile));
+    }
+
+    // assert loot tables are unique and drop items
+    for (i, table) in conf.loot.tables.iter().enumerate() {
+        if conf.loot.tables[..i].iter().any(|t| t.name == table.name) {
+            return Err(format!("ERROR: configuration file invalid: loot table {} is defined twice",table.name));
+        }
+        for entry in table.guaranteed.iter().chain(table.entries.iter()) {
+            if entry.table.is_empty() && entry.item >= conf.items.definitions.len() {
+                return Err(format!("ERROR: configuration file invalid: loot table {} item must be an item",table.name));
+            }
+            if !entry.table.is_empty() && !conf.loot.tables.iter().any(|t| t.name == entry.table) {
+                return Err(format!("ERROR: configuration file invalid: loot table {} nested table {} must be a loot table",table.name,entry.table));
+            }
+            if entry.count[0] > entry.count[1] || entry.weight < 0. {
+                return Err(format!("ERROR: configuration file invalid: loot table {} count must be [min, max] and weight positive",table.name));
+            }
+        }
+    }
+    if !conf.loot.tables.iter().any(|t| t.name == conf.loot.monster_table) {
+        return Err(format!("ERROR: configuration file invalid: loot monster_table {} must be a loot table",conf.loot.monster_table));
+    }
+
+    // assert levels start at 0 xp and modifiers multiply by positive factors
+    if conf.progression.levels.first() != Some(&0.) || conf.progression.levels.windows(2).any(|w| w[0] >= w[1]) {
+        return Err("ERROR: configuration file invalid: progression levels must start at 0 and be increasing".into());
+    }
+    let item_modifiers = conf.items.definitions.iter().flat_map(|d| d.modifiers.iter());
+    if conf.progression.level_modifiers.iter().chain(item_modifiers).any(|m| m.multiply <= 0.) {
+        return Err("ERROR: configuration file invalid: modifiers multiply must be greater than 0".into());
+    }
+    if conf.progression.char_health <= 0. || conf.progression.monster_health <= 0. || conf.progression.elite_monster_health <= 0. {
+        return Err("ERROR: configuration file invalid: progression health must be greater than 0".into());
+    }
+
+    // assert price tables are unique and trade items
+    for (i, table) in conf.shop.tables.iter().enumerate() {
+        if conf.shop.tables[..i].iter().any(|t| t.name == table.name) {
+            return Err(format!("ERROR: configuration file invalid: price table {} is defined twice",table.name));
+        }
+        if table.entries.iter().any(|e| e.item >= conf.items.definitions.len()) {
+            return Err(format!("ERROR: configuration file invalid: price table {} item must be an item",table.name));
+        }
+    }
+    if !conf.shop.tables.iter().any(|t| t.name == conf.shop.vendor_table) {
+        return Err(format!("ERROR: configuration file invalid: shop vendor_table {} must be a price table",conf.shop.vendor_table));
+    }
+
+    // assert abilities cost from defined pools
+    for pool in &conf.abilities.pools {
+        if pool.max <= 0. {
+            return Err(format!("ERROR: configuration file invalid: ability pool {} max must be greater than 0",pool.name));
+        }
+    }
+    for ability in &conf.abilities.definitions {
+        if !ability.pool.is_empty() && !conf.abilities.pools.iter().any(|p| p.name == ability.pool) {
+            return Err(format!("ERROR: configuration file invalid: ability {} pool {} must be an ability pool",ability.name,ability.pool));
+        }
+    }
+    for ability in &conf.abilities.definitions {
+        for effect in &ability.effects {
+            let known = match effect.effect {
+                ability::EffectType::Projectile => conf.projectiles.profiles.iter().any(|p| p.name == effect.name),
+                ability::EffectType::Status => conf.abilities.statuses.iter().any(|s| s.name == effect.name),
+                ability::EffectType::Summon => sequence::SPAWNABLE.contains(&&*effect.name),
+                ability::EffectType::Dash | ability::EffectType::Blast | ability::EffectType::Melee | ability::EffectType::Rope => true,
+            };
+            if !known {
+                return Err(format!("ERROR: configuration file invalid: ability {} effect name {} must be a projectile profile, a status or a spawnable entity",ability.name,effect.name));
+            }
+        }
+    }
+    for ability in &conf.abilities.definitions {
+        for effect in &ability.effects {
+            if effect.effect == ability::EffectType::Melee && (effect.amount <= 0. || effect.amount > 90.) {
+                return Err(format!("ERROR: configuration file invalid: ability {} melee amount must be in ]0,90]",ability.name));
+            }
+        }
+    }
+    for name in conf.abilities.player.iter().chain(conf.abilities.monster.iter()) {
+        if !conf.abilities.definitions.iter().any(|a| &a.name == name) {
+            return Err(format!("ERROR: configuration file invalid: abilities {} of player or monster must be an ability",name));
+        }
+    }
+    if !conf.abilities.definitions.iter().any(|a| a.name == conf.abilities.column) {
+        return Err(format!("ERROR: configuration file invalid: abilities column {} must be an ability",conf.abilities.column));
+    }
+    if conf.dash.speed <= 0. {
+        return Err("ERROR: configuration file invalid: dash speed must be greater than 0".into());
+    }
+    if !conf.dash.status.is_empty() && !conf.abilities.statuses.iter().any(|s| s.name == conf.dash.status) {
+        return Err(format!("ERROR: configuration file invalid: dash status {} must be a status",conf.dash.status));
+    }
+
+    // assert each movement mode is defined once
+    for mode in &[movement::MovementMode::Walk, movement::MovementMode::Climb, movement::MovementMode::Stairs, movement::MovementMode::Ledge] {
+        if conf.movement.modes.iter().filter(|d| d.mode == *mode).count() != 1 {
+            return Err(format!("ERROR: configuration file invalid: movement mode {} must be defined once",mode.name()));
+        }
+    }
+
+    // assert ambient prefabs are unique and loop dynamic sounds
+    for (i, prefab) in conf.ambient.prefabs.iter().enumerate() {
+        if conf.ambient.prefabs[..i].iter().any(|p| p.name == prefab.name) {
+            return Err(format!("ERROR: configuration file invalid: ambient prefab {} is defined twice",prefab.name));
+        }
+        if prefab.flicker < 0. || prefab.flicker > 1. {
+            return Err(format!("ERROR: configuration file invalid: ambient prefab {} flicker must be in [0, 1]",prefab.name));
+        }
+        if prefab.kind == ambient::EmitterKind::Sound
+           && (prefab.sound >= conf.audio.persistent_effects.len() || prefab.sound == conf.entities.laser_persistent_snd) {
+            return Err(format!("ERROR: configuration file invalid: ambient prefab {} sound must be a persistent effect other than laser_persistent_snd",prefab.name));
+        }
+    }
+
+    // assert the hazards of the maps are defined and tick
+    for name in ["lava","spikes"].iter() {
+        if !conf.hazards.kinds.iter().any(|k| k.name == *name) {
+            return Err(format!("ERROR: configuration file invalid: hazards kinds must define {}",name));
+        }
+    }
+    for (i, kind) in conf.hazards.kinds.iter().enumerate() {
+        if conf.hazards.kinds[..i].iter().any(|k| k.name == kind.name) {
+            return Err(format!("ERROR: configuration file invalid: hazard kind {} is defined twice",kind.name));
+        }
+        if kind.period <= 0. {
+            return Err(format!("ERROR: configuration file invalid: hazard kind {} period must be positive",kind.name));
+        }
+    }
+
+    if conf.bosses.add_interval < 0. {
+        return Err("ERROR: configuration file invalid: bosses.add_interval must be positive or zero".into());
+    }
+
+    // assert bosses start at full health and their phases follow each other
+    for (i, boss) in conf.bosses.definitions.iter().enumerate() {
+        if conf.bosses.definitions[..i].iter().any(|b| b.name == boss.name) {
+            return Err(format!("ERROR: configuration file invalid: boss {} is defined twice",boss.name));
+        }
+        if boss.phases.first().map(|p| p.threshold != 1.).unwrap_or(true) {
+            return Err(format!("ERROR: configuration file invalid: boss {} first phase threshold must be 1",boss.name));
+        }
+        if boss.phases.windows(2).any(|w| w[1].threshold >= w[0].threshold) {
+            return Err(format!("ERROR: configuration file invalid: boss {} phases thresholds must decrease",boss.name));
+        }
+        for phase in &boss.phases {
+            if !phase.ability.is_empty() && !conf.abilities.definitions.iter().any(|a| a.name == phase.ability) {
+                return Err(format!("ERROR: configuration file invalid: boss {} phase ability {} must be defined",boss.name,phase.ability));
+            }
+        }
+    }
+
+    // assert the language is one of the languages
+    if !conf.locale.languages.contains(&conf.locale.language) {
+        return Err("ERROR: configuration file invalid: locale language must be one of languages".into());
+    }
+
+    // assert the capture keeps frames
+    if conf.capture.fps <= 0. || conf.capture.scale == 0 {
+        return Err("ERROR: configuration file invalid: capture fps and scale must be positive".into());
+    }
+
+    // assert the colorblind palette replaces each hue color and the options are scales
+    if conf.accessibility.colorblind_palette.len() != 8 {
+        return Err("ERROR: configuration file invalid: accessibility colorblind_palette must have 8 colors".into());
+    }
+    if conf.accessibility.shake < 0. || conf.accessibility.shake > 1. {
+        return Err("ERROR: configuration file invalid: accessibility shake must be in [0, 1]".into());
+    }
+    if conf.accessibility.shake_decay <= 0. {
+        return Err("ERROR: configuration file invalid: accessibility shake_decay must be positive".into());
+    }
+    if conf.accessibility.game_speed <= 0. || conf.accessibility.game_speed > 1. {
+        return Err("ERROR: configuration file invalid: accessibility game_speed must be in (0, 1]".into());
+    }
+
+    // assert the window can be resized and letterboxed
+    if conf.window.resolutions.is_empty() {
+        return Err("ERROR: configuration file invalid: window resolutions mustn't be empty".into());
+    }
+    if conf.window.ui_scale < 0.5 || conf.window.ui_scale > 2. {
+        return Err("ERROR: configuration file invalid: window ui_scale must be in [0.5, 2]".into());
+    }
+    if conf.window.logical_resolution[0] == 0 || conf.window.logical_resolution[1] == 0 {
+        return Err("ERROR: configuration file invalid: window logical_resolution must be positive".into());
+    }
+
+    // assert the pixel art scene has pixels
+    if conf.graphics.pixel_art_resolution[0] == 0 || conf.graphics.pixel_art_resolution[1] == 0 {
+        return Err("ERROR: configuration file invalid: graphics pixel_art_resolution must be positive".into());
+    }
+
+    // assert the controls profile exists
+    if !conf.controls.profiles.contains(&conf.controls.profile) {
+        return Err(format!("ERROR: configuration file invalid: controls profile {} must be in profiles",conf.controls.profile));
+    }
+
+    // assert the materials used are loaded
+    for material in &[&conf.shaders.flash_material, &conf.shaders.outline_material, &conf.entities.portal_material] {
+        if !material.is_empty() && !conf.shaders.materials.contains(material) {
+            return Err(format!("ERROR: configuration file invalid: material {} must be in shaders materials",material));
+        }
+    }
+    if conf.shaders.flash_duration <= 0. {
+        return Err("ERROR: configuration file invalid: shaders flash_duration must be positive".into());
+    }
+    for region_lut in &conf.shaders.region_luts {
+        if !conf.shaders.luts.contains(&region_lut[1]) {
+            return Err(format!("ERROR: configuration file invalid: lut {} of region {} must be in shaders luts",region_lut[1],region_lut[0]));
+        }
+    }
+
+    // assert the hit particles can be rolled
+    if conf.combat.hit_particle_spread <= 0. || conf.combat.hit_particle_length[0] >= conf.combat.hit_particle_length[1] {
+        return Err("ERROR: configuration file invalid: combat hit_particle_spread must be positive and hit_particle_length increasing".into());
+    }
+    if conf.combat.hit_particle_persistance <= 0. {
+        return Err("ERROR: configuration file invalid: combat hit_particle_persistance must be positive".into());
+    }
+
+    // assert the palettes used are defined
+    for palette in &[&conf.entities.peer_palette, &conf.entities.elite_monster_palette] {
+        if !palette.is_empty() && !conf.palettes.definitions.iter().any(|p| p.name == **palette) {
+            return Err(format!("ERROR: configuration file invalid: palette {} must be in palettes definitions",palette));
+        }
+    }
+
+    // assert the render lod profiles are unique with a hysteresis less than the distance
+    for (i, profile) in conf.render_lod.profiles.iter().enumerate() {
+        if conf.render_lod.profiles[..i].iter().any(|p| p.name == profile.name) {
+            return Err(format!("ERROR: configuration file invalid: render_lod profile {} is defined twice",profile.name));
+        }
+        if profile.hysteresis < 0. || profile.hysteresis >= profile.distance {
+            return Err(format!("ERROR: configuration file invalid: render_lod profile {} hysteresis must be in [0, distance)",profile.name));
+        }
+        if profile.dot_size <= 0. {
+            return Err(format!("ERROR: configuration file invalid: render_lod profile {} dot_size must be positive",profile.name));
+        }
+    }
+    for lod in &[&conf.entities.monster_lod, &conf.entities.ball_lod, &conf.entities.pickup_lod] {
+        if !lod.is_empty() && !conf.render_lod.profiles.iter().any(|p| p.name == **lod) {
+            return Err(format!("ERROR: configuration file invalid: render lod {} must be in render_lod profiles",lod));
+        }
+    }
+
+    // assert the replication ids have bits for both the index and the generation
+    if conf.replication.id_index_bits == 0 || conf.replication.id_index_bits >= 32 {
+        return Err("ERROR: configuration file invalid: replication id_index_bits must be in [1, 31]".into());
+    }
+
+    // assert the quality levels go from the full quality down
+    if conf.quality.levels.is_empty() {
+        return Err("ERROR: configuration file invalid: quality levels must not be empty".into());
+    }
+    for level in &conf.quality.levels {
+        if level.particles < 0. || level.particles > 1. || level.ai_lod <= 0. || level.ai_lod > 1. {
+            return Err(format!("ERROR: configuration file invalid: quality level {} particles must be in [0, 1] and ai_lod in (0, 1]",level.name));
+        }
+    }
+    if conf.quality.smoothing <= 0. || conf.quality.smoothing > 1. {
+        return Err("ERROR: configuration file invalid: quality smoothing must be in (0, 1]".into());
+    }
+    if conf.quality.restore_ratio <= 0. || conf.quality.restore_ratio >= 1. {
+        return Err("ERROR: configuration file invalid: quality restore_ratio must be in (0, 1)".into());
+    }
+
+    // assert the monsters look for targets
+    if conf.targeting.lookups_per_frame == 0 {
+        return Err("ERROR: configuration file invalid: targeting lookups_per_frame must be greater than 0".into());
+    }
+
+    // assert far entities are still updated
+    if conf.physic.lod_max_period == 0 {
+        return Err("ERROR: configuration file invalid: physic lod_max_period must be greater than 0".into());
+    }
+
+    // assert quests have stages with known objectives
+    for quest in &conf.quests.definitions {
+        if quest.stages.len() == 0 {
+            return Err(format!("ERROR: configuration file invalid: quest {} stages mustn't be empty",quest.name));
+        }
+        if quest.stages.iter().any(|s| !["kill","pickup","reach","survive","defeat"].contains(&&*s.objective)) {
+            return Err(format!("ERROR: configuration file invalid: quest {} objective must be kill, pickup, reach, survive or defeat",quest.name));
+        }
+        if quest.reward_count > 0 && quest.reward_item >= conf.items.definitions.len() {
+            return Err(format!("ERROR: configuration file invalid: quest {} reward_item must be an item",quest.name));
+        }
+    }
+
     Ok(())
 }
 
+/// the configuration file
+pub const FILE: &'static str = "config.toml";
+
+/// load the configuration file again to check it
+pub fn check() -> Result<(),String> {
+    _load_config().map(|_| ())
+}
+
 configure!(
-    file = "config.toml";
-    debug_file = "config.toml";
-    save_file = "save.toml";
+    file = FILE;
+    debug_file = FILE;
+    save_file = &*settings::SAVE_PATH;
+    save_version = savegame::SAVE_VERSION;
+    save_migrations = savegame::SAVE_MIGRATIONS;
 
     constraint = config_constraint;
 
     general: {
         number_of_thread: t usize,
+        job_threads: t usize,
         persistent_snd_cooldown: t usize,
+        seed: t u32,
         difficulty: t f32 save difficulty,
     },
     keys: {
@@ -48,6 +512,21 @@ configure!(
         left: t VecU8,
         right: t VecU8,
         escape: t VecU8,
+        action: t VecU8,
+        inspector: t VecU8,
+        inspector_next: t VecU8,
+        inspector_field: t VecU8,
+        inspector_decrease: t VecU8,
+        inspector_increase: t VecU8,
+        memory_report: t VecU8,
+        abilities: t VecU8,
+        grab: t VecU8,
+        throw: t VecU8,
+        free_camera: t VecU8,
+        free_camera_pause: t VecU8,
+        free_camera_hud: t VecU8,
+        screenshot: t VecU8,
+        capture: t VecU8,
     },
     effect: {
         color: t Color,
@@ -60,6 +539,12 @@ configure!(
     physic: {
         rate: t f32,
         unit: t f32,
+        lod_distance: t f32,
+        lod_max_period: t usize,
+        dormancy_radius: t f32,
+        dormancy_wake_time: t f32,
+        los_move_threshold: t f32,
+        explosion_occlusion: t f32,
     },
     touch: {
         joystick_rec: t Array4F64,
@@ -80,6 +565,7 @@ configure!(
 
         background_width: t f32,
         background_height: t f32,
+        transition_time: t f32,
     },
     entities: {
         text_color: t Color,
@@ -112,9 +598,9 @@ configure!(
         column_radius: t f32,
         column_color: t Color,
         column_layer: t Layer,
-        column_cooldown: t f32,
         column_spawn_snd: t usize,
 
+        groups: t VecString,
         char_group: t BitflagU32,
         char_mask: t BitflagU32,
         char_radius: t f32,
@@ -131,6 +617,8 @@ configure!(
         wall_radius: t f32,
         wall_color: t Color,
         wall_layer: t Layer,
+        wall_edge: t f32,
+        wall_edge_color: t Color,
 
         monster_vision_mask: t BitflagU32,
         monster_killer_mask: t BitflagU32,
@@ -151,7 +639,119 @@ configure!(
         portal_end_layer: t Layer,
         portal_start_color: t Color,
         portal_start_layer: t Layer,
+        portal_material: t String,
+        portal_material_param: t f32,
+        peer_palette: t String,
+        elite_monster_palette: t String,
+        monster_lod: t String,
+        ball_lod: t String,
+        pickup_lod: t String,
         portal_snd: t usize,
+
+        pickup_group: t BitflagU32,
+        pickup_mask: t BitflagU32,
+        pickup_radius: t f32,
+        pickup_time: t f32,
+        pickup_weight: t f32,
+        pickup_scatter: t f32,
+        pickup_layer: t Layer,
+        pickup_snd: t usize,
+
+        char_inventory_size: t usize,
+
+        chest_group: t BitflagU32,
+        chest_mask: t BitflagU32,
+        chest_radius: t f32,
+        chest_color: t Color,
+        chest_layer: t Layer,
+        chest_inventory_size: t usize,
+        chest_item: t usize,
+        chest_count: t usize,
+        vendor_color: t Color,
+        vendor_layer: t Layer,
+        vendor_inventory_size: t usize,
+
+        checkpoint_color: t Color,
+        checkpoint_active_color: t Color,
+        checkpoint_layer: t Layer,
+        checkpoint_radius: t f32,
+        checkpoint_snd: t usize,
+        checkpoint_item_penalty: t f32,
+
+        liquid_group: t BitflagU32,
+        zone_group: t BitflagU32,
+        liquid_color: t Color,
+        liquid_layer: t Layer,
+        liquid_drag: t f32,
+        liquid_splash_color: t Color,
+        liquid_splash_snd: t usize,
+        liquid_sound_attenuation: t f32,
+
+        occluder_group: t BitflagU32,
+        foliage_color: t Color,
+        foliage_layer: t Layer,
+        low_wall_color: t Color,
+        low_wall_layer: t Layer,
+
+        hazard_group: t BitflagU32,
+        hazard_layer: t Layer,
+
+        crate_group: t BitflagU32,
+        crate_mask: t BitflagU32,
+        crate_radius: t f32,
+        crate_time: t f32,
+        crate_weight: t f32,
+        crate_color: t Color,
+        crate_layer: t Layer,
+
+        mount_mask: t BitflagU32,
+        mount_radius: t f32,
+        mount_velocity: t f32,
+        mount_time: t f32,
+        mount_weight: t f32,
+        mount_color: t Color,
+        mount_layer: t Layer,
+
+        interaction_mask: t BitflagU32,
+        interaction_range: t f32,
+        interaction_angle: t f32,
+    },
+    items: {
+        definitions: t VecItemDefinition,
+    },
+    stats: {
+        file: t VecStringPath,
+        achievements: t VecAchievementDefinition,
+        unlock_snd: t usize,
+        notification_time: t f32,
+        notification_position: t Array2F32,
+        secret_prefix: t String,
+    },
+    net: {
+        mode: e String [none,host,join,server,client],
+        address: t String,
+        port: t u16,
+        delay: t u32,
+        redundancy: t u32,
+        hash_period: t u32,
+        timeout: t f32,
+    },
+    replication: {
+        tick_rate: t f32,
+        interpolation_delay: t f32,
+        correction_distance: t f32,
+        history: t u32,
+        spawn_radius: t f32,
+        id_index_bits: t u32,
+        id_exhaustion: t IdExhaustion,
+    },
+    interpolation: {
+        buffer_size: t usize,
+        max_extrapolation: t f32,
+    },
+    quests: {
+        definitions: t VecQuestDefinition,
+        complete_snd: t usize,
     },
     levels: {
         hall_length: t usize,
@@ -159,14 +759,30 @@ configure!(
         dir: t VecStringPath,
         entry_music: t VecStringPath,
         check_level: e String [always,debug,never],
+        preload: t bool,
+        loading_screen: t bool,
+        loading_tips: t VecString,
+        loading_tip_time: t f32,
 
         empty_col: t Array3U8,
         char_col: t Array3U8,
         portal_col: t Array3U8,
         laser_col: t Array3U8,
         monster_col: t Array3U8,
+        elite_monster_col: t Array3U8,
         column_col: t Array3U8,
         wall_col: t Array3U8,
+        pickup_col: t Array3U8,
+        chest_col: t Array3U8,
+        vendor_col: t Array3U8,
+        crate_col: t Array3U8,
+        mount_col: t Array3U8,
+        checkpoint_col: t Array3U8,
+        liquid_col: t Array3U8,
+        lava_col: t Array3U8,
+        spikes_col: t Array3U8,
+        foliage_col: t Array3U8,
+        low_wall_col: t Array3U8,
     },
     audio: {
         effect_dir: t VecStringPath,
@@ -183,12 +799,16 @@ configure!(
         transition_time: t u64,
     },
     window: {
-        dimension: t Dimension,
-        vsync: t bool,
+        dimension: t Dimension save dimension,
+        resolutions: t VecDimension,
+        vsync: t bool save vsync,
         multisampling: t u16,
-        fullscreen: t bool,
+        mode: e String [windowed,fullscreen,borderless] save window_mode,
         fullscreen_on_primary_monitor: t bool,
         fullscreen_monitor: t usize,
+        ui_scale: t f32 save ui_scale,
+        letterbox: t bool save letterbox,
+        logical_resolution: t Dimension,
     },
     graphics: {
         base03: t Array4F32,
@@ -209,10 +829,22 @@ configure!(
         green: t Array4F32,
         mode: e String [light,dark] save mode,
         luminosity: t f32 save luminosity,
+        pixel_art: t bool save pixel_art,
+        pixel_art_resolution: t Dimension,
+        pixel_art_integer_scaling: t bool,
         circle_precision: t usize,
         font_file: t VecStringPath,
         billboard_font_scale: t f32,
         font_scale: t f32,
+        hud_position: t Array2F32,
+        prompt_position: t Array2F32,
+        dialogue_position: t Array2F32,
+        portrait_layer: t Layer,
+        journal_position: t Array2F32,
+        journal_interline: t f32,
+        inspector_position: t Array2F32,
+        shop_position: t Array2F32,
+        split_screen: t bool,
     },
     text: {
         top: t i32,
@@ -223,6 +855,239 @@ configure!(
     camera: {
         zoom: t f32,
     },
+    day_night: {
+        period: t f32,
+        night_ambient: t f32,
+        night_tint: t Array3F32,
+        threshold: t f32,
+    },
+    weather: {
+        states: t VecWeatherDefinition,
+        initial: t String,
+        transition_time: t f32,
+        particle_radius: t f32,
+        particle_length: t f32,
+        particle_persistance: t f32,
+        particle_thickness: t f32,
+    },
+    targeting: {
+        mask: t BitflagU32,
+        distance_weight: t f32,
+        visibility_weight: t f32,
+        threat_weight: t f32,
+        hostility_weight: t f32,
+        threat_per_hit: t f32,
+        threat_decay: t f32,
+        lookups_per_frame: t usize,
+        vision_radius: t f32,
+    },
+    projectiles: {
+        profiles: t VecMotionProfile,
+        column_profile: t String,
+    },
+    loot: {
+        tables: t VecLootTable,
+        monster_table: t String,
+        luck: t f32,
+        scatter: t f32,
+    },
+    combat: {
+        history_size: t usize,
+        float_persistance: t f32,
+        float_velocity: t f32,
+        float_scale: t f32,
+        float_color: t Color,
+        crit_color: t Color,
+        hit_flash: t bool,
+        hit_punch: t f32,
+        hit_punch_decay: t f32,
+        hit_outline: t f32,
+        hit_particles: t usize,
+        hit_particle_spread: t f32,
+        hit_particle_length: t Array2F32,
+        hit_particle_thickness: t f32,
+        hit_particle_persistance: t f32,
+        blood_color: t Color,
+        spark_color: t Color,
+    },
+    progression: {
+        levels: t VecF32,
+        level_modifiers: t VecModifier,
+        char_health: t f32,
+        char_damage: t f32,
+        monster_health: t f32,
+        monster_damage: t f32,
+        elite_monster_health: t f32,
+        elite_monster_damage: t f32,
+        character_xp: t f32,
+        monster_xp: t f32,
+        ball_xp: t f32,
+        level_up_snd: t usize,
+    },
+    shop: {
+        tables: t VecPriceTable,
+        vendor_table: t String,
+        start_money: t usize,
+        trade_snd: t usize,
+    },
+    abilities: {
+        pools: t VecPoolDefinition,
+        definitions: t VecAbilityDefinition,
+        statuses: t VecStatusDefinition,
+        column: t String,
+        player: t VecString,
+        monster: t VecString,
+        projectile_offset: t f32,
+        blast_mask: t BitflagU32,
+        blast_falloff: t f32,
+        hud_position: t Array2F32,
+        hud_size: t Array2F32,
+        hud_spacing: t f32,
+        hud_color: t Color,
+        hud_background_color: t Color,
+        hud_layer: t Layer,
+        melee_duration: t f32,
+        melee_mask: t BitflagU32,
+        rope_mask: t BitflagU32,
+    },
+    dash: {
+        speed: t f32,
+        damage: t f32,
+        stop_on_target: t bool,
+        target_mask: t BitflagU32,
+        status: t String,
+    },
+    movement: {
+        modes: t VecModeDefinition,
+    },
+    grab: {
+        mask: t BitflagU32,
+        range: t f32,
+        angle: t f32,
+        max_weight: t f32,
+        distance: t f32,
+        stiffness: t f32,
+        throw_impulse: t f32,
+        arc_steps: t usize,
+        arc_step: t f32,
+        arc_dot_size: t f32,
+        arc_color: t Color,
+        arc_layer: t Layer,
+    },
+    decals: {
+        capacity: t usize,
+        lifetime: t f32,
+        fade_time: t f32,
+        reach: t f32,
+        blood_color: t Color,
+        blood_radius: t f32,
+        scorch_color: t Color,
+        scorch_radius: t f32,
+        hole_color: t Color,
+        hole_radius: t f32,
+    },
+    mount: {
+        turn_rate: t f32,
+        grip: t f32,
+        dismount_radius: t f32,
+    },
+    hazards: {
+        mask: t BitflagU32,
+        nav_cost: t u8,
+        kinds: t VecHazardDefinition,
+    },
+    bosses: {
+        music: t VecStringPath,
+        bar_position: t Array2F32,
+        bar_size: t Array2F32,
+        bar_color: t Color,
+        bar_background_color: t Color,
+        bar_layer: t Layer,
+        add_interval: t f32,
+        definitions: t VecBossDefinition,
+    },
+    free_camera: {
+        speed: t f32,
+        screenshot_dir: t VecStringPath,
+    },
+    locale: {
+        dir: t VecStringPath,
+        languages: t VecString,
+        language: t String save language,
+    },
+    hot_reload: {
+        enabled: t bool,
+        period: t f32,
+    },
+    capture: {
+        enabled: t bool,
+        seconds: t f32,
+        fps: t f32,
+        scale: t usize,
+        dir: t VecStringPath,
+        on_achievement: t bool,
+    },
+    accessibility: {
+        colorblind: t bool save colorblind,
+        colorblind_palette: t VecArray4F32,
+        shake: t f32 save shake,
+        shake_amplitude: t f32,
+        shake_decay: t f32,
+        shake_damage: t f32,
+        shake_explosion: t f32,
+        game_speed: t f32 save game_speed,
+        hold_to_toggle: t bool save hold_to_toggle,
+    },
+    controls: {
+        dir: t VecStringPath,
+        profiles: t VecString,
+        profile: t String save profile,
+    },
+    shaders: {
+        dir: t VecStringPath,
+        materials: t VecString,
+        flash_material: t String,
+        flash_duration: t f32,
+        outline_material: t String,
+        outline_width: t f32,
+        post_passes: t VecString,
+        post_effects: t bool save post_effects,
+        luts_dir: t VecStringPath,
+        luts: t VecString,
+        region_luts: t VecArray2String,
+        lut_transition: t f32,
+    },
+    palettes: {
+        definitions: t VecPaletteDefinition,
+    },
+    highlight: {
+        interaction_color: t Color,
+        interaction_thickness: t f32,
+        selection_color: t Color,
+        selection_thickness: t f32,
+    },
+    render_lod: {
+        profiles: t VecRenderLodProfile,
+    },
+    quality: {
+        adaptive: t bool,
+        frame_budget: t f32,
+        smoothing: t f32,
+        degrade_time: t f32,
+        restore_time: t f32,
+        restore_ratio: t f32,
+        levels: t VecQualityLevel,
+    },
+    ambient: {
+        cull_distance: t f32,
+        max_particles: t usize,
+        prefabs: t VecAmbientPrefab,
+    },
+    factions: {
+        names: t VecString,
+        relations: t VecVecRelation,
+        friendly_fire: t bool,
+    },
     event_loop: {
         ups: t u64,
         max_fps: t u64,
@@ -12,6 +12,8 @@ configure!(
     file = "config.toml";
     debug_file = "config.toml";
     save_file= "save.toml";
+    save_version = 1;
+    save_migrations = &[];
 
     constraint = config_constraint;
 
@@ -5,6 +5,9 @@ extern crate toml;
 
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 #[macro_export]
 macro_rules! configure {
@@ -12,6 +15,8 @@ macro_rules! configure {
         file = $file:expr;
         debug_file = $debug_file:expr;
         save_file = $save_file:expr;
+        save_version = $save_version:expr;
+        save_migrations = $save_migrations:expr;
         constraint = $constraint:ident;
         $($table:ident: {
             $($key:ident: $(e $string:ident[$($variante:ident),*] $(save $enum_save_key:ident)*)* $(t $value:ident $(save $save_key:ident)*)*,)*
@@ -28,13 +33,10 @@ macro_rules! configure {
             $($($($($save_key: $value,)*)*)*)*
         });
         pub fn save(save: Save) -> std::io::Result<()> {
-            use std::fs::File;
-            use std::io::Write;
             use configuration::IntoToml;
             use std::path::Path;
 
-            let mut file = try!(File::create(Path::new($save_file)));
-            file.write_fmt(format_args!("{}",IntoToml::into_toml(save)))
+            configuration::write_atomic(Path::new($save_file), &*format!("{}",configuration::versioned(IntoToml::into_toml(save), $save_version)))
         }
         $(#[allow(non_camel_case_types)] pub struct $table {
             $(pub $key:
@@ -44,6 +46,8 @@ macro_rules! configure {
         })*
         pub struct Config {
             $(pub $table: $table,)*
+            /// the migration of the save file
+            pub save_report: configuration::SaveReport,
         }
 
         fn _get_table_from_file(file: &mut std::fs::File) -> Result<toml::Table,String> {
@@ -89,18 +93,38 @@ macro_rules! configure {
             }));
             let mut config_table = try!(_get_table_from_file(&mut config_file));
 
-            let save = if Path::new($save_file).is_file() {
-                let mut save_file = try!(File::open($save_file).map_err(|e| {
-                    format!("ERROR: an error occured when openning save file at {}{}{}",
-                            file,
+            // the saved values default to the ones of the configuration file
+            let mut defaults = toml::Table::new();
+            $($(
+                $($(
+                    if let Some(value) = configuration::lookup(&config_table, stringify!($table), stringify!($key)) {
+                        defaults.insert(stringify!($enum_save_key).into(), value);
+                    }
+                )*)*
+                $($(
+                    if let Some(value) = configuration::lookup(&config_table, stringify!($table), stringify!($key)) {
+                        defaults.insert(stringify!($save_key).into(), value);
+                    }
+                )*)*
+            )*)*
+            // a corrupted save file is replaced by its backup or the defaults
+            let loaded = configuration::read_recovering(Path::new($save_file), |path| {
+                let mut save_file = try!(File::open(path).map_err(|e| {
+                    format!("ERROR: an error occured when openning save file at {:?}{}{}",
+                            path,
                             format!("\n\tdescription: {}",e.description()),
                             if let Some(cause) = e.cause() { format!("\n\tcause: {}",cause.description()) } else { String::from("") })
                 }));
-                let save_table = try!(_get_table_from_file(&mut save_file));
-                Some(try!(Save::from_toml(&toml::Value::Table(save_table))
-                          .map_err(|e| format!("ERROR: save file invalid: {}",e))))
-            } else {
-                None
+                let mut save_table = try!(_get_table_from_file(&mut save_file));
+                let report = try!(configuration::migrate_save(&mut save_table, $save_version, $save_migrations, &defaults)
+                                  .map_err(|e| format!("ERROR: save file invalid:{}",e)));
+                let save = try!(Save::from_toml(&toml::Value::Table(save_table))
+                                .map_err(|e| format!("ERROR: save file invalid: {}",e)));
+                Ok((save, report))
+            });
+            let (save, save_report) = match loaded {
+                Some((save, report)) => (Some(save), report),
+                None => (None, configuration::SaveReport::default()),
             };
 
             let res = Config {
@@ -179,6 +203,7 @@ macro_rules! configure {
                         return Err(format!("ERROR: configuration file invalid: expect {} table",stringify!($table)));
                     }
                 },)*
+                save_report: save_report,
             };
             if !config_table.is_empty() {
                 let mut error = String::from("ERROR: configuration file invalid: unused keys:");
@@ -533,3 +558,195 @@ impl FromToml for VecStringPath {
     }
 }
 
+/// the path of the previous content of the file
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    PathBuf::from(name)
+}
+
+/// write the file through a temporary file renamed over it, so it is either the old
+/// or the new content whatever happens. the old content is kept as its backup
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        try!(fs::create_dir_all(dir));
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    {
+        let mut file = try!(fs::File::create(&temporary));
+        try!(file.write_all(contents.as_bytes()));
+        try!(file.sync_all());
+    }
+    if path.is_file() {
+        try!(fs::copy(path, backup_path(path)));
+    }
+    fs::rename(&temporary, path)
+}
+
+/// the file read by read, none if it doesn't exist. if read fails the file is moved
+/// aside to <file>.corrupt and its backup is read instead, none if it fails too
+pub fn read_recovering<T, F: Fn(&Path) -> Result<T,String>>(path: &Path, read: F) -> Option<T> {
+    if !path.is_file() {
+        return None;
+    }
+    let err = match read(path) {
+        Ok(value) => return Some(value),
+        Err(err) => err,
+    };
+    let mut corrupt = path.as_os_str().to_owned();
+    corrupt.push(".corrupt");
+    println!("WARNING: {:?} is corrupted and moved to {:?}: {}", path, corrupt, err);
+    if let Err(err) = fs::rename(path, &corrupt) {
+        println!("WARNING: move {:?} failed: {}", path, err);
+    }
+
+    let backup = backup_path(path);
+    if !backup.is_file() {
+        return None;
+    }
+    match read(&backup) {
+        Ok(value) => {
+            println!("WARNING: {:?} is recovered from its backup", path);
+            Some(value)
+        },
+        Err(err) => {
+            println!("WARNING: backup {:?} is corrupted too: {}", backup, err);
+            None
+        },
+    }
+}
+
+/// the key of the version of a save file
+pub const VERSION_KEY: &'static str = "version";
+
+/// a change of a save table from version to version + 1
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub migrate: fn(&mut toml::Table),
+}
+
+/// what happened to a save file when loaded
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct SaveReport {
+    /// the version of the file, 0 if it has none
+    pub version: u32,
+    /// the descriptions of the migrations applied
+    pub migrations: Vec<&'static str>,
+    /// the keys unknown to the current version
+    pub dropped: Vec<String>,
+    /// the keys missing in the file, set to their default
+    pub defaulted: Vec<String>,
+}
+impl SaveReport {
+    /// true if the file is loaded as it is
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty() && self.dropped.is_empty() && self.defaulted.is_empty()
+    }
+}
+
+/// apply the migrations from the version of the save table to version, then drop the
+/// keys not in defaults and set the missing ones to their default
+pub fn migrate_save(table: &mut toml::Table, version: u32, migrations: &[Migration], defaults: &toml::Table) -> Result<SaveReport,String> {
+    let mut report = SaveReport::default();
+    report.version = match table.remove(VERSION_KEY) {
+        Some(value) => try!(value.as_integer().ok_or(" expect integer version")) as u32,
+        None => 0,
+    };
+    if report.version > version {
+        return Err(format!(" version {} is newer than {}", report.version, version));
+    }
+    for v in report.version..version {
+        for migration in migrations.iter().filter(|m| m.version == v) {
+            (migration.migrate)(table);
+            report.migrations.push(migration.description);
+        }
+    }
+
+    report.dropped = table.keys().filter(|&key| !defaults.contains_key(key)).cloned().collect();
+    for key in &report.dropped {
+        table.remove(key);
+    }
+    for (key, value) in defaults {
+        if !table.contains_key(key) {
+            table.insert(key.clone(), value.clone());
+            report.defaulted.push(key.clone());
+        }
+    }
+    Ok(report)
+}
+
+/// the value of the key of the table of the configuration
+pub fn lookup(config: &toml::Table, table: &str, key: &str) -> Option<toml::Value> {
+    config.get(table)
+        .and_then(|table| table.as_table())
+        .and_then(|table| table.get(key))
+        .cloned()
+}
+
+/// the save table with its version
+pub fn versioned(value: toml::Value, version: u32) -> toml::Value {
+    match value {
+        toml::Value::Table(mut table) => {
+            table.insert(VERSION_KEY.into(), toml::Value::Integer(version as i64));
+            toml::Value::Table(table)
+        },
+        value => value,
+    }
+}
+
+#[test]
+fn test_migrate_save() {
+    fn rename(table: &mut toml::Table) {
+        if let Some(value) = table.remove("fullscreen") {
+            table.insert("mode".into(), value);
+        }
+    }
+    let migrations = [Migration { version: 0, description: "rename fullscreen", migrate: rename }];
+    let mut defaults = toml::Table::new();
+    defaults.insert("mode".into(), toml::Value::Boolean(false));
+    defaults.insert("volume".into(), toml::Value::Float(1.));
+
+    let mut table = toml::Table::new();
+    table.insert("fullscreen".into(), toml::Value::Boolean(true));
+    table.insert("unknown".into(), toml::Value::Integer(2));
+    let report = migrate_save(&mut table, 1, &migrations, &defaults).unwrap();
+    assert_eq!(report.migrations, vec!("rename fullscreen"));
+    assert_eq!(report.dropped, vec!(String::from("unknown")));
+    assert_eq!(report.defaulted, vec!(String::from("volume")));
+    assert_eq!(table.get("mode"), Some(&toml::Value::Boolean(true)));
+
+    let mut table = match versioned(toml::Value::Table(table), 1) {
+        toml::Value::Table(table) => table,
+        _ => unreachable!(),
+    };
+    assert!(migrate_save(&mut table, 1, &migrations, &defaults).unwrap().is_empty());
+    table.insert(VERSION_KEY.into(), toml::Value::Integer(2));
+    assert!(migrate_save(&mut table, 1, &migrations, &defaults).is_err());
+}
+
+#[test]
+fn test_write_read_recovering() {
+    use std::io::Read;
+
+    let dir = ::std::env::temp_dir().join("configuration_test_write_read_recovering");
+    let _ = fs::remove_dir_all(&dir);
+    let path = dir.join("save.toml");
+    let read = |path: &Path| {
+        let mut string = String::new();
+        try!(try!(fs::File::open(path).map_err(|e| format!("{}",e))).read_to_string(&mut string).map_err(|e| format!("{}",e)));
+        if string.starts_with("ok") { Ok(string) } else { Err(String::from("corrupted")) }
+    };
+    assert_eq!(read_recovering(&path, &read), None);
+
+    write_atomic(&path, "ok 1").unwrap();
+    write_atomic(&path, "ok 2").unwrap();
+    assert_eq!(read_recovering(&path, &read), Some(String::from("ok 2")));
+
+    fs::File::create(&path).unwrap().write_all(b"garbage").unwrap();
+    assert_eq!(read_recovering(&path, &read), Some(String::from("ok 1")));
+    assert!(!path.exists());
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -4,10 +4,14 @@ use resource::*;
 use specs::Join;
 use specs;
 use config;
-use rand;
+use rand::Rng;
 use rand::distributions::{IndependentSample, Range};
 use baal;
 use utils::Into3D;
+use faction::Relation;
+use targeting;
+use budget;
+use entity_ref::EntityRef;
 
 #[derive(Debug,Clone,Default)]
 pub struct PlayerControl;
@@ -43,7 +47,7 @@ impl specs::System<app::UpdateContext> for PlayerSystem {
                 if cooldown > 0. {
                     Some(cooldown - context.dt)
                 } else {
-                    context.control_tx.send(app::Control::ResetLevel).unwrap();
+                    context.control_tx.send(app::Control::Respawn).unwrap();
                     None
                 }
             } else {
@@ -92,16 +96,30 @@ impl specs::System<app::UpdateContext> for TowardPlayerSystem {
 
 pub struct MonsterControl {
     next_lookup: f32,
+    target: EntityRef,
 }
 impl specs::Component for MonsterControl {
     type Storage = specs::VecStorage<Self>;
 }
 impl MonsterControl {
-    pub fn new() -> Self {
-        let mut rng = rand::thread_rng();
+    /// time before the monster looks for a target
+    pub fn next_lookup(&self) -> f32 {
+        self.next_lookup
+    }
+    /// the hostile entity chosen at the last lookup
+    pub fn target(&self) -> Option<specs::Entity> {
+        self.target.get()
+    }
+    /// forget the target if it is the dead entity
+    pub fn invalidate(&mut self, dead: specs::Entity) {
+        self.target.invalidate(dead);
+    }
+    /// the first lookup is drawn from the world rng so lockstep peers agree on it
+    pub fn new<R: Rng>(rng: &mut R) -> Self {
         let range = Range::new(0.,config.entities.monster_vision_time);
         MonsterControl {
-            next_lookup: range.ind_sample(&mut rng),
+            next_lookup: range.ind_sample(rng),
+            target: EntityRef::default(),
         }
     }
 }
@@ -109,60 +127,106 @@ impl MonsterControl {
 pub struct MonsterSystem;
 impl specs::System<app::UpdateContext> for MonsterSystem {
     fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
-        let (mut monsters, players, mut forces, states, physic_world, entities) = arg.fetch(|world| {
+        let (mut monsters, mut casters, abilities, lives, factions, threats, mut forces, mut patrols, states, lods, physic_world, mut line_of_sight, weather, relations, nav_data, entities) = arg.fetch(|world| {
             (
                 world.write::<MonsterControl>(),
-                world.read::<PlayerControl>(),
+                world.write::<Caster>(),
+                world.read::<Abilities>(),
+                world.read::<Life>(),
+                world.read::<Faction>(),
+                world.read::<Threat>(),
                 world.write::<PhysicForce>(),
+                world.write::<Patrol>(),
                 world.read::<PhysicState>(),
+                world.read::<UpdateLod>(),
                 world.read_resource::<PhysicWorld>(),
+                world.write_resource::<LineOfSight>(),
+                world.read_resource::<Weather>(),
+                world.read_resource::<Relations>(),
+                world.read_resource::<NavData>(),
                 world.entities(),
             )
         });
 
-        let mut player_pos = None;
-        for (_, state) in (&players, &states).iter() {
-            player_pos = Some(state.position);
-            break;
-        }
+        let mut due = vec!();
+        for (mut monster, entity) in (&mut monsters, &entities).iter() {
+            if lods.get(entity).map(|lod| lod.dormant).unwrap_or(false) { continue }
+
+            let state = states.get(entity).expect("monster expect state component");
+            let force = forces.get_mut(entity).expect("monster expect force component");
+            let pos = state.position;
+
+            // patrolling monsters are steered by their patrol until they see their target,
+            // around the hazards on their way
+            let patrolling = patrols.get(entity).map(|patrol| patrol.active).unwrap_or(false);
+            let target = monster.target.check(|target| lives.get(target).map(|life| life.is_alive()).unwrap_or(false));
+            if let Some(target_state) = target.and_then(|target| states.get(target)) {
+                if !patrolling {
+                    let direction = (target_state.position[1] - pos[1]).atan2(target_state.position[0] - pos[0]);
+                    force.direction = nav_data.steer(pos, direction);
+                }
+            }
 
-        if let Some(player_pos) = player_pos {
-            for (mut monster, entity) in (&mut monsters, &entities).iter() {
-                let state = states.get(entity).expect("monster expect state component");
-                let force = forces.get_mut(entity).expect("monster expect force component");
+            monster.next_lookup -= context.dt;
 
-                let pos = state.position;
-                let angle = (player_pos[1] - pos[1]).atan2(player_pos[0] - pos[0]);
-
-                force.direction = angle;
-
-                monster.next_lookup -= context.dt;
-
-                if monster.next_lookup <= 0. {
-                    let length = ((player_pos[1] - pos[1]).powi(2) + (player_pos[0] - pos[0]).powi(2)).sqrt();
-                    let ray = Ray {
-                        origin: pos,
-                        angle: angle,
-                        length: length,
-                        mask: config.entities.monster_vision_mask.val,
-                    };
-
-                    let mut player_visible = false;
-                    physic_world.raycast(&ray, &mut |(other_entity,_,_)| {
-                        if players.get(other_entity).is_some() {
-                            player_visible = true;
-                        }
-                        true
-                    });
-                    if player_visible {
-                        force.intensity = 1.;
-                    } else {
-                        force.intensity = 0.;
-                    }
+            // the overdue lookups go first, then the ones of the monsters updated more often
+            if monster.next_lookup <= 0. {
+                let period = lods.get(entity).map(|lod| lod.period).unwrap_or(1);
+                due.push((entity, -monster.next_lookup / config.entities.monster_vision_time - (period - 1) as f32));
+            }
+        }
 
-                    monster.next_lookup = config.entities.monster_vision_time;
+        // the lookups are expensive, the ones over the budget wait for the next frames
+        for entity in budget::take(due, config.targeting.lookups_per_frame) {
+            let monster = monsters.get_mut(entity).expect("monster due expect monster component");
+            let pos = states.get(entity).expect("monster expect state component").position;
+            let force = forces.get_mut(entity).expect("monster expect force component");
+
+            // the weather range is unbounded in clear weather, the region queried is bounded
+            let range = weather.vision_range().min(config.targeting.vision_radius);
+            let targets = targeting::targets(entity, pos, factions.get(entity), range, config.entities.monster_vision_mask.val,
+                                             &physic_world, &mut line_of_sight, &relations, |other| {
+                if lives.get(other).is_none() {
+                    return None;
                 }
+                states.get(other).map(|other_state| targeting::Candidate {
+                    position: other_state.position,
+                    faction: factions.get(other),
+                    threat: threats.get(other).map(|threat| threat.value).unwrap_or(0.),
+                })
+            });
+            // no path leads to the targets in other regions, monsters outside the
+            // navigation data target anything
+            let outside = nav_data.region(pos) == 0;
+            let target = targets.into_iter().find(|target| {
+                target.relation == Relation::Hostile && (outside || nav_data.connected(pos, target.position))
+            });
+            monster.target = EntityRef::new(target.map(|target| target.entity));
+
+            let target_visible = target.map(|target| target.visible).unwrap_or(false);
+            if let Some(patrol) = patrols.get_mut(entity) {
+                patrol.active = !target_visible;
+            }
+            match target {
+                Some(target) if target.visible => {
+                    let direction = (target.position[1] - pos[1]).atan2(target.position[0] - pos[0]);
+                    force.direction = nav_data.steer(pos, direction);
+                    force.intensity = 1.;
+
+                    // use the first ready ability in range of the target
+                    let distance = ((target.position[0] - pos[0]).powi(2) + (target.position[1] - pos[1]).powi(2)).sqrt();
+                    if let (Some(caster), Some(abilities)) = (casters.get_mut(entity), abilities.get(entity)) {
+                        caster.requested = caster.slots().iter().position(|&ability| {
+                            distance <= config.abilities.definitions[ability].range && abilities.check(ability).is_ok()
+                        });
+                    }
+                },
+                _ => if patrols.get(entity).is_none() {
+                    force.intensity = 0.;
+                },
             }
+
+            monster.next_lookup = config.entities.monster_vision_time;
         }
     }
 }
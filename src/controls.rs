@@ -0,0 +1,251 @@
+//! the controls bind the keys to the actions of the players. the default keys are the
+//! ones of config.keys, the keys rebound in the controls screen of the menu are saved
+//! for the profile in controls.dir/<profile>.toml of the settings directory and
+//! override them.
+//!
+//! a key bound to an action is removed from the action it was bound to, which takes the
+//! keys of the rebound action if it has no key left so no action is unreachable. the
+//! debug keys are not rebindable and are reserved.
+
+use config;
+use configuration;
+use settings;
+use toml;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Escape,
+    Action,
+    /// the slot of the ability
+    Ability(usize),
+    Grab,
+    Throw,
+    Screenshot,
+    Capture,
+}
+
+impl Action {
+    /// the name in the profile files and the id of its label in the locale is
+    /// action_<name>
+    pub fn name(self) -> String {
+        match self {
+            Action::Up => "up".into(),
+            Action::Down => "down".into(),
+            Action::Left => "left".into(),
+            Action::Right => "right".into(),
+            Action::Escape => "escape".into(),
+            Action::Action => "action".into(),
+            Action::Ability(slot) => format!("ability_{}", slot + 1),
+            Action::Grab => "grab".into(),
+            Action::Throw => "throw".into(),
+            Action::Screenshot => "screenshot".into(),
+            Action::Capture => "capture".into(),
+        }
+    }
+
+    fn default_keys(self) -> Vec<u8> {
+        match self {
+            Action::Up => config.keys.up.clone(),
+            Action::Down => config.keys.down.clone(),
+            Action::Left => config.keys.left.clone(),
+            Action::Right => config.keys.right.clone(),
+            Action::Escape => config.keys.escape.clone(),
+            Action::Action => config.keys.action.clone(),
+            Action::Ability(slot) => vec!(config.keys.abilities[slot]),
+            Action::Grab => config.keys.grab.clone(),
+            Action::Throw => config.keys.throw.clone(),
+            Action::Screenshot => config.keys.screenshot.clone(),
+            Action::Capture => config.keys.capture.clone(),
+        }
+    }
+}
+
+/// the result of a binding
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Rebind {
+    Bound,
+    /// the key was taken from the action
+    Conflict(Action),
+    /// the key is a debug key
+    Reserved,
+}
+
+/// the keys that can't be bound
+fn reserved(key: u8) -> bool {
+    [&config.keys.inspector, &config.keys.inspector_next, &config.keys.inspector_field,
+     &config.keys.inspector_decrease, &config.keys.inspector_increase,
+     &config.keys.free_camera, &config.keys.free_camera_pause, &config.keys.free_camera_hud]
+        .iter().any(|keys| keys.contains(&key))
+}
+
+/// the name of the x11 keycode
+pub fn key_name(key: u8) -> String {
+    const ROWS: [(u8, &'static str); 4] = [(10, "1234567890"), (24, "qwertyuiop"), (38, "asdfghjkl"), (52, "zxcvbnm")];
+    for &(start, row) in &ROWS {
+        if key >= start && ((key - start) as usize) < row.len() {
+            return row[(key - start) as usize..].chars().next().unwrap().to_string();
+        }
+    }
+    match key {
+        9 => "escape".into(),
+        23 => "tab".into(),
+        36 => "return".into(),
+        37 => "ctrl".into(),
+        50 => "shift".into(),
+        65 => "space".into(),
+        67...76 => format!("f{}", key - 66),
+        95 => "f11".into(),
+        96 => "f12".into(),
+        111 => "up".into(),
+        113 => "left".into(),
+        114 => "right".into(),
+        116 => "down".into(),
+        _ => format!("key {}", key),
+    }
+}
+
+pub struct Controls {
+    profile: String,
+    bindings: Vec<(Action, Vec<u8>)>,
+}
+impl Controls {
+    /// the default keys
+    pub fn new(profile: &str) -> Self {
+        let mut actions = vec!(Action::Up, Action::Down, Action::Left, Action::Right, Action::Escape, Action::Action);
+        actions.extend((0..config.keys.abilities.len()).map(|slot| Action::Ability(slot)));
+        actions.extend_from_slice(&[Action::Grab, Action::Throw, Action::Screenshot, Action::Capture]);
+        Controls {
+            profile: profile.into(),
+            bindings: actions.into_iter().map(|action| (action, action.default_keys())).collect(),
+        }
+    }
+
+    fn path(profile: &str) -> PathBuf {
+        settings::file(&Path::new(&*config.controls.dir.val).join(profile).with_extension("toml"))
+    }
+
+    /// the keys of the profile, the default ones if it has never been saved or if it and
+    /// its backup are corrupted
+    pub fn load(profile: &str) -> Self {
+        let path = Controls::path(profile);
+        configuration::read_recovering(&path, |path| Controls::read(profile, path))
+            .unwrap_or(Controls::new(profile))
+    }
+
+    fn read(profile: &str, path: &Path) -> Result<Self,String> {
+        let mut controls = Controls::new(profile);
+        let mut string = String::new();
+        try!(try!(fs::File::open(&path).map_err(|e| format!("open controls {:?} failed: {}",path,e)))
+             .read_to_string(&mut string)
+             .map_err(|_| format!("controls {:?} invalid: not valid UTF-8",path)));
+        let mut parser = toml::Parser::new(&*string);
+        let table = try!(parser.parse().ok_or(format!("controls {:?} invalid: toml parsing failed",path)));
+
+        for &mut (action, ref mut keys) in &mut controls.bindings {
+            let value = match table.get(&*action.name()) {
+                Some(value) => value,
+                None => continue,
+            };
+            let array = try!(value.as_slice().ok_or(format!("controls {:?} invalid: {} expect array",path,action.name())));
+            let mut new_keys = vec!();
+            for key in array {
+                match key.as_integer() {
+                    Some(key) if key >= 0 && key <= u8::max_value() as i64 => new_keys.push(key as u8),
+                    _ => return Err(format!("controls {:?} invalid: {} expect keycodes",path,action.name())),
+                }
+            }
+            *keys = new_keys;
+        }
+        Ok(controls)
+    }
+
+    pub fn save(&self) -> Result<(),String> {
+        let path = Controls::path(&self.profile);
+        let mut string = String::new();
+        for &(action, ref keys) in &self.bindings {
+            let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+            string.push_str(&*format!("{} = [{}]\n", action.name(), keys.join(",")));
+        }
+        configuration::write_atomic(&path, &*string).map_err(|e| format!("write controls {:?} failed: {}",path,e))
+    }
+
+    pub fn profile(&self) -> &str {
+        &*self.profile
+    }
+
+    /// the actions and their keys in the order of the controls screen
+    pub fn bindings(&self) -> &[(Action, Vec<u8>)] {
+        &self.bindings
+    }
+
+    /// the action bound to the key
+    pub fn action(&self, key: u8) -> Option<Action> {
+        self.bindings.iter().find(|&&(_, ref keys)| keys.contains(&key)).map(|&(action, _)| action)
+    }
+
+    /// true if the key is bound to the action
+    pub fn is(&self, action: Action, key: u8) -> bool {
+        self.action(key) == Some(action)
+    }
+
+    /// bind the key to the action instead of its keys
+    pub fn bind(&mut self, action: Action, key: u8) -> Rebind {
+        if reserved(key) {
+            return Rebind::Reserved;
+        }
+        let previous = self.bindings.iter().find(|&&(a, _)| a == action).map(|&(_, ref keys)| keys.clone()).unwrap_or(vec!());
+        let conflict = self.action(key).and_then(|a| if a == action { None } else { Some(a) });
+        for &mut (a, ref mut keys) in &mut self.bindings {
+            if a == action {
+                *keys = vec!(key);
+            } else if Some(a) == conflict {
+                keys.retain(|&k| k != key);
+                if keys.is_empty() {
+                    *keys = previous.clone();
+                }
+            }
+        }
+        match conflict {
+            Some(conflict) => Rebind::Conflict(conflict),
+            None => Rebind::Bound,
+        }
+    }
+
+    /// the keys bound to several actions, by a profile file edited by hand
+    pub fn conflicts(&self) -> Vec<(u8, Action, Action)> {
+        let mut conflicts = vec!();
+        for (i, &(action, ref keys)) in self.bindings.iter().enumerate() {
+            for &(other, ref other_keys) in &self.bindings[i+1..] {
+                for &key in keys.iter().filter(|k| other_keys.contains(k)) {
+                    conflicts.push((key, action, other));
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+#[test]
+fn test_controls() {
+    let mut controls = Controls::new("test");
+    assert!(controls.conflicts().is_empty());
+    let up = config.keys.up[0];
+    let down = config.keys.down[0];
+    assert_eq!(controls.action(up), Some(Action::Up));
+
+    assert_eq!(controls.bind(Action::Up, down), Rebind::Conflict(Action::Down));
+    assert_eq!(controls.action(down), Some(Action::Up));
+    assert_eq!(controls.action(up), if config.keys.down.len() > 1 { None } else { Some(Action::Down) });
+    assert!(controls.conflicts().is_empty());
+
+    assert_eq!(controls.bind(Action::Up, config.keys.inspector[0]), Rebind::Reserved);
+    assert_eq!(key_name(24), "q");
+    assert_eq!(key_name(65), "space");
+}
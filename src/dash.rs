@@ -0,0 +1,131 @@
+//! dashes sweeping an entity along a direction over a distance.
+//!
+//! at each update the shape of the entity is cast over the distance travelled at
+//! dash.speed: the dash stops before the entities its body collides with and at the
+//! harmed entities of dash.target_mask if stop_on_target, otherwise it passes through
+//! them. targets are damaged once per dash by dash.damage times the damage of the
+//! dasher. the status dash.status is applied at the start for invulnerability frames.
+
+use app;
+use specs;
+use config;
+use components::*;
+use resource::*;
+use specs::Join;
+use combat::{Damage, DamageType};
+use progression;
+use ability;
+
+pub struct Dash {
+    pub speed: f32,
+    /// damage dealt to each target, 0 for none
+    pub damage: f32,
+    pub stop_on_target: bool,
+    direction: f32,
+    /// the distance left to travel
+    remaining: f32,
+    started: bool,
+    /// the entities damaged by the current dash
+    pub hit: Vec<specs::Entity>,
+}
+impl specs::Component for Dash {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Dash {
+    /// with the parameters of dash in the configuration
+    pub fn new() -> Self {
+        Dash {
+            speed: config.dash.speed,
+            damage: config.dash.damage,
+            stop_on_target: config.dash.stop_on_target,
+            direction: 0.,
+            remaining: 0.,
+            started: false,
+            hit: vec!(),
+        }
+    }
+
+    /// dash over distance along direction, a dash in progress is replaced
+    pub fn start(&mut self, direction: f32, distance: f32) {
+        self.direction = direction;
+        self.remaining = distance;
+        self.started = true;
+        self.hit.clear();
+    }
+
+    pub fn is_dashing(&self) -> bool {
+        self.remaining > 0.
+    }
+}
+
+pub struct DashSystem;
+impl specs::System<app::UpdateContext> for DashSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut dashes, mut states, mut lives, mut statuses, types, factions, attributes, physic_world, relations, entities) = arg.fetch(|world| {
+            (
+                world.write::<Dash>(),
+                world.write::<PhysicState>(),
+                world.write::<Life>(),
+                world.write::<Statuses>(),
+                world.read::<PhysicType>(),
+                world.read::<Faction>(),
+                world.read::<Attributes>(),
+                world.read_resource::<PhysicWorld>(),
+                world.read_resource::<Relations>(),
+                world.entities(),
+            )
+        });
+
+        for (dash, typ, entity) in (&mut dashes, &types, &entities).iter() {
+            if dash.started {
+                dash.started = false;
+                if let (Some(status), Some(statuses)) = (ability::status(&config.dash.status), statuses.get_mut(entity)) {
+                    statuses.apply(status);
+                }
+                context.event_tx.send(app::GameEvent::DashStarted(entity)).unwrap();
+            }
+            if dash.remaining <= 0. {
+                continue;
+            }
+            let position = match states.get(entity) {
+                Some(state) => state.position,
+                None => continue,
+            };
+
+            let step = (dash.speed*context.dt).min(dash.remaining);
+            let amount = dash.damage*progression::damage(attributes.get(entity));
+            let mask = typ.mask | config.dash.target_mask.val;
+            let mut travel = step;
+            let mut stopped = false;
+            for (other, distance) in physic_world.shapecast(&[entity], &position, dash.direction, step, &typ.shape, mask) {
+                let group = types.get(other).map(|t| t.group).unwrap_or(0);
+                let target = group & config.dash.target_mask.val != 0
+                    && lives.get(other).map(|life| life.is_alive()).unwrap_or(false)
+                    && relations.harms(factions.get(entity), factions.get(other));
+
+                if target && amount > 0. && !dash.hit.contains(&other) {
+                    dash.hit.push(other);
+                    lives.get_mut(other).unwrap().damage(amount, entity);
+                    let other_position = states.get(other).map(|s| s.position).unwrap_or(position);
+                    context.event_tx.send(app::GameEvent::Damaged(Damage::new(entity, other, other_position, amount, DamageType::Dash))).unwrap();
+                }
+                if (target && dash.stop_on_target) || (!target && group & typ.mask != 0) {
+                    travel = distance;
+                    stopped = true;
+                    break;
+                }
+            }
+
+            let state = states.get_mut(entity).unwrap();
+            state.position[0] += travel*dash.direction.cos();
+            state.position[1] += travel*dash.direction.sin();
+            state.velocity = [0., 0.];
+
+            dash.remaining = if stopped { 0. } else { dash.remaining - step };
+            if dash.remaining <= 0. {
+                dash.remaining = 0.;
+                context.event_tx.send(app::GameEvent::DashEnded(entity)).unwrap();
+            }
+        }
+    }
+}
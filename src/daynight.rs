@@ -0,0 +1,92 @@
+//! ambient light of the world animated over the game time.
+//!
+//! the ambient is 1 at noon and day_night.night_ambient at midnight, the colors are
+//! tinted toward day_night.night_tint as it darkens. Dawn and Dusk events are sent
+//! when the ambient crosses day_night.threshold so gameplay can react.
+
+use app;
+use specs;
+use config;
+use math;
+use std::f32::consts::PI;
+
+pub struct DayNight {
+    /// time since midnight
+    time: f32,
+    day: bool,
+}
+
+impl DayNight {
+    /// start at noon
+    pub fn new() -> Self {
+        DayNight {
+            time: config.day_night.period/2.,
+            day: true,
+        }
+    }
+
+    /// part of the day from 0 at midnight to 1 at noon
+    fn daylight(&self) -> f32 {
+        0.5 - 0.5*math::cos(2.*PI*self.time/config.day_night.period)
+    }
+
+    pub fn ambient(&self) -> f32 {
+        let night = config.day_night.night_ambient;
+        night + (1. - night)*self.daylight()
+    }
+
+    /// the factor of each channel of the colors
+    pub fn tint(&self) -> [f32;3] {
+        let ambient = self.ambient();
+        let daylight = self.daylight();
+        let night_tint = config.day_night.night_tint;
+        [
+            ambient*(night_tint[0] + (1. - night_tint[0])*daylight),
+            ambient*(night_tint[1] + (1. - night_tint[1])*daylight),
+            ambient*(night_tint[2] + (1. - night_tint[2])*daylight),
+        ]
+    }
+
+    /// advance the time, return whether it is day if it just changed
+    fn advance(&mut self, dt: f32) -> Option<bool> {
+        self.time = (self.time + dt) % config.day_night.period;
+        let day = self.ambient() >= config.day_night.threshold;
+        if day != self.day {
+            self.day = day;
+            Some(day)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct DayNightSystem;
+impl specs::System<app::UpdateContext> for DayNightSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let mut day_night = arg.fetch(|world| world.write_resource::<DayNight>());
+
+        match day_night.advance(context.dt) {
+            Some(true) => context.event_tx.send(app::GameEvent::Dawn).unwrap(),
+            Some(false) => context.event_tx.send(app::GameEvent::Dusk).unwrap(),
+            None => (),
+        }
+    }
+}
+
+#[test]
+fn test_day_night_cycle() {
+    let mut day_night = DayNight::new();
+    assert!((day_night.ambient() - 1.).abs() < 1e-6);
+    assert!(day_night.tint().iter().all(|c| (c - 1.).abs() < 1e-6));
+
+    let dt = config.day_night.period/100.;
+    let mut changes = vec!();
+    for _ in 0..100 {
+        if let Some(day) = day_night.advance(dt) {
+            changes.push(day);
+        }
+    }
+    // a full period from noon: dusk then dawn
+    assert_eq!(changes, vec!(false, true));
+    assert!(day_night.day);
+}
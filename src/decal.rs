@@ -0,0 +1,172 @@
+//! decals are persistent marks stamped where hits land: blood of the damaged
+//! entities, scorch of explosions and holes of the balls in the walls.
+//!
+//! a decal aimed at a direction is stamped on the first wall face within decals.reach,
+//! moved inside the wall along the normal of the face, or on the floor if no wall is
+//! hit. at most decals.capacity decals are kept, the oldest is dropped first, and
+//! each shrinks during the last decals.fade_time of its decals.lifetime.
+
+use specs;
+use config;
+use graphics;
+use components::*;
+use resource::PhysicWorld;
+use std::collections::VecDeque;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum DecalKind {
+    Blood,
+    Scorch,
+    Hole,
+}
+impl DecalKind {
+    fn color(&self) -> graphics::Color {
+        match *self {
+            DecalKind::Blood => config.decals.blood_color,
+            DecalKind::Scorch => config.decals.scorch_color,
+            DecalKind::Hole => config.decals.hole_color,
+        }
+    }
+    fn radius(&self) -> f32 {
+        match *self {
+            DecalKind::Blood => config.decals.blood_radius,
+            DecalKind::Scorch => config.decals.scorch_radius,
+            DecalKind::Hole => config.decals.hole_radius,
+        }
+    }
+}
+
+struct Decal {
+    kind: DecalKind,
+    position: [f32;2],
+    layer: graphics::Layer,
+    age: f32,
+}
+
+/// the normal of the face of the square centered on center the point is on
+fn square_normal(center: [f32;2], point: [f32;2]) -> [f32;2] {
+    let dx = point[0] - center[0];
+    let dy = point[1] - center[1];
+    if dx.abs() > dy.abs() {
+        [dx.signum(), 0.]
+    } else {
+        [0., dy.signum()]
+    }
+}
+
+/// the scale of the radius of a decal of age
+fn fade(age: f32) -> f32 {
+    let left = config.decals.lifetime - age;
+    if left >= config.decals.fade_time || config.decals.fade_time <= 0. {
+        1.
+    } else {
+        (left/config.decals.fade_time).max(0.)
+    }
+}
+
+pub struct Decals {
+    decals: VecDeque<Decal>,
+}
+impl Decals {
+    pub fn new() -> Self {
+        Decals {
+            decals: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.decals.len()
+    }
+
+    /// stamp a decal on the floor at position
+    pub fn stamp(&mut self, kind: DecalKind, position: [f32;2]) {
+        self.push(kind, position, graphics::Layer::Floor);
+    }
+
+    /// stamp a decal on the nearest wall face hit from origin along one of angles,
+    /// on the floor at origin if none is within decals.reach
+    pub fn stamp_on_surface(&mut self, world: &specs::World, kind: DecalKind, origin: [f32;2], angles: &[f32]) {
+        let physic_world = world.read_resource::<PhysicWorld>();
+        let states = world.read::<PhysicState>();
+        let options = RaycastOptions { max_hits: Some(1), static_only: true, .. RaycastOptions::default() };
+
+        let mut nearest: Option<([f32;2],[f32;2],f32)> = None;
+        for &angle in angles {
+            let ray = Ray { origin: origin, angle: angle, length: config.decals.reach, mask: config.entities.wall_group.val };
+            physic_world.raycast_with(&ray, &options, &mut |(entity,min,_)| {
+                let min = min.max(0.);
+                if nearest.map(|(_,_,d)| min < d).unwrap_or(true) {
+                    if let Some(state) = states.get(entity) {
+                        let point = [origin[0] + min*angle.cos(), origin[1] + min*angle.sin()];
+                        nearest = Some((point, square_normal(state.position, point), min));
+                    }
+                }
+                false
+            });
+        }
+
+        match nearest {
+            Some((point, normal, _)) => {
+                let radius = kind.radius();
+                let position = [point[0] - normal[0]*radius, point[1] - normal[1]*radius];
+                self.push(kind, position, config.entities.wall_layer);
+            },
+            None => self.stamp(kind, origin),
+        }
+    }
+
+    fn push(&mut self, kind: DecalKind, position: [f32;2], layer: graphics::Layer) {
+        if config.decals.capacity == 0 {
+            return;
+        }
+        while self.decals.len() >= config.decals.capacity {
+            self.decals.pop_front();
+        }
+        self.decals.push_back(Decal {
+            kind: kind,
+            position: position,
+            layer: layer,
+            age: 0.,
+        });
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for decal in &mut self.decals {
+            decal.age += dt;
+        }
+        // decals are ordered by age
+        while self.decals.front().map(|d| d.age >= config.decals.lifetime).unwrap_or(false) {
+            self.decals.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.decals.clear();
+    }
+
+    pub fn draw(&self, frame: &mut graphics::Frame) {
+        for decal in &self.decals {
+            let radius = decal.kind.radius()*fade(decal.age);
+            let p = decal.position;
+            if frame.is_visible(p[0],p[1],radius) {
+                frame.draw_circle(p[0],p[1],radius,decal.layer,decal.kind.color());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decals() {
+    assert_eq!(square_normal([0.,0.], [0.5,0.2]), [1.,0.]);
+    assert_eq!(square_normal([0.,0.], [-0.1,-0.5]), [0.,-1.]);
+
+    let mut decals = Decals::new();
+    for i in 0..config.decals.capacity + 3 {
+        decals.stamp(DecalKind::Blood, [i as f32, 0.]);
+    }
+    assert_eq!(decals.decals.len(), config.decals.capacity);
+    assert_eq!(fade(0.), 1.);
+
+    decals.update(config.decals.lifetime);
+    assert!(decals.decals.is_empty());
+}
@@ -0,0 +1,165 @@
+//! the display settings of the window, saved in save.toml and changed in the display
+//! menu: the window mode, the resolution of the window, vsync, the scale of the texts
+//! of the ui and the letterboxing to window.logical_resolution.
+//!
+//! the graphics apply the scale and the letterboxing at once. the mode, the resolution
+//! and vsync need the window to be rebuilt: the main loop takes the builder, rebuilds
+//! the window and reports the new size with a display event.
+
+use config;
+use glium::glutin;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum WindowMode {
+    Windowed,
+    Fullscreen,
+    /// a window without decorations of the size of the monitor
+    Borderless,
+}
+
+impl WindowMode {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "windowed" => WindowMode::Windowed,
+            "fullscreen" => WindowMode::Fullscreen,
+            "borderless" => WindowMode::Borderless,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WindowMode::Windowed => "windowed",
+            WindowMode::Fullscreen => "fullscreen",
+            WindowMode::Borderless => "borderless",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            WindowMode::Windowed => WindowMode::Fullscreen,
+            WindowMode::Fullscreen => WindowMode::Borderless,
+            WindowMode::Borderless => WindowMode::Windowed,
+        }
+    }
+}
+
+pub enum DisplayEvent {
+    /// the window has been rebuilt with the settings
+    Rebuilt,
+    /// the window couldn't be rebuilt
+    Failed(String),
+    /// the size of the framebuffer
+    Resized(u32,u32),
+}
+
+pub struct Display {
+    pub mode: WindowMode,
+    /// the size of the window when windowed
+    pub resolution: [u32;2],
+    pub vsync: bool,
+    pub ui_scale: f32,
+    pub letterbox: bool,
+    rebuild: bool,
+}
+
+impl Display {
+    pub fn new() -> Self {
+        Display {
+            mode: WindowMode::from_name(&*config.window.mode),
+            resolution: config.window.dimension,
+            vsync: config.window.vsync,
+            ui_scale: config.window.ui_scale,
+            letterbox: config.window.letterbox,
+            rebuild: false,
+        }
+    }
+
+    /// the logical resolution if letterboxed
+    pub fn letterbox(&self) -> Option<[u32;2]> {
+        if self.letterbox {
+            Some(config.window.logical_resolution)
+        } else {
+            None
+        }
+    }
+
+    pub fn cycle_mode(&mut self) {
+        self.mode = self.mode.next();
+        self.rebuild = true;
+    }
+
+    /// switch to the next resolution of window.resolutions or the previous one if offset
+    /// is negative
+    pub fn cycle_resolution(&mut self, offset: isize) {
+        let resolutions = &config.window.resolutions;
+        let len = resolutions.len() as isize;
+        let index = match resolutions.iter().position(|r| *r == self.resolution) {
+            Some(index) => ((index as isize + offset)%len + len) as usize % len as usize,
+            None => 0,
+        };
+        self.resolution = resolutions[index];
+        if self.mode == WindowMode::Windowed {
+            self.rebuild = true;
+        }
+    }
+
+    pub fn toggle_vsync(&mut self) {
+        self.vsync = !self.vsync;
+        self.rebuild = true;
+    }
+
+    /// the builder of the window if it must be rebuilt
+    pub fn take_rebuild(&mut self) -> Option<Result<glutin::WindowBuilder<'static>,String>> {
+        if self.rebuild {
+            self.rebuild = false;
+            Some(self.window_builder())
+        } else {
+            None
+        }
+    }
+
+    pub fn window_builder(&self) -> Result<glutin::WindowBuilder<'static>,String> {
+        let mut builder = glutin::WindowBuilder::new()
+            .with_title(format!("ruga"));
+
+        if self.vsync {
+            builder = builder.with_vsync();
+        }
+        if config.window.multisampling != 0 {
+            builder = builder.with_multisampling(config.window.multisampling);
+        }
+        let monitor = || if config.window.fullscreen_on_primary_monitor {
+            Ok(glutin::get_primary_monitor())
+        } else {
+            glutin::get_available_monitors().nth(config.window.fullscreen_monitor)
+                .ok_or(String::from("fullsceen monitor specified unavailable"))
+        };
+        Ok(match self.mode {
+            WindowMode::Windowed => builder.with_dimensions(self.resolution[0], self.resolution[1]),
+            WindowMode::Fullscreen => builder.with_fullscreen(try!(monitor())),
+            WindowMode::Borderless => {
+                let (width, height) = try!(monitor()).get_dimensions();
+                builder.with_decorations(false).with_dimensions(width, height)
+            },
+        })
+    }
+}
+
+#[test]
+fn test_display() {
+    let mut display = Display::new();
+    display.mode = WindowMode::Windowed;
+    display.resolution = config.window.resolutions[0];
+    display.cycle_resolution(-1);
+    assert_eq!(display.resolution, *config.window.resolutions.last().unwrap());
+    assert!(display.rebuild);
+
+    display.rebuild = false;
+    display.mode = WindowMode::Borderless;
+    display.cycle_resolution(1);
+    assert!(!display.rebuild);
+    display.cycle_mode();
+    assert_eq!(display.mode, WindowMode::Windowed);
+    assert!(display.rebuild);
+}
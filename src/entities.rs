@@ -2,9 +2,43 @@ use components::*;
 use specs;
 use config;
 use levels;
+use app;
+use inventory::Stack;
+use checkpoint::PlayerSnapshot;
+use life::Kind;
+use resource::WorldRng;
+use physic::IntoGrid;
 use std::sync::Arc;
+use projectile;
 
 pub fn add_character(world: &mut specs::World, pos: [isize;2]) {
+    add_player(world, pos, Inventory::new(config.entities.char_inventory_size), 0., config.shop.start_money, "");
+    world.create_now()
+        .with::<GridSquare>(GridSquare::new(pos))
+        .with::<Graphic>(Graphic::new(
+                config.entities.portal_end_color,
+                config.entities.portal_end_layer))
+        .build();
+}
+
+pub fn respawn_character(world: &mut specs::World, snapshot: &PlayerSnapshot) -> specs::Entity {
+    let entity = add_player(world, snapshot.position, snapshot.inventory.clone(), snapshot.xp, snapshot.money, "");
+    if let Some(life) = world.write::<Life>().get_mut(entity) {
+        // the maximum health is applied by the attributes keeping the ratio
+        life.set_health(snapshot.health);
+        life.set_respawns(true);
+    }
+    entity
+}
+
+/// the character of the second peer in lockstep, in the team colors
+pub fn add_peer_character(world: &mut specs::World, pos: [f32;2]) -> specs::Entity {
+    add_player(world, pos, Inventory::new(config.entities.char_inventory_size), 0., config.shop.start_money, &*config.entities.peer_palette)
+}
+
+fn add_player<T: IntoGrid>(world: &mut specs::World, pos: T, inventory: Inventory, xp: f32, money: usize, palette: &str) -> specs::Entity {
+    let mut experience = Experience::new();
+    experience.gain(xp);
     world.create_now()
         .with::<PhysicState>(PhysicState::new(pos))
         .with::<PhysicDynamic>(PhysicDynamic)
@@ -17,41 +51,90 @@ pub fn add_character(world: &mut specs::World, pos: [isize;2]) {
                 config.entities.char_time,
                 config.entities.char_weight))
         .with::<PhysicForce>(PhysicForce::new())
-        .with::<Life>(Life::new(config.entities.char_die_snd))
+        .with::<Life>(Life::new(Kind::Character,config.entities.char_die_snd))
         .with::<Graphic>(Graphic::new(
                 config.entities.char_color,
-                config.entities.char_layer))
+                config.entities.char_layer)
+            .with_palette(palette))
         .with::<PlayerControl>(PlayerControl)
-        .build();
+        .with::<Inventory>(inventory)
+        .with::<Interactor>(Interactor::new())
+        .with::<RegionTracker>(RegionTracker::new())
+        .with::<Faction>(Faction::named("player"))
+        .with::<Threat>(Threat::new())
+        .with::<Attributes>(Attributes::new(config.progression.char_health, config.progression.char_damage))
+        .with::<Experience>(experience)
+        .with::<Wallet>(Wallet::new(money))
+        .with::<Abilities>(Abilities::new())
+        .with::<Caster>(Caster::new(&config.abilities.player))
+        .with::<Statuses>(Statuses::new())
+        .with::<Dash>(Dash::new())
+        .with::<Movement>(Movement::new())
+        .with::<Grabber>(Grabber::new())
+        .with::<Replicated>(Replicated)
+        .build()
+}
+
+/// an entity only rendered at the position sent by the server
+pub fn add_puppet(world: &mut specs::World, kind: Kind, pos: [f32;2]) -> specs::Entity {
+    let (radius, color, layer) = match kind {
+        Kind::Character => (config.entities.char_radius, config.entities.char_color, config.entities.char_layer),
+        Kind::Monster => (config.entities.monster_radius, config.entities.monster_color, config.entities.monster_layer),
+        Kind::Ball => (config.entities.ball_radius, config.entities.ball_color, config.entities.ball_layer),
+    };
     world.create_now()
-        .with::<GridSquare>(GridSquare::new(pos))
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicType>(PhysicType::new_static(0, 0, Shape::Circle(radius)))
+        .with::<Graphic>(Graphic::new(color, layer))
+        .with::<Replicated>(Replicated)
+        .with::<Interpolated>(Interpolated::new())
+        .build()
+}
+
+pub fn add_wall(world: &mut specs::World, pos: [isize;2]) {
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(
+                config.entities.wall_group.val | config.entities.occluder_group.val,
+                config.entities.wall_mask.val,
+                Shape::Square(config.entities.wall_radius)))
         .with::<Graphic>(Graphic::new(
-                config.entities.portal_end_color,
-                config.entities.portal_end_layer))
+                config.entities.wall_color,
+                config.entities.wall_layer))
+        .with::<Autotile>(Autotile::new())
         .build();
 }
 
-pub fn add_wall(world: &mut specs::World, pos: [isize;2]) {
+/// a wall of the gates of an arena, removed when the encounter ends
+pub fn add_gate(world: &mut specs::World, pos: [isize;2]) {
     world.create_now()
+        .with::<ArenaGate>(ArenaGate)
         .with::<PhysicState>(PhysicState::new(pos))
         .with::<PhysicStatic>(PhysicStatic)
         .with::<PhysicType>(PhysicType::new_static(
-                config.entities.wall_group.val,
+                config.entities.wall_group.val | config.entities.occluder_group.val,
                 config.entities.wall_mask.val,
                 Shape::Square(config.entities.wall_radius)))
         .with::<Graphic>(Graphic::new(
                 config.entities.wall_color,
                 config.entities.wall_layer))
+        .with::<Autotile>(Autotile::new())
         .build();
 }
 
 pub fn add_column(world: &mut specs::World, pos: [isize;2]) {
+    let column = Column::new(config.entities.column_spawn_snd);
+    let mut abilities = Abilities::new();
+    abilities.start_cooldown(column.ability());
+
     world.create_now()
-        .with::<Column>(Column::new(config.entities.column_spawn_snd))
+        .with::<Column>(column)
+        .with::<Abilities>(abilities)
         .with::<PhysicState>(PhysicState::new(pos))
         .with::<PhysicStatic>(PhysicStatic)
         .with::<PhysicType>(PhysicType::new_static(
-                config.entities.column_group.val,
+                config.entities.column_group.val | config.entities.occluder_group.val,
                 config.entities.column_mask.val,
                 Shape::Square(config.entities.column_radius)))
         .with::<Graphic>(Graphic::new(
@@ -60,7 +143,51 @@ pub fn add_column(world: &mut specs::World, pos: [isize;2]) {
         .build();
 }
 
+/// a ball fired by an ability of the caster, it belongs to the faction of the caster
+pub fn add_projectile(world: &mut specs::World, caster: specs::Entity, pos: [f32;2], heading: f32, profile: usize) {
+    let faction = world.read::<Faction>().get(caster).map(|faction| faction.id);
+
+    let entity = world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<Ball>(Ball::new(Arc::new(())))
+        .with::<PhysicDynamic>(PhysicDynamic)
+        .with::<PhysicType>(PhysicType::new_movable(
+                config.entities.ball_group.val,
+                config.entities.ball_mask.val,
+                Shape::Circle(config.entities.ball_radius),
+                CollisionBehavior::Persist,
+                config.entities.ball_velocity,
+                config.entities.ball_time,
+                config.entities.ball_weight))
+        .with::<PhysicForce>(PhysicForce::new_full())
+        .with::<PhysicTrigger>(PhysicTrigger::new())
+        .with::<Life>(Life::new(Kind::Ball,config.entities.ball_die_snd))
+        .with::<Graphic>(Graphic::new(
+                config.entities.ball_color,
+                config.entities.ball_layer)
+            .with_lod(&*config.entities.ball_lod))
+        .with::<Motion>(Motion::new(profile, heading, None))
+        .with::<Killer>(Killer {
+            kamikaze: false,
+            mask: config.entities.ball_killer_mask.val,
+            kill_snd: config.entities.ball_kill_snd,
+        })
+        .with::<Replicated>(Replicated)
+        .build();
+    if let Some(id) = faction {
+        world.write::<Faction>().insert(entity, Faction { id: id });
+    }
+}
+
 pub fn add_ball(world: &mut specs::World, pos: [f32;2], arc: Arc<()>) {
+    use specs::Join;
+
+    // balls are fired at the player
+    let target = (&world.read::<PlayerControl>(), &world.read::<PhysicState>(), &world.entities()).iter()
+        .next()
+        .map(|(_, state, entity)| (entity, (state.position[1] - pos[1]).atan2(state.position[0] - pos[0])));
+    let profile = projectile::profile(&config.projectiles.column_profile).expect("column profile must be a projectile profile");
+
     world.create_now()
         .with::<PhysicState>(PhysicState::new(pos))
         .with::<Ball>(Ball::new(arc))
@@ -75,20 +202,32 @@ pub fn add_ball(world: &mut specs::World, pos: [f32;2], arc: Arc<()>) {
                 config.entities.ball_weight))
         .with::<PhysicForce>(PhysicForce::new_full())
         .with::<PhysicTrigger>(PhysicTrigger::new())
-        .with::<Life>(Life::new(config.entities.ball_die_snd))
+        .with::<Life>(Life::new(Kind::Ball,config.entities.ball_die_snd))
         .with::<Graphic>(Graphic::new(
                 config.entities.ball_color,
-                config.entities.ball_layer))
-        .with::<TowardPlayerControl>(TowardPlayerControl)
+                config.entities.ball_layer)
+            .with_lod(&*config.entities.ball_lod))
+        .with::<Motion>(Motion::new(profile, target.map(|t| t.1).unwrap_or(0.), target.map(|t| t.0)))
         .with::<Killer>(Killer {
             kamikaze: false,
             mask: config.entities.ball_killer_mask.val,
             kill_snd: config.entities.ball_kill_snd,
         })
+        .with::<Replicated>(Replicated)
         .build();
 }
 
 pub fn add_monster(world: &mut specs::World, pos: [isize;2]) {
+    build_monster(world, pos, config.progression.monster_health, config.progression.monster_damage, "");
+}
+
+/// a monster with more health and damage drawn with entities.elite_monster_palette
+pub fn add_elite_monster(world: &mut specs::World, pos: [isize;2]) {
+    build_monster(world, pos, config.progression.elite_monster_health, config.progression.elite_monster_damage, &*config.entities.elite_monster_palette);
+}
+
+fn build_monster(world: &mut specs::World, pos: [isize;2], health: f32, damage: f32, palette: &str) {
+    let control = MonsterControl::new(&mut *world.write_resource::<WorldRng>());
     world.create_now()
         .with::<PhysicState>(PhysicState::new(pos))
         .with::<PhysicDynamic>(PhysicDynamic)
@@ -101,21 +240,77 @@ pub fn add_monster(world: &mut specs::World, pos: [isize;2]) {
                 config.entities.monster_time,
                 config.entities.monster_weight))
         .with::<PhysicForce>(PhysicForce::new())
-        .with::<Life>(Life::new(config.entities.monster_die_snd))
+        .with::<Life>(Life::new(Kind::Monster,config.entities.monster_die_snd))
         .with::<Graphic>(Graphic::new(
                 config.entities.monster_color,
-                config.entities.monster_layer))
-        .with::<MonsterControl>(MonsterControl::new())
+                config.entities.monster_layer)
+            .with_palette(palette)
+            .with_lod(&*config.entities.monster_lod))
+        .with::<MonsterControl>(control)
+        .with::<UpdateLod>(UpdateLod::new())
         .with::<Killer>(Killer {
             kamikaze: true,
             mask: config.entities.monster_killer_mask.val,
             kill_snd: config.entities.monster_kill_snd,
         })
+        .with::<Faction>(Faction::named("monster"))
+        .with::<Threat>(Threat::new())
+        .with::<Loot>(Loot::named(&config.loot.monster_table))
+        .with::<Attributes>(Attributes::new(health, damage))
+        .with::<Abilities>(Abilities::new())
+        .with::<Caster>(Caster::new(&config.abilities.monster))
+        .with::<Statuses>(Statuses::new())
+        .with::<Movement>(Movement::new())
         .with::<DynPersistentSnd>(DynPersistentSnd::new(
                 config.entities.monster_persistent_snd))
+        .with::<Replicated>(Replicated)
         .build();
 }
 
+/// the boss of definition in bosses.definitions, controlled by its arena
+pub fn add_boss(world: &mut specs::World, pos: [isize;2], definition: usize) -> specs::Entity {
+    let boss = &config.bosses.definitions[definition];
+    let mut abilities: Vec<String> = vec!();
+    for phase in &boss.phases {
+        if !phase.ability.is_empty() && !abilities.contains(&phase.ability) {
+            abilities.push(phase.ability.clone());
+        }
+    }
+
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicDynamic>(PhysicDynamic)
+        .with::<PhysicType>(PhysicType::new_movable(
+                config.entities.monster_group.val,
+                config.entities.monster_mask.val,
+                Shape::Circle(boss.radius),
+                CollisionBehavior::Persist,
+                config.entities.monster_velocity,
+                config.entities.monster_time,
+                config.entities.monster_weight))
+        .with::<PhysicForce>(PhysicForce::new())
+        .with::<Life>(Life::new(Kind::Monster,config.entities.monster_die_snd))
+        .with::<Graphic>(Graphic::new(
+                boss.color,
+                config.entities.monster_layer))
+        .with::<Boss>(Boss::new(definition))
+        .with::<UpdateLod>(UpdateLod::never_sleeping())
+        .with::<Killer>(Killer {
+            kamikaze: false,
+            mask: config.entities.monster_killer_mask.val,
+            kill_snd: config.entities.monster_kill_snd,
+        })
+        .with::<Faction>(Faction::named("monster"))
+        .with::<Threat>(Threat::new())
+        .with::<Attributes>(Attributes::new(boss.health, boss.damage))
+        .with::<Abilities>(Abilities::new())
+        .with::<Caster>(Caster::new(&abilities))
+        .with::<Statuses>(Statuses::new())
+        .with::<Movement>(Movement::new())
+        .with::<Replicated>(Replicated)
+        .build()
+}
+
 pub fn add_laser(world: &mut specs::World, pos: [isize;2]) {
     world.create_now()
         .with::<PhysicState>(PhysicState::new(pos))
@@ -137,13 +332,209 @@ pub fn add_laser(world: &mut specs::World, pos: [isize;2]) {
         .build();
 }
 
+pub fn add_pickup<T: IntoGrid>(world: &mut specs::World, pos: T, velocity: [f32;2], stack: Stack) {
+    let mut state = PhysicState::new(pos);
+    state.velocity = velocity;
+
+    let color = config.items.definitions.get(stack.item)
+        .map(|d| d.color)
+        .unwrap_or(config.entities.text_color);
+
+    world.create_now()
+        .with::<PhysicState>(state)
+        .with::<PhysicDynamic>(PhysicDynamic)
+        .with::<PhysicType>(PhysicType::new_movable(
+                config.entities.pickup_group.val,
+                config.entities.pickup_mask.val,
+                Shape::Circle(config.entities.pickup_radius),
+                CollisionBehavior::Persist,
+                0.,
+                config.entities.pickup_time,
+                config.entities.pickup_weight))
+        .with::<PhysicForce>(PhysicForce::new())
+        .with::<Pickup>(Pickup::new(stack))
+        .with::<Graphic>(Graphic::new(
+                color,
+                config.entities.pickup_layer)
+            .with_lod(&*config.entities.pickup_lod))
+        .build();
+}
+
+pub fn add_crate(world: &mut specs::World, pos: [isize;2]) {
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicDynamic>(PhysicDynamic)
+        .with::<PhysicType>(PhysicType::new_movable(
+                config.entities.crate_group.val,
+                config.entities.crate_mask.val,
+                Shape::Square(config.entities.crate_radius),
+                CollisionBehavior::Persist,
+                0.,
+                config.entities.crate_time,
+                config.entities.crate_weight))
+        .with::<PhysicForce>(PhysicForce::new())
+        .with::<Graphic>(Graphic::new(
+                config.entities.crate_color,
+                config.entities.crate_layer))
+        .build();
+}
+
+/// a mount uses the physic group of chests to be interactable
+pub fn add_mount(world: &mut specs::World, pos: [isize;2]) {
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicDynamic>(PhysicDynamic)
+        .with::<PhysicType>(PhysicType::new_movable(
+                config.entities.chest_group.val,
+                config.entities.mount_mask.val,
+                Shape::Circle(config.entities.mount_radius),
+                CollisionBehavior::Persist,
+                config.entities.mount_velocity,
+                config.entities.mount_time,
+                config.entities.mount_weight))
+        .with::<PhysicForce>(PhysicForce::new())
+        .with::<Mount>(Mount::new())
+        .with::<Graphic>(Graphic::new(
+                config.entities.mount_color,
+                config.entities.mount_layer))
+        .with::<Interactable>(Interactable::new(
+                "ride".into(),
+                Box::new(|interactor, mount, context| {
+                    context.control_tx.send(app::Control::Mount(interactor,mount)).unwrap();
+                })))
+        .build();
+}
+
+pub fn add_chest(world: &mut specs::World, pos: [isize;2]) {
+    let mut inventory = Inventory::new(config.entities.chest_inventory_size);
+    inventory.insert(Stack {
+        item: config.entities.chest_item,
+        count: config.entities.chest_count,
+    });
+
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(
+                config.entities.chest_group.val,
+                config.entities.chest_mask.val,
+                Shape::Square(config.entities.chest_radius)))
+        .with::<Graphic>(Graphic::new(
+                config.entities.chest_color,
+                config.entities.chest_layer))
+        .with::<Inventory>(inventory)
+        .with::<Interactable>(Interactable::new(
+                "open chest".into(),
+                Box::new(|interactor, chest, context| {
+                    context.control_tx.send(app::Control::TransferInventory(chest,interactor)).unwrap();
+                })))
+        .build();
+}
+
+/// a vendor uses the physic group and mask of chests
+pub fn add_vendor(world: &mut specs::World, pos: [isize;2]) {
+    let vendor = Vendor::named(&config.shop.vendor_table);
+    let stock = vendor.stock();
+
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(
+                config.entities.chest_group.val,
+                config.entities.chest_mask.val,
+                Shape::Square(config.entities.chest_radius)))
+        .with::<Graphic>(Graphic::new(
+                config.entities.vendor_color,
+                config.entities.vendor_layer))
+        .with::<Inventory>(stock)
+        .with::<Vendor>(vendor)
+        .with::<Interactable>(Interactable::new(
+                "trade".into(),
+                Box::new(|interactor, vendor, context| {
+                    context.control_tx.send(app::Control::OpenShop(interactor,vendor)).unwrap();
+                })))
+        .build();
+}
+
+pub fn add_checkpoint(world: &mut specs::World, pos: [isize;2]) {
+    world.create_now()
+        .with::<Checkpoint>(Checkpoint::new())
+        .with::<GridSquare>(GridSquare::new(pos))
+        .with::<Graphic>(Graphic::new(
+                config.entities.checkpoint_color,
+                config.entities.checkpoint_layer))
+        .build();
+}
+
+pub fn add_liquid(world: &mut specs::World, pos: [isize;2]) {
+    world.create_now()
+        .with::<Liquid>(Liquid::new())
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(
+                config.entities.liquid_group.val,
+                0,
+                Shape::Square(0.5)))
+        .with::<Graphic>(Graphic::new(
+                config.entities.liquid_color,
+                config.entities.liquid_layer))
+        .build();
+}
+
+/// a static hazard of kind in hazards.kinds: lava or spikes
+pub fn add_hazard(world: &mut specs::World, pos: [isize;2], kind: usize) {
+    world.create_now()
+        .with::<Hazard>(Hazard::new(kind))
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(
+                config.entities.hazard_group.val,
+                0,
+                Shape::Square(0.5)))
+        .with::<Graphic>(Graphic::new(
+                config.hazards.kinds[kind].color,
+                config.entities.hazard_layer))
+        .build();
+}
+
+/// tall grass and bushes: block the sight but not the movement
+pub fn add_foliage(world: &mut specs::World, pos: [isize;2]) {
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(
+                config.entities.occluder_group.val,
+                0,
+                Shape::Square(0.5)))
+        .with::<Graphic>(Graphic::new(
+                config.entities.foliage_color,
+                config.entities.foliage_layer))
+        .build();
+}
+
+/// block the movement but not the sight
+pub fn add_low_wall(world: &mut specs::World, pos: [isize;2]) {
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(pos))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(
+                config.entities.wall_group.val,
+                config.entities.wall_mask.val,
+                Shape::Square(config.entities.wall_radius)))
+        .with::<Graphic>(Graphic::new(
+                config.entities.low_wall_color,
+                config.entities.low_wall_layer))
+        .build();
+}
+
 pub fn add_portal(world: &mut specs::World, pos: [isize;2], destination: levels::Level) {
     world.create_now()
         .with::<Portal>(Portal::new(destination))
         .with::<GridSquare>(GridSquare::new(pos))
         .with::<Graphic>(Graphic::new(
                 config.entities.portal_start_color,
-                config.entities.portal_start_layer))
+                config.entities.portal_start_layer)
+            .with_material(&*config.entities.portal_material, config.entities.portal_material_param))
         .build();
 }
 
@@ -0,0 +1,59 @@
+//! a reference to another entity held by a component, like the target of a monster
+//! or of a homing projectile. the handle alone stays valid once the entity is dead:
+//! the reference is invalidated by the death event of the entity and checked
+//! against the lives before it is used, so a dead target is never followed.
+
+use specs;
+
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct EntityRef(Option<specs::Entity>);
+impl EntityRef {
+    pub fn new(entity: Option<specs::Entity>) -> Self {
+        EntityRef(entity)
+    }
+
+    /// the entity until the reference is invalidated
+    pub fn get(&self) -> Option<specs::Entity> {
+        self.0
+    }
+
+    /// false once the reference is invalidated
+    pub fn is_alive(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// the entity if alive says it still lives, invalidate the reference otherwise
+    pub fn check<F: FnOnce(specs::Entity) -> bool>(&mut self, alive: F) -> Option<specs::Entity> {
+        if let Some(entity) = self.0 {
+            if !alive(entity) {
+                self.0 = None;
+            }
+        }
+        self.0
+    }
+
+    /// invalidate the reference if it is the dead entity
+    pub fn invalidate(&mut self, dead: specs::Entity) {
+        if self.0 == Some(dead) {
+            self.0 = None;
+        }
+    }
+}
+
+#[test]
+fn test_entity_ref() {
+    let mut world = specs::World::new();
+    let a = world.create_now().build();
+    let b = world.create_now().build();
+
+    let mut reference = EntityRef::new(Some(a));
+    reference.invalidate(b);
+    assert_eq!(reference.get(), Some(a));
+    assert_eq!(reference.check(|entity| entity == a), Some(a));
+    assert_eq!(reference.check(|_| false), None);
+    assert!(!reference.is_alive());
+
+    let mut reference = EntityRef::new(Some(a));
+    reference.invalidate(a);
+    assert!(!reference.is_alive());
+}
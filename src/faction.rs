@@ -0,0 +1,89 @@
+//! factions of the entities and their relations defined in factions of the
+//! configuration: relations[a][b] is the relation of faction a toward faction b.
+//!
+//! monsters target the nearest entity hostile to them and friendly entities don't
+//! harm each other unless factions.friendly_fire. the faction of an entity and the
+//! relations can be changed at runtime, for example to charm a monster.
+
+use config;
+use configuration::{self, FromToml};
+use toml;
+use specs;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Relation {
+    Hostile,
+    Neutral,
+    Friendly,
+}
+impl_from_into_toml_for_enum!(Relation {
+    Hostile,
+    Neutral,
+    Friendly,
+});
+
+/// the index of the faction named name
+pub fn id(name: &str) -> Option<usize> {
+    config.factions.names.iter().position(|n| n == name)
+}
+
+pub struct Faction {
+    pub id: usize,
+}
+impl specs::Component for Faction {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Faction {
+    pub fn named(name: &str) -> Self {
+        Faction {
+            id: id(name).expect("faction must be defined in factions.names"),
+        }
+    }
+}
+
+pub struct Relations {
+    relations: Vec<Vec<Relation>>,
+}
+
+impl Relations {
+    pub fn new() -> Self {
+        Relations {
+            relations: config.factions.relations.clone(),
+        }
+    }
+
+    /// the relation of a toward b, entities without faction are neutral
+    pub fn get(&self, a: Option<&Faction>, b: Option<&Faction>) -> Relation {
+        match (a, b) {
+            (Some(a), Some(b)) => self.relations[a.id][b.id],
+            _ => Relation::Neutral,
+        }
+    }
+
+    /// change the relation of a toward b, by faction index
+    pub fn set(&mut self, a: usize, b: usize, relation: Relation) {
+        self.relations[a][b] = relation;
+    }
+
+    /// whether a can harm b
+    pub fn harms(&self, a: Option<&Faction>, b: Option<&Faction>) -> bool {
+        config.factions.friendly_fire || self.get(a, b) != Relation::Friendly
+    }
+}
+
+#[test]
+fn test_relations() {
+    let player = Faction::named("player");
+    let monster = Faction::named("monster");
+    let mut relations = Relations::new();
+    assert_eq!(relations.get(Some(&player), Some(&monster)), Relation::Hostile);
+    assert_eq!(relations.get(Some(&player), None), Relation::Neutral);
+    assert!(relations.harms(None, Some(&player)));
+
+    // a charmed monster
+    let charmed = Faction::named("player");
+    assert_eq!(relations.harms(Some(&charmed), Some(&player)), config.factions.friendly_fire);
+
+    relations.set(monster.id, player.id, Relation::Neutral);
+    assert_eq!(relations.get(Some(&monster), Some(&player)), Relation::Neutral);
+}
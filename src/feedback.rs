@@ -0,0 +1,98 @@
+//! the feedback of a hit on an entity, triggered as one `Effect::HitFeedback`:
+//! * flash: the entity is drawn with shaders.flash_material
+//! * punch: the entity is scaled up and shrinks back
+//! * outline: the entity is outlined for a time
+//! * particles: blood or sparks sprayed away from the source of the hit
+//!
+//! the entity part is applied to its graphic when the effect is received, the particles
+//! are rolled then and drawn until the effect ends.
+
+use app::Graphic;
+use combat::{Damage, DamageType};
+use config;
+use graphics::{self, Color};
+use rand::{self, Rng};
+use specs;
+
+pub struct HitFeedback {
+    pub entity: specs::Entity,
+    pub position: [f32;2],
+    /// the direction of the hit, away from its source
+    pub angle: f32,
+    pub flash: bool,
+    /// the scale added to the entity
+    pub punch: f32,
+    /// the time the entity is outlined
+    pub outline: f32,
+    pub particles: usize,
+    pub color: Color,
+    /// the angle and the length of each particle
+    sprays: Vec<(f32,f32)>,
+    persistance: f32,
+}
+
+impl HitFeedback {
+    /// the feedback of combat with blood particles
+    pub fn new(entity: specs::Entity, position: [f32;2], angle: f32) -> Self {
+        HitFeedback {
+            entity: entity,
+            position: position,
+            angle: angle,
+            flash: config.combat.hit_flash,
+            punch: config.combat.hit_punch,
+            outline: config.combat.hit_outline,
+            particles: config.combat.hit_particles,
+            color: config.combat.blood_color,
+            sprays: vec!(),
+            persistance: config.combat.hit_particle_persistance,
+        }
+    }
+
+    /// the feedback of the damage, sparks for explosions and hazards
+    pub fn of_damage(damage: &Damage, angle: f32) -> Self {
+        let mut feedback = HitFeedback::new(damage.target, damage.position, angle);
+        match damage.typ {
+            DamageType::Explosion | DamageType::Hazard => feedback.color = config.combat.spark_color,
+            DamageType::Contact | DamageType::Melee | DamageType::Dash => (),
+        }
+        feedback
+    }
+
+    /// apply the feedback to the graphic of the entity and roll the particles
+    pub fn trigger(&mut self, graphic: Option<&mut Graphic>) {
+        if let Some(graphic) = graphic {
+            if self.flash {
+                graphic.flash();
+            }
+            graphic.punch(self.punch);
+            graphic.outline(self.outline);
+        }
+        let spread = config.combat.hit_particle_spread;
+        let length = config.combat.hit_particle_length;
+        // the sprays are cosmetic, they must not draw from the world rng
+        let mut rng = rand::thread_rng();
+        self.sprays = (0..self.particles)
+            .map(|_| (self.angle + rng.gen_range(-spread, spread), rng.gen_range(length[0], length[1])))
+            .collect();
+    }
+
+    pub fn next(mut self, dt: f32) -> Option<Self> {
+        self.persistance -= dt;
+        if self.persistance > 0. {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    /// the particles fly out and shorten
+    pub fn draw(&self, frame: &mut graphics::Frame) {
+        let progress = 1. - self.persistance / config.combat.hit_particle_persistance;
+        for &(angle, length) in &self.sprays {
+            let distance = length * progress;
+            let x = self.position[0] + distance * angle.cos();
+            let y = self.position[1] + distance * angle.sin();
+            frame.draw_line(x, y, angle, length - distance, config.combat.hit_particle_thickness, graphics::Layer::Middle, self.color);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! the free camera is a debug camera decoupled from the entities: it flies with the
+//! directions at free_camera.speed, can pause the time and hide the hud for promo
+//! shots.
+//!
+//! screenshots are saved as png files in free_camera.screenshot_dir, named after the
+//! first index not taken.
+
+use config;
+use png;
+use utils::Direction;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub struct FreeCamera {
+    pub position: [f32;2],
+    directions: Vec<Direction>,
+    paused: bool,
+    hud: bool,
+}
+impl FreeCamera {
+    pub fn new(position: [f32;2]) -> Self {
+        FreeCamera {
+            position: position,
+            directions: vec!(),
+            paused: false,
+            hud: true,
+        }
+    }
+
+    pub fn push_dir(&mut self, direction: Direction) {
+        if !self.directions.contains(&direction) {
+            self.directions.push(direction);
+        }
+    }
+
+    pub fn retain_dir(&mut self, direction: Direction) {
+        self.directions.retain(|&d| d != direction);
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_hud(&mut self) {
+        self.hud = !self.hud;
+    }
+
+    pub fn hud(&self) -> bool {
+        self.hud
+    }
+
+    /// fly along the directions pressed, dt is the real time so it flies during pause
+    pub fn update(&mut self, dt: f32) {
+        let mut x: f32 = 0.;
+        let mut y: f32 = 0.;
+        for direction in &self.directions {
+            match *direction {
+                Direction::Left => x -= 1.,
+                Direction::Right => x += 1.,
+                Direction::Up => y += 1.,
+                Direction::Down => y -= 1.,
+            }
+        }
+        let norm = (x*x + y*y).sqrt();
+        if norm > 0. {
+            self.position[0] += x/norm*config.free_camera.speed*dt;
+            self.position[1] += y/norm*config.free_camera.speed*dt;
+        }
+    }
+}
+
+/// save the rgba pixels, rows from bottom to top, as a png in free_camera.screenshot_dir
+pub fn save_screenshot(pixels: &[u8], width: u32, height: u32) -> Result<PathBuf, String> {
+    let dir = PathBuf::from(&*config.free_camera.screenshot_dir.val);
+    try!(fs::create_dir_all(&dir).map_err(|e| format!("create screenshot directory failed: {}",e)));
+    let path = (0..).map(|i| dir.join(format!("screenshot_{}.png",i)))
+        .find(|path| !path.exists())
+        .unwrap();
+
+    // png rows are from top to bottom
+    let row = (width*4) as usize;
+    let mut data = Vec::with_capacity(pixels.len());
+    for line in pixels.chunks(row).rev() {
+        data.extend_from_slice(line);
+    }
+
+    let file = try!(fs::File::create(&path).map_err(|e| format!("create screenshot failed: {}",e)));
+    let mut encoder = png::Encoder::new(io::BufWriter::new(file), width, height);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    let mut writer = try!(encoder.write_header().map_err(|e| format!("write screenshot failed: {}",e)));
+    try!(writer.write_image_data(&data).map_err(|e| format!("write screenshot failed: {}",e)));
+    Ok(path)
+}
+
+#[test]
+fn test_free_camera() {
+    let mut camera = FreeCamera::new([0.,0.]);
+    camera.push_dir(Direction::Right);
+    camera.push_dir(Direction::Right);
+    camera.update(1.);
+    assert!((camera.position[0] - config.free_camera.speed).abs() < 1e-6);
+
+    camera.push_dir(Direction::Left);
+    camera.update(1.);
+    assert!((camera.position[0] - config.free_camera.speed).abs() < 1e-6);
+
+    camera.retain_dir(Direction::Left);
+    camera.retain_dir(Direction::Right);
+    camera.toggle_pause();
+    assert!(camera.is_paused());
+}
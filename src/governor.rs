@@ -0,0 +1,133 @@
+//! the frame time governor: the times of the update and the render are smoothed and
+//! their sum compared to quality.frame_budget. over budget for quality.degrade_time the
+//! quality drops to the next level of quality.levels, under quality.restore_ratio of the
+//! budget for quality.restore_time it comes back to the previous one:
+//! * particles: the fraction of the ambient particles and the hit particles drawn
+//! * ai_lod: the factor of physic.lod_distance and physic.dormancy_radius, peers of a
+//!   networked game must step the same entities so it stays at the full quality there
+//! * lights: the ambient lights drawn at most
+
+use config;
+use configuration::{self, FromToml};
+use toml;
+use std::time::Duration;
+
+pub struct QualityLevel {
+    pub name: String,
+    pub particles: f32,
+    pub ai_lod: f32,
+    pub lights: usize,
+}
+impl_from_into_toml_for_struct!( QualityLevel {
+    name: String,
+    particles: f32,
+    ai_lod: f32,
+    lights: usize,
+});
+
+impl QualityLevel {
+    /// the number of particles drawn out of count
+    pub fn particles(&self, count: usize) -> usize {
+        (count as f32 * self.particles).ceil() as usize
+    }
+}
+
+/// the duration in seconds
+pub fn seconds(duration: Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 * 1e-9
+}
+
+pub struct Governor {
+    /// the index in quality.levels
+    level: usize,
+    /// the smoothed time of each subsystem measured
+    times: Vec<(&'static str, f32)>,
+    over: f32,
+    under: f32,
+}
+
+impl Governor {
+    pub fn new() -> Self {
+        Governor {
+            level: 0,
+            times: vec!(),
+            over: 0.,
+            under: 0.,
+        }
+    }
+
+    pub fn measure(&mut self, subsystem: &'static str, time: f32) {
+        match self.times.iter().position(|&(name, _)| name == subsystem) {
+            Some(i) => self.times[i].1 += (time - self.times[i].1) * config.quality.smoothing,
+            None => self.times.push((subsystem, time)),
+        }
+    }
+
+    pub fn frame_time(&self) -> f32 {
+        self.times.iter().map(|&(_, time)| time).sum()
+    }
+
+    /// the index of the new level if it changed
+    pub fn update(&mut self, dt: f32) -> Option<usize> {
+        if !config.quality.adaptive {
+            return None;
+        }
+        let frame_time = self.frame_time();
+        if frame_time > config.quality.frame_budget {
+            self.under = 0.;
+            self.over += dt;
+            if self.over >= config.quality.degrade_time && self.level + 1 < config.quality.levels.len() {
+                self.over = 0.;
+                self.level += 1;
+                return Some(self.level);
+            }
+        } else if frame_time < config.quality.frame_budget * config.quality.restore_ratio {
+            self.over = 0.;
+            self.under += dt;
+            if self.under >= config.quality.restore_time && self.level > 0 {
+                self.under = 0.;
+                self.level -= 1;
+                return Some(self.level);
+            }
+        } else {
+            self.over = 0.;
+            self.under = 0.;
+        }
+        None
+    }
+
+    pub fn index(&self) -> usize {
+        self.level
+    }
+
+    pub fn level(&self) -> &'static QualityLevel {
+        &config.quality.levels[self.level]
+    }
+
+    /// the times of the subsystems in milliseconds and the quality level
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for &(name, time) in &self.times {
+            text.push_str(&*format!("{}: {:.1}ms ", name, time * 1000.));
+        }
+        text.push_str(&*format!("quality: {}\n", self.level().name));
+        text
+    }
+}
+
+#[test]
+fn test_governor() {
+    if !config.quality.adaptive || config.quality.levels.len() < 2 {
+        return;
+    }
+    let mut governor = Governor::new();
+    governor.measure("update", config.quality.frame_budget * 2.);
+    assert_eq!(governor.update(config.quality.degrade_time / 2.), None);
+    assert_eq!(governor.update(config.quality.degrade_time / 2.), Some(1));
+    assert_eq!(governor.level().name, config.quality.levels[1].name);
+
+    let mut governor = Governor { level: 1, .. Governor::new() };
+    governor.measure("update", 0.);
+    assert_eq!(governor.update(config.quality.restore_time), Some(0));
+    assert_eq!(governor.update(config.quality.restore_time), None);
+}
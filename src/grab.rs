@@ -0,0 +1,164 @@
+//! grabbers hold a dynamic body in front of them: crates to push, pull and carry.
+//!
+//! the body is kept at grab.distance in front of the grabber by a spring of
+//! grab.stiffness and slows the grabber by the ratio of their weights. it is released
+//! on request, thrown with an impulse of grab.throw_impulse, and dropped when the
+//! grabber takes damage or the body is held back farther than grab.range.
+//! the arc the body would follow if thrown is predicted over grab.arc_steps steps.
+
+use app;
+use specs;
+use config;
+use components::*;
+use resource::PhysicWorld;
+use specs::Join;
+use progression::{Modifier, Source, Stat};
+
+pub struct Grabber {
+    /// grab the body in front or release the body held at next update
+    pub requested: bool,
+    /// throw the body held at next update
+    pub throw_requested: bool,
+    held: Option<specs::Entity>,
+    /// health of the grabber at last update, the body is dropped when it decreases
+    health: f32,
+}
+impl specs::Component for Grabber {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Grabber {
+    pub fn new() -> Self {
+        Grabber {
+            requested: false,
+            throw_requested: false,
+            held: None,
+            health: 0.,
+        }
+    }
+
+    pub fn held(&self) -> Option<specs::Entity> {
+        self.held
+    }
+}
+
+/// the positions the body held by the grabber would go through if thrown now,
+/// empty if it holds none
+pub fn throw_arc(world: &specs::World, grabber: specs::Entity) -> Vec<[f32;2]> {
+    let held = match world.read::<Grabber>().get(grabber).and_then(|grabber| grabber.held) {
+        Some(held) => held,
+        None => return vec!(),
+    };
+    let direction = match world.read::<PhysicForce>().get(grabber) {
+        Some(force) => force.direction,
+        None => return vec!(),
+    };
+    let types = world.read::<PhysicType>();
+    let (mut state, typ) = match (world.read::<PhysicState>().get(held).cloned(), types.get(held)) {
+        (Some(state), Some(typ)) => (state, typ),
+        _ => return vec!(),
+    };
+    state.apply_knockback(typ, direction, config.grab.throw_impulse);
+    world.read_resource::<PhysicWorld>().predict_trajectory(&state, typ, &PhysicForce::new(), config.grab.arc_steps, config.grab.arc_step)
+}
+
+/// the factor of the speed of a grabber holding a body
+fn slowdown(grabber_weight: f32, body_weight: f32) -> f32 {
+    grabber_weight/(grabber_weight + body_weight)
+}
+
+/// grab, release and throw the bodies and pull the bodies held
+pub struct GrabSystem;
+impl specs::System<app::UpdateContext> for GrabSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut grabbers, mut states, mut attributes, lives, types, forces, dynamics, physic_world, entities) = arg.fetch(|world| {
+            (
+                world.write::<Grabber>(),
+                world.write::<PhysicState>(),
+                world.write::<Attributes>(),
+                world.read::<Life>(),
+                world.read::<PhysicType>(),
+                world.read::<PhysicForce>(),
+                world.read::<PhysicDynamic>(),
+                world.read_resource::<PhysicWorld>(),
+                world.entities(),
+            )
+        });
+
+        for (grabber, typ, force, entity) in (&mut grabbers, &types, &forces, &entities).iter() {
+            let (position, velocity) = match states.get(entity) {
+                Some(state) => (state.position, state.velocity),
+                None => continue,
+            };
+            let direction = force.direction;
+            let requested = ::std::mem::replace(&mut grabber.requested, false);
+            let throw_requested = ::std::mem::replace(&mut grabber.throw_requested, false);
+
+            let health = lives.get(entity).map(|life| life.health()).unwrap_or(0.);
+            let damaged = health < grabber.health;
+            grabber.health = health;
+
+            if let Some(held) = grabber.held {
+                let distance = states.get(held).map(|s| ((s.position[0] - position[0]).powi(2) + (s.position[1] - position[1]).powi(2)).sqrt());
+                let lost = distance.map(|d| d > config.grab.range + config.grab.distance).unwrap_or(true);
+                if throw_requested && !lost {
+                    let held_type = types.get(held).expect("grabbed entity expect type component");
+                    states.get_mut(held).unwrap().apply_knockback(held_type, direction, config.grab.throw_impulse);
+                    grabber.held = None;
+                    context.event_tx.send(app::GameEvent::Thrown(entity,held)).unwrap();
+                } else if requested || damaged || lost {
+                    grabber.held = None;
+                    context.event_tx.send(app::GameEvent::Released(entity,held)).unwrap();
+                }
+            } else if requested {
+                let mut nearest: Option<(specs::Entity,f32)> = None;
+                physic_world.apply_on_cone_except(
+                    &[entity],
+                    &position,
+                    direction,
+                    config.grab.angle,
+                    config.grab.range,
+                    config.grab.mask.val,
+                    &mut |other_entity, distance| {
+                        let grabbable = dynamics.get(*other_entity).is_some()
+                            && types.get(*other_entity).map(|t| t.weight <= config.grab.max_weight).unwrap_or(false);
+                        if grabbable && nearest.map(|(_,d)| distance < d).unwrap_or(true) {
+                            nearest = Some((*other_entity,distance));
+                        }
+                    });
+                if let Some((held, _)) = nearest {
+                    grabber.held = Some(held);
+                    context.event_tx.send(app::GameEvent::Grabbed(entity,held)).unwrap();
+                }
+            }
+
+            let mut modifiers = vec!();
+            if let Some(held) = grabber.held {
+                let held_type = types.get(held).expect("grabbed entity expect type component");
+                let state = states.get_mut(held).unwrap();
+                let target = [
+                    position[0] + config.grab.distance*direction.cos(),
+                    position[1] + config.grab.distance*direction.sin(),
+                ];
+                state.velocity = [
+                    velocity[0] + (target[0] - state.position[0])*config.grab.stiffness,
+                    velocity[1] + (target[1] - state.position[1])*config.grab.stiffness,
+                ];
+                modifiers.push(Modifier {
+                    stat: Stat::Speed,
+                    add: 0.,
+                    multiply: slowdown(typ.weight, held_type.weight),
+                });
+            }
+            if let Some(attributes) = attributes.get_mut(entity) {
+                attributes.set_modifiers(Source::Grab, modifiers);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_slowdown() {
+    assert_eq!(slowdown(1., 0.), 1.);
+    assert_eq!(slowdown(1., 1.), 0.5);
+    assert!(slowdown(1., 4.) < slowdown(1., 2.));
+}
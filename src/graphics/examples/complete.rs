@@ -11,29 +11,36 @@ fn main() {
             .build_glium()
             .unwrap();
 
+    let colors = graphics::ColorsValue {
+        base03: [0.0, 0.0, 0.0, 1.0],
+        base02: [1.0, 1.0, 1.0, 1.0],
+        base01: [1.0, 1.0, 1.0, 1.0],
+        base00: [1.0, 1.0, 1.0, 1.0],
+        base0: [1.0, 1.0, 1.0, 1.0],
+        base1: [1.0, 1.0, 1.0, 1.0],
+        base2: [1.0, 1.0, 1.0, 1.0],
+        base3: [1.0, 1.0, 1.0, 1.0],
+        yellow: [1.0, 1.0, 1.0, 1.0],
+        orange: [1.0, 1.0, 1.0, 1.0],
+        red: [1.0, 0.0, 0.0, 0.5],
+        magenta: [1.0, 1.0, 1.0, 1.0],
+        violet: [1.0, 1.0, 1.0, 1.0],
+        blue: [0.0, 0.0, 1.0, 0.5],
+        cyan: [1.0, 1.0, 1.0, 1.0],
+        green: [0.0, 1.0, 0.0, 0.5],
+    };
+
     let mut graphics = graphics::Graphics::new(&window, graphics::GraphicsSetting {
-        colors: graphics::ColorsValue {
-            base03: [0.0, 0.0, 0.0, 1.0],
-            base02: [1.0, 1.0, 1.0, 1.0],
-            base01: [1.0, 1.0, 1.0, 1.0],
-            base00: [1.0, 1.0, 1.0, 1.0],
-            base0: [1.0, 1.0, 1.0, 1.0],
-            base1: [1.0, 1.0, 1.0, 1.0],
-            base2: [1.0, 1.0, 1.0, 1.0],
-            base3: [1.0, 1.0, 1.0, 1.0],
-            yellow: [1.0, 1.0, 1.0, 1.0],
-            orange: [1.0, 1.0, 1.0, 1.0],
-            red: [1.0, 0.0, 0.0, 0.5],
-            magenta: [1.0, 1.0, 1.0, 1.0],
-            violet: [1.0, 1.0, 1.0, 1.0],
-            blue: [0.0, 0.0, 1.0, 0.5],
-            cyan: [1.0, 1.0, 1.0, 1.0],
-            green: [0.0, 1.0, 0.0, 0.5],
-        },
+        colors: colors.clone(),
+        colorblind_colors: colors,
+        colorblind: false,
         mode: graphics::Mode::Dark,
         circle_precision: 32,
         luminosity: 1.0,
         billboard_font_scale: 0.04,
+        ui_scale: 1.0,
+        letterbox: None,
+        pixel_art: None,
         font: "assets/DejaVuSansMono-Bold.ttf".into(),
     }).unwrap();
 
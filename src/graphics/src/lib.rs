@@ -40,9 +40,50 @@ use std::rc::Rc;
 use std::borrow::Cow;
 use std::error::Error;
 use std::fmt;
+use std::time::Instant;
 
 pub type Transformation = vecmath::Matrix2x3<f32>;
 
+/// the vertex shader of the materials, the fragment shader of a material gets:
+/// * `v_color`: the color of the shape
+/// * `v_position`: the position in the shape, in [-1,1] for quads and of length 1 at the
+///   edge of circles
+/// * `v_param`: the parameter given with the material
+/// * `uniform float time`: the time in seconds
+/// * `uniform bool circle`: whether the shape is a circle
+const MATERIAL_VERTEX_SHADER: &'static str = r#"
+    #version 150
+    in vec2 position;
+    in mat4 trans;
+    in vec4 color;
+    in float param;
+    out vec4 v_color;
+    out vec2 v_position;
+    out float v_param;
+    uniform mat4 camera;
+    void main() {
+        v_color = color;
+        v_position = position;
+        v_param = param;
+        gl_Position = camera * trans * vec4(position, 0.0, 1.0);
+    }
+"#;
+
+/// the vertex shader of the post passes, the fragment shader of a pass gets:
+/// * `v_tex_coords`: the coordinates in the screen
+/// * `uniform sampler2D scene`: the screen drawn so far
+/// * `uniform vec2 resolution`: the size of the screen in pixels
+/// * `uniform float time`: the time in seconds
+const POST_PASS_VERTEX_SHADER: &'static str = r#"
+    #version 150
+    in vec2 position;
+    out vec2 v_tex_coords;
+    void main() {
+        v_tex_coords = position * 0.5 + 0.5;
+        gl_Position = vec4(position, 0.0, 1.0);
+    }
+"#;
+
 pub trait Transformed {
     fn translate(self, x: f32, y: f32) -> Self;
     fn rotate(self, angle: f32) -> Self;
@@ -90,20 +131,80 @@ impl Transformed for Transformation {
 #[derive(Debug,Clone)]
 pub struct GraphicsSetting {
     pub colors: ColorsValue,
+    /// the colors used instead of colors if colorblind
+    pub colorblind_colors: ColorsValue,
+    pub colorblind: bool,
     pub mode: Mode,
     pub luminosity: f32,
     pub circle_precision: usize,
     pub billboard_font_scale: f32,
+    /// scale of the billboard texts
+    pub ui_scale: f32,
+    /// the logical resolution drawn letterboxed, its ratio is kept
+    pub letterbox: Option<[u32;2]>,
+    pub pixel_art: Option<PixelArt>,
     pub font: String,
 }
 
 
+/// the world is drawn at a low resolution and upscaled without filtering
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct PixelArt {
+    pub resolution: [u32;2],
+    /// upscale by an integer factor only, with bars around the screen
+    pub integer_scaling: bool,
+}
+
 #[derive(Clone,Copy)]
 struct Vertex {
     position: [f32;2],
 }
 implement_vertex!(Vertex, position);
 
+/// a shape drawn by an instanced draw of its batch
+#[derive(Clone,Copy)]
+struct Instance {
+    trans: [[f32;4];4],
+    color: [f32;4],
+    param: f32,
+}
+implement_vertex!(Instance, trans, color, param);
+
+#[derive(Clone,Copy,PartialEq)]
+enum Shape {
+    Quad,
+    Circle,
+}
+
+/// the instances of a shape drawn with the same camera
+struct Batch {
+    shape: Shape,
+    material: Material,
+    billboard: bool,
+    /// a bit for each layer of the instances
+    layers: u8,
+    instances: Vec<Instance>,
+}
+
+/// a shader drawing the shapes, the default one draws them with their color
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct Material(usize);
+
+/// a shader applied to the whole screen
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct PostPass(usize);
+
+/// a color grading lookup table, the default one keeps the colors
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct Grading(usize);
+
+/// the number of instanced draws and of shapes drawn in a frame
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct DrawStats {
+    pub batches: usize,
+    pub instances: usize,
+}
+
 #[derive(Copy, Clone)]
 struct FontVertex {
     position: [f32; 2],
@@ -116,15 +217,36 @@ pub struct Graphics {
 
     colors: ColorsValue,
     colors_setting: ColorsValue,
+    colorblind_setting: ColorsValue,
+    colorblind: bool,
     mode: Mode,
     quad_vertex_buffer: VertexBuffer<Vertex>,
     quad_indices: index::NoIndices,
     circle_vertex_buffer: VertexBuffer<Vertex>,
     circle_indices: index::NoIndices,
     program: Program,
+    /// the programs of the materials, the default one first
+    materials: Vec<Program>,
+    post_programs: Vec<Program>,
+    /// the passes applied at the end of the frame in order
+    post_passes: Vec<PostPass>,
+    /// the copy of the screen read by the passes
+    post_texture: Option<Texture2d>,
+    /// the lookup tables of the gradings, the identity first
+    gradings: Vec<Texture2d>,
+    grading_program: Program,
+    /// the previous grading, the current one and the time and duration of the change
+    grading: (Grading, Grading, f32, f32),
+    start: Instant,
     luminosity: f32,
+    tint: [f32;3],
 
     billboard_font_scale: f32,
+    ui_scale: f32,
+    letterbox: Option<[u32;2]>,
+    pixel_art: Option<PixelArt>,
+    /// the copy of the low resolution scene to upscale
+    pixel_art_texture: Option<Texture2d>,
     font: Font<'static>,
     font_cache: Cache,
     //TODO allow redimension
@@ -132,6 +254,8 @@ pub struct Graphics {
     font_program: Program,
 
     draw_parameters: DrawParameters<'static>,
+    /// the stats of the last frame
+    stats: DrawStats,
 }
 
 #[derive(Debug)]
@@ -238,7 +362,51 @@ impl Graphics {
         let program = try!(Program::from_source(facade, vertex_shader_src, fragment_shader_src, None)
             .map_err(|pce| GraphicsCreationError::ProgramCreationError(pce)));
 
-        let mut colors = setting.colors.clone();
+        let instance_fragment_shader_src = r#"
+            #version 150
+            in vec4 v_color;
+            out vec4 out_color;
+            void main() {
+                out_color = v_color;
+            }
+        "#;
+        let instance_program = try!(Program::from_source(facade, MATERIAL_VERTEX_SHADER, instance_fragment_shader_src, None)
+            .map_err(|pce| GraphicsCreationError::ProgramCreationError(pce)));
+
+        let grading_fragment_shader_src = r#"
+            #version 150
+            in vec2 v_tex_coords;
+            out vec4 out_color;
+            uniform sampler2D scene;
+            uniform sampler2D from;
+            uniform sampler2D to;
+            uniform float blend;
+
+            // the lookup table is a strip of blue slices of red by green
+            vec3 grade(sampler2D lut, vec3 color) {
+                float size = float(textureSize(lut, 0).y);
+                float blue = color.b * (size - 1.0);
+                float slice = floor(blue);
+                vec2 uv = vec2((color.r * (size - 1.0) + 0.5) / (size * size), (color.g * (size - 1.0) + 0.5) / size);
+                vec3 low = texture(lut, uv + vec2(slice / size, 0.0)).rgb;
+                vec3 high = texture(lut, uv + vec2(min(slice + 1.0, size - 1.0) / size, 0.0)).rgb;
+                return mix(low, high, blue - slice);
+            }
+
+            void main() {
+                vec3 color = clamp(texture(scene, v_tex_coords).rgb, 0.0, 1.0);
+                out_color = vec4(mix(grade(from, color), grade(to, color), blend), 1.0);
+            }
+        "#;
+        let grading_program = try!(Program::from_source(facade, POST_PASS_VERTEX_SHADER, grading_fragment_shader_src, None)
+            .map_err(|pce| GraphicsCreationError::ProgramCreationError(pce)));
+        let identity = try!(lut_texture(facade, identity_lut(IDENTITY_LUT_SIZE), IDENTITY_LUT_SIZE));
+
+        let mut colors = if setting.colorblind {
+            setting.colorblind_colors.clone()
+        } else {
+            setting.colors.clone()
+        };
         colors.apply(&mut |color: &mut [f32;4]| {
             color[0] *= setting.luminosity;
             color[1] *= setting.luminosity;
@@ -304,46 +472,222 @@ impl Graphics {
             glium::texture::UncompressedFloatFormat::U8,
             glium::texture::MipmapsOption::NoMipmap).map_err(|e| GraphicsCreationError::Texture2dError(e)));
 
+        let pixel_art_texture = match setting.pixel_art {
+            Some(pixel_art) => Some(try!(Texture2d::empty(facade, pixel_art.resolution[0], pixel_art.resolution[1])
+                .map_err(|e| GraphicsCreationError::Texture2dError(e)))),
+            None => None,
+        };
+
         Ok(Graphics {
             context: facade.get_context().clone(),
             colors: colors,
             colors_setting: setting.colors,
+            colorblind_setting: setting.colorblind_colors,
+            colorblind: setting.colorblind,
             mode: setting.mode,
             quad_vertex_buffer: quad_vertex_buffer,
             quad_indices: quad_indices,
             circle_vertex_buffer: circle_vertex_buffer,
             circle_indices: circle_indices,
             program: program,
+            materials: vec!(instance_program),
+            post_programs: vec!(),
+            post_passes: vec!(),
+            post_texture: None,
+            gradings: vec!(identity),
+            grading_program: grading_program,
+            grading: (Grading::default(), Grading::default(), 0., 0.),
+            start: Instant::now(),
             luminosity: setting.luminosity,
+            tint: [1.,1.,1.],
 
             billboard_font_scale: setting.billboard_font_scale,
+            ui_scale: setting.ui_scale,
+            letterbox: setting.letterbox,
+            pixel_art: setting.pixel_art,
+            pixel_art_texture: pixel_art_texture,
             font_cache: font_cache,
             font: font,
             font_cache_tex: font_cache_tex,
             font_program: font_program,
 
             draw_parameters: draw_parameters,
+            stats: DrawStats::default(),
         })
     }
 
+    /// compile a material from the source of its fragment shader
+    pub fn add_material(&mut self, fragment_shader: &str) -> Result<Material,GraphicsCreationError> {
+        let program = try!(Program::from_source(&self.context, MATERIAL_VERTEX_SHADER, fragment_shader, None)
+            .map_err(|pce| GraphicsCreationError::ProgramCreationError(pce)));
+        self.materials.push(program);
+        Ok(Material(self.materials.len() - 1))
+    }
+
+    /// compile a post pass from the source of its fragment shader
+    pub fn add_post_pass(&mut self, fragment_shader: &str) -> Result<PostPass,GraphicsCreationError> {
+        let program = try!(Program::from_source(&self.context, POST_PASS_VERTEX_SHADER, fragment_shader, None)
+            .map_err(|pce| GraphicsCreationError::ProgramCreationError(pce)));
+        self.post_programs.push(program);
+        Ok(PostPass(self.post_programs.len() - 1))
+    }
+
+    pub fn set_post_passes(&mut self, post_passes: Vec<PostPass>) {
+        self.post_passes = post_passes;
+    }
+
+    pub fn post_passes(&self) -> &[PostPass] {
+        &self.post_passes
+    }
+
+    /// create a grading from a lookup table of size blue slices of size red by size
+    /// green, as rgb rows from top to bottom as in image files
+    pub fn add_grading(&mut self, lut: Vec<u8>, size: u32) -> Result<Grading,GraphicsCreationError> {
+        let texture = try!(lut_texture(&self.context, lut, size));
+        self.gradings.push(texture);
+        Ok(Grading(self.gradings.len() - 1))
+    }
+
+    /// blend from the current grading to this one over the duration in seconds
+    pub fn set_grading(&mut self, grading: Grading, duration: f32) {
+        if grading != self.grading.1 {
+            let time = self.time();
+            self.grading = (self.grading.1, grading, time, duration);
+        }
+    }
+
+    pub fn grading(&self) -> Grading {
+        self.grading.1
+    }
+
+    /// the time given to the shaders
+    fn time(&self) -> f32 {
+        let elapsed = self.start.elapsed();
+        elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.
+    }
+
+    /// the stats of the last frame drawn
+    pub fn stats(&self) -> DrawStats {
+        self.stats
+    }
+
     pub fn set_luminosity(&mut self, luminosity: f32) {
         self.luminosity = luminosity;
-        self.colors = self.colors_setting.clone();
-        self.colors.apply(&mut |color: &mut [f32;4]| {
-            color[0] *= luminosity;
-            color[1] *= luminosity;
-            color[2] *= luminosity;
-        });
+        self.update_colors();
     }
 
     pub fn luminosity(&self) -> f32 {
         self.luminosity
     }
 
+    /// multiply each channel of the colors, on top of the luminosity
+    /// the rgba pixels of the last frame finished and its dimensions,
+    /// rows are from bottom to top
+    pub fn read_front_buffer(&self) -> (Vec<u8>, u32, u32) {
+        let image: glium::texture::RawImage2d<u8> = self.context.read_front_buffer();
+        (image.data.into_owned(), image.width, image.height)
+    }
+
+    pub fn set_tint(&mut self, tint: [f32;3]) {
+        if tint != self.tint {
+            self.tint = tint;
+            self.update_colors();
+        }
+    }
+
+    pub fn tint(&self) -> [f32;3] {
+        self.tint
+    }
+
+    pub fn set_colorblind(&mut self, colorblind: bool) {
+        self.colorblind = colorblind;
+        self.update_colors();
+    }
+
+    pub fn colorblind(&self) -> bool {
+        self.colorblind
+    }
+
+    fn update_colors(&mut self) {
+        let luminosity = self.luminosity;
+        let tint = self.tint;
+        self.colors = if self.colorblind {
+            self.colorblind_setting.clone()
+        } else {
+            self.colors_setting.clone()
+        };
+        self.colors.apply(&mut |color: &mut [f32;4]| {
+            color[0] *= luminosity*tint[0];
+            color[1] *= luminosity*tint[1];
+            color[2] *= luminosity*tint[2];
+        });
+    }
+
     pub fn mode(&self) -> Mode {
         self.mode
     }
 
+    pub fn set_ui_scale(&mut self, ui_scale: f32) {
+        self.ui_scale = ui_scale;
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+
+    pub fn set_letterbox(&mut self, letterbox: Option<[u32;2]>) {
+        self.letterbox = letterbox;
+    }
+
+    pub fn letterbox(&self) -> Option<[u32;2]> {
+        self.letterbox
+    }
+
+    pub fn set_pixel_art(&mut self, pixel_art: Option<PixelArt>) -> Result<(),GraphicsCreationError> {
+        self.pixel_art_texture = match pixel_art {
+            Some(pixel_art) => Some(try!(Texture2d::empty(&self.context, pixel_art.resolution[0], pixel_art.resolution[1])
+                .map_err(|e| GraphicsCreationError::Texture2dError(e)))),
+            None => None,
+        };
+        self.pixel_art = pixel_art;
+        Ok(())
+    }
+
+    pub fn pixel_art(&self) -> Option<PixelArt> {
+        self.pixel_art
+    }
+
+    /// the part of the framebuffer drawn: the low resolution scene in the corner if pixel
+    /// art, the whole framebuffer if not letterboxed
+    fn screen(&self) -> glium::Rect {
+        let (width,height) = self.context.get_framebuffer_dimensions();
+        if let Some(pixel_art) = self.pixel_art {
+            return glium::Rect {
+                left: 0,
+                bottom: 0,
+                width: pixel_art.resolution[0].min(width),
+                height: pixel_art.resolution[1].min(height),
+            };
+        }
+        match self.letterbox {
+            Some(logical) => letterbox(width, height, logical),
+            None => glium::Rect { left: 0, bottom: 0, width: width, height: height },
+        }
+    }
+
+    /// the camera moved to the nearest texel of the pixel art so the world doesn't
+    /// shimmer when it moves
+    fn snap(&self, camera: &Camera, width: u32) -> Camera {
+        let mut camera = camera.clone();
+        if self.pixel_art.is_some() {
+            // the width of the screen is 2/zoom in world units
+            let texel = 2. / (camera.zoom * width as f32);
+            camera.x = (camera.x / texel).round() * texel;
+            camera.y = (camera.y / texel).round() * texel;
+        }
+        camera
+    }
+
     pub fn toggle_mode(&mut self) {
         self.mode = match self.mode {
             Mode::Light => Mode::Dark,
@@ -352,12 +696,19 @@ impl Graphics {
     }
 }
 
+/// the shapes are queued in batches and drawn with an instanced draw for each batch
+/// before the texts, when the view changes and when the frame is finished.
 pub struct Frame<'a> {
     frame: glium::Frame,
     graphics: &'a mut  Graphics,
-    camera: &'a Camera,
+    camera: Camera,
+    viewport: Option<glium::Rect>,
     camera_matrix: [[f32;4];4],
     billboard_camera_matrix: [[f32;4];4],
+    batches: Vec<Batch>,
+    material: Material,
+    material_param: f32,
+    stats: DrawStats,
 }
 
 #[derive(Clone,Debug)]
@@ -377,33 +728,74 @@ impl Camera {
     }
 }
 
+/// a camera rendered in a part of the screen
+#[derive(Clone,Debug)]
+pub struct View {
+    pub camera: Camera,
+    /// left, bottom, width and height in fraction of the screen
+    pub viewport: [f32;4],
+}
+
+impl View {
+    pub fn full(camera: Camera) -> Self {
+        View {
+            camera: camera,
+            viewport: [0., 0., 1., 1.],
+        }
+    }
+
+    /// split the screen in columns, one for each camera
+    pub fn split(cameras: Vec<Camera>) -> Vec<View> {
+        let n = cameras.len() as f32;
+        cameras.into_iter().enumerate().map(|(i, camera)| View {
+            camera: camera,
+            viewport: [i as f32 / n, 0., 1. / n, 1.],
+        }).collect()
+    }
+}
+
+fn camera_matrices(camera: &Camera, width: f32, height: f32) -> ([[f32;4];4],[[f32;4];4]) {
+    let ratio = width / height;
+
+    let camera_matrix = {
+        let kx = camera.zoom;
+        let ky = camera.zoom*ratio;
+        let dx = -camera.x;
+        let dy = -camera.y;
+        [
+            [   kx,    0., 0., 0.],
+            [   0.,    ky, 0., 0.],
+            [   0.,    0., 1., 0.],
+            [kx*dx, ky*dy, 0., 1.]
+        ]
+    };
+    let billboard_camera_matrix = {
+        let kx = 1.0;
+        let ky = ratio;
+        [
+            [   kx,    0., 0., 0.],
+            [   0.,    ky, 0., 0.],
+            [   0.,    0., 1., 0.],
+            [   0.,    0., 0., 1.]
+        ]
+    };
+
+    (camera_matrix, billboard_camera_matrix)
+}
+
 impl<'a> Frame<'a> {
-    pub fn new(graphics: &'a mut Graphics, mut frame: glium::Frame, camera: &'a Camera) -> Frame<'a> {
-        let (width,height) = graphics.context.get_framebuffer_dimensions();
-        let ratio = width as f32/ height as f32;
-
-        let camera_matrix = {
-            let kx = camera.zoom;
-            let ky = camera.zoom*ratio;
-            let dx = -camera.x;
-            let dy = -camera.y;
-            [
-                [   kx,    0., 0., 0.],
-                [   0.,    ky, 0., 0.],
-                [   0.,    0., 1., 0.],
-                [kx*dx, ky*dy, 0., 1.]
-            ]
-        };
-        let billboard_camera_matrix = {
-            let kx = 1.0;
-            let ky = ratio;
-            [
-                [   kx,    0., 0., 0.],
-                [   0.,    ky, 0., 0.],
-                [   0.,    0., 1., 0.],
-                [   0.,    0., 0., 1.]
-            ]
+    pub fn new(graphics: &'a mut Graphics, mut frame: glium::Frame, camera: &Camera) -> Frame<'a> {
+        let screen = graphics.screen();
+        let camera = graphics.snap(camera, screen.width);
+        let (camera_matrix, billboard_camera_matrix) = camera_matrices(&camera, screen.width as f32, screen.height as f32);
+        let viewport = if graphics.letterbox.is_some() || graphics.pixel_art.is_some() {
+            // the bars around the screen
+            frame.clear_color(0., 0., 0., 1.);
+            Some(screen)
+        } else {
+            None
         };
+        graphics.draw_parameters.viewport = viewport;
 
         frame.clear_depth(0f32);;
 
@@ -428,11 +820,56 @@ impl<'a> Frame<'a> {
             billboard_camera_matrix: billboard_camera_matrix,
             camera_matrix: camera_matrix,
             camera: camera,
+            viewport: viewport,
             frame: frame,
             graphics: graphics,
+            batches: vec!(),
+            material: Material::default(),
+            material_param: 0.,
+            stats: DrawStats::default(),
+        }
+    }
+
+    /// draw the next calls with the camera of the view in its part of the screen
+    pub fn set_view(&mut self, view: &View) {
+        self.flush();
+        let screen = self.graphics.screen();
+        let viewport = glium::Rect {
+            left: screen.left + (view.viewport[0] * screen.width as f32) as u32,
+            bottom: screen.bottom + (view.viewport[1] * screen.height as f32) as u32,
+            width: (view.viewport[2] * screen.width as f32) as u32,
+            height: (view.viewport[3] * screen.height as f32) as u32,
+        };
+        let camera = self.graphics.snap(&view.camera, viewport.width);
+        let (camera_matrix, billboard_camera_matrix) = camera_matrices(&camera, viewport.width as f32, viewport.height as f32);
+
+        self.camera = camera;
+        self.camera_matrix = camera_matrix;
+        self.billboard_camera_matrix = billboard_camera_matrix;
+        self.viewport = Some(viewport);
+        self.graphics.draw_parameters.viewport = Some(viewport);
+    }
+
+    /// dimensions of the current viewport in pixels
+    fn dimensions(&self) -> (f32,f32) {
+        match self.viewport {
+            Some(viewport) => (viewport.width as f32, viewport.height as f32),
+            None => {
+                let (w,h) = self.graphics.context.get_framebuffer_dimensions();
+                (w as f32, h as f32)
+            },
         }
     }
 
+    /// whether a shape of this radius at this position is inside the current view
+    pub fn is_visible(&self, x: f32, y: f32, radius: f32) -> bool {
+        let (width,height) = self.dimensions();
+        let half_width = 1. / self.camera.zoom;
+        let half_height = half_width * height / width;
+        (x - self.camera.x).abs() <= half_width + radius
+            && (y - self.camera.y).abs() <= half_height + radius
+    }
+
     pub fn draw_square(&mut self, x: f32, y: f32, radius: f32, layer: Layer, color: Color) {
         self.draw_rectangle(x,y,radius*2.,radius*2.,layer,color);
     }
@@ -447,18 +884,7 @@ impl<'a> Frame<'a> {
             ]
         };
 
-        let uniform = uniform!{
-            trans: trans,
-            camera: if layer == Layer::BillBoard { self.billboard_camera_matrix } else { self.camera_matrix },
-            color: color.into_vec4(self.graphics.mode,&self.graphics.colors),
-        };
-
-        self.frame.draw(
-            &self.graphics.quad_vertex_buffer,
-            &self.graphics.quad_indices,
-            &self.graphics.program,
-            &uniform,
-            &self.graphics.draw_parameters).unwrap();
+        self.queue(Shape::Quad, trans, layer, color);
     }
 
     pub fn draw_circle(&mut self, x: f32, y: f32, radius: f32, layer: Layer, color: Color) {
@@ -471,29 +897,80 @@ impl<'a> Frame<'a> {
             ]
         };
 
-        let uniform = uniform!{
+        self.queue(Shape::Circle, trans, layer, color);
+    }
+
+    /// draw the next shapes with the material and its parameter
+    pub fn set_material(&mut self, material: Material, param: f32) {
+        self.material = material;
+        self.material_param = param;
+    }
+
+    /// queue the shape in the last batch of its shape and camera unless a shape of its
+    /// layer has been queued since, so the shapes of a layer are drawn in order
+    fn queue(&mut self, shape: Shape, trans: [[f32;4];4], layer: Layer, color: Color) {
+        let instance = Instance {
             trans: trans,
-            camera: if layer == Layer::BillBoard { self.billboard_camera_matrix } else { self.camera_matrix },
             color: color.into_vec4(self.graphics.mode,&self.graphics.colors),
+            param: self.material_param,
         };
+        let material = self.material;
+        let billboard = layer == Layer::BillBoard;
+        let mask = layer.mask();
+        for batch in self.batches.iter_mut().rev() {
+            if batch.shape == shape && batch.material == material && batch.billboard == billboard {
+                batch.layers |= mask;
+                batch.instances.push(instance);
+                return;
+            }
+            if batch.layers & mask != 0 {
+                break;
+            }
+        }
+        self.batches.push(Batch {
+            shape: shape,
+            material: material,
+            billboard: billboard,
+            layers: mask,
+            instances: vec!(instance),
+        });
+    }
 
-        self.frame.draw(
-            &self.graphics.circle_vertex_buffer,
-            &self.graphics.circle_indices,
-            &self.graphics.program,
-            &uniform,
-            &self.graphics.draw_parameters).unwrap();
+    /// draw the queued batches
+    fn flush(&mut self) {
+        let batches = std::mem::replace(&mut self.batches, vec!());
+        let time = self.graphics.time();
+        for batch in batches {
+            let instances = VertexBuffer::dynamic(&self.graphics.context, &batch.instances).unwrap();
+            let (vertex_buffer, indices) = match batch.shape {
+                Shape::Quad => (&self.graphics.quad_vertex_buffer, &self.graphics.quad_indices),
+                Shape::Circle => (&self.graphics.circle_vertex_buffer, &self.graphics.circle_indices),
+            };
+            let uniform = uniform!{
+                camera: if batch.billboard { self.billboard_camera_matrix } else { self.camera_matrix },
+                time: time,
+                circle: batch.shape == Shape::Circle,
+            };
+
+            self.frame.draw(
+                (vertex_buffer, instances.per_instance().unwrap()),
+                indices,
+                &self.graphics.materials[batch.material.0],
+                &uniform,
+                &self.graphics.draw_parameters).unwrap();
+
+            self.stats.batches += 1;
+            self.stats.instances += batch.instances.len();
+        }
     }
 
     pub fn draw_billboard_centered_text(&mut self, text: &str, color: Color) {
-        let (screen_width, screen_height) = {
-            let (w,h) = self.graphics.context.get_framebuffer_dimensions();
-            (w as f32, h as f32)
-        };
+        self.flush();
+        let (screen_width, screen_height) = self.dimensions();
 
         let glyphs = {
             use unicode_normalization::UnicodeNormalization;
-            let scale = Scale::uniform(self.graphics.billboard_font_scale * screen_height);
+            let scale = Scale::uniform(self.graphics.billboard_font_scale * self.graphics.ui_scale * screen_height);
 
             let mut lines = vec!();
             let mut current_line = vec!();
@@ -630,13 +1107,11 @@ impl<'a> Frame<'a> {
 
     /// (x,y) correspond to the down-left anchor
     pub fn draw_text(&mut self, x: f32, y: f32, scale: f32, text: &str, layer: Layer, color: Color) {
+        self.flush();
         let glyphs = {
             use unicode_normalization::UnicodeNormalization;
 
-            let (screen_width,_) = {
-                let (w,h) = self.graphics.context.get_framebuffer_dimensions();
-                (w as f32, h as f32)
-            };
+            let (screen_width,_) = self.dimensions();
 
             let scale = if layer == Layer::BillBoard {
                 Scale::uniform(scale * screen_width)
@@ -693,10 +1168,7 @@ impl<'a> Frame<'a> {
         };
 
         let vertex_buffer = {
-            let (screen_width, screen_height) = {
-                let (w,h) = self.graphics.context.get_framebuffer_dimensions();
-                (w as f32, h as f32)
-            };
+            let (screen_width, screen_height) = self.dimensions();
 
             let origin = if layer == Layer::BillBoard {
                 unimplemented!();
@@ -764,18 +1236,7 @@ impl<'a> Frame<'a> {
             [          0.,          0.,           1., 0.],
             [ trans[0][2], trans[1][2], layer.into(), 1.]
         ];
-        let uniform = uniform!{
-            trans: trans,
-            camera: if layer == Layer::BillBoard { self.billboard_camera_matrix } else { self.camera_matrix },
-            color: color.into_vec4(self.graphics.mode,&self.graphics.colors),
-        };
-
-        self.frame.draw(
-            &self.graphics.quad_vertex_buffer,
-            &self.graphics.quad_indices,
-            &self.graphics.program,
-            &uniform,
-            &self.graphics.draw_parameters).unwrap();
+        self.queue(Shape::Quad, trans, layer, color);
     }
 
     pub fn draw_line(&mut self, x: f32, y: f32, angle: f32, length: f32, width: f32, layer: Layer, color: Color) {
@@ -794,13 +1255,105 @@ impl<'a> Frame<'a> {
         self.draw_quad(trans,layer,color);
     }
 
+    /// apply the grading and the post passes to the screen
+    fn post_process(&mut self) {
+        use glium::uniforms::{MagnifySamplerFilter, SamplerWrapFunction};
+
+        let time = self.graphics.time();
+        let (from, to, change, duration) = self.graphics.grading;
+        let blend = if duration > 0. { ((time - change) / duration).min(1.) } else { 1. };
+        let grading = blend < 1. || to != Grading::default();
+        if !grading && self.graphics.post_passes.is_empty() {
+            return;
+        }
+        let scene = self.graphics.screen();
+        let resize = match self.graphics.post_texture {
+            Some(ref texture) => texture.get_width() != scene.width || texture.get_height() != Some(scene.height),
+            None => true,
+        };
+        if resize {
+            self.graphics.post_texture = Texture2d::empty(&self.graphics.context, scene.width, scene.height).ok();
+        }
+        let texture = match self.graphics.post_texture {
+            Some(ref texture) => texture,
+            None => return,
+        };
+
+        let draw_parameters = DrawParameters {
+            viewport: Some(scene),
+            .. Default::default()
+        };
+        let target = glium::BlitTarget {
+            left: 0,
+            bottom: 0,
+            width: scene.width as i32,
+            height: scene.height as i32,
+        };
+        if grading {
+            self.frame.blit_color(&scene, &texture.as_surface(), &target, MagnifySamplerFilter::Nearest);
+            let uniform = uniform!{
+                scene: texture,
+                from: self.graphics.gradings[from.0].sampled().wrap_function(SamplerWrapFunction::Clamp),
+                to: self.graphics.gradings[to.0].sampled().wrap_function(SamplerWrapFunction::Clamp),
+                blend: blend,
+            };
+            self.frame.draw(
+                &self.graphics.quad_vertex_buffer,
+                &self.graphics.quad_indices,
+                &self.graphics.grading_program,
+                &uniform,
+                &draw_parameters).unwrap();
+        }
+        for pass in &self.graphics.post_passes {
+            // each pass reads the result of the previous one
+            self.frame.blit_color(&scene, &texture.as_surface(), &target, MagnifySamplerFilter::Nearest);
+            let uniform = uniform!{
+                scene: texture,
+                resolution: [scene.width as f32, scene.height as f32],
+                time: time,
+            };
+            self.frame.draw(
+                &self.graphics.quad_vertex_buffer,
+                &self.graphics.quad_indices,
+                &self.graphics.post_programs[pass.0],
+                &uniform,
+                &draw_parameters).unwrap();
+        }
+    }
+
     #[inline]
-    pub fn finish(self) -> Result<(), SwapBuffersError> {
+    pub fn finish(mut self) -> Result<(), SwapBuffersError> {
+        self.flush();
+        self.graphics.stats = self.stats;
+        self.post_process();
+        self.graphics.draw_parameters.viewport = None;
+        if let (Some(pixel_art), Some(texture)) = (self.graphics.pixel_art, self.graphics.pixel_art_texture.as_ref()) {
+            use glium::uniforms::MagnifySamplerFilter;
+
+            let scene = self.graphics.screen();
+            let (width,height) = self.graphics.context.get_framebuffer_dimensions();
+            let output = pixel_art_output(width, height, [scene.width, scene.height], pixel_art.integer_scaling);
+
+            // the scene is copied out of the framebuffer then upscaled over it
+            self.frame.blit_color(&scene, &texture.as_surface(), &glium::BlitTarget {
+                left: 0,
+                bottom: 0,
+                width: scene.width as i32,
+                height: scene.height as i32,
+            }, MagnifySamplerFilter::Nearest);
+            self.frame.clear_color(0., 0., 0., 1.);
+            texture.as_surface().blit_color(&scene, &self.frame, &glium::BlitTarget {
+                left: output.left,
+                bottom: output.bottom,
+                width: output.width as i32,
+                height: output.height as i32,
+            }, MagnifySamplerFilter::Nearest);
+        }
         self.frame.finish()
     }
 }
 
-#[derive(Debug,Clone,Copy)]
+#[derive(Debug,Clone,Copy,PartialEq)]
 pub enum Color {
     Base1,
     Base2,
@@ -933,6 +1486,16 @@ impl Layer {
             _ => unreachable!(),
         }
     }
+
+    /// the bit of the layer in the batches
+    fn mask(self) -> u8 {
+        match self {
+            Layer::Floor => 1,
+            Layer::Middle => 2,
+            Layer::Ceil => 4,
+            Layer::BillBoard => 8,
+        }
+    }
 }
 
 impl Into<f32> for Layer {
@@ -993,6 +1556,72 @@ impl ColorsValue {
     }
 }
 
+/// the largest rectangle of the ratio of the logical resolution centered in the framebuffer
+const IDENTITY_LUT_SIZE: u32 = 16;
+
+/// the lookup table keeping the colors
+fn identity_lut(size: u32) -> Vec<u8> {
+    let mut lut = Vec::with_capacity((size*size*size*3) as usize);
+    let value = |i: u32| (i * 255 / (size - 1)) as u8;
+    // rows from top to bottom
+    for g in (0..size).rev() {
+        for b in 0..size {
+            for r in 0..size {
+                lut.extend_from_slice(&[value(r), value(g), value(b)]);
+            }
+        }
+    }
+    lut
+}
+
+fn lut_texture<F: Facade>(facade: &F, lut: Vec<u8>, size: u32) -> Result<Texture2d,GraphicsCreationError> {
+    // textures start at the bottom row
+    let row = (size*size*3) as usize;
+    let data = lut.chunks(row).rev().flat_map(|r| r.iter().cloned()).collect::<Vec<u8>>();
+    Texture2d::with_format(
+        facade,
+        glium::texture::RawImage2d {
+            data: Cow::Owned(data),
+            width: size*size,
+            height: size,
+            format: glium::texture::ClientFormat::U8U8U8,
+        },
+        glium::texture::UncompressedFloatFormat::U8U8U8,
+        glium::texture::MipmapsOption::NoMipmap).map_err(|e| GraphicsCreationError::Texture2dError(e))
+}
+
+pub fn letterbox(width: u32, height: u32, logical: [u32;2]) -> glium::Rect {
+    let logical_width = logical[0].max(1) as u64;
+    let logical_height = logical[1].max(1) as u64;
+    let (w, h) = if width as u64 * logical_height > height as u64 * logical_width {
+        ((height as u64 * logical_width / logical_height) as u32, height)
+    } else {
+        (width, (width as u64 * logical_height / logical_width) as u32)
+    };
+    glium::Rect {
+        left: (width - w)/2,
+        bottom: (height - h)/2,
+        width: w,
+        height: h,
+    }
+}
+
+/// the part of the framebuffer the pixel art scene is upscaled to, keeping its ratio
+pub fn pixel_art_output(width: u32, height: u32, scene: [u32;2], integer_scaling: bool) -> glium::Rect {
+    if !integer_scaling {
+        return letterbox(width, height, scene);
+    }
+    let scale = (width/scene[0].max(1)).min(height/scene[1].max(1)).max(1);
+    let w = (scene[0]*scale).min(width);
+    let h = (scene[1]*scale).min(height);
+    glium::Rect {
+        left: (width - w)/2,
+        bottom: (height - h)/2,
+        width: w,
+        height: h,
+    }
+}
+
 fn pixel_perfect(p: (f32,f32), screen_width: f32, screen_height: f32) -> (f32,f32) {
     (
         (p.0*screen_width/2.0).round()/screen_width*2.0,
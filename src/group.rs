@@ -0,0 +1,60 @@
+//! named collision groups: entities.groups in the configuration lists the names of
+//! the groups, the group of a name is the bit of its index so it matches the bitflags
+//! of the configuration. masks can be written with names:
+//!
+//! ```ignore
+//! physic_world.apply_on_shape(&pos, mask!("wall", "char"), &shape, &mut callback);
+//! physic_world.apply_on_shape(&pos, "monster", &shape, &mut callback);
+//! ```
+
+use config;
+
+/// the group of name if it is in the configuration
+pub fn try_group(name: &str) -> Option<u32> {
+    config.entities.groups.iter().position(|g| g == name).map(|i| 1 << i)
+}
+
+/// the group of name, panic if it isn't in the configuration
+pub fn group(name: &str) -> u32 {
+    try_group(name).unwrap_or_else(|| panic!("unknown group {}", name))
+}
+
+/// the mask of all the groups named
+macro_rules! mask {
+    ($($name:expr),*) => {
+        0u32 $(| ::group::group($name))*
+    };
+}
+
+/// queries take either a precomputed mask or group names
+pub trait IntoMask {
+    fn into_mask(self) -> u32;
+}
+
+impl IntoMask for u32 {
+    fn into_mask(self) -> u32 {
+        self
+    }
+}
+
+impl<'a> IntoMask for &'a str {
+    fn into_mask(self) -> u32 {
+        group(self)
+    }
+}
+
+impl<'a> IntoMask for &'a [&'a str] {
+    fn into_mask(self) -> u32 {
+        self.iter().fold(0, |mask, name| mask | group(name))
+    }
+}
+
+#[test]
+fn test_named_groups() {
+    assert_eq!(group("char"), config.entities.char_group.val);
+    assert_eq!(mask!("wall", "char"), config.entities.wall_group.val | config.entities.char_group.val);
+    assert_eq!(mask!(), 0);
+    assert_eq!((&["wall", "char"][..]).into_mask(), mask!("wall", "char"));
+    assert_eq!("zone".into_mask(), config.entities.zone_group.val);
+    assert_eq!(try_group("unknown"), None);
+}
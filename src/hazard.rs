@@ -0,0 +1,156 @@
+//! hazards damage the living entities over them periodically: the lava and spike tiles
+//! of the maps, or any entity given a Hazard component.
+//!
+//! each hazard ticks every period of its kind in hazards.kinds, damages the entities of
+//! hazards.mask over it and emits its effect. an entity damaged by a hazard is immune
+//! to the hazards of the same kind during the immunity of the kind, so standing over
+//! several tiles doesn't multiply the damage. the navigation data gives the cells of
+//! static hazards the cost hazards.nav_cost so monsters walk around them.
+
+use app;
+use specs;
+use config;
+use graphics::{self, Color};
+use configuration::{self, FromToml};
+use toml;
+use components::*;
+use resource::PhysicWorld;
+use specs::Join;
+use combat::{Damage, DamageType};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// * name: the name of the kind, `lava` and `spikes` are the tiles of the maps
+/// * damage: dealt at each tick
+/// * period: time between two ticks
+/// * immunity: time an entity damaged isn't damaged again by the kind
+/// * color: of the tiles
+/// * effect_color: of the jet emitted at each tick
+pub struct HazardDefinition {
+    pub name: String,
+    pub damage: f32,
+    pub period: f32,
+    pub immunity: f32,
+    pub color: Color,
+    pub effect_color: Color,
+}
+impl_from_into_toml_for_struct!( HazardDefinition {
+    name: String,
+    damage: f32,
+    period: f32,
+    immunity: f32,
+    color: Color,
+    effect_color: Color,
+});
+
+/// the index of the kind of name in hazards.kinds
+pub fn kind(name: &str) -> Option<usize> {
+    config.hazards.kinds.iter().position(|k| k.name == name)
+}
+
+pub struct Hazard {
+    pub kind: usize,
+    /// time until next tick
+    timer: f32,
+}
+impl specs::Component for Hazard {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Hazard {
+    pub fn new(kind: usize) -> Self {
+        Hazard {
+            kind: kind,
+            timer: 0.,
+        }
+    }
+
+    pub fn definition(&self) -> &'static HazardDefinition {
+        &config.hazards.kinds[self.kind]
+    }
+
+    /// advance the timer, true if the hazard ticks
+    fn tick(&mut self, dt: f32) -> bool {
+        self.timer -= dt;
+        if self.timer > 0. {
+            return false;
+        }
+        self.timer = (self.timer + self.definition().period).max(0.);
+        true
+    }
+}
+
+/// tick the hazards and keep the immunities of the entities they damaged
+#[derive(Default)]
+pub struct HazardSystem {
+    /// time left of the immunity of an entity to a kind
+    immunities: HashMap<(specs::Entity,usize),f32>,
+}
+impl specs::System<app::UpdateContext> for HazardSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut hazards, mut lives, states, types, physic_world, entities) = arg.fetch(|world| {
+            (
+                world.write::<Hazard>(),
+                world.write::<Life>(),
+                world.read::<PhysicState>(),
+                world.read::<PhysicType>(),
+                world.read_resource::<PhysicWorld>(),
+                world.entities(),
+            )
+        });
+
+        for time in self.immunities.values_mut() {
+            *time -= context.dt;
+        }
+        self.immunities.retain(|_, time| *time > 0.);
+
+        for (hazard, state, typ, entity) in (&mut hazards, &states, &types, &entities).iter() {
+            if !hazard.tick(context.dt) {
+                continue;
+            }
+            let definition = hazard.definition();
+
+            context.effect_tx.send(app::Effect::Line {
+                origin: state.position,
+                length: config.effect.length,
+                angle: PI/2.,
+                persistance: config.effect.persistance,
+                thickness: config.effect.thickness,
+                layer: graphics::Layer::Ceil,
+                color: definition.effect_color,
+            }).unwrap();
+
+            let mut victims = vec!();
+            physic_world.apply_on_shape(&state.position, config.hazards.mask.val, &typ.shape, &mut |other_entity,_| {
+                if *other_entity != entity && !victims.contains(other_entity) {
+                    victims.push(*other_entity);
+                }
+            });
+
+            for victim in victims {
+                if self.immunities.contains_key(&(victim, hazard.kind)) {
+                    continue;
+                }
+                let position = match (lives.get_mut(victim), states.get(victim)) {
+                    (Some(life), Some(victim_state)) => {
+                        life.damage(definition.damage, entity);
+                        victim_state.position
+                    },
+                    _ => continue,
+                };
+                self.immunities.insert((victim, hazard.kind), definition.immunity);
+                context.event_tx.send(app::GameEvent::Damaged(Damage::new(entity, victim, position, definition.damage, DamageType::Hazard))).unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_hazard_tick() {
+    let lava = kind("lava").unwrap();
+    let period = config.hazards.kinds[lava].period;
+
+    let mut hazard = Hazard::new(lava);
+    assert!(hazard.tick(0.01));
+    assert!(!hazard.tick(period/2.));
+    assert!(hazard.tick(period/2.));
+}
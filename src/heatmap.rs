@@ -0,0 +1,122 @@
+//! occupancy of the cells of the physic world sampled over frames to tune physic.unit:
+//! overfull cells make collisions and queries slow, mostly empty ones waste lookups.
+//!
+//! the maximum and average number of entities of each cell is exported as csv and as
+//! a grey png where white is the most occupied cell.
+
+use physic::PhysicWorld;
+use png;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+
+pub struct Heatmap {
+    samples: usize,
+    /// maximum and sum of the number of entities of each cell over the samples
+    cells: HashMap<[i32;2],(usize,usize)>,
+}
+
+impl Heatmap {
+    pub fn new() -> Self {
+        Heatmap {
+            samples: 0,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn sample(&mut self, physic_world: &PhysicWorld) {
+        self.samples += 1;
+        for (cell, count) in physic_world.cell_counts() {
+            let entry = self.cells.entry(cell).or_insert((0,0));
+            entry.0 = entry.0.max(count);
+            entry.1 += count;
+        }
+    }
+
+    fn average(&self, sum: usize) -> f32 {
+        sum as f32 / self.samples.max(1) as f32
+    }
+
+    /// the lowest and highest cells ever occupied
+    fn bounds(&self) -> Option<([i32;2],[i32;2])> {
+        self.cells.keys().fold(None, |bounds, cell| match bounds {
+            None => Some((*cell,*cell)),
+            Some((min,max)) => Some((
+                [min[0].min(cell[0]), min[1].min(cell[1])],
+                [max[0].max(cell[0]), max[1].max(cell[1])],
+            )),
+        })
+    }
+
+    /// a line x,y,max,average per occupied cell
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut cells: Vec<_> = self.cells.iter().collect();
+        cells.sort_by_key(|&(cell,_)| *cell);
+
+        try!(writeln!(writer, "x,y,max,average"));
+        for (cell, &(max,sum)) in cells {
+            try!(writeln!(writer, "{},{},{},{:.3}", cell[0], cell[1], max, self.average(sum)));
+        }
+        Ok(())
+    }
+
+    /// a pixel per cell of the bounds, its luminosity is the maximum of the cell
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let (min,max) = self.bounds().unwrap_or(([0,0],[0,0]));
+        let width = (max[0] - min[0] + 1) as u32;
+        let height = (max[1] - min[1] + 1) as u32;
+        let highest = self.cells.values().map(|&(max,_)| max).max().unwrap_or(0).max(1);
+
+        let mut data = vec![0u8; (width*height) as usize];
+        for (cell, &(count,_)) in &self.cells {
+            // -y because opengl and image editor are usually inverted
+            let x = (cell[0] - min[0]) as u32;
+            let y = (max[1] - cell[1]) as u32;
+            data[(x + y*width) as usize] = (count*255/highest) as u8;
+        }
+
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set(png::ColorType::Grayscale).set(png::BitDepth::Eight);
+        let mut writer = try!(encoder.write_header());
+        writer.write_image_data(&data)
+    }
+
+    /// the number of cells of the bounds by maximum number of entities, and the
+    /// average number of entities of occupied cells
+    pub fn distribution(&self) -> String {
+        let mut counts = BTreeMap::new();
+        let mut area = 0;
+        if let Some((min,max)) = self.bounds() {
+            area = ((max[0] - min[0] + 1)*(max[1] - min[1] + 1)) as usize;
+        }
+        *counts.entry(0).or_insert(0) += area - self.cells.len();
+        for &(max,_) in self.cells.values() {
+            *counts.entry(max).or_insert(0) += 1;
+        }
+
+        let mut text = String::new();
+        for (max, cells) in counts {
+            writeln!(text, "  {:>3} entities: {:>6} cells ({:.1}%)", max, cells, 100.*cells as f32/area.max(1) as f32).unwrap();
+        }
+        let sum: f32 = self.cells.values().map(|&(_,sum)| self.average(sum)).sum();
+        writeln!(text, "  average of occupied cells: {:.2}", sum/self.cells.len().max(1) as f32).unwrap();
+        text
+    }
+}
+
+#[test]
+fn test_heatmap() {
+    let mut heatmap = Heatmap::new();
+    heatmap.samples = 2;
+    heatmap.cells.insert([0,0], (3,4));
+    heatmap.cells.insert([2,1], (1,1));
+    assert_eq!(heatmap.bounds(), Some(([0,0],[2,1])));
+
+    let mut csv = vec!();
+    heatmap.write_csv(&mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(), "x,y,max,average\n0,0,3,2.000\n2,1,1,0.500\n");
+
+    let distribution = heatmap.distribution();
+    assert!(distribution.contains("  0 entities:      4 cells"));
+    assert!(distribution.contains("average of occupied cells: 1.25"));
+}
@@ -0,0 +1,73 @@
+//! highlighted entities are drawn over an outline of their shape grown by the
+//! thickness, in a pass before the entities so it surrounds any shape and is hidden by
+//! the entities in front. the interactables targeted by the players and the entity
+//! picked by the inspector are highlighted.
+
+use app;
+use specs;
+use config;
+use graphics::Color;
+use components::*;
+use specs::Join;
+
+pub struct Highlight {
+    pub color: Color,
+    pub thickness: f32,
+    /// set by the interaction of a player
+    interaction: bool,
+}
+impl specs::Component for Highlight {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Highlight {
+    pub fn new(color: Color, thickness: f32) -> Self {
+        Highlight {
+            color: color,
+            thickness: thickness,
+            interaction: false,
+        }
+    }
+
+    /// the highlight of the entity picked by the inspector
+    pub fn selection() -> Self {
+        Highlight::new(config.highlight.selection_color, config.highlight.selection_thickness)
+    }
+
+    fn interaction() -> Self {
+        Highlight {
+            interaction: true,
+            .. Highlight::new(config.highlight.interaction_color, config.highlight.interaction_thickness)
+        }
+    }
+}
+
+/// highlight the interactables targeted by the players
+pub struct HighlightSystem;
+impl specs::System<app::UpdateContext> for HighlightSystem {
+    fn run(&mut self, arg: specs::RunArg, _context: app::UpdateContext) {
+        let (mut highlights, interactors, players, entities) = arg.fetch(|world| {
+            (
+                world.write::<Highlight>(),
+                world.read::<Interactor>(),
+                world.read::<PlayerControl>(),
+                world.entities(),
+            )
+        });
+
+        let targets = (&interactors, &players).iter()
+            .filter_map(|(interactor, _)| interactor.target())
+            .collect::<Vec<_>>();
+        let previous = (&highlights, &entities).iter()
+            .filter(|&(highlight, entity)| highlight.interaction && !targets.contains(&entity))
+            .map(|(_, entity)| entity)
+            .collect::<Vec<_>>();
+        for entity in previous {
+            highlights.remove(entity);
+        }
+        for target in targets {
+            if highlights.get(target).is_none() {
+                highlights.insert(target, Highlight::interaction());
+            }
+        }
+    }
+}
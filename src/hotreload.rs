@@ -0,0 +1,84 @@
+//! the watcher polls the modification time of the configuration file and of the files
+//! the current level is built from every hot_reload.period seconds, for fast iteration
+//! on the data without restarting.
+//!
+//! a file created, removed or modified is reported as changed. the level files rebuild
+//! the level, its entities are respawned. the configuration is loaded once at start
+//! and shared by every thread, so a change of it is only checked and reported.
+
+use config;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+pub struct Watcher {
+    /// the files and their modification time when last polled
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    /// time until the next poll
+    timer: f32,
+}
+impl Watcher {
+    pub fn new() -> Self {
+        Watcher {
+            files: vec!(),
+            timer: 0.,
+        }
+    }
+
+    /// watch the files instead of the previous ones
+    pub fn watch(&mut self, files: Vec<PathBuf>) {
+        self.files = files.into_iter().map(|file| {
+            let time = modified(&file);
+            (file, time)
+        }).collect();
+    }
+
+    /// the files changed since the last poll, if polled
+    pub fn update(&mut self, dt: f32) -> Vec<PathBuf> {
+        if !config.hot_reload.enabled {
+            return vec!();
+        }
+        self.timer -= dt;
+        if self.timer > 0. {
+            return vec!();
+        }
+        self.timer = config.hot_reload.period;
+        self.poll()
+    }
+
+    fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = vec!();
+        for &mut (ref file, ref mut time) in &mut self.files {
+            let new_time = modified(file);
+            if new_time != *time {
+                *time = new_time;
+                changed.push(file.clone());
+            }
+        }
+        changed
+    }
+}
+
+#[test]
+fn test_watcher() {
+    use std::env;
+    use std::io::Write;
+
+    let path = env::temp_dir().join("ruga_test_watcher.toml");
+    let _ = fs::remove_file(&path);
+
+    let mut watcher = Watcher::new();
+    watcher.watch(vec!(path.clone()));
+    assert!(watcher.poll().is_empty());
+
+    fs::File::create(&path).unwrap().write_all(b"a = 1").unwrap();
+    assert_eq!(watcher.poll(), vec!(path.clone()));
+    assert!(watcher.poll().is_empty());
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(watcher.poll(), vec!(path));
+}
@@ -0,0 +1,119 @@
+//! the ids of the replicated entities sent by the server. the ids of the entities of
+//! the world are reused as soon as an entity is deleted and start over in the world of
+//! each level, so a client could take a new entity for the one it replaced. an id is
+//! instead an index in its low replication.id_index_bits and the generation of the
+//! index in its high bits:
+//! * a freed index is reused after the ones freed before it, with the next generation
+//! * an index is retired once its generations are exhausted
+//! * when no index is left replication.id_exhaustion panics or refuses the id, the
+//!   entity isn't replicated then
+//!
+//! the allocator holds no other state, the same allocations and frees in the same
+//! order give the same ids.
+
+use config;
+use configuration::{self, FromToml};
+use toml;
+use std::collections::VecDeque;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum IdExhaustion {
+    Panic,
+    Refuse,
+}
+impl_from_into_toml_for_enum!(IdExhaustion {
+    Panic,
+    Refuse,
+});
+
+fn max_index() -> u32 {
+    1 << config.replication.id_index_bits
+}
+
+fn max_generation() -> u32 {
+    1 << (32 - config.replication.id_index_bits)
+}
+
+pub fn index(id: u32) -> u32 {
+    id & (max_index() - 1)
+}
+
+pub fn generation(id: u32) -> u32 {
+    id >> config.replication.id_index_bits
+}
+
+pub struct IdAllocator {
+    next: u32,
+    free: VecDeque<u32>,
+    generations: Vec<u32>,
+}
+
+impl IdAllocator {
+    pub fn new() -> Self {
+        IdAllocator {
+            next: 0,
+            free: VecDeque::new(),
+            generations: vec!(),
+        }
+    }
+
+    /// a free id, none if they are exhausted and refused
+    pub fn alloc(&mut self) -> Option<u32> {
+        let index = match self.free.pop_front() {
+            Some(index) => index,
+            None if self.next < max_index() => {
+                self.next += 1;
+                self.generations.push(0);
+                self.next - 1
+            },
+            None => match config.replication.id_exhaustion {
+                IdExhaustion::Panic => panic!("ERROR: the {} replication ids are exhausted", max_index()),
+                IdExhaustion::Refuse => return None,
+            },
+        };
+        Some(self.generations[index as usize] << config.replication.id_index_bits | index)
+    }
+
+    /// free the id if it is allocated
+    pub fn free(&mut self, id: u32) {
+        let index = index(id);
+        let current = match self.generations.get_mut(index as usize) {
+            Some(current) => current,
+            None => return,
+        };
+        if *current != generation(id) {
+            return;
+        }
+        *current += 1;
+        if *current < max_generation() {
+            self.free.push_back(index);
+        }
+    }
+}
+
+#[test]
+fn test_id_allocator() {
+    let mut ids = IdAllocator::new();
+    let a = ids.alloc().unwrap();
+    let b = ids.alloc().unwrap();
+    assert_eq!((index(a), index(b)), (0, 1));
+
+    ids.free(b);
+    ids.free(a);
+    ids.free(a);
+    // freed once and in order, with the next generation
+    let c = ids.alloc().unwrap();
+    assert_eq!((index(c), generation(c)), (1, 1));
+    assert!(c != b);
+    let d = ids.alloc().unwrap();
+    assert_eq!((index(d), generation(d)), (0, 1));
+    // a stale id is not freed
+    ids.free(b);
+    assert_eq!(index(ids.alloc().unwrap()), 2);
+
+    let mut other = IdAllocator::new();
+    let replay = (0..2).map(|_| other.alloc().unwrap()).collect::<Vec<_>>();
+    other.free(replay[1]);
+    other.free(replay[0]);
+    assert_eq!(other.alloc(), Some(c));
+}
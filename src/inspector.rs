@@ -0,0 +1,341 @@
+//! debug inspector of an entity: its components, timers and AI state and the last
+//! gameplay events and damages it took part in are displayed, numeric fields can be tweaked.
+//!
+//! the inspected entity is picked near the player with keys.inspector and cycled
+//! with keys.inspector_next, fields are selected with keys.inspector_field and
+//! tweaked with keys.inspector_decrease and keys.inspector_increase.
+
+use app::GameEvent;
+use combat::CombatLog;
+use specs;
+use config;
+use components::*;
+use progression::Stat;
+use specs::Join;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// number of events kept for the inspected entity
+const MESSAGES: usize = 8;
+/// number of damages of the combat log shown for the inspected entity
+const DAMAGES: usize = 4;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Field {
+    PositionX,
+    PositionY,
+    VelocityX,
+    VelocityY,
+    Weight,
+    Damping,
+    Force,
+    ForceDirection,
+    ForceIntensity,
+}
+
+const FIELDS: [Field;9] = [
+    Field::PositionX,
+    Field::PositionY,
+    Field::VelocityX,
+    Field::VelocityY,
+    Field::Weight,
+    Field::Damping,
+    Field::Force,
+    Field::ForceDirection,
+    Field::ForceIntensity,
+];
+
+impl Field {
+    fn name(&self) -> &'static str {
+        match *self {
+            Field::PositionX => "position.x",
+            Field::PositionY => "position.y",
+            Field::VelocityX => "velocity.x",
+            Field::VelocityY => "velocity.y",
+            Field::Weight => "weight",
+            Field::Damping => "damping",
+            Field::Force => "force",
+            Field::ForceDirection => "force.direction",
+            Field::ForceIntensity => "force.intensity",
+        }
+    }
+
+    /// the change of a tweak
+    fn step(&self) -> f32 {
+        match *self {
+            Field::PositionX | Field::PositionY => 0.5,
+            _ => 0.1,
+        }
+    }
+
+    fn get(&self, world: &specs::World, entity: specs::Entity) -> Option<f32> {
+        match *self {
+            Field::PositionX => world.read::<PhysicState>().get(entity).map(|s| s.position[0]),
+            Field::PositionY => world.read::<PhysicState>().get(entity).map(|s| s.position[1]),
+            Field::VelocityX => world.read::<PhysicState>().get(entity).map(|s| s.velocity[0]),
+            Field::VelocityY => world.read::<PhysicState>().get(entity).map(|s| s.velocity[1]),
+            Field::Weight => world.read::<PhysicType>().get(entity).map(|t| t.weight),
+            Field::Damping => world.read::<PhysicType>().get(entity).map(|t| t.damping),
+            Field::Force => world.read::<PhysicType>().get(entity).map(|t| t.force),
+            Field::ForceDirection => world.read::<PhysicForce>().get(entity).map(|f| f.direction),
+            Field::ForceIntensity => world.read::<PhysicForce>().get(entity).map(|f| f.intensity),
+        }
+    }
+
+    fn set(&self, world: &specs::World, entity: specs::Entity, value: f32) {
+        match *self {
+            Field::PositionX => if let Some(s) = world.write::<PhysicState>().get_mut(entity) { s.position[0] = value },
+            Field::PositionY => if let Some(s) = world.write::<PhysicState>().get_mut(entity) { s.position[1] = value },
+            Field::VelocityX => if let Some(s) = world.write::<PhysicState>().get_mut(entity) { s.velocity[0] = value },
+            Field::VelocityY => if let Some(s) = world.write::<PhysicState>().get_mut(entity) { s.velocity[1] = value },
+            Field::Weight => if let Some(t) = world.write::<PhysicType>().get_mut(entity) { t.weight = value.max(0.1) },
+            Field::Damping => if let Some(t) = world.write::<PhysicType>().get_mut(entity) { t.damping = value.max(0.) },
+            Field::Force => if let Some(t) = world.write::<PhysicType>().get_mut(entity) { t.force = value.max(0.) },
+            Field::ForceDirection => if let Some(f) = world.write::<PhysicForce>().get_mut(entity) { f.direction = value },
+            Field::ForceIntensity => if let Some(f) = world.write::<PhysicForce>().get_mut(entity) { f.intensity = value.max(0.).min(1.) },
+        }
+    }
+}
+
+/// the name of the event if the entity takes part in it
+fn event_name(event: &GameEvent, entity: specs::Entity) -> Option<String> {
+    match *event {
+        GameEvent::InventoryChanged(e) if e == entity => Some("inventory changed".into()),
+        GameEvent::ItemPickedUp(e,stack) if e == entity => Some(format!("picked up {} of item {}",stack.count,stack.item)),
+        GameEvent::InteractionPromptChanged(e,_) if e == entity => Some("interaction prompt changed".into()),
+        GameEvent::Interact(e,other) if e == entity => Some(format!("interact with {}",other.get_id())),
+        GameEvent::CheckpointReached(e,_) if e == entity => Some("checkpoint reached".into()),
+        GameEvent::Respawned(e) if e == entity => Some("respawned".into()),
+        GameEvent::Died(e,_,_) if e == entity => Some("died".into()),
+        GameEvent::EnteredLiquid(e) if e == entity => Some("entered liquid".into()),
+        GameEvent::LeftLiquid(e) if e == entity => Some("left liquid".into()),
+        GameEvent::OverlapEnter(a,b) if a == entity || b == entity => Some(format!("overlap enter {} {}",a.get_id(),b.get_id())),
+        GameEvent::OverlapExit(a,b) if a == entity || b == entity => Some(format!("overlap exit {} {}",a.get_id(),b.get_id())),
+        GameEvent::MeleeHit(a,b,_) if a == entity || b == entity => Some(format!("melee hit {} {}",a.get_id(),b.get_id())),
+        GameEvent::Damaged(damage) if damage.source == entity || damage.target == entity => {
+            Some(format!("{} damage {} {} {}",damage.typ.name(),damage.source.get_id(),damage.target.get_id(),damage.amount))
+        },
+        GameEvent::Killed(a,b,_) if a == entity || b == entity => Some(format!("killed {} {}",a.get_id(),b.get_id())),
+        GameEvent::Bought(a,b,stack,price) if a == entity || b == entity => Some(format!("bought {} of item {} for {}",stack.count,stack.item,price)),
+        GameEvent::Sold(a,b,stack,price) if a == entity || b == entity => Some(format!("sold {} of item {} for {}",stack.count,stack.item,price)),
+        GameEvent::DashStarted(e) if e == entity => Some("dash started".into()),
+        GameEvent::DashEnded(e) if e == entity => Some("dash ended".into()),
+        GameEvent::Grabbed(a,b) if a == entity || b == entity => Some(format!("grabbed {} {}",a.get_id(),b.get_id())),
+        GameEvent::Released(a,b) if a == entity || b == entity => Some(format!("released {} {}",a.get_id(),b.get_id())),
+        GameEvent::Thrown(a,b) if a == entity || b == entity => Some(format!("thrown {} {}",a.get_id(),b.get_id())),
+        GameEvent::MovementModeChanged(e,mode) if e == entity => Some(format!("movement mode {}",mode.name())),
+        GameEvent::BossEncounterStarted(e,_) if e == entity => Some("boss encounter started".into()),
+        GameEvent::BossPhaseChanged(e,phase) if e == entity => Some(format!("boss phase {}",phase)),
+        GameEvent::RegionEntered(e,ref name) if e == entity => Some(format!("entered region {}",name)),
+        GameEvent::RegionExited(e,ref name) if e == entity => Some(format!("exited region {}",name)),
+        _ => None,
+    }
+}
+
+pub struct Inspector {
+    entity: Option<specs::Entity>,
+    selected: usize,
+    messages: VecDeque<String>,
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Inspector {
+            entity: None,
+            selected: 0,
+            messages: VecDeque::new(),
+        }
+    }
+
+    /// the inspected entity
+    pub fn entity(&self) -> Option<specs::Entity> {
+        self.entity
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.entity.is_some()
+    }
+
+    pub fn close(&mut self) {
+        self.entity = None;
+    }
+
+    fn inspect(&mut self, entity: Option<specs::Entity>) {
+        if entity != self.entity {
+            self.messages.clear();
+        }
+        self.entity = entity;
+    }
+
+    /// inspect the entity the nearest to position
+    pub fn pick(&mut self, world: &specs::World, position: [f32;2]) {
+        let states = world.read::<PhysicState>();
+        let entities = world.entities();
+        let distance = |s: &PhysicState| (s.position[0] - position[0]).powi(2) + (s.position[1] - position[1]).powi(2);
+        let nearest = (&states, &entities).iter()
+            .filter(|&(_, entity)| Some(entity) != self.entity)
+            .fold(None, |nearest: Option<(f32,specs::Entity)>, (state, entity)| {
+                let d = distance(state);
+                match nearest {
+                    Some((nearest_d, _)) if nearest_d <= d => nearest,
+                    _ => Some((d, entity)),
+                }
+            });
+        self.inspect(nearest.map(|(_, entity)| entity));
+    }
+
+    /// inspect the entity of the next id
+    pub fn next(&mut self, world: &specs::World) {
+        let states = world.read::<PhysicState>();
+        let entities = world.entities();
+        let mut ids: Vec<specs::Entity> = (&states, &entities).iter().map(|(_, e)| e).collect();
+        ids.sort_by_key(|e| e.get_id());
+        let current = self.entity.map(|e| e.get_id()).unwrap_or(0);
+        let next = ids.iter().find(|e| e.get_id() > current).or(ids.first()).cloned();
+        self.inspect(next);
+    }
+
+    pub fn select_next_field(&mut self) {
+        self.selected = (self.selected + 1) % FIELDS.len();
+    }
+
+    /// add steps of the selected field to it
+    pub fn tweak(&mut self, world: &specs::World, steps: f32) {
+        if let Some(entity) = self.entity {
+            let field = FIELDS[self.selected];
+            if let Some(value) = field.get(world, entity) {
+                field.set(world, entity, value + steps*field.step());
+            }
+        }
+    }
+
+    /// keep the events of the inspected entity
+    pub fn record(&mut self, event: &GameEvent) {
+        if let Some(name) = self.entity.and_then(|entity| event_name(event, entity)) {
+            if self.messages.len() == MESSAGES {
+                self.messages.pop_front();
+            }
+            self.messages.push_back(name);
+        }
+    }
+
+    /// the last damages dealt or taken by the inspected entity
+    pub fn damages_text(&self, log: &CombatLog) -> String {
+        let mut text = String::new();
+        if let Some(entity) = self.entity {
+            for damage in log.involving(entity).into_iter().take(DAMAGES) {
+                let verb = if damage.source == entity { "dealt" } else { "taken" };
+                write!(text, "{} {:.1} {}
+", verb, damage.amount, damage.typ.name()).unwrap();
+            }
+        }
+        text
+    }
+
+    /// the description of the inspected entity, closed if it is deleted
+    pub fn text(&mut self, world: &specs::World) -> Option<String> {
+        let entity = match self.entity {
+            Some(entity) if world.read::<PhysicState>().get(entity).is_some() => entity,
+            _ => {
+                self.entity = None;
+                return None;
+            },
+        };
+
+        let mut text = String::new();
+        write!(text, "entity {}\n", entity.get_id()).unwrap();
+        for (i, field) in FIELDS.iter().enumerate() {
+            if let Some(value) = field.get(world, entity) {
+                let cursor = if i == self.selected { ">" } else { " " };
+                write!(text, "{} {}: {:.3}\n", cursor, field.name(), value).unwrap();
+            }
+        }
+        if let Some(typ) = world.read::<PhysicType>().get(entity) {
+            write!(text, "group: {:b} mask: {:b}\n", typ.group, typ.mask).unwrap();
+        }
+        if let Some(life) = world.read::<Life>().get(entity) {
+            write!(text, "life: {} alive: {} health: {:.1}/{:.1}\n", life.kind().name(), life.is_alive(), life.health(), life.max_health()).unwrap();
+        }
+        if let Some(experience) = world.read::<Experience>().get(entity) {
+            write!(text, "level: {} xp: {:.1}\n", experience.level, experience.xp).unwrap();
+        }
+        if let Some(attributes) = world.read::<Attributes>().get(entity) {
+            write!(text, "damage: {:.2} speed: {:.2}\n", attributes.value(Stat::Damage), attributes.value(Stat::Speed)).unwrap();
+        }
+        if let Some(lod) = world.read::<UpdateLod>().get(entity) {
+            write!(text, "lod period: {} dormant: {}\n", lod.period, lod.dormant).unwrap();
+        }
+        if let Some(monster) = world.read::<MonsterControl>().get(entity) {
+            write!(text, "monster next lookup: {:.2} target: {:?}\n", monster.next_lookup(), monster.target().map(|e| e.get_id())).unwrap();
+        }
+        if let Some(faction) = world.read::<Faction>().get(entity) {
+            write!(text, "faction: {}\n", config.factions.names[faction.id]).unwrap();
+        }
+        if let Some(patrol) = world.read::<Patrol>().get(entity) {
+            write!(text, "patrol active: {} target: {:?}\n", patrol.active, patrol.target()).unwrap();
+        }
+        if let Some(attack) = world.read::<MeleeAttack>().get(entity) {
+            write!(text, "melee swinging: {}\n", attack.is_swinging()).unwrap();
+        }
+        if let Some(dash) = world.read::<Dash>().get(entity) {
+            write!(text, "dashing: {}\n", dash.is_dashing()).unwrap();
+        }
+        if let Some(mount) = world.read::<Mount>().get(entity) {
+            write!(text, "mount heading: {:.2} rider: {:?}\n", mount.heading(), mount.rider().map(|e| e.get_id())).unwrap();
+        }
+        if let Some(boss) = world.read::<Boss>().get(entity) {
+            write!(text, "boss phase: {}\n", boss.phase()).unwrap();
+        }
+        if let Some(rope) = world.read::<Rope>().get(entity) {
+            write!(text, "rope length: {:.2} anchor: {:?}\n", rope.length, rope.anchor()).unwrap();
+        }
+        if let Some(inventory) = world.read::<Inventory>().get(entity) {
+            write!(text, "inventory: {} stacks\n", inventory.slots().iter().filter(|s| s.is_some()).count()).unwrap();
+        }
+        if let Some(abilities) = world.read::<Abilities>().get(entity) {
+            for (i, pool) in config.abilities.pools.iter().enumerate() {
+                write!(text, "{}: {:.1}\n", pool.name, abilities.pool(i)).unwrap();
+            }
+            for (i, ability) in config.abilities.definitions.iter().enumerate() {
+                write!(text, "{} cooldown: {:.2}\n", ability.name, abilities.cooldown(i)).unwrap();
+            }
+        }
+        if let Some((ability, remaining)) = world.read::<Caster>().get(entity).and_then(|caster| caster.casting()) {
+            write!(text, "casting {}: {:.2}\n", config.abilities.definitions[ability].name, remaining).unwrap();
+        }
+        if let Some(wallet) = world.read::<Wallet>().get(entity) {
+            write!(text, "money: {}\n", wallet.money).unwrap();
+        }
+        if world.read::<PlayerControl>().get(entity).is_some() {
+            write!(text, "player\n").unwrap();
+        }
+        for message in &self.messages {
+            write!(text, "- {}\n", message).unwrap();
+        }
+        Some(text)
+    }
+}
+
+#[test]
+fn test_inspector_tweak() {
+    let mut world = specs::World::new();
+    world.register::<PhysicState>();
+    world.register::<PhysicType>();
+    world.register::<PhysicForce>();
+    let far = world.create_now().with(PhysicState::new([10.,0.])).build();
+    let near = world.create_now().with(PhysicState::new([1.,0.])).build();
+
+    let mut inspector = Inspector::new();
+    inspector.pick(&world, [0.,0.]);
+    assert_eq!(inspector.entity, Some(near));
+    inspector.pick(&world, [0.,0.]);
+    assert_eq!(inspector.entity, Some(far));
+
+    inspector.tweak(&world, 2.);
+    assert_eq!(world.read::<PhysicState>().get(far).unwrap().position, [11.,0.]);
+
+    inspector.record(&GameEvent::Respawned(near));
+    inspector.record(&GameEvent::Respawned(far));
+    assert_eq!(inspector.messages.len(), 1);
+    assert_eq!(inspector.messages[0], "respawned");
+}
@@ -0,0 +1,95 @@
+use app;
+use specs;
+use config;
+use components::*;
+use resource::*;
+use specs::Join;
+
+/// called with the interactor and the interactable entities
+pub type InteractionCallback = Box<Fn(specs::Entity,specs::Entity,&app::UpdateContext) + Send + Sync>;
+
+pub struct Interactable {
+    pub prompt: String,
+    callback: InteractionCallback,
+}
+impl specs::Component for Interactable {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Interactable {
+    pub fn new(prompt: String, callback: InteractionCallback) -> Self {
+        Interactable {
+            prompt: prompt,
+            callback: callback,
+        }
+    }
+}
+
+/// an entity able to interact with the nearest interactable
+/// in front of it, the facing direction is the direction of its force
+pub struct Interactor {
+    target: Option<specs::Entity>,
+    pub requested: bool,
+}
+impl specs::Component for Interactor {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Interactor {
+    pub fn new() -> Self {
+        Interactor {
+            target: None,
+            requested: false,
+        }
+    }
+    pub fn target(&self) -> Option<specs::Entity> {
+        self.target
+    }
+}
+
+pub struct InteractionSystem;
+impl specs::System<app::UpdateContext> for InteractionSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut interactors, interactables, states, forces, physic_world, entities) = arg.fetch(|world| {
+            (
+                world.write::<Interactor>(),
+                world.read::<Interactable>(),
+                world.read::<PhysicState>(),
+                world.read::<PhysicForce>(),
+                world.read_resource::<PhysicWorld>(),
+                world.entities(),
+            )
+        });
+
+        for (interactor, state, force, entity) in (&mut interactors, &states, &forces, &entities).iter() {
+            let mut nearest: Option<(specs::Entity,f32)> = None;
+            physic_world.apply_on_cone_except(
+                &[entity],
+                &state.position,
+                force.direction,
+                config.entities.interaction_angle,
+                config.entities.interaction_range,
+                config.entities.interaction_mask.val,
+                &mut |other_entity, distance| {
+                    if interactables.get(*other_entity).is_none() { return }
+                    if nearest.map(|(_,d)| distance < d).unwrap_or(true) {
+                        nearest = Some((*other_entity,distance));
+                    }
+                });
+
+            let target = nearest.map(|(e,_)| e);
+            if target != interactor.target {
+                interactor.target = target;
+                let prompt = target.and_then(|t| interactables.get(t)).map(|i| i.prompt.clone());
+                context.event_tx.send(app::GameEvent::InteractionPromptChanged(entity,prompt)).unwrap();
+            }
+
+            if interactor.requested {
+                interactor.requested = false;
+                if let Some(target) = target {
+                    let interactable = interactables.get(target).expect("interactor target expect interactable component");
+                    context.event_tx.send(app::GameEvent::Interact(entity,target)).unwrap();
+                    (interactable.callback)(entity,target,&context);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use specs;
+use config;
+use std::collections::VecDeque;
+
+/// authoritative positions of an entity with their time,
+/// the entity is rendered at the position interpolated at the render time
+pub struct Interpolated {
+    samples: VecDeque<(f32,[f32;2],[f32;2])>,
+}
+impl specs::Component for Interpolated {
+    type Storage = specs::VecStorage<Self>;
+}
+
+impl Interpolated {
+    pub fn new() -> Self {
+        Interpolated {
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// samples older than the last one are ignored
+    pub fn push(&mut self, time: f32, position: [f32;2], velocity: [f32;2]) {
+        if self.samples.back().map(|&(t,_,_)| time <= t).unwrap_or(false) {
+            return;
+        }
+        self.samples.push_back((time, position, velocity));
+        while self.samples.len() > config.interpolation.buffer_size {
+            self.samples.pop_front();
+        }
+    }
+
+    /// forget the samples, for entities teleported
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// the position interpolated between the samples around time,
+    /// or extrapolated from the last sample velocity for at most the extrapolation time
+    pub fn position(&self, time: f32) -> Option<[f32;2]> {
+        let mut before = None;
+        for &sample in &self.samples {
+            let (t, position, _) = sample;
+            if t > time {
+                return Some(match before {
+                    Some((t0, p0, _)) => {
+                        let rate = (time - t0) / (t - t0);
+                        [p0[0] + (position[0] - p0[0])*rate, p0[1] + (position[1] - p0[1])*rate]
+                    },
+                    None => position,
+                });
+            }
+            before = Some(sample);
+        }
+
+        before.map(|(t, position, velocity)| {
+            let dt = (time - t).min(config.interpolation.max_extrapolation);
+            [position[0] + velocity[0]*dt, position[1] + velocity[1]*dt]
+        })
+    }
+}
+
+#[test]
+fn test_interpolated_position() {
+    let mut interpolated = Interpolated::new();
+    assert_eq!(interpolated.position(0.), None);
+
+    interpolated.push(1., [0., 0.], [1., 0.]);
+    interpolated.push(2., [2., 4.], [1., 0.]);
+    interpolated.push(1.5, [9., 9.], [0., 0.]);
+
+    assert_eq!(interpolated.position(0.), Some([0., 0.]));
+    assert_eq!(interpolated.position(1.5), Some([1., 2.]));
+
+    let extrapolated = interpolated.position(100.).unwrap();
+    assert_eq!(extrapolated[0], 2. + config.interpolation.max_extrapolation);
+    assert_eq!(extrapolated[1], 4.);
+}
@@ -0,0 +1,216 @@
+use app;
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use baal;
+use graphics::Color;
+use components::*;
+use resource::*;
+use specs::Join;
+use utils::Into3D;
+use progression::Modifier;
+
+pub type VecModifier = Vec<Modifier>;
+
+/// an item as defined in the items table of the configuration file,
+/// items are refered by their index in this table,
+/// the modifiers apply to the stats of the entities holding the item
+pub struct ItemDefinition {
+    pub name: String,
+    pub max_stack: usize,
+    pub color: Color,
+    pub modifiers: VecModifier,
+}
+impl_from_into_toml_for_struct!( ItemDefinition {
+    name: String,
+    max_stack: usize,
+    color: Color,
+    modifiers: VecModifier,
+});
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Stack {
+    pub item: usize,
+    pub count: usize,
+}
+
+#[inline]
+fn max_stack(item: usize) -> usize {
+    config.items.definitions.get(item).map(|d| d.max_stack).unwrap_or(1)
+}
+
+#[derive(Debug,Clone)]
+pub struct Inventory {
+    slots: Vec<Option<Stack>>,
+}
+impl specs::Component for Inventory {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Inventory {
+    pub fn new(size: usize) -> Self {
+        Inventory {
+            slots: vec![None; size],
+        }
+    }
+
+    /// fill existing stacks first then empty slots,
+    /// return the number of items that didn't fit
+    pub fn insert(&mut self, stack: Stack) -> usize {
+        self.insert_with_max_stack(stack, max_stack(stack.item))
+    }
+
+    fn insert_with_max_stack(&mut self, stack: Stack, max_stack: usize) -> usize {
+        let mut left = stack.count;
+
+        for slot in self.slots.iter_mut() {
+            if left == 0 { break }
+            if let Some(ref mut slot_stack) = *slot {
+                if slot_stack.item == stack.item && slot_stack.count < max_stack {
+                    let moved = left.min(max_stack - slot_stack.count);
+                    slot_stack.count += moved;
+                    left -= moved;
+                }
+            }
+        }
+        for slot in self.slots.iter_mut() {
+            if left == 0 { break }
+            if slot.is_none() {
+                let moved = left.min(max_stack);
+                *slot = Some(Stack {
+                    item: stack.item,
+                    count: moved,
+                });
+                left -= moved;
+            }
+        }
+
+        left
+    }
+
+    /// remove up to count items, return the number of items removed
+    pub fn remove(&mut self, item: usize, count: usize) -> usize {
+        let mut removed = 0;
+        for slot in self.slots.iter_mut().rev() {
+            if removed == count { break }
+            let empty = if let Some(ref mut slot_stack) = *slot {
+                if slot_stack.item == item {
+                    let moved = (count - removed).min(slot_stack.count);
+                    slot_stack.count -= moved;
+                    removed += moved;
+                }
+                slot_stack.count == 0
+            } else {
+                false
+            };
+            if empty {
+                *slot = None;
+            }
+        }
+        removed
+    }
+
+    pub fn count(&self, item: usize) -> usize {
+        self.slots.iter()
+            .filter_map(|slot| slot.as_ref())
+            .filter(|stack| stack.item == item)
+            .fold(0, |sum, stack| sum + stack.count)
+    }
+
+    pub fn slots(&self) -> &[Option<Stack>] {
+        &self.slots
+    }
+
+    /// empty the inventory and return its stacks
+    pub fn drain(&mut self) -> Vec<Stack> {
+        self.slots.iter_mut().filter_map(|slot| slot.take()).collect()
+    }
+}
+
+#[test]
+fn test_inventory_insert_remove() {
+    let mut inventory = Inventory::new(2);
+    assert_eq!(inventory.insert_with_max_stack(Stack { item: 0, count: 3 }, 5), 0);
+    assert_eq!(inventory.insert_with_max_stack(Stack { item: 0, count: 4 }, 5), 0);
+    assert_eq!(inventory.slots(), &[Some(Stack { item: 0, count: 5 }), Some(Stack { item: 0, count: 2 })]);
+    assert_eq!(inventory.insert_with_max_stack(Stack { item: 1, count: 1 }, 5), 1);
+    assert_eq!(inventory.insert_with_max_stack(Stack { item: 0, count: 4 }, 5), 1);
+    assert_eq!(inventory.count(0), 10);
+
+    assert_eq!(inventory.remove(0, 7), 7);
+    assert_eq!(inventory.slots(), &[Some(Stack { item: 0, count: 3 }), None]);
+    assert_eq!(inventory.remove(0, 7), 3);
+    assert_eq!(inventory.drain(), vec!());
+}
+
+/// an item laying on the ground,
+/// it is transfered into the inventory of the entities that overlap it
+pub struct Pickup {
+    pub stack: Stack,
+}
+impl specs::Component for Pickup {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Pickup {
+    pub fn new(stack: Stack) -> Self {
+        Pickup {
+            stack: stack,
+        }
+    }
+}
+
+pub struct PickupSystem;
+impl specs::System<app::UpdateContext> for PickupSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut inventories, mut pickups, states, types, physic_world, entities) = arg.fetch(|world| {
+            (
+                world.write::<Inventory>(),
+                world.write::<Pickup>(),
+                world.read::<PhysicState>(),
+                world.read::<PhysicType>(),
+                world.read_resource::<PhysicWorld>(),
+                world.entities(),
+            )
+        });
+
+        for (inventory, state, typ, entity) in (&mut inventories, &states, &types, &entities).iter() {
+            let mut changed = false;
+            physic_world.apply_on_shape(&state.position, config.entities.pickup_group.val, &typ.shape, &mut |other_entity,_| {
+                if let Some(pickup) = pickups.get_mut(*other_entity) {
+                    if pickup.stack.count == 0 { return }
+
+                    let left = inventory.insert(pickup.stack);
+                    if left != pickup.stack.count {
+                        context.event_tx.send(app::GameEvent::ItemPickedUp(entity, Stack {
+                            item: pickup.stack.item,
+                            count: pickup.stack.count - left,
+                        })).unwrap();
+                        baal::effect::short::play(config.entities.pickup_snd,state.position.into_3d());
+
+                        pickup.stack.count = left;
+                        if left == 0 {
+                            arg.delete(*other_entity);
+                        }
+                        changed = true;
+                    }
+                }
+            });
+            if changed {
+                context.event_tx.send(app::GameEvent::InventoryChanged(entity)).unwrap();
+            }
+        }
+    }
+}
+
+/// the scattering velocities of n items dropped at the same place
+pub fn scatter_velocities(n: usize) -> Vec<[f32;2]> {
+    use std::f32::consts::PI;
+
+    if n == 1 {
+        return vec!([0.,0.]);
+    }
+    (0..n).map(|i| {
+        let angle = 2.*PI*i as f32/n as f32;
+        [config.entities.pickup_scatter*angle.cos(), config.entities.pickup_scatter*angle.sin()]
+    }).collect()
+}
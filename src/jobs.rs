@@ -0,0 +1,121 @@
+//! a pool of worker threads running the background jobs: the preload of the levels,
+//! the export of the captures and screenshots and the bake of the navigation data.
+//!
+//! a job either returns its result to its handle, polled or waited by its owner, or
+//! returns a completion queued for the main thread, which drains the queue at the
+//! start of each update. a job that panics is caught so its worker goes on with the
+//! next jobs.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+type Job = Box<FnMut() + Send>;
+
+/// the result of a job
+pub struct JobHandle<T> {
+    result_rx: mpsc::Receiver<T>,
+}
+impl<T> JobHandle<T> {
+    /// the result once the job is done without blocking, some none if it panicked
+    pub fn try_wait(&self) -> Option<Option<T>> {
+        match self.result_rx.try_recv() {
+            Ok(result) => Some(Some(result)),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(None),
+        }
+    }
+
+    /// block until the job is done, none if it panicked
+    pub fn wait(self) -> Option<T> {
+        self.result_rx.recv().ok()
+    }
+}
+
+pub struct Jobs<C> {
+    job_tx: mpsc::Sender<Job>,
+    completion_tx: mpsc::Sender<C>,
+    completion_rx: mpsc::Receiver<C>,
+}
+impl<C: Send + 'static> Jobs<C> {
+    pub fn new(threads: usize) -> Self {
+        let (job_tx, job_rx) = mpsc::channel::<Job>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        for _ in 0..threads.max(1) {
+            let job_rx = job_rx.clone();
+            thread::spawn(move || loop {
+                // the lock is released before the job runs
+                let job = job_rx.lock().unwrap().recv();
+                match job {
+                    // the result channel of the job is dropped so its handle gets none
+                    Ok(mut job) => { let _ = panic::catch_unwind(AssertUnwindSafe(|| job())); },
+                    // the pool is dropped
+                    Err(_) => return,
+                }
+            });
+        }
+        let (completion_tx, completion_rx) = mpsc::channel();
+        Jobs {
+            job_tx: job_tx,
+            completion_tx: completion_tx,
+            completion_rx: completion_rx,
+        }
+    }
+
+    fn push<F: FnOnce() + Send + 'static>(&self, job: F) -> Result<(),String> {
+        let mut job = Some(job);
+        self.job_tx.send(Box::new(move || (job.take().expect("job must run once"))()))
+            .map_err(|_| String::from("job workers are gone"))
+    }
+
+    /// run the job on a worker, its result is got from the handle,
+    /// the handle gets none if the job can't be run
+    pub fn spawn<T: Send + 'static, F: FnOnce() -> T + Send + 'static>(&self, job: F) -> JobHandle<T> {
+        let (result_tx, result_rx) = mpsc::channel();
+        // on error the job and its result channel are dropped
+        let _ = self.push(move || {
+            // the handle may have been dropped
+            let _ = result_tx.send(job());
+        });
+        JobHandle {
+            result_rx: result_rx,
+        }
+    }
+
+    /// run the job on a worker and queue its completion for the main thread
+    pub fn spawn_completed<F: FnOnce() -> C + Send + 'static>(&self, job: F) -> Result<(),String> {
+        let completion_tx = self.completion_tx.clone();
+        self.push(move || {
+            let _ = completion_tx.send(job());
+        })
+    }
+
+    /// the completions of the jobs done since the last drain
+    pub fn drain(&self) -> Vec<C> {
+        self.completion_rx.try_iter().collect()
+    }
+}
+
+#[test]
+fn test_jobs() {
+    let jobs = Jobs::new(1);
+    let handles = (0..4).map(|i| jobs.spawn(move || i*2)).collect::<Vec<_>>();
+    assert_eq!(handles.into_iter().map(|h| h.wait().unwrap()).collect::<Vec<_>>(), vec!(0, 2, 4, 6));
+
+    // a single worker runs the jobs in order
+    jobs.spawn_completed(|| "done");
+    jobs.spawn(|| ()).wait().unwrap();
+    assert_eq!(jobs.drain(), vec!("done"));
+    assert!(jobs.drain().is_empty());
+
+    // the worker of the job panicking runs the next jobs
+    let jobs = Jobs::<()>::new(1);
+    let handle = jobs.spawn(|| -> u32 { panic!("job panicked") });
+    let mut result = handle.try_wait();
+    while result.is_none() {
+        ::std::thread::yield_now();
+        result = handle.try_wait();
+    }
+    assert_eq!(result, Some(None));
+    assert_eq!(jobs.spawn(|| 3).wait(), Some(3));
+}
@@ -0,0 +1,94 @@
+//! audit of the references held by components to other entities. an entity handle
+//! doesn't keep its entity alive: once the entity is deleted the handle dangles, and a
+//! component holding it keeps acting on an entity that is gone until something clears
+//! it. the audit reports the dangling references and the pairs that must reference each
+//! other (a mount and its rider) and don't; it's printed in the memory report.
+
+use specs;
+use components::*;
+use specs::Join;
+use std::collections::HashSet;
+
+/// a reference of the component of holder to an entity
+#[derive(Debug,Clone,PartialEq)]
+pub struct Suspect {
+    pub holder: specs::Entity,
+    pub component: &'static str,
+    pub reference: specs::Entity,
+    /// the referenced entity is alive but doesn't reference the holder back
+    pub one_sided: bool,
+}
+
+/// the dangling and one-sided references of the world
+pub fn audit(world: &specs::World) -> Vec<Suspect> {
+    let entities = world.entities();
+    let alive = (&entities).iter().collect::<HashSet<_>>();
+    let mounts = world.read::<Mount>();
+    let riders = world.read::<Rider>();
+
+    let mut references: Vec<(specs::Entity, &'static str, specs::Entity)> = vec!();
+    for (motion, entity) in (&world.read::<Motion>(), &entities).iter() {
+        references.extend(motion.target.get().map(|target| (entity, "Motion", target)));
+    }
+    for (grabber, entity) in (&world.read::<Grabber>(), &entities).iter() {
+        references.extend(grabber.held().map(|held| (entity, "Grabber", held)));
+    }
+    for (interactor, entity) in (&world.read::<Interactor>(), &entities).iter() {
+        references.extend(interactor.target().map(|target| (entity, "Interactor", target)));
+    }
+    for (arena, entity) in (&world.read::<Arena>(), &entities).iter() {
+        references.extend(arena.boss().map(|boss| (entity, "Arena", boss)));
+    }
+    for (rope, entity) in (&world.read::<Rope>(), &entities).iter() {
+        if let Anchor::Entity(anchor) = rope.anchor() {
+            references.push((entity, "Rope", anchor));
+        }
+    }
+    for (trigger, entity) in (&world.read::<PhysicTrigger>(), &entities).iter() {
+        references.extend(trigger.overlapping.iter().map(|&other| (entity, "PhysicTrigger", other)));
+    }
+
+    let mut suspects = references.into_iter()
+        .filter(|&(_, _, reference)| !alive.contains(&reference))
+        .map(|(holder, component, reference)| Suspect {
+            holder: holder,
+            component: component,
+            reference: reference,
+            one_sided: false,
+        })
+        .collect::<Vec<_>>();
+
+    for (mount, entity) in (&mounts, &entities).iter() {
+        if let Some(rider) = mount.rider() {
+            if !alive.contains(&rider) || riders.get(rider).map(|r| r.mount) != Some(entity) {
+                suspects.push(Suspect { holder: entity, component: "Mount", reference: rider, one_sided: alive.contains(&rider) });
+            }
+        }
+    }
+    for (rider, entity) in (&riders, &entities).iter() {
+        if !alive.contains(&rider.mount) || mounts.get(rider.mount).and_then(|m| m.rider()) != Some(entity) {
+            suspects.push(Suspect { holder: entity, component: "Rider", reference: rider.mount, one_sided: alive.contains(&rider.mount) });
+        }
+    }
+    suspects
+}
+
+#[test]
+fn test_audit() {
+    use worlds;
+
+    let mut world = worlds::new_world();
+    let target = world.create_now().build();
+    let projectile = world.create_now()
+        .with::<Motion>(Motion::new(0, 0., Some(target)))
+        .build();
+    let mount = world.create_now().with::<Mount>(Mount::new()).build();
+    world.create_now().with::<Rider>(Rider { mount: mount }).build();
+    assert_eq!(audit(&world).len(), 1);
+    assert!(audit(&world)[0].one_sided);
+
+    world.delete_now(target);
+    let suspects = audit(&world);
+    assert!(suspects.contains(&Suspect { holder: projectile, component: "Motion", reference: target, one_sided: false }));
+    assert_eq!(suspects.len(), 2);
+}
@@ -6,6 +6,9 @@ use std::path::Path;
 use std::path::PathBuf;
 use specs::Join;
 use physic;
+use los;
+use daynight;
+use weather;
 use toml;
 use baal;
 use configuration;
@@ -15,6 +18,20 @@ use std::io::Read;
 use configuration::FromToml;
 use std::io;
 use png;
+use worlds;
+use std::sync::{Arc, Mutex};
+use jobs::{Jobs, JobHandle};
+use inventory::Stack;
+use zone;
+use patrol;
+use nav;
+use region;
+use ambient;
+use hazard;
+use boss;
+use faction;
+use rng;
+use autotile;
 
 type VecDungeonSetting = Vec<DungeonSetting>;
 type VecString = Vec<String>;
@@ -152,7 +169,7 @@ pub fn load_castles(mut musics: Vec<String>) -> Result<(Vec<Castle>,Vec<String>)
 }
 
 
-#[derive(Debug,Clone)]
+#[derive(Debug,Clone,PartialEq)]
 pub enum Level {
     Room {
         castle: usize,
@@ -184,9 +201,25 @@ impl Level {
             panic!("INTERN ERROR: cannot call next on entry dungeon");
         }
     }
+
+    /// castle/dungeon/room for rooms, castle for corridors and entry for the entry
+    pub fn name(&self, castles: &Vec<Castle>) -> String {
+        match *self {
+            Level::Room { castle, dungeon, room } => {
+                let castle = castles.get(castle);
+                let dungeon = castle.and_then(|c| c.dungeons.get(dungeon));
+                format!("{}/{}/{}",
+                        castle.map(|c| &*c.name).unwrap_or("?"),
+                        dungeon.map(|d| &*d.name).unwrap_or("?"),
+                        dungeon.and_then(|d| d.rooms.get(room)).map(|r| &**r).unwrap_or("?"))
+            },
+            Level::Corridor { castle } => castles.get(castle).map(|c| c.name.clone()).unwrap_or("?".into()),
+            Level::Entry => "entry".into(),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone)]
 pub struct Castle {
     pub name: String,
     pub music: usize,
@@ -215,6 +248,13 @@ pub enum LoadLevelError {
     PngDecodingError(png::DecodingError),
     UnexpectedColor,
     IoError(io::Error),
+    ZoneError(String),
+    PatrolError(String),
+    PreloadFailed,
+    NavError(String),
+    RegionError(String),
+    AmbientError(String),
+    BossError(String),
 }
 impl fmt::Display for LoadLevelError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -229,6 +269,13 @@ impl fmt::Display for LoadLevelError {
             AmbiguousLevelDefinition => write!(fmt,"ambiguous level definition: both .txt and .png file exists"),
             InvalidUTF8 => write!(fmt,"text level invalid UTF-8"),
             NoLevelDefinition => write!(fmt,"level doesn't exist"),
+            ZoneError(ref e) => write!(fmt,"{}",e),
+            PatrolError(ref e) => write!(fmt,"{}",e),
+            PreloadFailed => write!(fmt,"preload job panicked"),
+            NavError(ref e) => write!(fmt,"{}",e),
+            RegionError(ref e) => write!(fmt,"{}",e),
+            AmbientError(ref e) => write!(fmt,"{}",e),
+            BossError(ref e) => write!(fmt,"{}",e),
         }
     }
 }
@@ -240,16 +287,68 @@ impl From<io::Error> for LoadLevelError {
 }
 
 pub fn load_level<'l>(level: &Level, castles: &Vec<Castle>, world: &mut specs::World) -> Result<(),LoadLevelError> {
+    play_level_sounds(level, castles);
+    build_level(level, castles, world, &mut |_| ())
+}
+
+/// stop the sounds of the previous level and play the music of the level
+pub fn play_level_sounds(level: &Level, castles: &Vec<Castle>) {
+    baal::effect::short::stop_all();
+    baal::effect::persistent::clear_positions_for_all();
+    baal::effect::persistent::update_volume_for_all();
+    baal::effect::short::play(config.entities.portal_snd, baal::effect::listener());
+    play_level_music(level, castles);
+}
+
+/// play the music of the level if it isn't played already
+pub fn play_level_music(level: &Level, castles: &Vec<Castle>) {
+    let level_music = match level {
+        &Level::Room { castle, dungeon, .. } => castles.get(castle).and_then(|c| c.dungeons.get(dungeon)).map(|d| d.music),
+        &Level::Corridor { castle } => castles.get(castle).map(|c| c.music),
+        &Level::Entry => Some(0),
+    };
+    if let (Some(music), Some(level_music)) = (baal::music::index(), level_music) {
+        if music != level_music {
+            baal::music::play(level_music);
+        }
+    }
+}
+
+/// the files a level is built from, existing or not
+pub fn level_files(level: &Level, castles: &Vec<Castle>) -> Vec<PathBuf> {
+    let mut files = vec!();
+    if let Level::Room { castle, dungeon, room } = *level {
+        let castle = castles.get(castle);
+        if let (Some(castle), Some(room)) = (castle, castle.and_then(|c| c.dungeons.get(dungeon)).and_then(|d| d.rooms.get(room))) {
+            let dir = PathBuf::new()
+                .join(Path::new(&*config.levels.dir.val))
+                .join(Path::new(&*castle.name));
+            for name in ["texts","maps"].iter() {
+                files.push(dir.join(Path::new(name)).join(Path::new(&*room)));
+            }
+            for name in ["zones","paths","ambient","bosses","regions","sequences"].iter() {
+                files.push(dir.join(Path::new(name)).join(Path::new(&*room).with_extension("toml")));
+            }
+        }
+    }
+    files
+}
+
+/// build the level in the world without playing sounds so it can be done on any
+/// thread, progress is called with the part of the level built from 0 to 1
+pub fn build_level<F: FnMut(f32)>(level: &Level, castles: &Vec<Castle>, world: &mut specs::World, progress: &mut F) -> Result<(),LoadLevelError> {
     // flush world
     for entity in world.entities().iter() {
         world.delete_later(entity);
     }
     world.maintain();
 
-    baal::effect::short::stop_all();
-    baal::effect::persistent::clear_positions_for_all();
-    baal::effect::persistent::update_volume_for_all();
-    baal::effect::short::play(config.entities.portal_snd, baal::effect::listener());
+    // the monsters of the level draw from it when they are built
+    if !world.has_resource::<resource::WorldRng>() {
+        world.add_resource(rng::WorldRng::new())
+    }
+
+    let mut regions = region::Regions::new();
 
     // read level file
     match level {
@@ -257,12 +356,6 @@ pub fn load_level<'l>(level: &Level, castles: &Vec<Castle>, world: &mut specs::W
             let castle = try!(castles.get(castle_id).ok_or(LoadLevelError::GetCastleError));
             let dungeon = try!(castle.dungeons.get(dungeon_id).ok_or(LoadLevelError::GetDungeonError));
 
-            if let Some(music) = baal::music::index() {
-                if music != dungeon.music {
-                    baal::music::play(dungeon.music);
-                }
-            }
-
             let room = try!(dungeon.rooms.get(room_id).ok_or(LoadLevelError::GetRoomError));
 
             let txt_path = PathBuf::new()
@@ -277,6 +370,36 @@ pub fn load_level<'l>(level: &Level, castles: &Vec<Castle>, world: &mut specs::W
                 .join(Path::new("maps"))
                 .join(Path::new(&*room));
 
+            let zones_path = PathBuf::new()
+                .join(Path::new(&*config.levels.dir.val))
+                .join(Path::new(&*castle.name))
+                .join(Path::new("zones"))
+                .join(Path::new(&*room).with_extension("toml"));
+
+            let paths_path = PathBuf::new()
+                .join(Path::new(&*config.levels.dir.val))
+                .join(Path::new(&*castle.name))
+                .join(Path::new("paths"))
+                .join(Path::new(&*room).with_extension("toml"));
+
+            let ambient_path = PathBuf::new()
+                .join(Path::new(&*config.levels.dir.val))
+                .join(Path::new(&*castle.name))
+                .join(Path::new("ambient"))
+                .join(Path::new(&*room).with_extension("toml"));
+
+            let bosses_path = PathBuf::new()
+                .join(Path::new(&*config.levels.dir.val))
+                .join(Path::new(&*castle.name))
+                .join(Path::new("bosses"))
+                .join(Path::new(&*room).with_extension("toml"));
+
+            let regions_path = PathBuf::new()
+                .join(Path::new(&*config.levels.dir.val))
+                .join(Path::new(&*castle.name))
+                .join(Path::new("regions"))
+                .join(Path::new(&*room).with_extension("toml"));
+
             match (txt_path.exists(),png_path.exists()) {
                 (true,true) => return Err(LoadLevelError::AmbiguousLevelDefinition),
                 (false,false) => return Err(LoadLevelError::NoLevelDefinition),
@@ -293,6 +416,7 @@ pub fn load_level<'l>(level: &Level, castles: &Vec<Castle>, world: &mut specs::W
                     try!(reader.next_frame(&mut data).map_err(|e| LoadLevelError::PngDecodingError(e)));
 
                     for x in 0..info.width {
+                        progress(x as f32/info.width as f32);
                         for y in 0..info.height {
                             let offset = ((x + y*info.width)*3) as usize;
                             let col = [data[offset],data[offset+1],data[offset+2]];
@@ -308,10 +432,34 @@ pub fn load_level<'l>(level: &Level, castles: &Vec<Castle>, world: &mut specs::W
                                 entities::add_laser(world,pos);
                             } else if col == config.levels.monster_col {
                                 entities::add_monster(world,pos);
+                            } else if col == config.levels.elite_monster_col {
+                                entities::add_elite_monster(world,pos);
                             } else if col == config.levels.column_col {
                                 entities::add_column(world,pos);
                             } else if col == config.levels.wall_col {
                                 entities::add_wall(world,pos);
+                            } else if col == config.levels.checkpoint_col {
+                                entities::add_checkpoint(world,pos);
+                            } else if col == config.levels.liquid_col {
+                                entities::add_liquid(world,pos);
+                            } else if col == config.levels.lava_col {
+                                entities::add_hazard(world,pos,hazard::kind("lava").expect("hazards.kinds must define lava"));
+                            } else if col == config.levels.spikes_col {
+                                entities::add_hazard(world,pos,hazard::kind("spikes").expect("hazards.kinds must define spikes"));
+                            } else if col == config.levels.foliage_col {
+                                entities::add_foliage(world,pos);
+                            } else if col == config.levels.low_wall_col {
+                                entities::add_low_wall(world,pos);
+                            } else if col == config.levels.chest_col {
+                                entities::add_chest(world,pos);
+                            } else if col == config.levels.vendor_col {
+                                entities::add_vendor(world,pos);
+                            } else if col == config.levels.crate_col {
+                                entities::add_crate(world,pos);
+                            } else if col == config.levels.mount_col {
+                                entities::add_mount(world,pos);
+                            } else if col == config.levels.pickup_col {
+                                entities::add_pickup(world,pos,[0.,0.],Stack { item: 0, count: 1 });
                             } else {
                                 return Err(LoadLevelError::UnexpectedColor);
                             }
@@ -319,16 +467,16 @@ pub fn load_level<'l>(level: &Level, castles: &Vec<Castle>, world: &mut specs::W
                     }
                 },
             }
+
+            try!(zone::load(&zones_path, world).map_err(|e| LoadLevelError::ZoneError(e)));
+            try!(patrol::load(&paths_path, world).map_err(|e| LoadLevelError::PatrolError(e)));
+            try!(ambient::load(&ambient_path, world).map_err(|e| LoadLevelError::AmbientError(e)));
+            try!(boss::load(&bosses_path, world).map_err(|e| LoadLevelError::BossError(e)));
+            regions = try!(region::load(&regions_path).map_err(|e| LoadLevelError::RegionError(e)));
         },
         &Level::Corridor { castle: castle_id } => {
             let castle = try!(castles.get(castle_id).ok_or(LoadLevelError::GetCastleError));
 
-            if let Some(music) = baal::music::index() {
-                if music != castle.music {
-                    baal::music::play(castle.music);
-                }
-            }
-
             let levels = castle.dungeons.iter().enumerate().map(|(i,dungeon)| {
                 (
                     dungeon.name.clone(),
@@ -343,12 +491,6 @@ pub fn load_level<'l>(level: &Level, castles: &Vec<Castle>, world: &mut specs::W
             create_corridor(Some(Level::Entry),levels,world);
         },
         &Level::Entry => {
-            if let Some(music) = baal::music::index() {
-                if music != 0 {
-                    baal::music::play(0);
-                }
-            }
-
             let levels = castles.iter().enumerate().map(|(i,castle)| {
                 (
                     castle.name.clone(),
@@ -366,14 +508,111 @@ pub fn load_level<'l>(level: &Level, castles: &Vec<Castle>, world: &mut specs::W
     if !world.has_resource::<resource::PhysicWorld>() {
         world.add_resource(physic::PhysicWorld::new())
     }
-
+    // the sights are of the entities of the previous level
+    if world.has_resource::<resource::LineOfSight>() {
+        *world.write_resource::<resource::LineOfSight>() = los::LineOfSight::new();
+    } else {
+        world.add_resource(los::LineOfSight::new())
+    }
+    if !world.has_resource::<resource::DayNight>() {
+        world.add_resource(daynight::DayNight::new())
+    }
+    if !world.has_resource::<resource::Weather>() {
+        world.add_resource(weather::Weather::new())
+    }
+    if !world.has_resource::<resource::Relations>() {
+        world.add_resource(faction::Relations::new())
+    }
     // fill physic_world
-    let mut physic_world = world.write_resource::<physic::PhysicWorld>();
-    physic_world.fill(&world);
+    {
+        let mut physic_world = world.write_resource::<physic::PhysicWorld>();
+        physic_world.fill(&world);
+    }
+
+    // navigation data baked with the room or baked now
+    let nav_data = match nav_path(level, castles) {
+        Some(path) => try!(nav::load(&path, world).map_err(|e| LoadLevelError::NavError(e))),
+        None => nav::NavData::bake(world),
+    };
+    if world.has_resource::<resource::NavData>() {
+        *world.write_resource::<resource::NavData>() = nav_data;
+    } else {
+        world.add_resource(nav_data);
+    }
+    if world.has_resource::<resource::Regions>() {
+        *world.write_resource::<resource::Regions>() = regions;
+    } else {
+        world.add_resource(regions);
+    }
+    // the walls are all new
+    if world.has_resource::<resource::AutotileCells>() {
+        *world.write_resource::<resource::AutotileCells>() = autotile::AutotileCells::new();
+    } else {
+        world.add_resource(autotile::AutotileCells::new());
+    }
 
+    progress(1.);
     Ok(())
 }
 
+/// the file of the navigation data of a room
+pub fn nav_path(level: &Level, castles: &Vec<Castle>) -> Option<PathBuf> {
+    match level {
+        &Level::Room { castle, dungeon, room } => {
+            castles.get(castle).and_then(|c| c.dungeons.get(dungeon).and_then(|d| d.rooms.get(room)).map(|room| {
+                PathBuf::new()
+                    .join(Path::new(&*config.levels.dir.val))
+                    .join(Path::new(&*c.name))
+                    .join(Path::new("nav"))
+                    .join(Path::new(&*room).with_extension("toml"))
+            }))
+        },
+        _ => None,
+    }
+}
+
+/// a level built in a new world by a job, to be swapped in when entered
+pub struct Preload {
+    pub level: Level,
+    progress: Arc<Mutex<f32>>,
+    world: JobHandle<Result<specs::World,LoadLevelError>>,
+}
+
+impl Preload {
+    pub fn start<C: Send + 'static>(level: Level, castles: Vec<Castle>, jobs: &Jobs<C>) -> Self {
+        let progress = Arc::new(Mutex::new(0.));
+
+        let job_level = level.clone();
+        let job_progress = progress.clone();
+        let world = jobs.spawn(move || {
+            let mut world = worlds::new_world();
+            let result = build_level(&job_level, &castles, &mut world, &mut |p| *job_progress.lock().unwrap() = p);
+            result.map(|_| world)
+        });
+
+        Preload {
+            level: level,
+            progress: progress,
+            world: world,
+        }
+    }
+
+    /// part of the level built from 0 to 1
+    pub fn progress(&self) -> f32 {
+        *self.progress.lock().unwrap()
+    }
+
+    /// the built world once it is, without blocking
+    pub fn poll(&self) -> Option<Result<specs::World,LoadLevelError>> {
+        self.world.try_wait().map(|world| world.unwrap_or(Err(LoadLevelError::PreloadFailed)))
+    }
+
+    /// the built world, block until it is
+    pub fn wait(self) -> Result<specs::World,LoadLevelError> {
+        self.world.wait().unwrap_or(Err(LoadLevelError::PreloadFailed))
+    }
+}
+
 fn create_text_level(next: Level, text: String, world: &mut specs::World) {
     let bottom = config.text.bottom as isize;
     let left = config.text.left as isize;
@@ -1,4 +1,8 @@
 use app;
+use inventory;
+use loot;
+use progression;
+use ability;
 use graphics;
 use components::*;
 use resource::*;
@@ -8,10 +12,36 @@ use utils::Into3D;
 use baal;
 use config;
 use std::sync::Arc;
+use combat::{Damage, DamageType};
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Kind {
+    Character,
+    Monster,
+    Ball,
+}
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Kind::Character => "character",
+            Kind::Monster => "monster",
+            Kind::Ball => "ball",
+        }
+    }
+}
 
 pub struct Life {
     alive: bool,
+    kind: Kind,
     die_snd: usize,
+    health: f32,
+    max_health: f32,
+    /// the last entity that damaged it
+    killer: Option<specs::Entity>,
+    invulnerable: bool,
+    /// respawned at a checkpoint with the inventory of the snapshot, so the inventory
+    /// isn't dropped on death
+    respawns: bool,
 }
 
 impl specs::Component for Life {
@@ -19,24 +49,73 @@ impl specs::Component for Life {
 }
 
 impl Life {
-    pub fn new(die_snd: usize) -> Self {
+    pub fn new(kind: Kind, die_snd: usize) -> Self {
         Life {
             alive: true,
+            kind: kind,
             die_snd: die_snd,
+            health: 1.,
+            max_health: 1.,
+            killer: None,
+            invulnerable: false,
+            respawns: false,
         }
     }
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+    pub fn is_alive(&self) -> bool {
+        self.alive
+    }
     pub fn kill(&mut self) {
         self.alive = false;
     }
+    /// remove amount of health, it dies when no health is left.
+    /// an invulnerable life takes no damage but can still be killed
+    pub fn damage(&mut self, amount: f32, source: specs::Entity) {
+        if self.invulnerable {
+            return;
+        }
+        self.health -= amount;
+        self.killer = Some(source);
+        if self.health <= 0. {
+            self.alive = false;
+        }
+    }
+    pub fn set_invulnerable(&mut self, invulnerable: bool) {
+        self.invulnerable = invulnerable;
+    }
+    pub fn health(&self) -> f32 {
+        self.health
+    }
+    pub fn set_health(&mut self, health: f32) {
+        self.health = health.min(self.max_health);
+    }
+    pub fn set_respawns(&mut self, respawns: bool) {
+        self.respawns = respawns;
+    }
+    pub fn max_health(&self) -> f32 {
+        self.max_health
+    }
+    /// the health keeps its ratio to the maximum health
+    pub fn set_max_health(&mut self, max_health: f32) {
+        if max_health != self.max_health {
+            self.health *= max_health/self.max_health;
+            self.max_health = max_health;
+        }
+    }
 }
 
 pub struct LifeSystem;
 impl specs::System<app::UpdateContext> for LifeSystem {
     fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
-        let (mut lives, mut states, entities) = arg.fetch(|world| {
+        let (mut lives, mut states, mut inventories, loots, mut rng, entities) = arg.fetch(|world| {
             (
                 world.write::<Life>(),
                 world.write::<PhysicState>(),
+                world.write::<Inventory>(),
+                world.read::<Loot>(),
+                world.write_resource::<WorldRng>(),
                 world.entities(),
             )
         });
@@ -61,6 +140,25 @@ impl specs::System<app::UpdateContext> for LifeSystem {
                     }).unwrap();
                 }
 
+                // the inventory of a player respawning is the one of its snapshot
+                if let (false, Some(inventory)) = (life.respawns, inventories.get_mut(entity)) {
+                    let stacks = inventory.drain();
+                    for (stack,velocity) in stacks.iter().zip(inventory::scatter_velocities(stacks.len())) {
+                        context.control_tx.send(app::Control::CreatePickup(state.position,velocity,*stack)).unwrap();
+                    }
+                }
+
+                if let Some(loot) = loots.get(entity) {
+                    for stack in loot::roll(&config.loot.tables, loot.table, config.loot.luck, &mut *rng) {
+                        let velocity = loot::scatter_velocity(&mut *rng);
+                        context.control_tx.send(app::Control::CreatePickup(state.position,velocity,stack)).unwrap();
+                    }
+                }
+
+                context.event_tx.send(app::GameEvent::Died(entity,life.kind,state.position)).unwrap();
+                if let Some(killer) = life.killer {
+                    context.event_tx.send(app::GameEvent::Killed(killer,entity,life.kind)).unwrap();
+                }
                 baal::effect::short::play(life.die_snd,state.position.into_3d());
                 arg.delete(entity);
             }
@@ -79,27 +177,42 @@ impl specs::Component for Killer {
 
 pub struct KillerSystem;
 impl specs::System<app::UpdateContext> for KillerSystem {
-    fn run(&mut self, arg: specs::RunArg, _context: app::UpdateContext) {
-        let (mut lives, states, types, physic_world, killers, entities) = arg.fetch(|world| {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut lives, mut threats, states, types, physic_world, relations, factions, attributes, killers, entities) = arg.fetch(|world| {
             (
                 world.write::<Life>(),
+                world.write::<Threat>(),
                 world.read::<PhysicState>(),
                 world.read::<PhysicType>(),
                 world.read_resource::<PhysicWorld>(),
+                world.read_resource::<Relations>(),
+                world.read::<Faction>(),
+                world.read::<Attributes>(),
                 world.read::<Killer>(),
                 world.entities(),
             )
         });
 
         for (killer, state, typ, entity) in (&killers, &states, &types, &entities).iter() {
+            let amount = progression::damage(attributes.get(entity));
             let mut kill = false;
-            physic_world.apply_on_shape(&state.position, killer.mask, &typ.shape, &mut |other_entity,_| {
+            physic_world.apply_on_shape_except(&[entity], &state.position, killer.mask, &typ.shape, &mut |other_entity,_| {
+                if !relations.harms(factions.get(entity), factions.get(*other_entity)) {
+                    return;
+                }
                 if let Some(life) = lives.get_mut(*other_entity) {
                     baal::effect::short::play(killer.kill_snd,state.position.into_3d());
-                    life.kill();
+                    life.damage(amount, entity);
                     kill = true;
+                    let position = states.get(*other_entity).map(|s| s.position).unwrap_or(state.position);
+                    context.event_tx.send(app::GameEvent::Damaged(Damage::new(entity, *other_entity, position, amount, DamageType::Contact))).unwrap();
                 }
             });
+            if kill {
+                if let Some(threat) = threats.get_mut(entity) {
+                    threat.hit();
+                }
+            }
             if kill && killer.kamikaze {
                 lives.get_mut(entity).expect("killer kamikaze expect life component").kill();
             }
@@ -158,9 +271,11 @@ impl specs::System<app::UpdateContext> for BallSystem {
     }
 }
 
+/// a column fires a ball with its ability when the cooldown is over,
+/// the cooldown starts again when the ball is destroyed
 pub struct Column {
     spawn_snd: usize,
-    cooldown: Option<f32>,
+    ability: usize,
     arc: Arc<()>,
 }
 impl specs::Component for Column {
@@ -170,35 +285,32 @@ impl Column {
     pub fn new(snd: usize) -> Column {
         Column {
             spawn_snd: snd,
-            cooldown: Some(config.entities.column_cooldown),
+            ability: ability::ability(&config.abilities.column).expect("column ability must be defined in abilities.definitions"),
             arc: Arc::new(()),
         }
     }
+    pub fn ability(&self) -> usize {
+        self.ability
+    }
 }
 pub struct ColumnSystem;
 impl specs::System<app::UpdateContext> for ColumnSystem {
     fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
-        let (mut columns, states, entities) = arg.fetch(|world| {
+        let (mut columns, mut abilities, states, entities) = arg.fetch(|world| {
             (
                 world.write::<Column>(),
+                world.write::<Abilities>(),
                 world.read::<PhysicState>(),
                 world.entities(),
             )
         });
-        for (column, entity) in (&mut columns, &entities).iter() {
-            column.cooldown = if let Some(cooldown) = column.cooldown {
-                if cooldown > 0. {
-                    Some(cooldown - context.dt)
-                } else {
-                    let state = states.get(entity).expect("column component expect state component");
-                    context.control_tx.send(app::Control::CreateBall(state.position,column.arc.clone())).unwrap();
-                    baal::effect::short::play(column.spawn_snd,state.position.into_3d());
-                    None
-                }
-            } else if let Some(_) = Arc::get_mut(&mut column.arc) {
-                Some(config.entities.column_cooldown)
-            } else {
-                column.cooldown
+        for (column, abilities, entity) in (&mut columns, &mut abilities, &entities).iter() {
+            if Arc::get_mut(&mut column.arc).is_none() {
+                abilities.start_cooldown(column.ability);
+            } else if abilities.try_use(column.ability).is_ok() {
+                let state = states.get(entity).expect("column component expect state component");
+                context.control_tx.send(app::Control::CreateBall(state.position,column.arc.clone())).unwrap();
+                baal::effect::short::play(column.spawn_snd,state.position.into_3d());
             }
         }
     }
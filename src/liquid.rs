@@ -0,0 +1,91 @@
+use app;
+use specs;
+use config;
+use graphics;
+use baal;
+use components::*;
+use resource::PhysicWorld;
+use specs::Join;
+use utils::Into3D;
+use std::collections::HashSet;
+
+/// entities are immersed when this circle around their center overlaps a liquid
+const IMMERSION_RADIUS: f32 = 0.1;
+
+/// dynamic entities immersed in a liquid are slowed down by its drag
+pub struct Liquid {
+    pub drag: f32,
+}
+impl specs::Component for Liquid {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Liquid {
+    pub fn new() -> Self {
+        Liquid {
+            drag: config.entities.liquid_drag,
+        }
+    }
+}
+
+/// apply drag to immersed entities and splash when they enter or leave liquids
+#[derive(Default)]
+pub struct LiquidSystem {
+    immersed: HashSet<specs::Entity>,
+}
+impl specs::System<app::UpdateContext> for LiquidSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (dynamics, mut states, liquids, physic_world, entities) = arg.fetch(|world| {
+            (
+                world.read::<PhysicDynamic>(),
+                world.write::<PhysicState>(),
+                world.read::<Liquid>(),
+                world.read_resource::<PhysicWorld>(),
+                world.entities(),
+            )
+        });
+
+        let mut immersed = HashSet::new();
+        for (_, state, entity) in (&dynamics, &mut states, &entities).iter() {
+            let mut drag = None;
+            physic_world.apply_on_shape(&state.position, config.entities.liquid_group.val, &Shape::Circle(IMMERSION_RADIUS), &mut |other_entity,_| {
+                if let Some(liquid) = liquids.get(*other_entity) {
+                    drag = Some(drag.unwrap_or(0f32).max(liquid.drag));
+                }
+            });
+
+            if let Some(drag) = drag {
+                let rate = (-drag*context.dt).exp();
+                state.velocity[0] *= rate;
+                state.velocity[1] *= rate;
+                immersed.insert(entity);
+            }
+        }
+
+        for &entity in immersed.difference(&self.immersed) {
+            splash(states.get(entity).unwrap().position, &context);
+            context.event_tx.send(app::GameEvent::EnteredLiquid(entity)).unwrap();
+        }
+        for &entity in self.immersed.difference(&immersed) {
+            if let Some(state) = states.get(entity) {
+                splash(state.position, &context);
+            }
+            context.event_tx.send(app::GameEvent::LeftLiquid(entity)).unwrap();
+        }
+        self.immersed = immersed;
+    }
+}
+
+fn splash(position: [f32;2], context: &app::UpdateContext) {
+    for &angle in &config.effect.angles {
+        context.effect_tx.send(app::Effect::Line {
+            origin: position,
+            length: config.effect.length,
+            angle: angle,
+            persistance: config.effect.persistance,
+            thickness: config.effect.thickness,
+            layer: graphics::Layer::Ceil,
+            color: config.entities.liquid_splash_color,
+        }).unwrap();
+    }
+    baal::effect::short::play(config.entities.liquid_splash_snd,position.into_3d());
+}
@@ -0,0 +1,69 @@
+//! the loading screen shown while the level entered is built by a job: the world of the
+//! previous level is neither updated nor rendered and the world of the level is only
+//! swapped in once it is built, with its navigation data. a level preloaded from the
+//! portals of the previous one continues where its job is.
+//!
+//! the screen shows the level loading, the part built and a tip of levels.loading_tips,
+//! the next one each levels.loading_tip_time. the networked games and the games with
+//! levels.loading_screen false block on the build instead.
+
+use config;
+use levels::{Level, LoadLevelError, Preload};
+use graphics::{self, Layer};
+use specs;
+
+const BAR_WIDTH: f32 = 15.;
+const BAR_HEIGHT: f32 = 0.6;
+const BAR_BORDER: f32 = 0.1;
+
+pub struct Loading {
+    preload: Preload,
+    time: f32,
+    /// the tip shown first
+    first_tip: usize,
+}
+
+impl Loading {
+    pub fn new(preload: Preload, first_tip: usize) -> Self {
+        Loading {
+            preload: preload,
+            time: 0.,
+            first_tip: first_tip,
+        }
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.preload.level
+    }
+
+    /// the built world once it is
+    pub fn update(&mut self, dt: f32) -> Option<Result<specs::World,LoadLevelError>> {
+        self.time += dt;
+        self.preload.poll()
+    }
+
+    /// the part of the level built from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.preload.progress().max(0.).min(1.)
+    }
+
+    /// the locale key of the tip shown, none if there is no tip
+    pub fn tip(&self) -> Option<&'static str> {
+        let tips = &config.levels.loading_tips;
+        if tips.is_empty() {
+            return None;
+        }
+        let index = self.first_tip + (self.time / config.levels.loading_tip_time) as usize;
+        Some(&*tips[index % tips.len()])
+    }
+
+    /// draw the screen with the text above and under the progress bar
+    pub fn draw(&self, title: &str, tip: &str, frame: &mut graphics::Frame) {
+        frame.draw_rectangle(0.,0.,config.menu.background_width,config.menu.background_height,Layer::BillBoard,config.menu.background_color);
+        frame.draw_rectangle(0.,0.,BAR_WIDTH,BAR_HEIGHT,Layer::BillBoard,config.menu.entry_color);
+        frame.draw_rectangle(0.,0.,BAR_WIDTH-2.*BAR_BORDER,BAR_HEIGHT-2.*BAR_BORDER,Layer::BillBoard,config.menu.background_color);
+        let width = (BAR_WIDTH-2.*BAR_BORDER)*self.progress();
+        frame.draw_rectangle((width-BAR_WIDTH)/2.+BAR_BORDER,0.,width,BAR_HEIGHT-2.*BAR_BORDER,Layer::BillBoard,config.menu.cursor_color);
+        frame.draw_billboard_centered_text(&*format!("{}\n\n\n\n{}",title,tip),config.menu.entry_color);
+    }
+}
@@ -0,0 +1,163 @@
+//! the locale holds the strings of the menu, the hud and the dialogues in each language
+//! of locale.languages, loaded from the toml file named after the language in
+//! locale.dir, and switches between them at runtime.
+//!
+//! a file has a table `strings` from ids to strings, or to tables of plural forms by
+//! category (`one`, `other`). `{name}` in a string is replaced by the argument of name.
+//! an id missing in the language is looked up in the first language, then the id
+//! itself is used so dialogues and names can be written as plain text and translated
+//! later.
+
+use config;
+use toml;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+enum Entry {
+    Text(String),
+    Plural(HashMap<String,String>),
+}
+
+/// the plural category of count in the language
+fn plural_category(language: &str, count: usize) -> &'static str {
+    match language {
+        "fr" => if count <= 1 { "one" } else { "other" },
+        _ => if count == 1 { "one" } else { "other" },
+    }
+}
+
+fn load_strings(path: &Path) -> Result<HashMap<String,Entry>,String> {
+    let mut string = String::new();
+    try!(try!(fs::File::open(path).map_err(|e| format!("open locale {:?} failed: {}",path,e)))
+         .read_to_string(&mut string)
+         .map_err(|_| format!("locale {:?} invalid: not valid UTF-8",path)));
+
+    let mut parser = toml::Parser::new(&*string);
+    let table = try!(parser.parse().ok_or(format!("locale {:?} invalid: toml parsing failed",path)));
+    let strings = match table.get("strings") {
+        Some(&toml::Value::Table(ref strings)) => strings,
+        _ => return Err(format!("locale {:?} invalid: expect strings table",path)),
+    };
+
+    let mut entries = HashMap::new();
+    for (id, value) in strings {
+        let entry = match *value {
+            toml::Value::String(ref text) => Entry::Text(text.clone()),
+            toml::Value::Table(ref forms) => {
+                let mut plural = HashMap::new();
+                for (category, form) in forms {
+                    match *form {
+                        toml::Value::String(ref text) => { plural.insert(category.clone(), text.clone()); },
+                        _ => return Err(format!("locale {:?} invalid: strings.{}.{} expect string",path,id,category)),
+                    }
+                }
+                if !plural.contains_key("other") {
+                    return Err(format!("locale {:?} invalid: strings.{} expect other form",path,id));
+                }
+                Entry::Plural(plural)
+            },
+            _ => return Err(format!("locale {:?} invalid: strings.{} expect string or table",path,id)),
+        };
+        entries.insert(id.clone(), entry);
+    }
+    Ok(entries)
+}
+
+fn replace_args(text: &str, args: &[(&str,&str)]) -> String {
+    let mut text = String::from(text);
+    for &(name, value) in args {
+        text = text.replace(&*format!("{{{}}}",name), value);
+    }
+    text
+}
+
+pub struct Locale {
+    language: usize,
+    /// the strings of each language
+    tables: Vec<HashMap<String,Entry>>,
+}
+impl Locale {
+    /// load every language so switching can't fail
+    pub fn load() -> Result<Self,String> {
+        let mut tables = vec!();
+        for language in &config.locale.languages {
+            let path = PathBuf::from(&*config.locale.dir.val).join(Path::new(language).with_extension("toml"));
+            tables.push(try!(load_strings(&path)));
+        }
+        let language = try!(config.locale.languages.iter().position(|l| *l == config.locale.language)
+            .ok_or(format!("locale language {} is not in locale.languages",config.locale.language)));
+        Ok(Locale {
+            language: language,
+            tables: tables,
+        })
+    }
+
+    pub fn language(&self) -> &'static str {
+        &*config.locale.languages[self.language]
+    }
+
+    /// switch to the next language or the previous one if offset is negative
+    pub fn cycle(&mut self, offset: isize) {
+        let len = self.tables.len() as isize;
+        self.language = ((self.language as isize + offset)%len + len) as usize % len as usize;
+    }
+
+    fn entry(&self, id: &str) -> Option<&Entry> {
+        self.tables[self.language].get(id).or_else(|| self.tables[0].get(id))
+    }
+
+    /// the string of id, or id itself
+    pub fn get(&self, id: &str) -> String {
+        self.format(id, &[])
+    }
+
+    /// the string of id, or default
+    pub fn get_or(&self, id: &str, default: &str) -> String {
+        match self.entry(id) {
+            Some(_) => self.get(id),
+            None => default.into(),
+        }
+    }
+
+    /// the string of id with the arguments replaced
+    pub fn format(&self, id: &str, args: &[(&str,&str)]) -> String {
+        match self.entry(id) {
+            Some(&Entry::Text(ref text)) => replace_args(text, args),
+            Some(&Entry::Plural(ref forms)) => replace_args(&forms["other"], args),
+            None => replace_args(id, args),
+        }
+    }
+
+    /// the plural form of id for count with the arguments and count replaced
+    pub fn plural(&self, id: &str, count: usize, args: &[(&str,&str)]) -> String {
+        let count_string = count.to_string();
+        let mut all_args = vec!(("count", &*count_string));
+        all_args.extend_from_slice(args);
+        match self.entry(id) {
+            Some(&Entry::Plural(ref forms)) => {
+                let form = forms.get(plural_category(self.language(), count)).unwrap_or(&forms["other"]);
+                replace_args(form, &all_args)
+            },
+            _ => self.format(id, &all_args),
+        }
+    }
+}
+
+#[test]
+fn test_locale() {
+    let mut locale = Locale::load().unwrap();
+    assert_eq!(locale.get("unknown {name}"), "unknown {name}");
+    assert_eq!(locale.format("unknown {name}", &[("name","id")]), "unknown id");
+
+    let english = config.locale.languages.iter().position(|l| l == "en").unwrap();
+    while locale.language != english {
+        locale.cycle(1);
+    }
+    assert_eq!(locale.plural("money", 1, &[]), "money: 1 coin");
+    assert_eq!(locale.plural("money", 0, &[]), "money: 0 coins");
+
+    assert_eq!(plural_category("fr", 0), "one");
+    assert_eq!(plural_category("en", 0), "other");
+}
@@ -0,0 +1,210 @@
+use app;
+use specs;
+use config;
+use components::*;
+use specs::Join;
+use configuration::{self, FromToml};
+use toml;
+
+/// dynamic entities far from the players are integrated by the physic system only
+/// every period updates, with the time accumulated meanwhile.
+///
+/// beyond the dormancy radius entities that can sleep are dormant: frozen and
+/// removed from the physic world until a player gets near. a woken entity stays
+/// awake for dormancy_wake_time so it doesn't sleep again at the edge of the radius
+pub struct UpdateLod {
+    pub period: usize,
+    pub can_sleep: bool,
+    pub dormant: bool,
+    awake: f32,
+    frame: usize,
+    dt: f32,
+}
+impl specs::Component for UpdateLod {
+    type Storage = specs::VecStorage<Self>;
+}
+impl UpdateLod {
+    pub fn new() -> Self {
+        UpdateLod {
+            period: 1,
+            can_sleep: true,
+            dormant: false,
+            awake: 0.,
+            frame: 0,
+            dt: 0.,
+        }
+    }
+
+    /// for important actors that must be updated even far from the players
+    pub fn never_sleeping() -> Self {
+        UpdateLod {
+            can_sleep: false,
+            .. UpdateLod::new()
+        }
+    }
+
+    /// keep the entity awake for dormancy_wake_time
+    pub fn wake(&mut self) {
+        self.dormant = false;
+        self.awake = config.physic.dormancy_wake_time;
+    }
+
+    /// accumulate dt and return the time to integrate if the entity is updated this frame
+    pub fn step(&mut self, dt: f32) -> Option<f32> {
+        self.dt += dt;
+        self.frame += 1;
+        if self.frame >= self.period {
+            self.frame = 0;
+            Some(::std::mem::replace(&mut self.dt, 0.))
+        } else {
+            None
+        }
+    }
+}
+
+/// period of an entity at distance of the nearest player:
+/// one more frame every lod_distance scaled by ai_lod up to lod_max_period
+fn period(distance: f32, ai_lod: f32) -> usize {
+    if config.physic.lod_distance <= 0. {
+        return 1;
+    }
+    (1 + (distance / (config.physic.lod_distance * ai_lod)) as usize).min(config.physic.lod_max_period)
+}
+
+/// set the period and the dormancy of entities according to their distance to the players
+pub struct UpdateLodSystem;
+impl specs::System<app::UpdateContext> for UpdateLodSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (players, states, mut lods) = arg.fetch(|world| {
+            (
+                world.read::<PlayerControl>(),
+                world.read::<PhysicState>(),
+                world.write::<UpdateLod>(),
+            )
+        });
+
+        let player_positions: Vec<[f32;2]> = (&players, &states).iter().map(|(_, state)| state.position).collect();
+
+        for (lod, state) in (&mut lods, &states).iter() {
+            // without player everything is far
+            let distance = player_positions.iter()
+                .map(|p| ((p[0] - state.position[0]).powi(2) + (p[1] - state.position[1]).powi(2)).sqrt())
+                .fold(::std::f32::MAX, f32::min);
+            lod.period = period(distance, context.ai_lod);
+
+            lod.awake = (lod.awake - context.dt).max(0.);
+
+            let far = config.physic.dormancy_radius > 0. && distance > config.physic.dormancy_radius * context.ai_lod;
+            if lod.dormant && !far {
+                lod.wake();
+            }
+            lod.dormant = lod.can_sleep && far && lod.awake == 0.;
+        }
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum LodRepresentation {
+    Dot,
+    Skip,
+}
+impl_from_into_toml_for_enum!(LodRepresentation {
+    Dot,
+    Skip,
+});
+
+/// the render lod of the entities drawn with it:
+/// * distance: from the nearest view beyond which the entity is far, at camera.zoom;
+///   a view zoomed out by a factor shortens it by that factor
+/// * hysteresis: the entity gets far beyond distance + hysteresis and near again
+///   within distance - hysteresis so it doesn't pop at the limit
+/// * representation: far entities are drawn as a dot of their color or skipped
+/// * dot_size: the radius of the dot
+pub struct RenderLodProfile {
+    pub name: String,
+    pub distance: f32,
+    pub hysteresis: f32,
+    pub representation: LodRepresentation,
+    pub dot_size: f32,
+}
+impl_from_into_toml_for_struct!( RenderLodProfile {
+    name: String,
+    distance: f32,
+    hysteresis: f32,
+    representation: LodRepresentation,
+    dot_size: f32,
+});
+
+/// whether a graphic is drawn by the far representation of its profile
+pub struct RenderLod {
+    profile: usize,
+    pub far: bool,
+}
+impl RenderLod {
+    /// the render lod of the profile of name in render_lod.profiles, none if empty
+    pub fn named(name: &str) -> Option<Self> {
+        if name.is_empty() {
+            None
+        } else {
+            Some(RenderLod {
+                profile: config.render_lod.profiles.iter().position(|p| p.name == name)
+                    .expect("render lod must be checked by the configuration"),
+                far: false,
+            })
+        }
+    }
+
+    pub fn profile(&self) -> &'static RenderLodProfile {
+        &config.render_lod.profiles[self.profile]
+    }
+
+    /// update with the distance to the nearest view scaled by its zoom relative to camera.zoom
+    pub fn update(&mut self, distance: f32) {
+        let profile = self.profile();
+        if self.far {
+            self.far = distance > profile.distance - profile.hysteresis;
+        } else {
+            self.far = distance > profile.distance + profile.hysteresis;
+        }
+    }
+}
+
+#[test]
+fn test_update_lod_accumulate() {
+    let mut lod = UpdateLod::new();
+    assert_eq!(lod.step(0.25), Some(0.25));
+
+    lod.period = 3;
+    assert_eq!(lod.step(0.25), None);
+    assert_eq!(lod.step(0.25), None);
+    assert_eq!(lod.step(0.25), Some(0.75));
+    assert_eq!(lod.step(0.25), None);
+}
+
+#[test]
+fn test_update_lod_wake() {
+    let mut lod = UpdateLod::new();
+    lod.dormant = true;
+    lod.wake();
+    assert!(!lod.dormant);
+    assert_eq!(lod.awake, config.physic.dormancy_wake_time);
+
+    let lod = UpdateLod::never_sleeping();
+    assert!(!lod.can_sleep);
+}
+
+#[test]
+fn test_render_lod_hysteresis() {
+    for profile in &config.render_lod.profiles {
+        let mut lod = RenderLod::named(&*profile.name).unwrap();
+        lod.update(profile.distance + profile.hysteresis / 2.);
+        assert!(!lod.far);
+        lod.update(profile.distance + profile.hysteresis * 2.);
+        assert!(lod.far);
+        lod.update(profile.distance - profile.hysteresis / 2.);
+        assert!(lod.far);
+        lod.update(profile.distance - profile.hysteresis * 2.);
+        assert!(!lod.far);
+    }
+    assert!(RenderLod::named("").is_none());
+}
@@ -0,0 +1,167 @@
+//! loot tables defined in loot.tables of the configuration, rolled with the world
+//! random number generator when an entity with a Loot component dies.
+//!
+//! the guaranteed entries of a table always drop, then an entry is drawn rolls times
+//! by weight. the weight of an entry changes by its luck per point of luck and an
+//! entry can draw a nested table instead of an item.
+
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use inventory::Stack;
+use rand::Rng;
+
+pub type Array2Usize = [usize;2];
+pub type VecLootEntry = Vec<LootEntry>;
+
+/// nested tables deeper than this are not rolled
+const MAX_DEPTH: usize = 8;
+
+/// * weight: chance to be drawn relative to the other entries
+/// * luck: weight added per point of luck, negative for entries less drawn with luck
+/// * item: index of the item in items.definitions
+/// * count: minimum and maximum count of the item
+/// * table: name of a table to roll instead of the item if not empty
+pub struct LootEntry {
+    pub weight: f32,
+    pub luck: f32,
+    pub item: usize,
+    pub count: Array2Usize,
+    pub table: String,
+}
+impl_from_into_toml_for_struct!( LootEntry {
+    weight: f32,
+    luck: f32,
+    item: usize,
+    count: Array2Usize,
+    table: String,
+});
+
+pub struct LootTable {
+    pub name: String,
+    pub rolls: usize,
+    pub guaranteed: VecLootEntry,
+    pub entries: VecLootEntry,
+}
+impl_from_into_toml_for_struct!( LootTable {
+    name: String,
+    rolls: usize,
+    guaranteed: VecLootEntry,
+    entries: VecLootEntry,
+});
+
+/// the index of the table named name
+pub fn table(name: &str) -> Option<usize> {
+    config.loot.tables.iter().position(|t| t.name == name)
+}
+
+/// the table an entity drops when it dies
+pub struct Loot {
+    pub table: usize,
+}
+impl specs::Component for Loot {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Loot {
+    pub fn named(name: &str) -> Self {
+        Loot {
+            table: table(name).expect("loot table must be defined in loot.tables"),
+        }
+    }
+}
+
+/// the stacks dropped by the table with luck
+pub fn roll<R: Rng>(tables: &[LootTable], table: usize, luck: f32, rng: &mut R) -> Vec<Stack> {
+    let mut stacks = vec!();
+    roll_into(tables, table, luck, rng, 0, &mut stacks);
+    stacks
+}
+
+fn roll_into<R: Rng>(tables: &[LootTable], table: usize, luck: f32, rng: &mut R, depth: usize, stacks: &mut Vec<Stack>) {
+    if depth >= MAX_DEPTH {
+        return;
+    }
+    let table = &tables[table];
+    for entry in &table.guaranteed {
+        drop_entry(tables, entry, luck, rng, depth, stacks);
+    }
+
+    let weight = |entry: &LootEntry| (entry.weight + entry.luck*luck).max(0.);
+    let total: f32 = table.entries.iter().map(&weight).sum();
+    if total <= 0. {
+        return;
+    }
+    for _ in 0..table.rolls {
+        let mut drawn = rng.gen_range(0., total);
+        let entry = table.entries.iter()
+            .find(|entry| {
+                drawn -= weight(*entry);
+                drawn < 0.
+            })
+            .unwrap_or(table.entries.last().unwrap());
+        drop_entry(tables, entry, luck, rng, depth, stacks);
+    }
+}
+
+fn drop_entry<R: Rng>(tables: &[LootTable], entry: &LootEntry, luck: f32, rng: &mut R, depth: usize, stacks: &mut Vec<Stack>) {
+    if !entry.table.is_empty() {
+        if let Some(nested) = tables.iter().position(|t| t.name == entry.table) {
+            roll_into(tables, nested, luck, rng, depth + 1, stacks);
+        }
+        return;
+    }
+    let count = rng.gen_range(entry.count[0], entry.count[1] + 1);
+    if count > 0 {
+        stacks.push(Stack { item: entry.item, count: count });
+    }
+}
+
+/// a small impulse in a random direction for a dropped stack
+pub fn scatter_velocity<R: Rng>(rng: &mut R) -> [f32;2] {
+    use std::f32::consts::PI;
+
+    let angle = rng.gen_range(-PI, PI);
+    [config.loot.scatter*angle.cos(), config.loot.scatter*angle.sin()]
+}
+
+#[test]
+fn test_roll() {
+    use rng::WorldRng;
+
+    let entry = |weight, luck, item, table: &str| LootEntry {
+        weight: weight,
+        luck: luck,
+        item: item,
+        count: [1, 1],
+        table: table.into(),
+    };
+    let tables = vec!(
+        LootTable {
+            name: "monster".into(),
+            rolls: 3,
+            guaranteed: vec!(entry(1., 0., 0, "")),
+            entries: vec!(entry(1., -1., 0, ""), entry(0., 1., 0, "rare")),
+        },
+        LootTable {
+            name: "rare".into(),
+            rolls: 1,
+            guaranteed: vec!(),
+            entries: vec!(entry(1., 0., 1, "")),
+        },
+    );
+
+    // without luck the rare table can't be drawn
+    let stacks = roll(&tables, 0, 0., &mut WorldRng::from_seed(1));
+    assert_eq!(stacks.len(), 4);
+    assert!(stacks.iter().all(|s| s.item == 0));
+
+    // with luck only the rare table can be drawn
+    let stacks = roll(&tables, 0, 1., &mut WorldRng::from_seed(1));
+    assert_eq!(stacks.iter().filter(|s| s.item == 1).count(), 3);
+
+    // the same seed drops the same loot
+    let a = roll(&tables, 0, 0.5, &mut WorldRng::from_seed(3));
+    let b = roll(&tables, 0, 0.5, &mut WorldRng::from_seed(3));
+    assert_eq!(a, b);
+}
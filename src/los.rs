@@ -0,0 +1,142 @@
+use specs;
+use config;
+use physic::{PhysicWorld, Ray, RaycastOptions};
+use math;
+use std::collections::HashMap;
+
+/// number of sights of the cache, it is cleared when full so the sights of deleted
+/// entities don't pile up
+const CAPACITY: usize = 4096;
+
+fn distance(a: [f32;2], b: [f32;2]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+/// visibility cached with the positions it was computed from
+struct Sight {
+    visible: bool,
+    from: [f32;2],
+    to: [f32;2],
+}
+
+impl Sight {
+    fn is_valid(&self, from: [f32;2], to: [f32;2]) -> bool {
+        distance(self.from, from) <= config.physic.los_move_threshold
+            && distance(self.to, to) <= config.physic.los_move_threshold
+    }
+}
+
+/// line of sight between entities of the physic world.
+///
+/// results are cached until one of the ends moves more than los_move_threshold
+/// or the static entities of the physic world change, up to CAPACITY results
+pub struct LineOfSight {
+    static_version: usize,
+    pairs: HashMap<(specs::Entity,specs::Entity,u32),Sight>,
+}
+
+impl LineOfSight {
+    pub fn new() -> Self {
+        LineOfSight {
+            static_version: 0,
+            pairs: HashMap::new(),
+        }
+    }
+
+    fn check_walls(&mut self, physic_world: &PhysicWorld) {
+        if physic_world.static_version() != self.static_version {
+            self.static_version = physic_world.static_version();
+            self.pairs.clear();
+        }
+    }
+
+    /// whether the first entity of mask on the ray from a to b is b
+    pub fn los(&mut self, physic_world: &PhysicWorld, a: specs::Entity, a_pos: [f32;2], b: specs::Entity, b_pos: [f32;2], mask: u32) -> bool {
+        self.check_walls(physic_world);
+
+        if let Some(sight) = self.pairs.get(&(a,b,mask)) {
+            if sight.is_valid(a_pos, b_pos) {
+                return sight.visible;
+            }
+        }
+
+        let mut visible = false;
+        physic_world.raycast_with(&ray(a_pos, b_pos, mask), &RaycastOptions { exclude: &[a], max_hits: Some(1), static_only: false }, &mut |(entity,_,_)| {
+            visible = entity == b;
+            false
+        });
+
+        if self.pairs.len() >= CAPACITY {
+            self.pairs.clear();
+        }
+        self.pairs.insert((a,b,mask), Sight { visible: visible, from: a_pos, to: b_pos });
+        visible
+    }
+}
+
+fn ray(from: [f32;2], to: [f32;2], mask: u32) -> Ray {
+    Ray {
+        origin: from,
+        angle: math::atan2(to[1] - from[1], to[0] - from[0]),
+        length: distance(from, to),
+        mask: mask,
+    }
+}
+
+#[test]
+fn test_line_of_sight_cache() {
+    use physic::Shape;
+
+    let mut world = specs::World::new();
+    let mut physic_world = PhysicWorld::new();
+    let mut line_of_sight = LineOfSight::new();
+    let a = world.create_now().build();
+    let b = world.create_now().build();
+    let wall = world.create_now().build();
+    physic_world.insert_static(b, &[6.,0.5], 1, &Shape::Circle(0.4));
+
+    assert!(line_of_sight.los(&physic_world, a, [0.,0.5], b, [6.,0.5], 1));
+
+    // the cached result is kept for small moves and dropped when walls change
+    physic_world.insert_static(wall, &[3.,0.5], 1, &Shape::Square(0.5));
+    assert!(!line_of_sight.los(&physic_world, a, [0.1,0.5], b, [6.,0.5], 1));
+}
+
+#[test]
+fn test_occluders() {
+    use physic::Shape;
+
+    let mut world = specs::World::new();
+    let mut physic_world = PhysicWorld::new();
+    let mut line_of_sight = LineOfSight::new();
+    let a = world.create_now().build();
+    let b = world.create_now().build();
+    let foliage = world.create_now().build();
+    let low_wall = world.create_now().build();
+    let char_group = config.entities.char_group.val;
+    let vision_mask = config.entities.monster_vision_mask.val;
+    physic_world.insert_static(b, &[6.,0.5], char_group, &Shape::Circle(0.4));
+    physic_world.insert_static(low_wall, &[3.,0.5], config.entities.wall_group.val, &Shape::Square(0.5));
+
+    // low walls block the movement but not the sight
+    assert!(line_of_sight.los(&physic_world, a, [0.,0.5], b, [6.,0.5], vision_mask));
+    assert!(!physic_world.fits(&[3.,0.5], &Shape::Circle(0.4), config.entities.wall_group.val));
+
+    // foliage blocks the sight but not the movement
+    physic_world.insert_static(foliage, &[4.5,0.5], config.entities.occluder_group.val, &Shape::Square(0.5));
+    assert!(!line_of_sight.los(&physic_world, a, [0.,0.5], b, [6.,0.5], vision_mask));
+    assert!(physic_world.fits(&[4.5,0.5], &Shape::Circle(0.4), config.entities.wall_group.val));
+}
+
+#[test]
+fn test_line_of_sight_capacity() {
+    let mut world = specs::World::new();
+    let physic_world = PhysicWorld::new();
+    let mut line_of_sight = LineOfSight::new();
+    let a = world.create_now().build();
+    for i in 0..CAPACITY + 10 {
+        let b = world.create_now().build();
+        line_of_sight.los(&physic_world, a, [0.,0.], b, [i as f32,1.], 1);
+    }
+    assert!(line_of_sight.pairs.len() <= CAPACITY);
+}
@@ -11,6 +11,7 @@ extern crate fnv;
 extern crate png;
 extern crate gilrs;
 
+#[macro_use] mod group;
 mod persistent_snd;
 mod levels;
 mod app;
@@ -23,6 +24,79 @@ mod utils;
 mod life;
 mod portal;
 mod text;
+mod inventory;
+mod interaction;
+mod checkpoint;
+mod stats;
+mod sequence;
+mod quest;
+mod net;
+mod replication;
+mod interpolation;
+mod task;
+mod liquid;
+mod zone;
+mod lod;
+mod math;
+mod los;
+mod melee;
+mod rope;
+mod patrol;
+mod daynight;
+mod weather;
+mod inspector;
+mod worlds;
+mod nav;
+mod region;
+mod faction;
+mod targeting;
+mod budget;
+mod jobs;
+mod ids;
+mod savegame;
+mod settings;
+mod profiles;
+mod governor;
+mod scene;
+mod loading;
+mod projectile;
+mod combat;
+mod rng;
+mod loot;
+mod progression;
+mod shop;
+mod ability;
+mod dash;
+mod movement;
+mod grab;
+mod mount;
+mod autotile;
+mod decal;
+mod ambient;
+mod hazard;
+mod boss;
+mod freecam;
+mod capture;
+mod hotreload;
+mod telemetry;
+mod locale;
+mod accessibility;
+mod controls;
+mod display;
+mod shaders;
+mod palette;
+mod feedback;
+mod highlight;
+mod memory;
+mod leak;
+mod entity_ref;
+mod schedule;
+#[cfg(test)]
+mod trace;
+#[cfg(any(test, feature = "bench"))]
+mod heatmap;
+#[cfg(feature = "bench")]
+mod bench;
 
 mod components {
     pub use control::{
@@ -38,8 +112,11 @@ mod components {
         PhysicDynamic,
         PhysicStatic,
         PhysicTrigger,
+        PhysicTeleported,
         Shape,
         Ray,
+        RaycastOptions,
+        QueryRegion,
         CollisionBehavior,
     };
     pub use life::{
@@ -49,7 +126,42 @@ mod components {
         Ball,
     };
     pub use portal::Portal;
+    pub use inventory::{
+        Inventory,
+        Pickup,
+    };
+    pub use interaction::{
+        Interactable,
+        Interactor,
+    };
+    pub use checkpoint::Checkpoint;
+    pub use liquid::Liquid;
+    pub use zone::{ForceZone, ProjectileField, Surface};
+    pub use lod::UpdateLod;
+    pub use melee::MeleeAttack;
+    pub use rope::{Rope, Anchor};
+    pub use patrol::Patrol;
+    pub use region::RegionTracker;
+    pub use faction::Faction;
+    pub use targeting::Threat;
+    pub use projectile::Motion;
+    pub use loot::Loot;
+    pub use progression::{Attributes, Experience};
+    pub use shop::{Wallet, Vendor};
+    pub use ability::{Abilities, Caster, Statuses};
+    pub use dash::Dash;
+    pub use movement::{Movement, ModeZone};
+    pub use grab::Grabber;
+    pub use mount::{Mount, Rider};
+    pub use autotile::Autotile;
+    pub use ambient::AmbientEmitter;
+    pub use hazard::Hazard;
+    pub use boss::{Boss, Arena, ArenaGate};
+    pub use replication::Replicated;
+    pub use net::Peer;
+    pub use interpolation::Interpolated;
     pub use app::Graphic;
+    pub use highlight::Highlight;
     pub use text::{
         FixedCameraText,
         FixedCamera,
@@ -62,9 +174,19 @@ mod components {
 }
 mod resource {
     pub use physic::PhysicWorld;
+    pub use los::LineOfSight;
+    pub use daynight::DayNight;
+    pub use weather::Weather;
+    pub use nav::NavData;
+    pub use region::Regions;
+    pub use faction::Relations;
+    pub use rng::WorldRng;
+    pub use autotile::AutotileCells;
 }
 mod systems {
     pub use physic::PhysicSystem;
+    #[cfg(feature = "validate")]
+    pub use physic::ValidateSystem;
     pub use life::{
         LifeSystem,
         KillerSystem,
@@ -77,6 +199,31 @@ mod systems {
         TowardPlayerSystem,
     };
     pub use portal::PortalSystem;
+    pub use inventory::PickupSystem;
+    pub use interaction::InteractionSystem;
+    pub use highlight::HighlightSystem;
+    pub use checkpoint::CheckpointSystem;
+    pub use liquid::LiquidSystem;
+    pub use zone::{ForceZoneSystem, ProjectileFieldSystem};
+    pub use lod::UpdateLodSystem;
+    pub use melee::MeleeSystem;
+    pub use region::RegionSystem;
+    pub use targeting::ThreatSystem;
+    pub use projectile::MotionSystem;
+    pub use progression::ProgressionSystem;
+    pub use ability::AbilitySystem;
+    pub use dash::DashSystem;
+    pub use movement::MovementSystem;
+    pub use grab::GrabSystem;
+    pub use mount::MountSystem;
+    pub use autotile::AutotileSystem;
+    pub use rope::RopeSystem;
+    pub use patrol::PatrolSystem;
+    pub use daynight::DayNightSystem;
+    pub use weather::WeatherSystem;
+    pub use ambient::AmbientSystem;
+    pub use hazard::HazardSystem;
+    pub use boss::BossSystem;
     pub use persistent_snd::PersistentSndSystem;
 }
 
@@ -90,12 +237,17 @@ use event_loop::{
     Events,
     Event,
 };
+use display::DisplayEvent;
 
 fn init() -> Result<(app::App,glium::backend::glutin_backend::GlutinFacade,event_loop::WindowEvents,gilrs::Gilrs),String> {
     use glium::DisplayBuild;
 
+    // load the settings before the window and the world
+    savegame::print_report(&*settings::SAVE_PATH.to_string_lossy(), &config.save_report);
+
     let mut musics = vec!();
     musics.push(config.levels.entry_music.val.clone());
+    musics.push(config.bosses.music.val.clone());
 
     // load casltes
     let (castles,mut musics) = try!(levels::load_castles(musics).map_err(|e| format!("ERROR: levels castles load failed: {}",e)));
@@ -126,31 +278,37 @@ fn init() -> Result<(app::App,glium::backend::glutin_backend::GlutinFacade,event
     // init window
     // TODO if fail then disable vsync and then multisampling and then vsync and multisamping
     let window = {
-        let mut builder = glium::glutin::WindowBuilder::new();
-
-        if config.window.vsync {
-            builder = builder.with_vsync();
-        }
-        if config.window.multisampling != 0 {
-            builder = builder.with_multisampling(config.window.multisampling);
-        }
-        if config.window.fullscreen {
-            if config.window.fullscreen_on_primary_monitor {
-                builder = builder.with_fullscreen(glutin::get_primary_monitor());
-            } else {
-                builder = builder.with_fullscreen(try!(glutin::get_available_monitors().nth(config.window.fullscreen_monitor)
-                                                  .ok_or("ERROR: window init failed: fullsceen monitor specified unavailable")));
-            }
-        } else {
-            builder = builder.with_dimensions(config.window.dimension[0], config.window.dimension[1])
-                .with_title(format!("ruga"));
-        }
+        let builder = try!(display::Display::new().window_builder().map_err(|e| format!("ERROR: window init failed: {}",e)));
         try!(builder.build_glium().map_err(|e| format!("ERROR: window init failed: {}",e)))
     };
     window.get_window().unwrap().set_cursor_state(glium::glutin::CursorState::Hide).unwrap();
 
     // init app
-    let app = try!(app::App::new(&window,castles).map_err(|e| format!("ERROR: app creation failed: {}",e)));
+    let mut app = try!(app::App::new(&window,castles).map_err(|e| format!("ERROR: app creation failed: {}",e)));
+
+    // export the combat log if asked for
+    let mut args = std::env::args().skip_while(|arg| arg != "--combat-log");
+    if args.next().is_some() {
+        let path = try!(args.next().ok_or("ERROR: combat log export failed: expect a file after --combat-log"));
+        try!(app.export_combat_log(path.as_ref()).map_err(|e| format!("ERROR: combat log export failed: {}",e)));
+    }
+
+    // export the telemetry if asked for
+    let mut args = std::env::args().skip_while(|arg| arg != "--telemetry");
+    if args.next().is_some() {
+        let path = try!(args.next().ok_or("ERROR: telemetry export failed: expect a file after --telemetry"));
+        try!(app.export_telemetry(path.as_ref()).map_err(|e| format!("ERROR: telemetry export failed: {}",e)));
+    }
+
+    // init lockstep
+    match &*config.net.mode {
+        "host" => app.set_net(try!(net::Lockstep::host(config.net.port).map_err(|e| format!("ERROR: net host failed: {}",e)))),
+        "join" => app.set_net(try!(net::Lockstep::join(&*config.net.address).map_err(|e| format!("ERROR: net join failed: {}",e)))),
+        "server" => app.set_server(try!(replication::Server::bind(config.net.port).map_err(|e| format!("ERROR: net server failed: {}",e)))),
+        "client" => app.set_client(try!(replication::Client::connect(&*config.net.address).map_err(|e| format!("ERROR: net client failed: {}",e)))),
+        "none" => (),
+        _ => unreachable!(),
+    }
 
     // init event loop
     let window_events = window.events(&event_loop::Setting {
@@ -162,7 +320,77 @@ fn init() -> Result<(app::App,glium::backend::glutin_backend::GlutinFacade,event
     Ok((app,window,window_events,gilrs::Gilrs::new()))
 }
 
+/// run the stress scenes instead of the game if asked for
+#[cfg(feature = "bench")]
+fn bench() -> bool {
+    if std::env::args().any(|arg| arg == "--bench") {
+        bench::run();
+        true
+    } else {
+        false
+    }
+}
+#[cfg(not(feature = "bench"))]
+fn bench() -> bool {
+    false
+}
+
+/// bake the navigation data of the rooms instead of running the game if asked for
+fn bake_nav() -> bool {
+    if !std::env::args().any(|arg| arg == "--bake-nav") {
+        return false;
+    }
+    let result = levels::load_castles(vec!())
+        .map_err(|e| format!("ERROR: levels castles load failed: {}",e))
+        .and_then(|(castles,_)| nav::bake_all(&castles).map_err(|e| format!("ERROR: navigation bake failed: {}",e)));
+    if let Err(err) = result {
+        println!("{}",err);
+        std::process::exit(1);
+    }
+    true
+}
+
+/// export the death heatmap of a telemetry file instead of running the game if asked for
+fn telemetry_heatmap() -> bool {
+    let mut args = std::env::args().skip_while(|arg| arg != "--telemetry-heatmap");
+    if args.next().is_none() {
+        return false;
+    }
+    let result = match (args.next(), args.next()) {
+        (Some(telemetry), Some(csv)) => telemetry::export_death_heatmap(telemetry.as_ref(), csv.as_ref())
+            .map_err(|e| format!("ERROR: telemetry heatmap failed: {}",e)),
+        _ => Err("ERROR: telemetry heatmap failed: expect a telemetry file and a csv file after --telemetry-heatmap".into()),
+    };
+    if let Err(err) = result {
+        println!("{}",err);
+        std::process::exit(1);
+    }
+    true
+}
+
+/// remove a profile and its files instead of running the game if asked for
+fn remove_profile() -> bool {
+    let mut args = std::env::args().skip_while(|arg| arg != "--remove-profile");
+    if args.next().is_none() {
+        return false;
+    }
+    let result = match args.next() {
+        Some(name) => profiles::Profiles::load(&settings::dir()).remove(&name)
+            .map_err(|e| format!("ERROR: profile removal failed: {}",e)),
+        None => Err("ERROR: profile removal failed: expect a profile after --remove-profile".into()),
+    };
+    if let Err(err) = result {
+        println!("{}",err);
+        std::process::exit(1);
+    }
+    true
+}
+
 fn main() {
+    if bench() || bake_nav() || telemetry_heatmap() || remove_profile() {
+        return;
+    }
+
     // init
     let (mut app,mut window,mut window_events, mut gamepad) = match init() {
         Ok(t) => t,
@@ -177,7 +405,7 @@ fn main() {
         match event {
             Event::Update(args) => app.update(args),
             Event::Render(args) => app.render(args),
-            Event::GlutinEvent(glutin::Event::Closed) => break,
+            Event::GlutinEvent(glutin::Event::Closed) => app.quit = true,
             Event::GlutinEvent(glutin::Event::KeyboardInput(state,keycode,_)) => {
                 if state == glutin::ElementState::Pressed {
                     app.key_pressed(keycode);
@@ -186,6 +414,7 @@ fn main() {
                 }
             },
             Event::GlutinEvent(glutin::Event::Focused(f)) => app.focused(f),
+            Event::GlutinEvent(glutin::Event::Resized(width,height)) => app.display_event(DisplayEvent::Resized(width,height)),
             Event::GlutinEvent(glutin::Event::Touch(t)) => app.touch(t),
             Event::GlutinEvent(_) => (),
             Event::GilrsEvent(gilrs::Event::ButtonPressed(button, _)) => app.button_pressed(button),
@@ -195,7 +424,21 @@ fn main() {
             Event::Idle(args) => thread::sleep(args.dt),
         }
 
+        if let Some(builder) = app.take_window_rebuild() {
+            use glium::DisplayBuild;
+
+            let result = builder.and_then(|builder| builder.rebuild_glium(&window).map_err(|e| format!("window rebuild failed: {}",e)));
+            match result {
+                Ok(()) => {
+                    window.get_window().unwrap().set_cursor_state(glium::glutin::CursorState::Hide).unwrap();
+                    app.display_event(DisplayEvent::Rebuilt);
+                },
+                Err(err) => app.display_event(DisplayEvent::Failed(err)),
+            }
+        }
+
         if app.quit {
+            app.save_stats();
             baal::close();
             return;
         }
@@ -0,0 +1,170 @@
+//! trigonometric and logarithm functions used by the physic integration and collision resolution.
+//!
+//! the platform libm may round them differently from one machine to another, with the
+//! deterministic feature they are computed in f64 with additions and multiplications only,
+//! that are exactly rounded, so identical inputs give bit-identical worlds in lockstep.
+
+use std::f64::consts::{PI, FRAC_PI_2, FRAC_PI_4, LN_2, SQRT_2};
+
+#[cfg(not(feature = "deterministic"))]
+pub fn cos(x: f32) -> f32 {
+    x.cos()
+}
+
+#[cfg(not(feature = "deterministic"))]
+pub fn sin(x: f32) -> f32 {
+    x.sin()
+}
+
+#[cfg(not(feature = "deterministic"))]
+pub fn atan2(y: f32, x: f32) -> f32 {
+    y.atan2(x)
+}
+
+#[cfg(not(feature = "deterministic"))]
+pub fn ln(x: f32) -> f32 {
+    x.ln()
+}
+
+#[cfg(feature = "deterministic")]
+pub fn cos(x: f32) -> f32 {
+    portable_sin(x as f64 + FRAC_PI_2) as f32
+}
+
+#[cfg(feature = "deterministic")]
+pub fn sin(x: f32) -> f32 {
+    portable_sin(x as f64) as f32
+}
+
+#[cfg(feature = "deterministic")]
+pub fn atan2(y: f32, x: f32) -> f32 {
+    portable_atan2(y as f64, x as f64) as f32
+}
+
+#[cfg(feature = "deterministic")]
+pub fn ln(x: f32) -> f32 {
+    portable_ln(x as f64) as f32
+}
+
+/// taylor series after reduction to [-pi/2,pi/2], error is below 1e-10
+#[cfg_attr(not(feature = "deterministic"), allow(dead_code))]
+fn portable_sin(x: f64) -> f64 {
+    // reduce to [-pi,pi] then to [-pi/2,pi/2] with sin(pi-x) = sin(x)
+    let mut x = x - 2.*PI*(x/(2.*PI)).round();
+    if x > FRAC_PI_2 {
+        x = PI - x;
+    } else if x < -FRAC_PI_2 {
+        x = -PI - x;
+    }
+
+    let x2 = x*x;
+    let mut term = x;
+    let mut sum = x;
+    for n in 1..9 {
+        term = -term*x2/((2*n*(2*n+1)) as f64);
+        sum += term;
+    }
+    sum
+}
+
+/// series of atan after reduction to [-tan(pi/8),tan(pi/8)], error is below 1e-10
+#[cfg_attr(not(feature = "deterministic"), allow(dead_code))]
+fn portable_atan(x: f64) -> f64 {
+    const TAN_PI_8: f64 = 0.41421356237309504880;
+
+    if x < 0. {
+        return -portable_atan(-x);
+    }
+    if x > 1. {
+        return FRAC_PI_2 - portable_atan(1./x);
+    }
+    if x > TAN_PI_8 {
+        return FRAC_PI_4 + portable_atan((x - 1.)/(x + 1.));
+    }
+
+    let x2 = x*x;
+    let mut power = x;
+    let mut sum = x;
+    for n in 1..14 {
+        power = -power*x2;
+        sum += power/((2*n+1) as f64);
+    }
+    sum
+}
+
+#[cfg_attr(not(feature = "deterministic"), allow(dead_code))]
+fn portable_atan2(y: f64, x: f64) -> f64 {
+    if x > 0. {
+        portable_atan(y/x)
+    } else if x < 0. {
+        if y >= 0. {
+            portable_atan(y/x) + PI
+        } else {
+            portable_atan(y/x) - PI
+        }
+    } else if y > 0. {
+        FRAC_PI_2
+    } else if y < 0. {
+        -FRAC_PI_2
+    } else {
+        0.
+    }
+}
+
+/// series of atanh after reduction to [1/sqrt(2),sqrt(2)] by powers of two, that are exact,
+/// error is below 1e-10
+#[cfg_attr(not(feature = "deterministic"), allow(dead_code))]
+fn portable_ln(x: f64) -> f64 {
+    if x.is_nan() || x < 0. {
+        return ::std::f64::NAN;
+    }
+    if x == 0. {
+        return ::std::f64::NEG_INFINITY;
+    }
+    if x.is_infinite() {
+        return x;
+    }
+
+    let mut x = x;
+    let mut exponent = 0.;
+    while x > SQRT_2 {
+        x /= 2.;
+        exponent += 1.;
+    }
+    while x < 1./SQRT_2 {
+        x *= 2.;
+        exponent -= 1.;
+    }
+
+    // ln(x) = 2*atanh((x-1)/(x+1))
+    let z = (x - 1.)/(x + 1.);
+    let z2 = z*z;
+    let mut power = z;
+    let mut sum = z;
+    for n in 1..12 {
+        power *= z2;
+        sum += power/((2*n+1) as f64);
+    }
+    2.*sum + exponent*LN_2
+}
+
+#[test]
+fn test_portable_trigonometry() {
+    for i in -2000..2000 {
+        let x = i as f64 * 0.01;
+        assert!((portable_sin(x) - x.sin()).abs() < 1e-9, "sin {}", x);
+        assert!((portable_atan(x) - x.atan()).abs() < 1e-9, "atan {}", x);
+        for &y in &[-3., -0.5, 0., 0.5, 3.] {
+            assert!((portable_atan2(y, x) - y.atan2(x)).abs() < 1e-9, "atan2 {} {}", y, x);
+        }
+    }
+}
+
+#[test]
+fn test_portable_ln() {
+    for i in 1..4000 {
+        let x = i as f64 * 0.01;
+        assert!((portable_ln(x) - x.ln()).abs() < 1e-9, "ln {}", x);
+        assert!((portable_ln(x*1e6) - (x*1e6).ln()).abs() < 1e-9, "ln {}", x*1e6);
+    }
+}
@@ -0,0 +1,136 @@
+use app;
+use specs;
+use components::*;
+use resource::{PhysicWorld, Relations};
+use specs::Join;
+use std::collections::HashSet;
+use std::f32::consts::FRAC_PI_2;
+use combat::{Damage, DamageType};
+use progression;
+use math;
+
+/// number of segments of the arc of the sector
+const ARC_SEGMENTS: usize = 8;
+
+/// an attack in a sector in front of the entity, of half opening angle and of range,
+/// during a swing each entity of mask overlapping the sector is hit once.
+/// angle is clamped to PI/2 so the sector is convex
+pub struct MeleeAttack {
+    pub range: f32,
+    pub angle: f32,
+    pub duration: f32,
+    pub mask: u32,
+    direction: f32,
+    remaining: f32,
+    hit: HashSet<specs::Entity>,
+}
+impl specs::Component for MeleeAttack {
+    type Storage = specs::VecStorage<Self>;
+}
+
+impl MeleeAttack {
+    pub fn new(range: f32, angle: f32, duration: f32, mask: u32) -> Self {
+        MeleeAttack {
+            range: range,
+            angle: angle.min(FRAC_PI_2),
+            duration: duration,
+            mask: mask,
+            direction: 0.,
+            remaining: 0.,
+            hit: HashSet::new(),
+        }
+    }
+
+    /// start a swing in direction, entities hit by the previous swing can be hit again
+    pub fn swing(&mut self, direction: f32) {
+        self.direction = direction;
+        self.remaining = self.duration;
+        self.hit.clear();
+    }
+
+    pub fn is_swinging(&self) -> bool {
+        self.remaining > 0.
+    }
+}
+
+/// the sector from pos as a polygon
+fn sector(pos: [f32;2], direction: f32, angle: f32, range: f32) -> QueryRegion {
+    let mut points = vec!(pos);
+    for i in 0..ARC_SEGMENTS+1 {
+        let a = direction - angle + 2.*angle*i as f32/ARC_SEGMENTS as f32;
+        points.push([pos[0] + range*math::cos(a), pos[1] + range*math::sin(a)]);
+    }
+    QueryRegion::Polygon(points)
+}
+
+/// damage the targets by the damage of the attacker and send MeleeHit events with
+/// the direction from the attacker to the target
+pub struct MeleeSystem;
+impl specs::System<app::UpdateContext> for MeleeSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut attacks, mut threats, mut lives, states, factions, attributes, physic_world, relations, entities) = arg.fetch(|world| {
+            (
+                world.write::<MeleeAttack>(),
+                world.write::<Threat>(),
+                world.write::<Life>(),
+                world.read::<PhysicState>(),
+                world.read::<Faction>(),
+                world.read::<Attributes>(),
+                world.read_resource::<PhysicWorld>(),
+                world.read_resource::<Relations>(),
+                world.entities(),
+            )
+        });
+
+        for (attack, state, entity) in (&mut attacks, &states, &entities).iter() {
+            if !attack.is_swinging() { continue }
+            attack.remaining -= context.dt;
+
+            let region = sector(state.position, attack.direction, attack.angle, attack.range);
+            let mut targets = vec!();
+            physic_world.apply_on_region(&region, attack.mask, &mut |other_entity| {
+                if *other_entity != entity && relations.harms(factions.get(entity), factions.get(*other_entity)) {
+                    targets.push(*other_entity);
+                }
+            });
+            targets.sort_by_key(|e| e.get_id());
+
+            for target in targets {
+                if !attack.hit.insert(target) { continue }
+                let direction = states.get(target)
+                    .map(|s| math::atan2(s.position[1] - state.position[1], s.position[0] - state.position[0]))
+                    .unwrap_or(attack.direction);
+                context.event_tx.send(app::GameEvent::MeleeHit(entity, target, direction)).unwrap();
+                let position = states.get(target).map(|s| s.position).unwrap_or(state.position);
+                let amount = progression::damage(attributes.get(entity));
+                if let Some(life) = lives.get_mut(target) {
+                    life.damage(amount, entity);
+                }
+                context.event_tx.send(app::GameEvent::Damaged(Damage::new(entity, target, position, amount, DamageType::Melee))).unwrap();
+                if let Some(threat) = threats.get_mut(entity) {
+                    threat.hit();
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_sector() {
+    use std::f32::consts::PI;
+
+    let region = sector([0.,0.], 0., PI/4., 2.);
+    assert!(region.overlaps([1.5,0.], &Shape::Circle(0.1)));
+    assert!(region.overlaps([2.2,0.], &Shape::Circle(0.3)));
+    assert!(!region.overlaps([-1.,0.], &Shape::Circle(0.5)));
+    assert!(!region.overlaps([0.,1.5], &Shape::Circle(0.3)));
+    assert!(region.overlaps([0.,1.5], &Shape::Square(0.9)));
+}
+
+#[test]
+fn test_melee_angle_clamped() {
+    use std::f32::consts::PI;
+
+    assert_eq!(MeleeAttack::new(1., PI, 0.2, 1).angle, FRAC_PI_2);
+    assert_eq!(MeleeAttack::new(1., PI/4., 0.2, 1).angle, PI/4.);
+}
@@ -0,0 +1,92 @@
+//! memory report of a world to track down the growth of long sessions: the entities
+//! by kind, the count and size of each component, the buckets of the spatial hash
+//! and the pools of effects, particles and decals. it is printed with
+//! keys.memory_report with the suspects of the leak audit.
+
+use specs;
+use life::Kind;
+use components::*;
+use resource::PhysicWorld;
+use leak;
+use specs::Join;
+use std::fmt::Write;
+use std::mem;
+
+/// the number of components of each type and their size
+macro_rules! component_memory {
+    ($world:expr, $report:expr, [$($component:ty),*]) => {
+        $(
+            let count = $world.read::<$component>().iter().count();
+            if count > 0 {
+                write!($report, "  {}: {} ({} bytes)\n", stringify!($component), count, count*mem::size_of::<$component>()).unwrap();
+            }
+        )*
+    };
+}
+
+/// the report of the world and of the pools given by name and size
+pub fn report(world: &specs::World, pools: &[(&str, usize)]) -> String {
+    let mut report = String::new();
+
+    let entities = world.entities();
+    let lives = world.read::<Life>();
+    let statics = world.read::<PhysicStatic>();
+    let total = (&entities).iter().count();
+    let kind = |kind: Kind| lives.iter().filter(|life| life.kind() == kind).count();
+    let (characters, monsters, balls) = (kind(Kind::Character), kind(Kind::Monster), kind(Kind::Ball));
+    let statics = statics.iter().count();
+    write!(report, "entities: {}\n  character: {}\n  monster: {}\n  ball: {}\n  static: {}\n  other: {}\n",
+           total, characters, monsters, balls, statics,
+           total.saturating_sub(characters + monsters + balls + statics)).unwrap();
+
+    write!(report, "components:\n").unwrap();
+    component_memory!(world, report, [
+        PlayerControl, TowardPlayerControl, MonsterControl,
+        PhysicState, PhysicForce, PhysicType, PhysicDynamic, PhysicStatic, PhysicTrigger, PhysicTeleported, GridSquare,
+        Graphic, Highlight,
+        Life, Killer, Ball, Column,
+        Portal,
+        Inventory, Pickup,
+        Interactable, Interactor,
+        Checkpoint, Liquid, ForceZone, ProjectileField, Surface, UpdateLod, MeleeAttack, Rope, Patrol, RegionTracker, Faction, Threat, Motion, Loot, Attributes, Experience, Wallet, Vendor, Abilities, Caster, Statuses, Dash, Movement, ModeZone, Grabber, Mount, Rider, Autotile, AmbientEmitter, Hazard, Boss, Arena, ArenaGate,
+        FixedCameraText, Text, FixedCamera,
+        Replicated, Interpolated, Peer,
+        DynPersistentSnd, StaticPersistentSnd
+    ]);
+
+    if world.has_resource::<PhysicWorld>() {
+        let (buckets, entries, bytes) = world.read_resource::<PhysicWorld>().memory();
+        write!(report, "spatial hash: {} buckets {} entries ({} bytes)\n", buckets, entries, bytes).unwrap();
+    }
+
+    let particles = world.read::<AmbientEmitter>().iter().map(|emitter| emitter.particles()).sum::<usize>();
+    write!(report, "pools:\n  ambient particles: {}\n", particles).unwrap();
+    for &(name, size) in pools {
+        write!(report, "  {}: {}\n", name, size).unwrap();
+    }
+
+    let suspects = leak::audit(world);
+    write!(report, "leak suspects: {}\n", suspects.len()).unwrap();
+    for suspect in suspects {
+        write!(report, "  {:?} {} -> {:?}{}\n", suspect.holder, suspect.component, suspect.reference,
+               if suspect.one_sided { " one-sided" } else { " dangling" }).unwrap();
+    }
+    report
+}
+
+#[test]
+fn test_report() {
+    use worlds;
+
+    let mut world = worlds::new_world();
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new([0.5,0.5]))
+        .with::<PhysicStatic>(PhysicStatic)
+        .build();
+    let report = report(&world, &[("effects", 3)]);
+    assert!(report.contains("entities: 1\n"));
+    assert!(report.contains("  static: 1\n"));
+    assert!(report.contains(&*format!("  PhysicState: 1 ({} bytes)\n", mem::size_of::<PhysicState>())));
+    assert!(!report.contains("PhysicForce"));
+    assert!(report.contains("  effects: 3\n"));
+}
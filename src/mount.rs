@@ -0,0 +1,183 @@
+//! mounts are vehicles ridden by a driver.
+//!
+//! the rider is seated on the mount: it leaves the physic world and follows the mount,
+//! and the force of its input drives the mount instead. the mount turns toward the
+//! direction of the input at mount.turn_rate, accelerates along its heading and drifts
+//! sideways until its grip absorbs the lateral velocity. the rider is put back at a
+//! free position around the mount when it dismounts.
+
+use app;
+use specs;
+use config;
+use physic;
+use components::*;
+use resource::PhysicWorld;
+use specs::Join;
+use utils::minus_pi_pi;
+
+pub struct Mount {
+    rider: Option<specs::Entity>,
+    /// the direction the mount faces, it accelerates along it
+    heading: f32,
+}
+impl specs::Component for Mount {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Mount {
+    pub fn new() -> Self {
+        Mount {
+            rider: None,
+            heading: 0.,
+        }
+    }
+
+    pub fn rider(&self) -> Option<specs::Entity> {
+        self.rider
+    }
+
+    pub fn heading(&self) -> f32 {
+        self.heading
+    }
+}
+
+/// an entity seated on a mount
+pub struct Rider {
+    pub mount: specs::Entity,
+}
+impl specs::Component for Rider {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// turn heading toward direction by at most max_turn
+fn steer(heading: f32, direction: f32, max_turn: f32) -> f32 {
+    let delta = minus_pi_pi(direction - heading);
+    minus_pi_pi(heading + delta.max(-max_turn).min(max_turn))
+}
+
+/// the velocity with its part perpendicular to heading reduced by rate
+fn drift(velocity: [f32;2], heading: f32, rate: f32) -> [f32;2] {
+    let (cos, sin) = (heading.cos(), heading.sin());
+    let forward = velocity[0]*cos + velocity[1]*sin;
+    let lateral = (-velocity[0]*sin + velocity[1]*cos)*rate;
+    [forward*cos - lateral*sin, forward*sin + lateral*cos]
+}
+
+/// seat the rider on the mount, false if the mount is taken or the rider already rides
+pub fn mount(world: &mut specs::World, rider: specs::Entity, mount: specs::Entity) -> bool {
+    {
+        let mut mounts = world.write::<Mount>();
+        let mount_component = match mounts.get_mut(mount) {
+            Some(mount_component) if mount_component.rider.is_none() => mount_component,
+            _ => return false,
+        };
+        if world.read::<Rider>().get(rider).is_some() {
+            return false;
+        }
+        mount_component.rider = Some(rider);
+    }
+    world.write::<PhysicDynamic>().remove(rider);
+    world.write::<Rider>().insert(rider, Rider { mount: mount });
+    true
+}
+
+/// put the rider at a free position around its mount,
+/// false if no position is free within mount.dismount_radius
+pub fn dismount(world: &mut specs::World, rider: specs::Entity) -> bool {
+    let mount = match world.read::<Rider>().get(rider) {
+        Some(rider) => rider.mount,
+        None => return false,
+    };
+    let position = {
+        let states = world.read::<PhysicState>();
+        let types = world.read::<PhysicType>();
+        let physic_world = world.read_resource::<PhysicWorld>();
+        let near = states.get(mount).or(states.get(rider)).map(|s| s.position);
+        match (near, types.get(rider)) {
+            (Some(near), Some(typ)) => physic_world.find_free_position(&typ.shape, near, config.mount.dismount_radius, typ.mask),
+            _ => None,
+        }
+    };
+    let position = match position {
+        Some(position) => position,
+        None => return false,
+    };
+
+    if let Some(mount) = world.write::<Mount>().get_mut(mount) {
+        mount.rider = None;
+    }
+    world.write::<Rider>().remove(rider);
+    world.write::<PhysicDynamic>().insert(rider, PhysicDynamic);
+    physic::teleport(world, rider, position);
+    true
+}
+
+/// drive the mounts with the force of their rider and seat the riders on them
+pub struct MountSystem;
+impl specs::System<app::UpdateContext> for MountSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut mounts, mut riders, mut dynamics, mut states, mut forces, entities) = arg.fetch(|world| {
+            (
+                world.write::<Mount>(),
+                world.write::<Rider>(),
+                world.write::<PhysicDynamic>(),
+                world.write::<PhysicState>(),
+                world.write::<PhysicForce>(),
+                world.entities(),
+            )
+        });
+
+        let mut unseated = vec!();
+        for (mount, entity) in (&mut mounts, &entities).iter() {
+            let input = mount.rider.and_then(|rider| forces.get(rider)).map(|f| (f.direction, f.intensity));
+            if input.is_none() {
+                mount.rider = None;
+            }
+            let (direction, intensity) = input.unwrap_or((mount.heading, 0.));
+
+            if intensity > 0. {
+                mount.heading = steer(mount.heading, direction, config.mount.turn_rate*context.dt);
+            }
+            if let Some(force) = forces.get_mut(entity) {
+                force.direction = mount.heading;
+                force.intensity = intensity;
+            }
+
+            let position = match states.get_mut(entity) {
+                Some(state) => {
+                    state.velocity = drift(state.velocity, mount.heading, (-config.mount.grip*context.dt).exp());
+                    state.position
+                },
+                None => continue,
+            };
+            if let Some(rider) = mount.rider {
+                if let Some(state) = states.get_mut(rider) {
+                    state.position = position;
+                    state.velocity = [0.,0.];
+                }
+            }
+        }
+
+        // riders of deleted mounts are put back in the physic world where they are
+        for (rider, entity) in (&riders, &entities).iter() {
+            if mounts.get(rider.mount).map(|m| m.rider != Some(entity)).unwrap_or(true) {
+                unseated.push(entity);
+            }
+        }
+        for entity in unseated {
+            riders.remove(entity);
+            dynamics.insert(entity, PhysicDynamic);
+        }
+    }
+}
+
+#[test]
+fn test_steer_drift() {
+    use std::f32::consts::PI;
+
+    assert!((steer(0., PI/2., 0.1) - 0.1).abs() < 1e-6);
+    assert!((steer(0., 0.05, 0.1) - 0.05).abs() < 1e-6);
+    assert!((steer(PI - 0.05, -PI + 0.05, 0.5) - (-PI + 0.05)).abs() < 1e-5);
+
+    let velocity = drift([1.,1.], 0., 0.5);
+    assert!((velocity[0] - 1.).abs() < 1e-6 && (velocity[1] - 0.5).abs() < 1e-6);
+}
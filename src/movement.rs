@@ -0,0 +1,124 @@
+//! movement modes switched by the mode zones of levels (ladders, stairs, ledges).
+//!
+//! an entity with Movement takes the mode of the zone it stands in, or walks outside
+//! of them. the definition of the mode in movement.modes alters the integration of
+//! its physic: the force is scaled per axis, the damping scaled and a drift
+//! accelerates it like a gravity pulling off ledges.
+
+use app;
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use components::*;
+use resource::PhysicWorld;
+use specs::Join;
+
+pub type Array2F32 = [f32;2];
+
+/// entities are in a zone when this circle around their center overlaps it
+const ZONE_RADIUS: f32 = 0.1;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum MovementMode {
+    Walk,
+    Climb,
+    Stairs,
+    Ledge,
+}
+impl_from_into_toml_for_enum!(MovementMode {
+    Walk,
+    Climb,
+    Stairs,
+    Ledge,
+});
+impl MovementMode {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MovementMode::Walk => "walk",
+            MovementMode::Climb => "climb",
+            MovementMode::Stairs => "stairs",
+            MovementMode::Ledge => "ledge",
+        }
+    }
+}
+
+/// * force: factor of the force on each axis, [0, 1] to climb vertically only
+/// * damping: factor of the damping
+/// * drift: acceleration applied whatever the force
+pub struct ModeDefinition {
+    pub mode: MovementMode,
+    pub force: Array2F32,
+    pub damping: f32,
+    pub drift: Array2F32,
+}
+impl_from_into_toml_for_struct!( ModeDefinition {
+    mode: MovementMode,
+    force: Array2F32,
+    damping: f32,
+    drift: Array2F32,
+});
+
+/// the definition of the mode in movement.modes
+pub fn definition(mode: MovementMode) -> &'static ModeDefinition {
+    config.movement.modes.iter().find(|d| d.mode == mode).expect("movement mode must be defined in movement.modes")
+}
+
+/// a tile of a mode zone
+pub struct ModeZone {
+    pub mode: MovementMode,
+}
+impl specs::Component for ModeZone {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// the movement mode of an entity
+pub struct Movement {
+    mode: MovementMode,
+}
+impl specs::Component for Movement {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Movement {
+    pub fn new() -> Self {
+        Movement {
+            mode: MovementMode::Walk,
+        }
+    }
+
+    pub fn mode(&self) -> MovementMode {
+        self.mode
+    }
+}
+
+/// set the mode of the entities from the zone they stand in and send MovementModeChanged
+pub struct MovementSystem;
+impl specs::System<app::UpdateContext> for MovementSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut movements, states, zones, physic_world, entities) = arg.fetch(|world| {
+            (
+                world.write::<Movement>(),
+                world.read::<PhysicState>(),
+                world.read::<ModeZone>(),
+                world.read_resource::<PhysicWorld>(),
+                world.entities(),
+            )
+        });
+
+        for (movement, state, entity) in (&mut movements, &states, &entities).iter() {
+            // the tile of lowest id gives the mode when zones overlap
+            let mut tile: Option<specs::Entity> = None;
+            physic_world.apply_on_shape(&state.position, config.entities.zone_group.val, &Shape::Circle(ZONE_RADIUS), &mut |other_entity,_| {
+                if zones.get(*other_entity).is_some() && tile.map(|t| other_entity.get_id() < t.get_id()).unwrap_or(true) {
+                    tile = Some(*other_entity);
+                }
+            });
+
+            let mode = tile.map(|t| zones.get(t).unwrap().mode).unwrap_or(MovementMode::Walk);
+            if mode != movement.mode {
+                movement.mode = mode;
+                context.event_tx.send(app::GameEvent::MovementModeChanged(entity, mode)).unwrap();
+            }
+        }
+    }
+}
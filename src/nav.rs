@@ -0,0 +1,405 @@
+//! navigation data of a level: the clearance of each cell, its distance to the nearest
+//! wall, the connected regions of the free cells, and the cost of the cells of hazards
+//! monsters steer around.
+//!
+//! the data of rooms is baked with `--bake-nav` into `nav/<room>.toml` next to the map
+//! and loaded with the level if it was baked from the same walls, it is baked at load
+//! otherwise.
+
+use specs;
+use config;
+use levels::{self, Castle, Level};
+use configuration::{self, FromToml, IntoToml};
+use toml;
+use worlds;
+use jobs::Jobs;
+use std::sync::Arc;
+use components::*;
+use specs::Join;
+use fnv::FnvHasher;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::f32::consts::PI;
+
+pub type Array2I32 = [i32;2];
+pub type VecU8 = Vec<u8>;
+pub type VecU32 = Vec<u32>;
+
+/// * hash: hash of the walls the data is baked from
+/// * min: the lowest cell of the grid
+/// * clearance: distance in cells to the nearest wall, 0 on walls, by row
+/// * regions: region of each cell, free cells of the same region are connected,
+///   0 on walls
+/// * costs: cost of walking over each cell, hazards.nav_cost on hazards, 0 elsewhere
+pub struct NavData {
+    pub hash: String,
+    pub min: Array2I32,
+    pub width: usize,
+    pub height: usize,
+    pub clearance: VecU8,
+    pub regions: VecU32,
+    pub costs: VecU8,
+}
+impl_from_into_toml_for_struct!( NavData {
+    hash: String,
+    min: Array2I32,
+    width: usize,
+    height: usize,
+    clearance: VecU8,
+    regions: VecU32,
+    costs: VecU8,
+});
+
+/// cells of the static entities blocking monsters
+fn walls(world: &specs::World) -> Vec<[i32;2]> {
+    let statics = world.read::<PhysicStatic>();
+    let states = world.read::<PhysicState>();
+    let types = world.read::<PhysicType>();
+
+    let mut walls: Vec<[i32;2]> = (&statics, &states, &types).iter()
+        .filter(|&(_, _, typ)| typ.group & config.entities.monster_mask.val != 0)
+        .map(|(_, state, _)| NavData::cell(state.position))
+        .collect();
+    walls.sort();
+    walls.dedup();
+    walls
+}
+
+/// cells of the static hazards
+fn hazards(world: &specs::World) -> Vec<[i32;2]> {
+    let statics = world.read::<PhysicStatic>();
+    let states = world.read::<PhysicState>();
+    let hazards = world.read::<Hazard>();
+
+    let mut cells: Vec<[i32;2]> = (&statics, &states, &hazards).iter()
+        .map(|(_, state, _)| NavData::cell(state.position))
+        .collect();
+    cells.sort();
+    cells.dedup();
+    cells
+}
+
+/// hash of sorted walls and hazards
+fn hash(walls: &[[i32;2]], hazards: &[[i32;2]]) -> String {
+    let mut hasher = FnvHasher::default();
+    for wall in walls {
+        hasher.write_i32(wall[0]);
+        hasher.write_i32(wall[1]);
+    }
+    // hazards are hashed after a separator so a hazard isn't taken for a wall
+    if !hazards.is_empty() {
+        hasher.write_u8(0xff);
+    }
+    for hazard in hazards {
+        hasher.write_i32(hazard[0]);
+        hasher.write_i32(hazard[1]);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+impl NavData {
+    pub fn bake(world: &specs::World) -> Self {
+        NavData::from_cells(&walls(world), &hazards(world))
+    }
+
+    /// walls and hazards must be sorted
+    fn from_cells(walls: &[[i32;2]], hazards: &[[i32;2]]) -> Self {
+        let (min, max) = walls.iter().fold(([i32::max_value();2], [i32::min_value();2]), |(min, max), wall| {
+            ([min[0].min(wall[0]), min[1].min(wall[1])], [max[0].max(wall[0]), max[1].max(wall[1])])
+        });
+        let (width, height) = if walls.is_empty() {
+            (0, 0)
+        } else {
+            ((max[0] - min[0] + 1) as usize, (max[1] - min[1] + 1) as usize)
+        };
+
+        let mut nav = NavData {
+            hash: hash(walls, hazards),
+            min: if walls.is_empty() { [0,0] } else { min },
+            width: width,
+            height: height,
+            clearance: vec![u8::max_value(); width*height],
+            regions: vec![0; width*height],
+            costs: vec![0; width*height],
+        };
+
+        for hazard in hazards {
+            if let Some(i) = nav.index(*hazard) {
+                nav.costs[i] = config.hazards.nav_cost;
+            }
+        }
+
+        // clearance by breadth first search from the walls
+        let mut queue = VecDeque::new();
+        for wall in walls {
+            let i = nav.index(*wall).unwrap();
+            nav.clearance[i] = 0;
+            queue.push_back(*wall);
+        }
+        while let Some(cell) = queue.pop_front() {
+            let clearance = nav.clearance[nav.index(cell).unwrap()];
+            for dx in -1..2 {
+                for dy in -1..2 {
+                    let neighbour = [cell[0] + dx, cell[1] + dy];
+                    if let Some(i) = nav.index(neighbour) {
+                        if nav.clearance[i] > clearance.saturating_add(1) {
+                            nav.clearance[i] = clearance.saturating_add(1);
+                            queue.push_back(neighbour);
+                        }
+                    }
+                }
+            }
+        }
+
+        // regions by flood fill of the free cells
+        let mut region = 0;
+        for start in 0..width*height {
+            if nav.clearance[start] == 0 || nav.regions[start] != 0 {
+                continue;
+            }
+            region += 1;
+            nav.regions[start] = region;
+            queue.push_back([nav.min[0] + (start % width) as i32, nav.min[1] + (start / width) as i32]);
+            while let Some(cell) = queue.pop_front() {
+                for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                    let neighbour = [cell[0] + dx, cell[1] + dy];
+                    if let Some(i) = nav.index(neighbour) {
+                        if nav.clearance[i] != 0 && nav.regions[i] == 0 {
+                            nav.regions[i] = region;
+                            queue.push_back(neighbour);
+                        }
+                    }
+                }
+            }
+        }
+
+        nav
+    }
+
+    fn index(&self, cell: [i32;2]) -> Option<usize> {
+        let x = cell[0] - self.min[0];
+        let y = cell[1] - self.min[1];
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            Some(x as usize + y as usize*self.width)
+        }
+    }
+
+    /// tiles are centred on integer positions
+    fn cell(position: [f32;2]) -> [i32;2] {
+        [position[0].round() as i32, position[1].round() as i32]
+    }
+
+    /// region of the position, 0 on walls and outside the walls
+    pub fn region(&self, position: [f32;2]) -> u32 {
+        self.index(NavData::cell(position)).map(|i| self.regions[i]).unwrap_or(0)
+    }
+
+    /// cost of walking over the position, the maximum on walls and outside the walls
+    pub fn cost(&self, position: [f32;2]) -> u8 {
+        match self.index(NavData::cell(position)) {
+            Some(i) if self.clearance[i] != 0 => self.costs[i],
+            _ => u8::max_value(),
+        }
+    }
+
+    /// the direction nearest to direction whose next cell costs the least,
+    /// to walk around the costly cells
+    pub fn steer(&self, position: [f32;2], direction: f32) -> f32 {
+        let step = |angle: f32| self.cost([position[0] + angle.cos(), position[1] + angle.sin()]);
+        let mut best = (direction, step(direction));
+        for i in 1..5 {
+            for &side in &[1., -1.] {
+                if best.1 == 0 {
+                    return best.0;
+                }
+                let angle = direction + side*i as f32*PI/4.;
+                let cost = step(angle);
+                if cost < best.1 {
+                    best = (angle, cost);
+                }
+            }
+        }
+        best.0
+    }
+
+    /// whether a path without walls joins the positions
+    pub fn connected(&self, a: [f32;2], b: [f32;2]) -> bool {
+        let region = self.region(a);
+        region != 0 && region == self.region(b)
+    }
+
+    fn save(self, path: &Path) -> Result<(),String> {
+        if let Some(dir) = path.parent() {
+            try!(fs::create_dir_all(dir).map_err(|e| format!("create {:?} failed: {}", dir, e)));
+        }
+        let mut file = try!(File::create(path).map_err(|e| format!("create {:?} failed: {}", path, e)));
+        file.write_fmt(format_args!("{}", IntoToml::into_toml(self))).map_err(|e| format!("write {:?} failed: {}", path, e))
+    }
+}
+
+/// the navigation data baked at path if it matches the walls of the world,
+/// baked from the world otherwise
+pub fn load(path: &Path, world: &specs::World) -> Result<NavData,String> {
+    let walls = walls(world);
+    let hazards = hazards(world);
+    if !path.is_file() {
+        return Ok(NavData::from_cells(&walls, &hazards));
+    }
+
+    let mut string = String::new();
+    try!(try!(File::open(path).map_err(|e| format!("open {:?} failed: {}", path, e)))
+         .read_to_string(&mut string)
+         .map_err(|_| format!("{:?} invalid: not valid UTF-8", path)));
+
+    let mut parser = toml::Parser::new(&*string);
+    let table = try!(parser.parse().ok_or(format!("{:?} invalid: toml parsing failed", path)));
+    let nav = try!(NavData::from_toml(&toml::Value::Table(table)).map_err(|e| format!("{:?} invalid:{}", path, e)));
+
+    if nav.hash != hash(&walls, &hazards) || nav.clearance.len() != nav.width*nav.height
+       || nav.regions.len() != nav.width*nav.height || nav.costs.len() != nav.width*nav.height {
+        return Ok(NavData::from_cells(&walls, &hazards));
+    }
+    Ok(nav)
+}
+
+/// bake the navigation data of every room, a job per room
+pub fn bake_all(castles: &Vec<Castle>) -> Result<(),String> {
+    let jobs = Jobs::<()>::new(config.general.job_threads);
+    let shared = Arc::new(castles.clone());
+    let mut bakes = vec!();
+    for (c, castle) in castles.iter().enumerate() {
+        for (d, dungeon) in castle.dungeons.iter().enumerate() {
+            for r in 0..dungeon.rooms.len() {
+                let level = Level::Room { castle: c, dungeon: d, room: r };
+                let path = levels::nav_path(&level, castles).unwrap();
+                let castles = shared.clone();
+                let job_path = path.clone();
+                bakes.push((path, jobs.spawn(move || {
+                    let mut world = worlds::new_world();
+                    try!(levels::build_level(&level, &castles, &mut world, &mut |_| ())
+                         .map_err(|e| format!("load level {:?} failed: {}", job_path, e)));
+                    NavData::bake(&world).save(&job_path)
+                })));
+            }
+        }
+    }
+    for (path, bake) in bakes {
+        try!(bake.wait().unwrap_or(Err(format!("bake {:?} panicked", path))));
+        println!("{:?} baked", path);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_nav_bake() {
+    // a room split in two by a wall
+    let mut walls = vec!();
+    for i in 0..7 {
+        walls.push([i, 0]);
+        walls.push([i, 4]);
+        walls.push([0, i.min(4)]);
+        walls.push([6, i.min(4)]);
+        walls.push([3, i.min(4)]);
+    }
+    walls.sort();
+    walls.dedup();
+
+    let nav = NavData::from_cells(&walls, &[]);
+    assert_eq!(nav.clearance[nav.index(NavData::cell([1., 2.])).unwrap()], 1);
+    assert!(nav.connected([1., 1.], [2., 3.]));
+    assert!(!nav.connected([1., 1.], [4., 1.]));
+    assert_eq!(nav.region([3., 2.]), 0);
+
+    let value = IntoToml::into_toml(NavData::from_cells(&walls, &[]));
+    let loaded = NavData::from_toml(&value).unwrap();
+    assert_eq!(loaded.hash, nav.hash);
+    assert_eq!(loaded.regions, nav.regions);
+}
+
+#[test]
+fn test_nav_steer() {
+    // a room with a hazard in front of the position
+    let mut walls = vec!();
+    for i in 0..7 {
+        walls.push([i, 0]);
+        walls.push([i, 6]);
+        walls.push([0, i]);
+        walls.push([6, i]);
+    }
+    walls.sort();
+    walls.dedup();
+
+    let nav = NavData::from_cells(&walls, &[[3, 3]]);
+    assert_eq!(nav.cost([3., 3.]), config.hazards.nav_cost);
+    assert_eq!(nav.cost([2., 3.]), 0);
+    assert_eq!(nav.cost([0., 3.]), u8::max_value());
+
+    assert_eq!(nav.steer([2., 2.], PI/2.), PI/2.);
+    let direction = nav.steer([2., 3.], 0.);
+    assert!(direction != 0. && nav.cost([2. + direction.cos(), 3. + direction.sin()]) == 0);
+}
+
+#[test]
+fn test_nav_cells() {
+    assert_eq!(NavData::cell([0., 0.]), [0, 0]);
+    assert_eq!(NavData::cell([0.49, -0.49]), [0, 0]);
+    assert_eq!(NavData::cell([0.51, -0.51]), [1, -1]);
+    assert_eq!(NavData::cell([2.99, 3.01]), [3, 3]);
+
+    let mut world = specs::World::new();
+    world.register::<PhysicState>();
+    world.register::<PhysicType>();
+    world.register::<PhysicStatic>();
+    for &position in &[[0., 0.], [1., 0.], [-1., 2.]] {
+        world.create_now()
+            .with::<PhysicState>(PhysicState::new(position))
+            .with::<PhysicStatic>(PhysicStatic)
+            .with::<PhysicType>(PhysicType::new_static(config.entities.wall_group.val, config.entities.wall_mask.val, Shape::Square(0.5)))
+            .build();
+    }
+    assert_eq!(walls(&world), vec!([-1, 2], [0, 0], [1, 0]));
+
+    // a body near the edge of a free cell is in that cell, not in the wall next to it
+    let nav = NavData::from_cells(&[[0, 0], [0, 1], [0, 2], [1, 0], [1, 2], [2, 0], [2, 1], [2, 2]], &[]);
+    assert_eq!(nav.region([1., 1.]), 1);
+    assert_eq!(nav.region([1.45, 0.55]), 1);
+    assert_eq!(nav.region([1.55, 1.]), 0);
+}
+
+#[test]
+fn test_nav_hazards() {
+    let mut world = specs::World::new();
+    world.register::<PhysicState>();
+    world.register::<PhysicType>();
+    world.register::<PhysicStatic>();
+    world.register::<Hazard>();
+    for i in 0..7 {
+        for &position in &[[i as f32, 0.], [i as f32, 6.], [0., i as f32], [6., i as f32]] {
+            world.create_now()
+                .with::<PhysicState>(PhysicState::new(position))
+                .with::<PhysicStatic>(PhysicStatic)
+                .with::<PhysicType>(PhysicType::new_static(config.entities.wall_group.val, config.entities.wall_mask.val, Shape::Square(0.5)))
+                .build();
+        }
+    }
+    // hazards are centred on integer positions like the walls
+    for &position in &[[3., 3.], [3., 4.]] {
+        world.create_now()
+            .with::<PhysicState>(PhysicState::new(position))
+            .with::<PhysicStatic>(PhysicStatic)
+            .with::<Hazard>(Hazard::new(0))
+            .build();
+    }
+    assert_eq!(hazards(&world), vec!([3, 3], [3, 4]));
+
+    let nav = NavData::bake(&world);
+    assert_eq!(nav.cost([3., 3.]), config.hazards.nav_cost);
+    assert_eq!(nav.cost([3.45, 4.45]), config.hazards.nav_cost);
+    assert_eq!(nav.cost([3., 2.]), 0);
+    assert_eq!(nav.cost([3.55, 3.]), 0);
+}
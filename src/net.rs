@@ -0,0 +1,337 @@
+//! input lockstep between two peers
+//!
+//! each peer send its input for the frame `frame + delay`, a frame is simulated
+//! only once both inputs are known. the update must be deterministic for peers to
+//! stay in sync, desync is detected by comparing world hashes every hash period.
+
+use specs;
+use config;
+use fnv::FnvHasher;
+use components::*;
+use specs::Join;
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use std::{fmt, io};
+
+const HELLO: u8 = 0;
+const INPUTS: u8 = 1;
+const INPUT_SIZE: usize = 9;
+
+/// the lockstep peer controlling a character: 0 for the host and 1 for the peer that
+/// joined, the inputs of a peer go to its character whatever the order of the entities
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Peer {
+    pub index: usize,
+}
+impl specs::Component for Peer {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// input of a peer for one frame
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Input {
+    pub direction: f32,
+    pub intensity: f32,
+    pub action: bool,
+}
+impl Input {
+    pub fn none() -> Self {
+        Input {
+            direction: 0.,
+            intensity: 0.,
+            action: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    Timeout,
+    InvalidPacket,
+    Desync(u32),
+}
+impl fmt::Display for NetError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::NetError::*;
+        match *self {
+            Io(ref e) => write!(fmt,"io error: {}",e),
+            Timeout => write!(fmt,"peer timed out"),
+            InvalidPacket => write!(fmt,"invalid packet received"),
+            Desync(frame) => write!(fmt,"desync detected at frame {}",frame),
+        }
+    }
+}
+impl From<io::Error> for NetError {
+    fn from(e: io::Error) -> Self {
+        NetError::Io(e)
+    }
+}
+
+pub fn write_u32(buf: &mut Vec<u8>, v: u32) {
+    for i in 0..4 {
+        buf.push((v >> (24 - 8*i)) as u8);
+    }
+}
+
+pub fn write_u64(buf: &mut Vec<u8>, v: u64) {
+    for i in 0..8 {
+        buf.push((v >> (56 - 8*i)) as u8);
+    }
+}
+
+pub fn read_u32(buf: &[u8]) -> u32 {
+    buf[..4].iter().fold(0, |v, &b| (v << 8) | b as u32)
+}
+
+pub fn read_u64(buf: &[u8]) -> u64 {
+    buf[..8].iter().fold(0, |v, &b| (v << 8) | b as u64)
+}
+
+/// inputs from frame to frame + inputs.len() and the last hash computed
+struct Packet {
+    frame: u32,
+    inputs: Vec<Input>,
+    hash: Option<(u32,u64)>,
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = vec!(INPUTS);
+        write_u32(&mut buf, self.frame);
+        buf.push(self.inputs.len() as u8);
+        for input in &self.inputs {
+            write_u32(&mut buf, input.direction.to_bits());
+            write_u32(&mut buf, input.intensity.to_bits());
+            buf.push(input.action as u8);
+        }
+        if let Some((frame,hash)) = self.hash {
+            write_u32(&mut buf, frame);
+            write_u64(&mut buf, hash);
+        }
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Result<Packet,NetError> {
+        if buf.len() < 6 || buf[0] != INPUTS {
+            return Err(NetError::InvalidPacket);
+        }
+        let frame = read_u32(&buf[1..]);
+        let count = buf[5] as usize;
+        let end = 6 + count*INPUT_SIZE;
+
+        let hash = if buf.len() == end {
+            None
+        } else if buf.len() == end + 12 {
+            Some((read_u32(&buf[end..]),read_u64(&buf[end+4..])))
+        } else {
+            return Err(NetError::InvalidPacket);
+        };
+
+        let inputs = buf[6..end].chunks(INPUT_SIZE).map(|chunk| Input {
+            direction: f32::from_bits(read_u32(chunk)),
+            intensity: f32::from_bits(read_u32(&chunk[4..])),
+            action: chunk[8] != 0,
+        }).collect();
+
+        Ok(Packet {
+            frame: frame,
+            inputs: inputs,
+            hash: hash,
+        })
+    }
+}
+
+pub struct Lockstep {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    local_index: usize,
+    frame: u32,
+    local: BTreeMap<u32,Input>,
+    remote: BTreeMap<u32,Input>,
+    local_hashes: BTreeMap<u32,u64>,
+    remote_hashes: BTreeMap<u32,u64>,
+    last_received: Instant,
+}
+
+impl Lockstep {
+    /// wait for a peer to join on this port
+    pub fn host(port: u16) -> Result<Lockstep,NetError> {
+        let socket = try!(UdpSocket::bind(("0.0.0.0",port)));
+        try!(socket.set_read_timeout(Some(Duration::from_millis((config.net.timeout*1000.) as u64))));
+
+        let mut buf = [0u8;1];
+        let peer = loop {
+            let (len, peer) = try!(socket.recv_from(&mut buf).map_err(|_| NetError::Timeout));
+            if len == 1 && buf[0] == HELLO {
+                break peer;
+            }
+        };
+        try!(socket.send_to(&[HELLO], peer));
+
+        Lockstep::new(socket, peer, 0)
+    }
+
+    /// join the peer hosting at address
+    pub fn join(address: &str) -> Result<Lockstep,NetError> {
+        let socket = try!(UdpSocket::bind("0.0.0.0:0"));
+        try!(socket.connect(address));
+        let peer = try!(socket.peer_addr());
+        try!(socket.set_read_timeout(Some(Duration::from_millis(500))));
+
+        let start = Instant::now();
+        let mut buf = [0u8;1];
+        loop {
+            try!(socket.send(&[HELLO]));
+            match socket.recv(&mut buf) {
+                Ok(1) if buf[0] == HELLO => break,
+                _ => (),
+            }
+            if start.elapsed() > Duration::from_millis((config.net.timeout*1000.) as u64) {
+                return Err(NetError::Timeout);
+            }
+        }
+
+        Lockstep::new(socket, peer, 1)
+    }
+
+    fn new(socket: UdpSocket, peer: SocketAddr, local_index: usize) -> Result<Lockstep,NetError> {
+        try!(socket.set_nonblocking(true));
+
+        let mut local = BTreeMap::new();
+        let mut remote = BTreeMap::new();
+        for frame in 0..config.net.delay {
+            local.insert(frame, Input::none());
+            remote.insert(frame, Input::none());
+        }
+
+        Ok(Lockstep {
+            socket: socket,
+            peer: peer,
+            local_index: local_index,
+            frame: 0,
+            local: local,
+            remote: remote,
+            local_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            last_received: Instant::now(),
+        })
+    }
+
+    /// 0 for the host and 1 for the peer that joined
+    pub fn local_index(&self) -> usize {
+        self.local_index
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// schedule the local input for frame + delay
+    pub fn push_input(&mut self, input: Input) {
+        self.local.insert(self.frame + config.net.delay, input);
+    }
+
+    /// send the last inputs so that a lost packet is covered by the next ones
+    pub fn send(&mut self) -> Result<(),NetError> {
+        let first = self.local.keys().next().cloned().unwrap_or(0);
+        let from = self.local.keys().next_back().map(|&f| f.saturating_sub(config.net.redundancy as u32 - 1)).unwrap_or(0).max(first);
+        let inputs: Vec<Input> = self.local.range(from..).map(|(_,&i)| i).collect();
+        let packet = Packet {
+            frame: from,
+            inputs: inputs,
+            hash: self.local_hashes.iter().next_back().map(|(&f,&h)| (f,h)),
+        };
+        try!(self.socket.send_to(&*packet.encode(), self.peer));
+        Ok(())
+    }
+
+    pub fn receive(&mut self) -> Result<(),NetError> {
+        let mut buf = [0u8;512];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, peer)) => {
+                    if peer != self.peer || (len == 1 && buf[0] == HELLO) { continue }
+                    let packet = try!(Packet::decode(&buf[..len]));
+                    for (i, input) in packet.inputs.into_iter().enumerate() {
+                        let frame = packet.frame + i as u32;
+                        if frame >= self.frame {
+                            self.remote.insert(frame, input);
+                        }
+                    }
+                    if let Some((frame,hash)) = packet.hash {
+                        self.remote_hashes.insert(frame, hash);
+                    }
+                    self.last_received = Instant::now();
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(NetError::Io(e)),
+            }
+        }
+
+        if self.last_received.elapsed() > Duration::from_millis((config.net.timeout*1000.) as u64) {
+            return Err(NetError::Timeout);
+        }
+        self.check_hashes()
+    }
+
+    /// inputs of the current frame ordered by peer index if both are known
+    pub fn inputs(&self) -> Option<[Input;2]> {
+        match (self.local.get(&self.frame), self.remote.get(&self.frame)) {
+            (Some(&local), Some(&remote)) => if self.local_index == 0 {
+                Some([local,remote])
+            } else {
+                Some([remote,local])
+            },
+            _ => None,
+        }
+    }
+
+    /// go to next frame, hash is the hash of the world once the current frame is simulated
+    pub fn advance(&mut self, hash: u64) -> Result<(),NetError> {
+        if self.frame % config.net.hash_period == 0 {
+            self.local_hashes.insert(self.frame, hash);
+        }
+        self.local.remove(&self.frame);
+        self.remote.remove(&self.frame);
+        self.frame += 1;
+        self.check_hashes()
+    }
+
+    fn check_hashes(&mut self) -> Result<(),NetError> {
+        let frames: Vec<u32> = self.remote_hashes.keys().cloned().filter(|f| self.local_hashes.contains_key(f)).collect();
+        for frame in frames {
+            if self.local_hashes.remove(&frame) != self.remote_hashes.remove(&frame) {
+                return Err(NetError::Desync(frame));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// hash of the physic state of all entities
+pub fn world_hash(world: &specs::World) -> u64 {
+    let states = world.read::<PhysicState>();
+    let mut hasher = FnvHasher::default();
+    for state in states.iter() {
+        for v in state.position.iter().chain(state.velocity.iter()) {
+            hasher.write_u32(v.to_bits());
+        }
+    }
+    hasher.finish()
+}
+
+#[test]
+fn test_packet_encode_decode() {
+    let packet = Packet {
+        frame: 42,
+        inputs: vec!(Input { direction: 1.5, intensity: 1., action: true }, Input::none()),
+        hash: Some((40, 0xdead_beef_0123_4567)),
+    };
+    let decoded = Packet::decode(&*packet.encode()).unwrap();
+    assert_eq!(decoded.frame, 42);
+    assert_eq!(decoded.inputs, packet.inputs);
+    assert_eq!(decoded.hash, packet.hash);
+}
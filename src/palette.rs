@@ -0,0 +1,48 @@
+//! palette swaps of the colors of an entity: the team colors of the peer character and
+//! the elite variant of the monsters are the same entities drawn with a palette of
+//! palettes.definitions replacing some of their colors.
+
+use config;
+use configuration::{self, FromToml};
+use toml;
+use graphics::Color;
+
+pub type VecArray2Color = Vec<[Color;2]>;
+
+pub struct PaletteDefinition {
+    pub name: String,
+    /// the colors replaced and their replacement
+    pub swaps: VecArray2Color,
+}
+impl_from_into_toml_for_struct!( PaletteDefinition {
+    name: String,
+    swaps: VecArray2Color,
+});
+
+/// the index of the palette of name in palettes.definitions, none if empty
+pub fn palette(name: &str) -> Option<usize> {
+    if name.is_empty() {
+        None
+    } else {
+        Some(config.palettes.definitions.iter().position(|p| p.name == name)
+             .expect("palette must be checked by the configuration"))
+    }
+}
+
+/// the color in the palette
+pub fn swap(palette: usize, color: Color) -> Color {
+    config.palettes.definitions[palette].swaps.iter()
+        .find(|swap| swap[0] == color)
+        .map(|swap| swap[1])
+        .unwrap_or(color)
+}
+
+#[test]
+fn test_swap() {
+    for (i, definition) in config.palettes.definitions.iter().enumerate() {
+        for s in &definition.swaps {
+            assert_eq!(swap(i, s[0]), s[1]);
+        }
+    }
+    assert_eq!(palette(""), None);
+}
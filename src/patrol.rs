@@ -0,0 +1,271 @@
+use app;
+use specs;
+use configuration::{self, FromToml};
+use toml;
+use components::*;
+use specs::Join;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub type Array2Isize = [isize;2];
+pub type VecArray2F32 = Vec<[f32;2]>;
+pub type VecF32 = Vec<f32>;
+
+/// distance under which a waypoint is reached
+const ARRIVAL_RADIUS: f32 = 0.1;
+
+/// after the last point:
+/// * loop: go back to the first point
+/// * pingpong: go back through the points in reverse order
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum PatrolMode {
+    Loop,
+    PingPong,
+}
+impl_from_into_toml_for_enum!(PatrolMode {
+    Loop,
+    PingPong,
+});
+
+/// a path for the entity at position (map rows are negative),
+/// waits are the times to wait at each point, missing ones are zero.
+/// living entities are steered along the path through their force (guards),
+/// the others are carried at speed (moving platforms)
+pub struct PatrolDefinition {
+    pub position: Array2Isize,
+    pub points: VecArray2F32,
+    pub waits: VecF32,
+    pub mode: PatrolMode,
+    pub speed: f32,
+}
+impl_from_into_toml_for_struct!( PatrolDefinition {
+    position: Array2Isize,
+    points: VecArray2F32,
+    waits: VecF32,
+    mode: PatrolMode,
+    speed: f32,
+});
+
+pub struct Patrol {
+    points: Vec<[f32;2]>,
+    waits: Vec<f32>,
+    mode: PatrolMode,
+    speed: f32,
+    target: usize,
+    backward: bool,
+    wait: f32,
+    /// monsters chasing the player stop to patrol
+    pub active: bool,
+}
+impl specs::Component for Patrol {
+    type Storage = specs::VecStorage<Self>;
+}
+
+impl Patrol {
+    pub fn new(points: Vec<[f32;2]>, waits: Vec<f32>, mode: PatrolMode, speed: f32) -> Self {
+        Patrol {
+            points: points,
+            waits: waits,
+            mode: mode,
+            speed: speed,
+            target: 0,
+            backward: false,
+            wait: 0.,
+            active: true,
+        }
+    }
+
+    /// the point the entity goes to, none while it waits
+    pub fn target(&self) -> Option<[f32;2]> {
+        if self.wait > 0. {
+            None
+        } else {
+            Some(self.points[self.target])
+        }
+    }
+
+    /// wait at the target point and then aim at the next one
+    fn arrive(&mut self) {
+        self.wait = self.waits.get(self.target).cloned().unwrap_or(0.);
+
+        let last = self.points.len() - 1;
+        if last == 0 {
+            return;
+        }
+        match self.mode {
+            PatrolMode::Loop => self.target = (self.target + 1) % self.points.len(),
+            PatrolMode::PingPong => {
+                if self.backward && self.target == 0 || !self.backward && self.target == last {
+                    self.backward = !self.backward;
+                }
+                if self.backward {
+                    self.target -= 1;
+                } else {
+                    self.target += 1;
+                }
+            },
+        }
+    }
+
+    /// update the wait and the target, return the target and its distance if the entity must move
+    fn update(&mut self, position: [f32;2], dt: f32) -> Option<([f32;2],f32)> {
+        if self.wait > 0. {
+            self.wait -= dt;
+            return None;
+        }
+
+        let target = self.points[self.target];
+        let dx = target[0] - position[0];
+        let dy = target[1] - position[1];
+        let distance = (dx.powi(2) + dy.powi(2)).sqrt();
+        if distance <= ARRIVAL_RADIUS {
+            self.arrive();
+            return None;
+        }
+        Some((target, distance))
+    }
+}
+
+/// attach the patrols defined in the file if it exists:
+/// a toml file with an array of tables `paths`
+pub fn load(path: &Path, world: &mut specs::World) -> Result<(),String> {
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    let mut string = String::new();
+    try!(try!(fs::File::open(&path).map_err(|e| format!("open paths failed: {}",e)))
+         .read_to_string(&mut string)
+         .map_err(|_| String::from("paths invalid: not valid UTF-8")));
+
+    let mut parser = toml::Parser::new(&*string);
+    let table = try!(parser.parse().ok_or(format!("paths {:?} invalid: toml parsing failed",path)));
+    let paths = try!(table.get("paths").ok_or(format!("paths {:?} invalid: expect paths",path)));
+    let paths: Vec<PatrolDefinition> = try!(FromToml::from_toml(paths).map_err(|e| format!("paths {:?} invalid: paths{}",path,e)));
+
+    for (i, patrol) in paths.into_iter().enumerate() {
+        if patrol.points.is_empty() {
+            return Err(format!("paths {:?} invalid: paths[{}].points must not be empty",path,i));
+        }
+        if patrol.speed <= 0. {
+            return Err(format!("paths {:?} invalid: paths[{}].speed must be positive",path,i));
+        }
+
+        let center = PhysicState::new(patrol.position).position;
+        let entity = {
+            let states = world.read::<PhysicState>();
+            let dynamics = world.read::<PhysicDynamic>();
+            let entities = world.entities();
+            let found = (&states, &dynamics, &entities).iter()
+                .find(|&(state,_,_)| state.position == center)
+                .map(|(_,_,entity)| entity);
+            try!(found.ok_or(format!("paths {:?} invalid: paths[{}] no dynamic entity at {:?}",path,i,patrol.position)))
+        };
+        world.write::<Patrol>().insert(entity, Patrol::new(patrol.points, patrol.waits, patrol.mode, patrol.speed));
+    }
+    Ok(())
+}
+
+pub struct PatrolSystem;
+impl specs::System<app::UpdateContext> for PatrolSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut patrols, mut forces, mut states, lives, entities) = arg.fetch(|world| {
+            (
+                world.write::<Patrol>(),
+                world.write::<PhysicForce>(),
+                world.write::<PhysicState>(),
+                world.read::<Life>(),
+                world.entities(),
+            )
+        });
+
+        for (patrol, state, entity) in (&mut patrols, &mut states, &entities).iter() {
+            if !patrol.active { continue }
+
+            let step = patrol.update(state.position, context.dt);
+            let force = forces.get_mut(entity);
+            if lives.get(entity).is_some() {
+                if let Some(force) = force {
+                    match step {
+                        Some((target, _)) => {
+                            force.direction = (target[1] - state.position[1]).atan2(target[0] - state.position[0]);
+                            force.intensity = 1.;
+                        },
+                        None => force.intensity = 0.,
+                    }
+                }
+                continue;
+            }
+
+            // carried kinematically, the physic doesn't move it on its own
+            if let Some(force) = force {
+                force.intensity = 0.;
+            }
+            state.velocity = [0., 0.];
+            if let Some((target, distance)) = step {
+                let travel = (patrol.speed*context.dt).min(distance);
+                state.position[0] += (target[0] - state.position[0])*travel/distance;
+                state.position[1] += (target[1] - state.position[1])*travel/distance;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_patrol_modes() {
+    let points = vec!([0.,0.], [1.,0.], [2.,0.]);
+
+    let mut patrol = Patrol::new(points.clone(), vec!(), PatrolMode::Loop, 1.);
+    let mut order = vec!();
+    for _ in 0..5 {
+        order.push(patrol.target);
+        patrol.arrive();
+    }
+    assert_eq!(order, vec!(0, 1, 2, 0, 1));
+
+    let mut patrol = Patrol::new(points, vec!(0., 0.5), PatrolMode::PingPong, 1.);
+    let mut order = vec!();
+    for _ in 0..7 {
+        order.push(patrol.target);
+        patrol.arrive();
+    }
+    assert_eq!(order, vec!(0, 1, 2, 1, 0, 1, 2));
+
+    // wait at a point before going to the next one
+    patrol.target = 1;
+    assert_eq!(patrol.update([1.,0.], 0.1), None);
+    assert_eq!(patrol.target(), None);
+    assert_eq!(patrol.update([1.,0.], 0.6), None);
+    assert_eq!(patrol.target(), Some([0.,0.]));
+}
+
+#[test]
+fn test_patrol_carries_platform() {
+    use config;
+    use resource::PhysicWorld;
+    use systems::PhysicSystem;
+    use trace;
+
+    let mut world = trace::new_world();
+    world.register::<Patrol>();
+    world.register::<Life>();
+    let platform = trace::add_body(&mut world, [0.5, 0.5]);
+    world.write::<Patrol>().insert(platform, Patrol::new(vec!([0.5, 0.5], [4.5, 0.5]), vec!(), PatrolMode::Loop, 2.));
+    world.write_resource::<PhysicWorld>().fill(&world);
+
+    let mut planner = specs::Planner::new(world, 1);
+    app::add_system(&mut planner, PatrolSystem, "patrol", "control");
+    app::add_system(&mut planner, PhysicSystem, "physic", "physic");
+    // the first frame arrives at the first point
+    for _ in 0..31 {
+        planner.dispatch(trace::new_context());
+        planner.wait();
+    }
+
+    let world = planner.mut_world();
+    let state = world.read::<PhysicState>().get(platform).unwrap().clone();
+    let expected = 0.5 + 30.*2./config.event_loop.ups as f32;
+    assert!((state.position[0] - expected).abs() < 1e-4 && (state.position[1] - 0.5).abs() < 1e-6);
+    assert_eq!(state.velocity, [0., 0.]);
+}
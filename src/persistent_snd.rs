@@ -3,6 +3,8 @@ use config;
 use baal;
 use app;
 use components::*;
+use resource::Weather;
+use ambient::EmitterKind;
 use specs::Join;
 use utils::Into3D;
 
@@ -47,10 +49,12 @@ impl Default for PersistentSndSystem {
 
 impl specs::System<app::UpdateContext> for PersistentSndSystem {
     fn run(&mut self, arg: specs::RunArg, _context: app::UpdateContext) {
-        let (dyn_persistent_snds, states) = arg.fetch(|world| {
+        let (dyn_persistent_snds, emitters, states, weather) = arg.fetch(|world| {
             (
                 world.read::<DynPersistentSnd>(),
+                world.read::<AmbientEmitter>(),
                 world.read::<PhysicState>(),
+                world.read_resource::<Weather>(),
             )
         });
 
@@ -66,6 +70,21 @@ impl specs::System<app::UpdateContext> for PersistentSndSystem {
                 dyn_persistent_snd_ids.push(dyn_persistent_snd.id);
             }
 
+            // the sound emitters loop where they are unless culled
+            for (emitter, state) in (&emitters, &states).iter() {
+                let prefab = emitter.prefab();
+                if emitter.is_active() && prefab.kind == EmitterKind::Sound {
+                    vec[prefab.sound].1.push(state.position.into_3d());
+                    dyn_persistent_snd_ids.push(prefab.sound);
+                }
+            }
+
+            // the ambient loops of the weather follow the listener
+            for &id in &config.weather.states[weather.current()].ambient_snds {
+                vec[id].1.push(baal::effect::listener());
+                dyn_persistent_snd_ids.push(id);
+            }
+
             vec.retain(|&(_,ref v)| !v.is_empty());
 
             baal::effect::persistent::add_positions_for_all(vec);
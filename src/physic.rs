@@ -7,6 +7,12 @@ use std::collections::HashSet;
 use std::hash::BuildHasherDefault;
 use fnv::FnvHasher;
 use std::f32;
+use utils::{CellRect, cell_center};
+use lod::UpdateLod;
+use math;
+use group::IntoMask;
+use interpolation::Interpolated;
+use movement::{self, Movement, ModeDefinition};
 
 pub trait IntoGrid {
     fn into_grid(&self) -> [f32;2];
@@ -56,9 +62,12 @@ pub enum CollisionBehavior {
     #[allow(dead_code)] Stop,
 }
 
+/// active while overlapping some entity, the physic system sends OverlapEnter
+/// and OverlapExit events when an entity starts and stops overlapping it
 #[derive(Debug,Clone)]
 pub struct PhysicTrigger {
     pub active: bool,
+    pub overlapping: HashSet<specs::Entity>,
 }
 impl specs::Component for PhysicTrigger {
     type Storage = specs::VecStorage<Self>;
@@ -67,6 +76,7 @@ impl PhysicTrigger {
     pub fn new() -> Self {
         PhysicTrigger {
             active: false,
+            overlapping: HashSet::new(),
         }
     }
 }
@@ -90,6 +100,100 @@ impl specs::Component for PhysicState {
     type Storage = specs::VecStorage<Self>;
 }
 
+/// static entities have an infinite weight
+fn inverse_weight(weight: f32) -> f32 {
+    if weight == f32::MAX { 0. } else { 1./weight }
+}
+
+impl PhysicState {
+    /// give an impulse of strength in direction: the velocity change is inversely
+    /// proportional to the weight so heavy entities are barely pushed
+    pub fn apply_knockback(&mut self, typ: &PhysicType, direction: f32, strength: f32) {
+        let inverse = inverse_weight(typ.weight);
+        self.velocity[0] += strength*inverse*math::cos(direction);
+        self.velocity[1] += strength*inverse*math::sin(direction);
+    }
+
+    /// move during dt according to the force and the damping of the type,
+    /// altered by the definition of the movement mode if any
+    fn integrate(&mut self, typ: &PhysicType, force: &PhysicForce, mode: Option<&ModeDefinition>, dt: f32) {
+        let (force_factor, damping, drift) = match mode {
+            Some(mode) => (mode.force, typ.damping*mode.damping, mode.drift),
+            None => ([1.,1.], typ.damping, [0.,0.]),
+        };
+
+        self.acceleration[0] = (force_factor[0]*typ.force*force.intensity*math::cos(force.direction)
+                                - damping*self.velocity[0])/typ.weight + drift[0];
+
+        self.acceleration[1] = (force_factor[1]*typ.force*force.intensity*math::sin(force.direction)
+                                - damping*self.velocity[1])/typ.weight + drift[1];
+
+        self.velocity[0] += dt*self.acceleration[0];
+        self.velocity[1] += dt*self.acceleration[1];
+
+        self.position[0] += dt*self.velocity[0];
+        self.position[1] += dt*self.velocity[1];
+    }
+
+    /// move out of collision and change the velocity according to the collision behavior
+    fn resolve(&mut self, typ: &PhysicType, res: &Resolution) {
+        use std::f32::consts::PI;
+
+        self.position[0] += res.dx;
+        self.position[1] += res.dy;
+
+        match typ.collision_behavior {
+            CollisionBehavior::Bounce => {
+                let angle = math::atan2(self.velocity[1], self.velocity[0]) + PI;
+                self.velocity[0] = math::cos(angle);
+                self.velocity[1] = math::sin(angle);
+            },
+            CollisionBehavior::Stop => self.velocity = [0.,0.],
+            CollisionBehavior::Back => {
+                self.velocity[0] = -self.velocity[0];
+                self.velocity[1] = -self.velocity[1];
+            },
+            CollisionBehavior::Persist => (),
+        }
+    }
+}
+
+#[test]
+fn test_integrate_mode() {
+    use movement::MovementMode;
+
+    let typ = PhysicType::new_movable(0, 0, Shape::Circle(0.5), CollisionBehavior::Persist, 10., 1., 1.);
+    let force = PhysicForce::new_full();
+    let climb = ModeDefinition { mode: MovementMode::Climb, force: [0.,1.], damping: 1., drift: [0.,-1.] };
+
+    let mut state = PhysicState::new([0.,0.]);
+    state.integrate(&typ, &force, None, 0.1);
+    assert!(state.position[0] > 0. && state.position[1] == 0.);
+
+    let mut state = PhysicState::new([0.,0.]);
+    state.integrate(&typ, &force, Some(&climb), 0.1);
+    assert!(state.position[0] == 0. && state.position[1] < 0.);
+}
+
+#[test]
+fn test_knockback_weight() {
+    let light = PhysicType::new_movable(0, 0, Shape::Circle(0.5), CollisionBehavior::Persist, 10., 1., 1.);
+    let heavy = PhysicType::new_movable(0, 0, Shape::Circle(0.5), CollisionBehavior::Persist, 10., 1., 4.);
+    let wall = PhysicType::new_static(0, 0, Shape::Square(0.5));
+
+    let mut state = PhysicState::new([0.,0.]);
+    state.apply_knockback(&light, 0., 2.);
+    assert_eq!(state.velocity, [2., 0.]);
+
+    let mut state = PhysicState::new([0.,0.]);
+    state.apply_knockback(&heavy, 0., 2.);
+    assert_eq!(state.velocity, [0.5, 0.]);
+
+    let mut state = PhysicState::new([0.,0.]);
+    state.apply_knockback(&wall, 0., 2.);
+    assert_eq!(state.velocity, [0., 0.]);
+}
+
 #[derive(Debug,Clone)]
 pub struct PhysicType {
     pub shape: Shape,
@@ -102,7 +206,7 @@ pub struct PhysicType {
 }
 impl PhysicType {
     pub fn new_movable(group: u32, mask: u32, shape: Shape, collision: CollisionBehavior, velocity: f32, time_to_reach_v_max: f32, weight: f32) -> Self {
-        let damping = -weight * (1.-config.physic.rate).ln() / time_to_reach_v_max;
+        let damping = -weight * math::ln(1.-config.physic.rate) / time_to_reach_v_max;
         let force = velocity * damping;
         PhysicType {
             shape: shape,
@@ -165,6 +269,38 @@ impl specs::Component for PhysicStatic {
     type Storage = specs::NullStorage<Self>;
 }
 
+/// the entity was teleported since the last update,
+/// its collisions are not resolved on the next update
+#[derive(Debug,Clone,Default)]
+pub struct PhysicTeleported;
+impl specs::Component for PhysicTeleported {
+    type Storage = specs::NullStorage<Self>;
+}
+
+/// move the entity to position at once: it is moved in the physic world, its velocity
+/// and interpolation history are cleared and its collisions are not resolved on the
+/// next update so it isn't pushed far away if it lands in something
+pub fn teleport(world: &mut specs::World, entity: specs::Entity, position: [f32;2]) {
+    let old_position = match world.write::<PhysicState>().get_mut(entity) {
+        Some(state) => {
+            let old_position = state.position;
+            state.position = position;
+            state.velocity = [0.,0.];
+            old_position
+        },
+        None => return,
+    };
+
+    if let Some(typ) = world.read::<PhysicType>().get(entity) {
+        let is_static = world.read::<PhysicStatic>().get(entity).is_some();
+        world.write_resource::<PhysicWorld>().relocate(entity, &old_position, &position, typ.group, &typ.shape, is_static);
+    }
+    if let Some(interpolated) = world.write::<Interpolated>().get_mut(entity) {
+        interpolated.clear();
+    }
+    world.write::<PhysicTeleported>().insert(entity, PhysicTeleported);
+}
+
 #[derive(Debug,Clone)]
 pub struct Ray {
     pub origin: [f32;2],
@@ -173,6 +309,136 @@ pub struct Ray {
     pub mask: u32,
 }
 
+/// options of raycast_with:
+/// * exclude: entities never given to callback, typically the caster
+/// * max_hits: number of entities given to callback before the raycast stops
+/// * static_only: only static entities are tested, dynamic ones are not even looked up,
+///   for sight checks that only care about walls
+pub struct RaycastOptions<'a> {
+    pub exclude: &'a [specs::Entity],
+    pub max_hits: Option<usize>,
+    pub static_only: bool,
+}
+
+impl<'a> Default for RaycastOptions<'a> {
+    fn default() -> Self {
+        RaycastOptions {
+            exclude: &[],
+            max_hits: None,
+            static_only: false,
+        }
+    }
+}
+
+/// an area to query entities in, entities are given if their shape overlaps the area
+#[derive(Debug,Clone)]
+pub enum QueryRegion {
+    Circle {
+        center: [f32;2],
+        radius: f32,
+    },
+    /// vertices of a convex polygon in either order
+    Polygon(Vec<[f32;2]>),
+}
+
+impl QueryRegion {
+    /// the bounding box of the region: min and max coordinates
+    fn bounds(&self) -> ([f32;2],[f32;2]) {
+        match *self {
+            QueryRegion::Circle { center, radius } => {
+                ([center[0]-radius, center[1]-radius], [center[0]+radius, center[1]+radius])
+            },
+            QueryRegion::Polygon(ref vertices) => {
+                vertices.iter().fold(([f32::MAX,f32::MAX],[f32::MIN,f32::MIN]), |(min,max),v| {
+                    ([min[0].min(v[0]), min[1].min(v[1])], [max[0].max(v[0]), max[1].max(v[1])])
+                })
+            },
+        }
+    }
+
+    /// distance from the point to the region, 0 inside
+    fn distance(&self, p: [f32;2]) -> f32 {
+        match *self {
+            QueryRegion::Circle { center, radius } => {
+                (((p[0]-center[0]).powi(2) + (p[1]-center[1]).powi(2)).sqrt() - radius).max(0.)
+            },
+            QueryRegion::Polygon(ref vertices) => {
+                let edges = || vertices.iter().zip(vertices.iter().cycle().skip(1));
+                let crosses: Vec<f32> = edges().map(|(a,b)| (b[0]-a[0])*(p[1]-a[1]) - (b[1]-a[1])*(p[0]-a[0])).collect();
+                if crosses.iter().all(|&c| c >= 0.) || crosses.iter().all(|&c| c <= 0.) {
+                    0.
+                } else {
+                    edges().map(|(&a,&b)| segment_distance(p, a, b)).fold(f32::MAX, f32::min)
+                }
+            },
+        }
+    }
+
+    /// whether the region overlaps the square of center and half side
+    fn overlaps_square(&self, center: [f32;2], half: f32) -> bool {
+        match *self {
+            QueryRegion::Circle { center: c, radius } => box_distance(c, center, [half,half]) < radius,
+            QueryRegion::Polygon(ref vertices) => {
+                // separating axis theorem on the axes of the square and the normals of the polygon
+                let normals = vertices.iter().zip(vertices.iter().cycle().skip(1))
+                    .map(|(a,b)| [a[1]-b[1], b[0]-a[0]])
+                    .chain(vec!([1.,0.],[0.,1.]));
+                for n in normals {
+                    let projections = vertices.iter().map(|v| v[0]*n[0] + v[1]*n[1]);
+                    let (min, max) = projections.fold((f32::MAX,f32::MIN), |(min,max),p| (min.min(p), max.max(p)));
+                    let square = center[0]*n[0] + center[1]*n[1];
+                    let square_half = half*(n[0].abs() + n[1].abs());
+                    if max <= square - square_half || square + square_half <= min {
+                        return false;
+                    }
+                }
+                true
+            },
+        }
+    }
+
+    fn overlaps(&self, pos: [f32;2], shape: &Shape) -> bool {
+        match *shape {
+            Shape::Circle(radius) => self.distance(pos) < radius,
+            Shape::Square(radius) => self.overlaps_square(pos, radius),
+        }
+    }
+}
+
+/// distance from the point to the box of center and half sizes, 0 inside
+fn box_distance(p: [f32;2], center: [f32;2], half: [f32;2]) -> f32 {
+    let dx = ((p[0]-center[0]).abs() - half[0]).max(0.);
+    let dy = ((p[1]-center[1]).abs() - half[1]).max(0.);
+    (dx.powi(2) + dy.powi(2)).sqrt()
+}
+
+/// distance from the point to the segment from a to b
+fn segment_distance(p: [f32;2], a: [f32;2], b: [f32;2]) -> f32 {
+    let ab = [b[0]-a[0], b[1]-a[1]];
+    let length2 = ab[0].powi(2) + ab[1].powi(2);
+    let t = if length2 == 0. {
+        0.
+    } else {
+        (((p[0]-a[0])*ab[0] + (p[1]-a[1])*ab[1]) / length2).max(0.).min(1.)
+    };
+    ((p[0]-a[0]-t*ab[0]).powi(2) + (p[1]-a[1]-t*ab[1]).powi(2)).sqrt()
+}
+
+#[test]
+fn test_query_region_overlaps() {
+    let circle = QueryRegion::Circle { center: [0.,0.], radius: 1. };
+    assert!(circle.overlaps([1.4,0.], &Shape::Square(0.5)));
+    assert!(!circle.overlaps([1.6,0.], &Shape::Square(0.5)));
+    assert!(!circle.overlaps([1.,1.], &Shape::Circle(0.4)));
+
+    let triangle = QueryRegion::Polygon(vec!([0.,0.], [4.,0.], [0.,4.]));
+    assert!(triangle.overlaps([1.,1.], &Shape::Circle(0.1)));
+    assert!(!triangle.overlaps([2.5,2.5], &Shape::Square(0.4)));
+    assert!(triangle.overlaps([2.5,2.5], &Shape::Square(0.6)));
+    assert!(!triangle.overlaps([3.,3.], &Shape::Circle(1.4)));
+    assert!(triangle.overlaps([3.,3.], &Shape::Circle(1.5)));
+}
+
 /// if A collide with B then collision must represent
 /// the smallest vector to move A so it doesn't collide anymore
 pub struct Collision {
@@ -182,6 +448,8 @@ pub struct Collision {
 
 pub struct PhysicWorld {
     unit: f32,
+    /// incremented each time static entities change
+    static_version: usize,
     static_hashmap: HashMap<[i32;2],Vec<(specs::Entity,[f32;2],u32,Shape)>,BuildHasherDefault<FnvHasher>>,
     movable_hashmap: HashMap<[i32;2],Vec<(specs::Entity,[f32;2],u32,Shape)>,BuildHasherDefault<FnvHasher>>,
 }
@@ -202,17 +470,19 @@ impl Resolution {
 pub struct PhysicSystem;
 impl specs::System<app::UpdateContext> for PhysicSystem {
     fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
-        use std::f32::consts::PI;
         use specs::Join;
 
-        let (dynamics,mut states,forces,types,mut physic_world,mut triggers,entities) = arg.fetch(|world| {
+        let (dynamics,mut states,forces,types,movements,mut physic_world,mut triggers,mut lods,mut teleporteds,entities) = arg.fetch(|world| {
             (
                 world.read::<PhysicDynamic>(),
                 world.write::<PhysicState>(),
                 world.read::<PhysicForce>(),
                 world.read::<PhysicType>(),
+                world.read::<Movement>(),
                 world.write_resource::<PhysicWorld>(),
                 world.write::<PhysicTrigger>(),
+                world.write::<UpdateLod>(),
+                world.write::<PhysicTeleported>(),
                 world.entities(),
             )
         });
@@ -221,9 +491,13 @@ impl specs::System<app::UpdateContext> for PhysicSystem {
 
         let mut resolutions = HashMap::<specs::Entity,Resolution>::new();
 
-        for trigger in (&mut triggers).iter() {
+        let mut previous_overlappings = HashMap::<specs::Entity,HashSet<specs::Entity>>::new();
+        for (trigger,entity) in (&mut triggers, &entities).iter() {
             trigger.active = false;
+            previous_overlappings.insert(entity, ::std::mem::replace(&mut trigger.overlapping, HashSet::new()));
         }
+
+        // move dynamic entities and hash them all before looking for collisions
         let fnv = BuildHasherDefault::<FnvHasher>::default();
         physic_world.movable_hashmap = HashMap::with_hasher(fnv);
         for (_,entity) in (&dynamics, &entities).iter() {
@@ -231,40 +505,65 @@ impl specs::System<app::UpdateContext> for PhysicSystem {
             let force = forces.get(entity).expect("dynamic entity expect force component");
             let typ = types.get(entity).expect("dynamic entity expect type component");
 
-            state.acceleration[0] = (typ.force*force.intensity*force.direction.cos()
-                                     - typ.damping*state.velocity[0])/typ.weight;
+            // entities not updated this frame keep their position but still collide,
+            // dormant entities don't
+            let dt = match lods.get_mut(entity) {
+                Some(lod) if lod.dormant => continue,
+                Some(lod) => match lod.step(dt) {
+                    Some(dt) => dt,
+                    None => {
+                        physic_world.insert_movable(entity, &state.position, typ.group, &typ.shape);
+                        continue;
+                    },
+                },
+                None => dt,
+            };
+
+            let mode = movements.get(entity).map(|m| movement::definition(m.mode()));
+            state.integrate(typ, force, mode, dt);
 
-            state.acceleration[1] = (typ.force*force.intensity*force.direction.sin()
-                                     - typ.damping*state.velocity[1])/typ.weight;
+            physic_world.insert_movable(entity, &state.position, typ.group, &typ.shape);
+        }
 
-            state.velocity[0] += dt*state.acceleration[0];
-            state.velocity[1] += dt*state.acceleration[1];
+        // each colliding pair is resolved exactly once per update: a pair of dynamic
+        // entities by the one of lower id, a pair with a static entity by the dynamic one
+        for (_,entity) in (&dynamics, &entities).iter() {
+            if lods.get(entity).map(|lod| lod.dormant).unwrap_or(false) { continue }
 
-            state.position[0] += dt*state.velocity[0];
-            state.position[1] += dt*state.velocity[1];
+            let position = states.get(entity).expect("dynamic entity expect state component").position;
+            let typ = types.get(entity).expect("dynamic entity expect type component");
 
             if typ.mask == 0 { continue }
 
-            physic_world.apply_on_shape(&state.position, typ.mask, &typ.shape, &mut |other_entity,collision| {
+            physic_world.apply_on_shape(&position, typ.mask, &typ.shape, &mut |other_entity,collision| {
+                if dynamics.get(*other_entity).is_some() && other_entity.get_id() <= entity.get_id() {
+                    return;
+                }
+                if teleporteds.get(entity).is_some() || teleporteds.get(*other_entity).is_some() {
+                    return;
+                }
+
                 let other_type = types.get(*other_entity).expect("physic entity expect type component");
 
                 if other_type.mask & typ.group != 0 {
 
                     if let Some(trigger) = triggers.get_mut(entity) {
                         trigger.active = true;
+                        trigger.overlapping.insert(*other_entity);
                     }
                     if let Some(trigger) = triggers.get_mut(*other_entity) {
                         trigger.active = true;
+                        trigger.overlapping.insert(entity);
                     }
 
-                    let rate = {
-                        if other_type.weight == f32::MAX {
-                            0.
-                        } else if typ.weight == f32::MAX {
-                            1.
-                        } else {
-                            typ.weight/(typ.weight+other_type.weight)
-                        }
+                    // the correction is shared by inverse weight so the lighter entity moves more,
+                    // rate is the share of the other entity
+                    let inverse = inverse_weight(typ.weight);
+                    let other_inverse = inverse_weight(other_type.weight);
+                    let rate = if inverse + other_inverse == 0. {
+                        0.
+                    } else {
+                        other_inverse/(inverse + other_inverse)
                     };
 
                     if rate != 1. {
@@ -282,7 +581,7 @@ impl specs::System<app::UpdateContext> for PhysicSystem {
                             dx: -collision.delta_x*rate,
                             dy: -collision.delta_y*rate,
                         };
-                        match resolutions.entry(entity) {
+                        match resolutions.entry(*other_entity) {
                             Entry::Occupied(mut entry) => entry.get_mut().push(resolution),
                             Entry::Vacant(entry) => {entry.insert(resolution);},
                         }
@@ -290,40 +589,71 @@ impl specs::System<app::UpdateContext> for PhysicSystem {
 
                 }
             });
+        }
 
-            physic_world.insert_movable(entity, &state.position, typ.group, &typ.shape);
+        // the pairs not tested this update keep their overlap: the ones of dormant entities,
+        // out of the physic world, and the ones of teleported entities
+        {
+            let skipped = |entity| lods.get(entity).map(|lod| lod.dormant).unwrap_or(false) || teleporteds.get(entity).is_some();
+            for (trigger,entity) in (&mut triggers, &entities).iter() {
+                if let Some(previous) = previous_overlappings.get(&entity) {
+                    for &other_entity in previous {
+                        if skipped(entity) || skipped(other_entity) {
+                            trigger.overlapping.insert(other_entity);
+                        }
+                    }
+                }
+                trigger.active = !trigger.overlapping.is_empty();
+            }
+        }
+
+        for (trigger,entity) in (&triggers, &entities).iter() {
+            let empty = HashSet::new();
+            let previous = previous_overlappings.get(&entity).unwrap_or(&empty);
+            for &other_entity in trigger.overlapping.difference(previous) {
+                context.event_tx.send(app::GameEvent::OverlapEnter(entity,other_entity)).unwrap();
+            }
+            for &other_entity in previous.difference(&trigger.overlapping) {
+                context.event_tx.send(app::GameEvent::OverlapExit(entity,other_entity)).unwrap();
+            }
         }
 
         for (entity,res) in resolutions {
             let state = states.get_mut(entity).unwrap();
             let typ = types.get(entity).unwrap();
 
-            state.position[0] += res.dx;
-            state.position[1] += res.dy;
+            state.resolve(typ, &res);
+        }
 
-            match typ.collision_behavior {
-                CollisionBehavior::Bounce => {
-                    let angle = state.velocity[1].atan2(state.velocity[0]) + PI;
-                    state.velocity[0] = angle.cos();
-                    state.velocity[1] = angle.sin();
-                },
-                CollisionBehavior::Stop => state.velocity = [0.,0.],
-                CollisionBehavior::Back => {
-                    state.velocity[0] = -state.velocity[0];
-                    state.velocity[1] = -state.velocity[1];
-                },
-                CollisionBehavior::Persist => (),
-            }
+        let teleported: Vec<specs::Entity> = (&teleporteds, &entities).iter().map(|(_,entity)| entity).collect();
+        for entity in teleported {
+            teleporteds.remove(entity);
         }
 
         let fnv = BuildHasherDefault::<FnvHasher>::default();
         physic_world.movable_hashmap = HashMap::with_hasher(fnv);
         for (_,state,typ,entity) in (&dynamics, &mut states, &types, &entities).iter() {
+            if lods.get(entity).map(|lod| lod.dormant).unwrap_or(false) { continue }
             physic_world.insert_movable(entity, &state.position, typ.group, &typ.shape);
         }
     }
 }
 
+/// report the violations of the invariants of the physic world right after its update
+/// so a corruption is caught where it happens and not as a panic frames later
+#[cfg(feature = "validate")]
+pub struct ValidateSystem;
+#[cfg(feature = "validate")]
+impl specs::System<app::UpdateContext> for ValidateSystem {
+    fn run(&mut self, arg: specs::RunArg, _: app::UpdateContext) {
+        let violations = arg.fetch(|world| world.read_resource::<PhysicWorld>().validate(world));
+
+        for violation in violations {
+            println!("ERROR: physic world invalid: {}", violation);
+        }
+    }
+}
+
 impl PhysicWorld {
     pub fn new() -> Self {
         let fnv0 = BuildHasherDefault::<FnvHasher>::default();
@@ -331,6 +661,7 @@ impl PhysicWorld {
 
         let physic_world = PhysicWorld {
             unit: config.physic.unit,
+            static_version: 0,
             static_hashmap: HashMap::with_hasher(fnv0),
             movable_hashmap: HashMap::with_hasher(fnv1),
         };
@@ -340,6 +671,24 @@ impl PhysicWorld {
         physic_world
     }
 
+    pub fn static_version(&self) -> usize {
+        self.static_version
+    }
+
+    /// the number of buckets and entries of the hashmaps and the bytes they hold
+    pub fn memory(&self) -> (usize, usize, usize) {
+        let mut memory = (0, 0, 0);
+        for hashmap in &[&self.static_hashmap, &self.movable_hashmap] {
+            memory.0 += hashmap.len();
+            memory.2 += hashmap.capacity() * ::std::mem::size_of::<([i32;2],Vec<(specs::Entity,[f32;2],u32,Shape)>)>();
+            for bucket in hashmap.values() {
+                memory.1 += bucket.len();
+                memory.2 += bucket.capacity() * ::std::mem::size_of::<(specs::Entity,[f32;2],u32,Shape)>();
+            }
+        }
+        memory
+    }
+
     pub fn fill(&mut self, world: &specs::World) {
         let dynamics = world.read::<PhysicDynamic>();
         let statics = world.read::<PhysicStatic>();
@@ -349,6 +698,7 @@ impl PhysicWorld {
 
         self.static_hashmap.clear();
         self.movable_hashmap.clear();
+        self.static_version += 1;
 
         for (_,state,typ,entity) in (&dynamics, &states, &types, &entities).iter() {
             self.insert_movable(entity, &state.position, typ.group, &typ.shape);
@@ -358,38 +708,308 @@ impl PhysicWorld {
         }
     }
 
+    /// the violations of the invariants of the physic world as it is after the physic
+    /// update: every physic entity is hashed in the cells of its shape in the hashmap
+    /// of its kind only, no deleted entity is hashed, an id is hashed for one entity
+    /// only and no position or velocity is NaN
+    #[cfg(any(test, feature = "validate"))]
+    pub fn validate(&self, world: &specs::World) -> Vec<String> {
+        let dynamics = world.read::<PhysicDynamic>();
+        let statics = world.read::<PhysicStatic>();
+        let states = world.read::<PhysicState>();
+        let types = world.read::<PhysicType>();
+        let entities = world.entities();
+
+        let mut violations = vec!();
+
+        for (state,entity) in (&states, &entities).iter() {
+            if state.position.iter().chain(state.velocity.iter()).any(|v| v.is_nan()) {
+                violations.push(format!("entity {} has position {:?} and velocity {:?}", entity.get_id(), state.position, state.velocity));
+            }
+        }
+        for (_,_,entity) in (&dynamics, &statics, &entities).iter() {
+            violations.push(format!("entity {} is both dynamic and static", entity.get_id()));
+        }
+
+        let mut ids = HashMap::<u32,specs::Entity>::new();
+        let mut hashed = HashSet::new();
+        for &(name, hashmap, is_static) in &[("static", &self.static_hashmap, true), ("movable", &self.movable_hashmap, false)] {
+            let mut cells_of_entity = HashMap::<specs::Entity,Vec<[i32;2]>>::new();
+            for (cell,vec) in hashmap.iter() {
+                for &(entity,_,_,_) in vec {
+                    cells_of_entity.entry(entity).or_insert(Vec::new()).push(*cell);
+                }
+            }
+
+            for (entity,mut cells) in cells_of_entity {
+                let id = entity.get_id() as u32;
+                if *ids.entry(id).or_insert(entity) != entity {
+                    violations.push(format!("id {} is hashed for two entities", id));
+                }
+                hashed.insert((entity,is_static));
+
+                let of_kind = if is_static { statics.get(entity).is_some() } else { dynamics.get(entity).is_some() };
+                let (state,typ) = match (states.get(entity), types.get(entity)) {
+                    (Some(state), Some(typ)) if of_kind => (state,typ),
+                    _ => {
+                        violations.push(format!("entity {} is in the {} hashmap but is deleted or not {}", id, name, if is_static { "static" } else { "dynamic" }));
+                        continue;
+                    },
+                };
+
+                let mut expected = self.cells_of_shape(&state.position, &typ.shape);
+                cells.sort();
+                expected.sort();
+                if cells != expected {
+                    violations.push(format!("entity {} at {:?} is in cells {:?} of the {} hashmap instead of {:?}", id, state.position, cells, name, expected));
+                }
+            }
+        }
+
+        // dormant entities are out of the physic world
+        let lods = world.read::<UpdateLod>();
+        for (_,_,_,entity) in (&dynamics, &states, &types, &entities).iter() {
+            if lods.get(entity).map(|lod| lod.dormant).unwrap_or(false) { continue }
+            if !hashed.contains(&(entity,false)) {
+                violations.push(format!("dynamic entity {} is not in the movable hashmap", entity.get_id()));
+            }
+        }
+        for (_,_,_,entity) in (&statics, &states, &types, &entities).iter() {
+            if !hashed.contains(&(entity,true)) {
+                violations.push(format!("static entity {} is not in the static hashmap", entity.get_id()));
+            }
+        }
+
+        violations
+    }
+
+    /// the number of entities hashed in each non empty cell
+    #[cfg(any(test, feature = "bench"))]
+    pub fn cell_counts(&self) -> HashMap<[i32;2],usize> {
+        let mut counts = HashMap::new();
+        for (cell,vec) in self.static_hashmap.iter().chain(self.movable_hashmap.iter()) {
+            *counts.entry(*cell).or_insert(0) += vec.len();
+        }
+        counts.retain(|_, count| *count > 0);
+        counts
+    }
+
     fn cells_of_shape(&self, pos: &[f32;2], shape: &Shape) -> Vec<[i32;2]> {
         let radius = match *shape {
             Shape::Circle(r) => r,
             Shape::Square(r) => r,
         };
 
-        let min_x = ((pos[0]-radius+f32::EPSILON)/self.unit).floor() as i32;
-        let max_x = ((pos[0]+radius-f32::EPSILON)/self.unit).ceil() as i32;
-        let min_y = ((pos[1]-radius+f32::EPSILON)/self.unit).floor() as i32;
-        let max_y = ((pos[1]+radius-f32::EPSILON)/self.unit).ceil() as i32;
+        CellRect::covering([pos[0]-radius, pos[1]-radius], [pos[0]+radius, pos[1]+radius], self.unit).iter().collect()
+    }
+
+    pub fn apply_on_shape<M: IntoMask, F: FnMut(&specs::Entity,&Collision)>(&self, pos: &[f32;2], mask: M, shape: &Shape, callback: &mut F) {
+        self.apply_on_shape_except(&[], pos, mask, shape, callback);
+    }
+
+    /// entities of mask overlapping the circle of radius around pos with the intensity of
+    /// the explosion on them: (1 - distance/radius)^falloff with distance to their center.
+    /// the intensity is multiplied by explosion_occlusion for each static entity of
+    /// occlusion mask between pos and them, so walls protect from explosions
+    pub fn explode<M: IntoMask, O: IntoMask>(&self, pos: [f32;2], radius: f32, falloff: f32, mask: M, occlusion_mask: O) -> Vec<(specs::Entity,f32)> {
+        let mask = mask.into_mask();
+        let occlusion_mask = occlusion_mask.into_mask();
 
-        let mut cells = Vec::new();
-        for x in min_x..max_x {
-            for y in min_y..max_y {
-                cells.push([x,y]);
+        let mut targets = vec!();
+        let mut visited = HashSet::new();
+        for cell in self.cells_of_shape(&pos,&Shape::Circle(radius)) {
+            self.apply_on_index(cell, mask, &mut |other_entity, other_pos, other_shape| {
+                if !visited.insert(*other_entity) { return }
+                if shape_collide(&pos,&Shape::Circle(radius),other_pos,other_shape).is_some() {
+                    targets.push((*other_entity,*other_pos));
+                }
+            });
+        }
+        targets.sort_by_key(|&(entity,_)| entity.get_id());
+
+        targets.iter().map(|&(entity,target_pos)| {
+            let dx = target_pos[0] - pos[0];
+            let dy = target_pos[1] - pos[1];
+            let distance = (dx.powi(2) + dy.powi(2)).sqrt();
+            let mut intensity = (1. - distance/radius).max(0.).powf(falloff);
+
+            if distance > 0. && intensity > 0. {
+                let ray = Ray {
+                    origin: pos,
+                    angle: math::atan2(dy, dx),
+                    length: distance,
+                    mask: occlusion_mask,
+                };
+                let options = RaycastOptions { exclude: &[entity], static_only: true, .. RaycastOptions::default() };
+                self.raycast_with(&ray, &options, &mut |_| {
+                    intensity *= config.physic.explosion_occlusion;
+                    intensity == 0.
+                });
             }
+            (entity,intensity)
+        }).collect()
+    }
+
+    /// whether shape at pos overlaps no entity of mask
+    pub fn fits<M: IntoMask>(&self, pos: &[f32;2], shape: &Shape, mask: M) -> bool {
+        let mut free = true;
+        self.apply_on_shape(pos, mask, shape, &mut |_,_| free = false);
+        free
+    }
+
+    /// the nearest position to near where shape fits, within max_radius, so entities are
+    /// not placed inside walls or each other. positions are searched on circles around near
+    /// spaced by the radius of the shape
+    pub fn find_free_position<M: IntoMask>(&self, shape: &Shape, near: [f32;2], max_radius: f32, mask: M) -> Option<[f32;2]> {
+        use std::f32::consts::PI;
+
+        let mask = mask.into_mask();
+        if self.fits(&near, shape, mask) {
+            return Some(near);
         }
-        cells
+
+        let step = match *shape {
+            Shape::Circle(r) | Shape::Square(r) => r.max(0.1),
+        };
+        let mut radius = step;
+        while radius <= max_radius {
+            let samples = (2.*PI*radius/step).ceil() as usize;
+            for i in 0..samples {
+                let angle = 2.*PI*i as f32/samples as f32;
+                let pos = [near[0] + radius*math::cos(angle), near[1] + radius*math::sin(angle)];
+                if self.fits(&pos, shape, mask) {
+                    return Some(pos);
+                }
+            }
+            radius += step;
+        }
+        None
     }
 
-    pub fn apply_on_shape<F: FnMut(&specs::Entity,&Collision)>(&self, pos: &[f32;2], mask: u32, shape: &Shape, callback: &mut F) {
+    /// as apply_on_shape but entities of except are not given to callback,
+    /// typically the entity doing the query, return the entities skipped
+    pub fn apply_on_shape_except<M: IntoMask, F: FnMut(&specs::Entity,&Collision)>(&self, except: &[specs::Entity], pos: &[f32;2], mask: M, shape: &Shape, callback: &mut F) -> Vec<specs::Entity> {
+        let mask = mask.into_mask();
         let mut visited = HashSet::new();
+        let mut skipped = vec!();
 
         for cell in self.cells_of_shape(pos,shape) {
             self.apply_on_index(cell, mask, &mut |other_entity, other_pos, other_shape| {
                 if visited.contains(other_entity) { return; }
                 visited.insert(*other_entity);
                 if let Some(collision) = shape_collide(pos,shape,other_pos,other_shape) {
-                    callback(other_entity,&collision);
+                    if except.contains(other_entity) {
+                        skipped.push(*other_entity);
+                    } else {
+                        callback(other_entity,&collision);
+                    }
+                }
+            });
+        }
+        skipped
+    }
+
+    /// entities touched by the shape moved from origin along direction for length,
+    /// with the distance the shape travels before touching them, by increasing distance.
+    /// the shape is tested at steps of half its size. entities already touched at origin
+    /// are given at distance 0 only if the shape moves into them
+    pub fn shapecast<M: IntoMask>(&self, except: &[specs::Entity], origin: &[f32;2], direction: f32, length: f32, shape: &Shape, mask: M) -> Vec<(specs::Entity,f32)> {
+        let mask = mask.into_mask();
+        let size = match *shape {
+            Shape::Circle(radius) | Shape::Square(radius) => radius,
+        };
+        let step = size.max(1e-3);
+        let steps = (length/step).ceil() as usize;
+        let (cos, sin) = (math::cos(direction), math::sin(direction));
+
+        let mut touched = HashSet::new();
+        let mut result = vec!();
+        self.apply_on_shape_except(except, origin, mask, shape, &mut |entity, collision| {
+            touched.insert(*entity);
+            if collision.delta_x*cos + collision.delta_y*sin < 0. {
+                result.push((*entity, 0.));
+            }
+        });
+
+        let mut previous = 0.;
+        for i in 1..steps+1 {
+            let distance = (i as f32*step).min(length);
+            let pos = [origin[0] + distance*cos, origin[1] + distance*sin];
+            let mut new = vec!();
+            self.apply_on_shape_except(except, &pos, mask, shape, &mut |entity, _| {
+                if touched.insert(*entity) {
+                    new.push((*entity, previous));
+                }
+            });
+            new.sort_by_key(|&(entity, _)| entity.get_id());
+            result.extend(new);
+            previous = distance;
+        }
+        result
+    }
+
+    /// apply callback on entities whose shape overlaps the region
+    pub fn apply_on_region<M: IntoMask, F: FnMut(&specs::Entity)>(&self, region: &QueryRegion, mask: M, callback: &mut F) {
+        let mask = mask.into_mask();
+        let mut visited = HashSet::new();
+
+        for cell in self.cells_of_region(region) {
+            self.apply_on_index(cell, mask, &mut |other_entity, other_pos, other_shape| {
+                if visited.contains(other_entity) { return; }
+                visited.insert(*other_entity);
+                if region.overlaps(*other_pos, other_shape) {
+                    callback(other_entity);
+                }
+            });
+        }
+    }
+
+    /// cells of the bounding box of the region that overlap the region
+    fn cells_of_region(&self, region: &QueryRegion) -> Vec<[i32;2]> {
+        let (min, max) = region.bounds();
+        let half = self.unit/2.;
+
+        CellRect::covering(min, max, self.unit).iter()
+            .filter(|&cell| region.overlaps_square(cell_center(cell, self.unit), half + f32::EPSILON))
+            .collect()
+    }
+
+    /// apply callback on entities whose center is inside the cone of origin pos,
+    /// oriented by direction, of half opening angle and of radius,
+    /// the callback get the distance to the center of the entity
+    pub fn apply_on_cone<M: IntoMask, F: FnMut(&specs::Entity,f32)>(&self, pos: &[f32;2], direction: f32, angle: f32, radius: f32, mask: M, callback: &mut F) {
+        self.apply_on_cone_except(&[], pos, direction, angle, radius, mask, callback);
+    }
+
+    /// as apply_on_cone but entities of except are not given to callback,
+    /// typically the entity doing the query, return the entities skipped
+    pub fn apply_on_cone_except<M: IntoMask, F: FnMut(&specs::Entity,f32)>(&self, except: &[specs::Entity], pos: &[f32;2], direction: f32, angle: f32, radius: f32, mask: M, callback: &mut F) -> Vec<specs::Entity> {
+        use utils::minus_pi_pi;
+
+        let mask = mask.into_mask();
+        let mut visited = HashSet::new();
+        let mut skipped = vec!();
+
+        for cell in self.cells_of_shape(pos,&Shape::Square(radius)) {
+            self.apply_on_index(cell, mask, &mut |other_entity, other_pos, _| {
+                if visited.contains(other_entity) { return; }
+                visited.insert(*other_entity);
+
+                let dx = other_pos[0]-pos[0];
+                let dy = other_pos[1]-pos[1];
+                let distance = (dx.powi(2) + dy.powi(2)).sqrt();
+                if distance > radius { return; }
+
+                if distance == 0. || minus_pi_pi(math::atan2(dy, dx) - direction).abs() <= angle {
+                    if except.contains(other_entity) {
+                        skipped.push(*other_entity);
+                    } else {
+                        callback(other_entity,distance);
+                    }
                 }
             });
         }
+        skipped
     }
 
     fn apply_on_index<F: FnMut(&specs::Entity,&[f32;2],&Shape)>(&self, cell: [i32;2], mask: u32, callback: &mut F) {
@@ -405,6 +1025,7 @@ impl PhysicWorld {
     }
 
     pub fn insert_static(&mut self, entity: specs::Entity, pos: &[f32;2], group: u32, shape: &Shape) {
+        self.static_version += 1;
         for cell in self.cells_of_shape(pos,shape) {
             self.static_hashmap.entry(cell).or_insert(Vec::new()).push((entity,pos.clone(),group,shape.clone()));
         }
@@ -424,17 +1045,78 @@ impl PhysicWorld {
         }
     }
 
+    /// move the entity hashed at old_pos to new_pos
+    fn relocate(&mut self, entity: specs::Entity, old_pos: &[f32;2], new_pos: &[f32;2], group: u32, shape: &Shape, is_static: bool) {
+        for cell in self.cells_of_shape(old_pos,shape) {
+            let hashmap = if is_static { &mut self.static_hashmap } else { &mut self.movable_hashmap };
+            if let Some(vec) = hashmap.get_mut(&cell) {
+                vec.retain(|&(other_entity,_,_,_)| other_entity != entity);
+            }
+        }
+        if is_static {
+            self.insert_static(entity, new_pos, group, shape);
+        } else {
+            self.insert_movable(entity, new_pos, group, shape);
+        }
+    }
+
+    /// positions of a ghost body after each of steps updates of dt, starting from state
+    /// and pushed by force, so aiming and throws can be evaluated before being done.
+    /// the ghost collides only the static entities of its mask and the physic world is not modified
+    pub fn predict_trajectory(&self, state: &PhysicState, typ: &PhysicType, force: &PhysicForce, steps: usize, dt: f32) -> Vec<[f32;2]> {
+        let mut state = state.clone();
+        let mut trajectory = Vec::with_capacity(steps);
+
+        for _ in 0..steps {
+            state.integrate(typ, force, None, dt);
+
+            let mut resolution = Resolution { dx: 0., dy: 0. };
+            let mut collided = false;
+            let mut visited = HashSet::new();
+            for cell in self.cells_of_shape(&state.position, &typ.shape) {
+                let empty_vec = vec!();
+                for &(entity,ref pos,group,ref shape) in self.static_hashmap.get(&cell).unwrap_or(&empty_vec) {
+                    if group & typ.mask == 0 || !visited.insert(entity) { continue }
+                    if let Some(collision) = shape_collide(&state.position, &typ.shape, pos, shape) {
+                        resolution.push(Resolution {
+                            dx: collision.delta_x,
+                            dy: collision.delta_y,
+                        });
+                        collided = true;
+                    }
+                }
+            }
+            if collided {
+                state.resolve(typ, &resolution);
+            }
+
+            trajectory.push(state.position);
+        }
+        trajectory
+    }
+
+    /// apply callback on entities crossed by the ray with the distances to the entry and
+    /// the exit of the entity, the entry is negative if the entity contains the origin.
+    /// entities are given cell by cell along the ray, and within a cell by increasing
+    /// entry distance then by increasing entity id, so the order is deterministic.
+    /// raycast stops when callback returns true
     pub fn raycast<F: FnMut((specs::Entity,f32,f32)) -> bool>(&self, ray: &Ray, callback: &mut F) {
+        self.raycast_with(ray, &RaycastOptions::default(), callback);
+    }
+
+    /// as raycast but entities of options.exclude are not given to callback and
+    /// raycast stops once options.max_hits entities have been given to callback,
+    /// the remaining cells along the ray are not traversed
+    pub fn raycast_with<F: FnMut((specs::Entity,f32,f32)) -> bool>(&self, ray: &Ray, options: &RaycastOptions, callback: &mut F) {
         use std::f32::consts::PI;
-        use std::cmp::Ordering;
         use utils::minus_pi_pi;
 
         let angle = minus_pi_pi(ray.angle);
 
         let x0 = ray.origin[0];
         let y0 = ray.origin[1];
-        let x1 = x0+ray.length*angle.cos();
-        let y1 = y0+ray.length*angle.sin();
+        let x1 = x0+ray.length*math::cos(angle);
+        let y1 = y0+ray.length*math::sin(angle);
         let cells = grid_raycast(x0/self.unit, y0/self.unit, x1/self.unit, y1/self.unit);
 
         // equation ax + by + c = 0
@@ -449,6 +1131,7 @@ impl PhysicWorld {
         let line_end = x0.max(x1);
 
         let mut visited: HashSet<specs::Entity> = HashSet::new();
+        let mut hits = 0;
 
         for cell in cells {
             // abscisse of start and end the segment of
@@ -461,12 +1144,14 @@ impl PhysicWorld {
 
             {
                 let null_vec = vec!();
-                let entities = self.movable_hashmap.get(&cell).unwrap_or(&null_vec).iter()
+                let movables = if options.static_only { &null_vec } else { self.movable_hashmap.get(&cell).unwrap_or(&null_vec) };
+                let entities = movables.iter()
                     .chain(self.static_hashmap.get(&cell).unwrap_or(&null_vec).iter());
 
                 for &(entity,ref pos,group,ref shape) in entities {
                     if (group & ray.mask) == 0 { continue; }
                     if visited.contains(&entity) { continue; }
+                    if options.exclude.contains(&entity) { continue; }
 
                     let intersections = match *shape {
                         Shape::Circle(radius) => circle_raycast(pos[0],pos[1],radius,a,b,c),
@@ -504,18 +1189,13 @@ impl PhysicWorld {
                 }
             }
 
-            bodies.sort_by(|&(_,min_a,_),&(_,min_b,_)| {
-                if min_a > min_b {
-                    Ordering::Greater
-                } else if min_a == min_b {
-                    Ordering::Equal
-                } else {
-                    Ordering::Less
-                }
+            bodies.sort_by(|&(entity_a,min_a,_),&(entity_b,min_b,_)| {
+                total_cmp(min_a,min_b).then_with(|| entity_a.get_id().cmp(&entity_b.get_id()))
             });
 
             for (entity,min,max) in bodies {
-                if callback((entity,min,max)) {
+                hits += 1;
+                if callback((entity,min,max)) || options.max_hits.map(|max_hits| hits >= max_hits).unwrap_or(false) {
                     return;
                 }
             }
@@ -523,6 +1203,29 @@ impl PhysicWorld {
     }
 }
 
+/// total order on floats: -NaN < -inf < ... < -0 < +0 < ... < inf < NaN
+fn total_cmp(a: f32, b: f32) -> ::std::cmp::Ordering {
+    let key = |v: f32| {
+        let bits = v.to_bits() as i32;
+        bits ^ (((bits >> 31) as u32) >> 1) as i32
+    };
+    key(a).cmp(&key(b))
+}
+
+#[test]
+fn test_total_cmp() {
+    use std::cmp::Ordering;
+    use std::f32;
+
+    let ordered = [-f32::NAN, f32::NEG_INFINITY, -1., -0., 0., f32::MIN_POSITIVE, 1., f32::INFINITY, f32::NAN];
+    for (i, &a) in ordered.iter().enumerate() {
+        for (j, &b) in ordered.iter().enumerate() {
+            assert_eq!(total_cmp(a,b), i.cmp(&j));
+        }
+    }
+    assert_eq!(total_cmp(f32::NAN,f32::NAN), Ordering::Equal);
+}
+
 const MOVE: f32 = 7./8.;
 const FACTOR: f32 = 8.*1.41421356237309504880;
 fn shape_collide(a_pos: &[f32;2], a_shape: &Shape, b_pos: &[f32;2], b_shape: &Shape) -> Option<Collision> {
@@ -535,12 +1238,12 @@ fn shape_collide(a_pos: &[f32;2], a_shape: &Shape, b_pos: &[f32;2], b_shape: &Sh
                     let dn2 = dx.powi(2) + dy.powi(2);
                     let rad = a_rad+b_rad;
                     if dn2 < rad.powi(2) {
-                        let angle = dy.atan2(dx);
+                        let angle = math::atan2(dy, dx);
                         let dn = dn2.sqrt();
                         let delta = rad - dn;
                         Some(Collision {
-                            delta_x: delta*angle.cos(),
-                            delta_y: delta*angle.sin(),
+                            delta_x: delta*math::cos(angle),
+                            delta_y: delta*math::sin(angle),
                         })
                     } else {
                         None
@@ -661,6 +1364,7 @@ fn grid_raycast(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<[i32;2]> {
         let x0_i32 = x0.floor() as i32;
         let y0_i32 = y0.floor() as i32;
         let x1_i32 = x1.floor() as i32;
+        let y1_i32 = y1.floor() as i32;
 
         // equation y = ax + b
         let a = (y1 - y0)/(x1 - x0);
@@ -681,15 +1385,6 @@ fn grid_raycast(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<[i32;2]> {
             y0.ceil()-(a*x0.floor()+b)
         };
 
-        //TODO cut some cells at the end
-        //let mut error_end = if a > 0. {
-        //    y1.ceil() - (a*x1.ceil()+b)
-        //} else {
-        //    (y1.floor() - (a*x1.ceil()+b))
-        //};
-        //println!("debut: error: {}",error);
-        //println!("error end : {}", error_end);
-
         let mut vec = Vec::new();
         let mut y = y0_i32;
 
@@ -697,18 +1392,15 @@ fn grid_raycast(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<[i32;2]> {
             vec.push([x,y]);
             error += delta_error;
             //println!("error: {}",error);
-            while error >= 1.0 {
+            // the line never goes past the row of its end, stepping further would
+            // add cells after the end that come first once the cells are reversed
+            while error >= 1.0 && y != y1_i32 {
                 y += signum;
                 error -= 1.0;
                 //println!("error -= 1.0: {}",error);
                 vec.push([x,y]);
             }
         }
-        //while error_end >= 0. {
-        //    vec.pop();
-        //    error_end -= 1.0;
-        //}
-
         //println!("result: {:?}",vec);
         vec
     }
@@ -735,6 +1427,15 @@ fn circle_raycast(x: f32, y: f32, radius: f32, a: f32, b: f32, c: f32) -> Option
         } else {
             None
         }
+    } else if b.abs() < a.abs() {
+        // solve on the ordinate axis, dividing by b is imprecise for nearly vertical lines
+        circle_raycast(y,x,radius,b,a,c).map(|(y1,x1,y2,x2)| {
+            if x1 <= x2 {
+                (x1,y1,x2,y2)
+            } else {
+                (x2,y2,x1,y1)
+            }
+        })
     } else {
         // the equation of intersection abscisse: d*x^2 + e*x + f = 0
         let d = 1. + (a/b).powi(2);
@@ -908,3 +1609,433 @@ fn test_bounding_box_raycast() {
     assert_eq!(Some((-4.,-1.96,2.,-2.02)),bounding_box_raycast( -1., -2., 6., 2., 0.01, 1., 2.));
 }
 
+#[test]
+fn test_grid_raycast_quadrants() {
+    // cells are floored so a ray in any quadrant starts in the cell of its origin,
+    // ends in the cell of its end and covers every point of the segment
+    let rays = [
+        ( 0.5,  0.5,  3.7,  2.2),
+        (-0.5,  0.5, -3.7,  2.2),
+        (-0.5, -0.5, -3.7, -2.2),
+        ( 0.5, -0.5,  3.7, -2.2),
+        ( 7.1, -3.36, 4.62, -5.34),
+        (-4.9,  4.53, -5.76, 5.07),
+        (-2.5, -0.1, -2.5, -4.9),
+        (-0.2, -1.5, -6.8, -1.5),
+    ];
+    for &(x0,y0,x1,y1) in &rays {
+        let cells = grid_raycast(x0,y0,x1,y1);
+        assert_eq!(cells[0], [x0.floor() as i32, y0.floor() as i32], "{:?}", (x0,y0,x1,y1));
+        assert_eq!(*cells.last().unwrap(), [x1.floor() as i32, y1.floor() as i32], "{:?}", (x0,y0,x1,y1));
+        for k in 0..101 {
+            let t = k as f32/100.;
+            let cell = [(x0+(x1-x0)*t).floor() as i32, (y0+(y1-y0)*t).floor() as i32];
+            assert!(cells.contains(&cell), "{:?} {:?}", (x0,y0,x1,y1), cell);
+        }
+    }
+}
+
+
+#[test]
+fn test_physic_world_quadrants() {
+    use std::f32::consts::PI;
+
+    let mut world = specs::World::new();
+    let mut physic_world = PhysicWorld::new();
+    let positions = [[2.3,1.6], [-2.3,1.6], [-2.3,-1.6], [2.3,-1.6], [0.,-3.5], [-3.5,0.]];
+    let entities: Vec<specs::Entity> = positions.iter().map(|_| world.create_now().build()).collect();
+    for (entity, pos) in entities.iter().zip(positions.iter()) {
+        physic_world.insert_static(*entity, pos, 1, &Shape::Circle(0.4));
+    }
+
+    for (entity, pos) in entities.iter().zip(positions.iter()) {
+        let mut found = vec!();
+        physic_world.apply_on_shape(pos, 1u32, &Shape::Circle(0.1), &mut |e,_| found.push(*e));
+        assert_eq!(found, vec!(*entity));
+
+        // a ray from the origin hits the entity first, a ray going away misses it
+        let angle = pos[1].atan2(pos[0]);
+        let mut hits = vec!();
+        physic_world.raycast(&Ray { origin: [0.,0.], angle: angle, length: 10., mask: 1 }, &mut |(e,_,_)| {
+            hits.push(e);
+            true
+        });
+        assert_eq!(hits, vec!(*entity));
+
+        let mut hits = vec!();
+        physic_world.raycast(&Ray { origin: *pos, angle: angle + PI, length: 0.3, mask: 1 }, &mut |(e,_,_)| {
+            hits.push(e);
+            false
+        });
+        assert_eq!(hits, vec!(*entity));
+    }
+}
+
+
+#[test]
+fn test_raycast_options() {
+    let mut world = specs::World::new();
+    let mut physic_world = PhysicWorld::new();
+    let entities: Vec<specs::Entity> = (0..4).map(|i| {
+        let entity = world.create_now().build();
+        physic_world.insert_static(entity, &[i as f32 * 2., 0.5], 1, &Shape::Circle(0.4));
+        entity
+    }).collect();
+    let ray = Ray { origin: [0.,0.5], angle: 0., length: 10., mask: 1 };
+
+    let mut hits = vec!();
+    let options = RaycastOptions { exclude: &entities[..1], max_hits: Some(2), static_only: false };
+    physic_world.raycast_with(&ray, &options, &mut |(e,_,_)| {
+        hits.push(e);
+        false
+    });
+    assert_eq!(hits, vec!(entities[1], entities[2]));
+
+    let body = world.create_now().build();
+    physic_world.insert_movable(body, &[1.,0.5], 1, &Shape::Circle(0.4));
+    let mut hits = vec!();
+    let options = RaycastOptions { exclude: &entities[..1], max_hits: Some(1), static_only: true };
+    physic_world.raycast_with(&ray, &options, &mut |(e,_,_)| {
+        hits.push(e);
+        false
+    });
+    assert_eq!(hits, vec!(entities[1]));
+}
+
+#[test]
+fn test_dormant_bodies_out_of_physic_world() {
+    use trace;
+
+    let mut world = trace::new_world();
+    let shape = Shape::Circle(config.entities.monster_radius);
+    let sleeping = trace::add_body(&mut world, [0.5, 0.5]);
+    let mut lod = UpdateLod::new();
+    lod.dormant = true;
+    world.write::<UpdateLod>().insert(sleeping, lod);
+    // overlapping the dormant body
+    let awake = trace::add_body(&mut world, [0.7, 0.5]);
+    world.write_resource::<PhysicWorld>().fill(&world);
+
+    let mut planner = specs::Planner::new(world, 1);
+    app::add_system(&mut planner, PhysicSystem, "physic", "physic");
+    planner.dispatch(trace::new_context());
+    planner.wait();
+
+    let world = planner.mut_world();
+    let states = world.read::<PhysicState>();
+    assert_eq!(states.get(sleeping).unwrap().position, [0.5, 0.5]);
+    assert_eq!(states.get(awake).unwrap().position, [0.7, 0.5]);
+    let physic_world = world.read_resource::<PhysicWorld>();
+    let mut hashed = vec!();
+    physic_world.apply_on_shape(&[0.5, 0.5], config.entities.monster_group.val, &shape, &mut |entity, _| hashed.push(*entity));
+    assert_eq!(hashed, vec!(awake));
+}
+
+#[test]
+fn test_dormant_overlap_kept() {
+    use std::sync::mpsc;
+    use trace;
+
+    let mut world = trace::new_world();
+    let trigger = trace::add_body(&mut world, [0.5, 0.5]);
+    world.write::<PhysicTrigger>().insert(trigger, PhysicTrigger::new());
+    let other = trace::add_body(&mut world, [0.7, 0.5]);
+    world.write::<UpdateLod>().insert(other, UpdateLod::new());
+    for entity in &[trigger, other] {
+        world.write::<PhysicType>().get_mut(*entity).unwrap().mask = config.entities.monster_group.val;
+    }
+
+    let (event_tx, event_rx) = mpsc::channel();
+    let mut context = trace::new_context();
+    context.event_tx = event_tx;
+    let mut planner = specs::Planner::new(world, 1);
+    app::add_system(&mut planner, PhysicSystem, "physic", "physic");
+    planner.dispatch(context.clone());
+    planner.wait();
+    assert!(event_rx.try_iter().any(|event| match event {
+        app::GameEvent::OverlapEnter(a, b) => a == trigger && b == other,
+        _ => false,
+    }));
+
+    // out of the physic world once dormant but still overlapping
+    planner.mut_world().write::<UpdateLod>().get_mut(other).unwrap().dormant = true;
+    planner.dispatch(context);
+    planner.wait();
+    assert!(event_rx.try_iter().all(|event| match event {
+        app::GameEvent::OverlapEnter(_, _) | app::GameEvent::OverlapExit(_, _) => false,
+        _ => true,
+    }));
+    assert!(planner.mut_world().read::<PhysicTrigger>().get(trigger).unwrap().overlapping.contains(&other));
+}
+
+#[test]
+fn test_predict_trajectory() {
+    let mut world = specs::World::new();
+    let mut physic_world = PhysicWorld::new();
+    let wall = world.create_now().build();
+    physic_world.insert_static(wall, &[4.,0.], 1, &Shape::Square(0.5));
+
+    let typ = PhysicType::new_movable(2, 1, Shape::Circle(0.5), CollisionBehavior::Persist, 10., 0.1, 1.);
+    let force = PhysicForce { direction: 0., intensity: 1. };
+    let state = PhysicState::new([0.,0.]);
+    let trajectory = physic_world.predict_trajectory(&state, &typ, &force, 100, 0.02);
+
+    // the ghost moves toward the wall and stays against it
+    assert_eq!(trajectory.len(), 100);
+    assert!(trajectory[0][0] > 0.);
+    assert!(trajectory.iter().all(|p| p[0] <= 3. + 1e-3 && p[1] == 0.));
+    assert!((trajectory[99][0] - 3.).abs() < 1e-3);
+    assert_eq!(physic_world.movable_hashmap.len(), 0);
+}
+
+
+#[test]
+fn test_find_free_position() {
+    let mut world = specs::World::new();
+    let mut physic_world = PhysicWorld::new();
+    for pos in &[[0.,0.],[1.,0.],[-1.,0.],[0.,1.],[0.,-1.]] {
+        let wall = world.create_now().build();
+        physic_world.insert_static(wall, pos, 1, &Shape::Square(0.5));
+    }
+
+    let shape = Shape::Circle(0.4);
+    assert!(!physic_world.fits(&[0.,0.], &shape, 1u32));
+    assert!(physic_world.fits(&[0.,0.], &shape, 2u32));
+    assert!(physic_world.fits(&[1.,1.], &shape, 1u32));
+    assert_eq!(physic_world.find_free_position(&shape, [0.,0.], 0.5, 1u32), None);
+
+    let pos = physic_world.find_free_position(&shape, [0.,0.], 2., 1u32).unwrap();
+    assert!(physic_world.fits(&pos, &shape, 1u32));
+    assert!((pos[0].powi(2) + pos[1].powi(2)).sqrt() <= 2.);
+}
+
+#[test]
+fn test_shapecast() {
+    use std::f32::consts::PI;
+
+    let mut world = specs::World::new();
+    let mut physic_world = PhysicWorld::new();
+    let wall = world.create_now().build();
+    physic_world.insert_static(wall, &[5.,0.], 1, &Shape::Square(0.5));
+    let target = world.create_now().build();
+    physic_world.insert_static(target, &[2.1,0.], 2, &Shape::Circle(0.4));
+
+    let shape = Shape::Circle(0.4);
+    let hits = physic_world.shapecast(&[], &[0.,0.], 0., 10., &shape, 3u32);
+    assert_eq!(hits.iter().map(|&(e,_)| e).collect::<Vec<_>>(), vec!(target, wall));
+    assert!(hits[0].1 <= 2.1 - 0.8);
+    assert!(hits[1].1 <= 5. - 0.9 && hits[1].1 >= 5. - 0.9 - 0.4);
+
+    // touched at origin only when moving into it
+    let hits = physic_world.shapecast(&[], &[4.2,0.], PI, 1., &shape, 1u32);
+    assert!(hits.is_empty());
+    let hits = physic_world.shapecast(&[], &[4.2,0.], 0., 1., &shape, 1u32);
+    assert_eq!(hits, vec!((wall, 0.)));
+}
+
+
+#[test]
+fn test_teleport() {
+    let mut world = specs::World::new();
+    world.register::<PhysicState>();
+    world.register::<PhysicType>();
+    world.register::<PhysicStatic>();
+    world.register::<PhysicTeleported>();
+    world.register::<Interpolated>();
+    world.add_resource(PhysicWorld::new());
+
+    let shape = Shape::Circle(0.4);
+    let entity = world.create_now()
+        .with::<PhysicState>(PhysicState::new([0.,0.]))
+        .with::<PhysicType>(PhysicType::new_movable(1, 1, shape.clone(), CollisionBehavior::Persist, 10., 1., 1.))
+        .build();
+    world.write_resource::<PhysicWorld>().insert_movable(entity, &[0.,0.], 1, &shape);
+
+    teleport(&mut world, entity, [10.,-10.]);
+    assert_eq!(world.read::<PhysicState>().get(entity).unwrap().position, [10.,-10.]);
+    assert!(world.read::<PhysicTeleported>().get(entity).is_some());
+    let physic_world = world.read_resource::<PhysicWorld>();
+    assert!(physic_world.fits(&[0.,0.], &shape, 1u32));
+    assert!(!physic_world.fits(&[10.,-10.], &shape, 1u32));
+}
+
+#[test]
+fn test_validate() {
+    let mut world = specs::World::new();
+    world.register::<PhysicState>();
+    world.register::<PhysicType>();
+    world.register::<PhysicDynamic>();
+    world.register::<PhysicStatic>();
+    world.register::<UpdateLod>();
+    let mut physic_world = PhysicWorld::new();
+
+    let wall = world.create_now()
+        .with::<PhysicState>(PhysicState::new([2.,2.]))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(1, 1, Shape::Square(0.5)))
+        .build();
+    let body = world.create_now()
+        .with::<PhysicState>(PhysicState::new([0.,0.]))
+        .with::<PhysicDynamic>(PhysicDynamic)
+        .with::<PhysicType>(PhysicType::new_movable(1, 1, Shape::Circle(0.4), CollisionBehavior::Persist, 10., 1., 1.))
+        .build();
+    physic_world.fill(&world);
+    assert!(physic_world.validate(&world).is_empty());
+
+    {
+        let mut states = world.write::<PhysicState>();
+        let state = states.get_mut(body).unwrap();
+        state.position = [5.,5.];
+        state.velocity = [f32::NAN,0.];
+    }
+    world.delete_now(wall);
+    // NaN velocity, body out of its cells and deleted wall
+    assert_eq!(physic_world.validate(&world).len(), 3);
+}
+
+#[test]
+fn test_explode() {
+    let mut world = specs::World::new();
+    let mut physic_world = PhysicWorld::new();
+    let near = world.create_now().build();
+    let far = world.create_now().build();
+    let hidden = world.create_now().build();
+    let wall = world.create_now().build();
+    physic_world.insert_movable(near, &[1.,0.], 1, &Shape::Circle(0.4));
+    physic_world.insert_movable(far, &[3.,0.], 1, &Shape::Circle(0.4));
+    physic_world.insert_movable(hidden, &[0.,-3.], 1, &Shape::Circle(0.4));
+    physic_world.insert_static(wall, &[0.,-1.5], 2, &Shape::Square(0.5));
+
+    let hits = physic_world.explode([0.,0.], 4., 1., 1u32, 2u32);
+    assert_eq!(hits.len(), 3);
+    let intensity = |entity| hits.iter().find(|&&(e,_)| e == entity).unwrap().1;
+    assert_eq!(intensity(near), 0.75);
+    assert_eq!(intensity(far), 0.25);
+    assert_eq!(intensity(hidden), 0.25*config.physic.explosion_occlusion);
+}
+
+
+/// number of random cases checked by each geometry property
+#[cfg(test)]
+const PROPERTY_CASES: usize = 10000;
+/// seed of the geometry properties, RUGA_PROPERTY_SEED overrides it
+#[cfg(test)]
+const PROPERTY_SEED: u32 = 0x5eed;
+
+/// prints the seed if the property fails
+#[cfg(test)]
+struct PropertySeed(u32);
+#[cfg(test)]
+impl Drop for PropertySeed {
+    fn drop(&mut self) {
+        if ::std::thread::panicking() {
+            println!("property failed with seed {}, replay it with RUGA_PROPERTY_SEED={}", self.0, self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+fn property_rng() -> (PropertySeed, ::rng::WorldRng) {
+    let seed = ::std::env::var("RUGA_PROPERTY_SEED").ok().and_then(|s| s.parse().ok()).unwrap_or(PROPERTY_SEED);
+    (PropertySeed(seed), ::rng::WorldRng::from_seed(seed))
+}
+
+#[cfg(test)]
+fn random_shape<R: ::rand::Rng>(rng: &mut R) -> Shape {
+    let radius = rng.gen_range(0.05, 2.);
+    if rng.gen() { Shape::Circle(radius) } else { Shape::Square(radius) }
+}
+
+#[cfg(test)]
+fn random_position<R: ::rand::Rng>(rng: &mut R) -> [f32;2] {
+    [rng.gen_range(-10., 10.), rng.gen_range(-10., 10.)]
+}
+
+/// the line ax + by + c = 0 through a random point with a random angle, built as in raycast
+#[cfg(test)]
+fn random_line<R: ::rand::Rng>(rng: &mut R) -> (f32,f32,f32) {
+    use std::f32::consts::PI;
+
+    let origin = random_position(rng);
+    let angle = if rng.gen_weighted_bool(10) { 0. } else { rng.gen_range(-PI, PI) };
+    if angle == 0. {
+        (0.,1.,-origin[1])
+    } else {
+        let b = -1./angle.tan();
+        (1.,b,-origin[0]-b*origin[1])
+    }
+}
+
+#[test]
+fn property_raycast_intersections_on_line_and_body() {
+    let (_seed, mut rng) = property_rng();
+    for _ in 0..PROPERTY_CASES {
+        let pos = random_position(&mut rng);
+        let shape = random_shape(&mut rng);
+        let (a,b,c) = random_line(&mut rng);
+        let (intersections, radius) = match shape {
+            Shape::Circle(r) => (circle_raycast(pos[0],pos[1],r,a,b,c), r),
+            Shape::Square(r) => (bounding_box_raycast(pos[0],pos[1],r*2.,r*2.,a,b,c), r),
+        };
+
+        if let Some((x_min,y_min,x_max,y_max)) = intersections {
+            assert!(x_min <= x_max, "{:?} {:?} {:?}", pos, shape, (a,b,c));
+            for &(x,y) in &[(x_min,y_min),(x_max,y_max)] {
+                let eps = 1e-3 * (1. + a.abs() + b.abs() + c.abs());
+                assert!((a*x + b*y + c).abs() <= eps, "{:?} {:?} {:?} {:?}", pos, shape, (a,b,c), (x,y));
+
+                let eps = 1e-3 * (1. + radius);
+                match shape {
+                    Shape::Circle(r) => assert!(((x-pos[0]).powi(2) + (y-pos[1]).powi(2)).sqrt() <= r + eps,
+                                                "{:?} {:?} {:?} {:?}", pos, shape, (a,b,c), (x,y)),
+                    Shape::Square(r) => assert!((x-pos[0]).abs() <= r + eps && (y-pos[1]).abs() <= r + eps,
+                                                "{:?} {:?} {:?} {:?}", pos, shape, (a,b,c), (x,y)),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn property_shape_collide_symmetric() {
+    use rand::Rng;
+
+    let (_seed, mut rng) = property_rng();
+    for _ in 0..PROPERTY_CASES {
+        let a_pos = random_position(&mut rng);
+        let b_pos = [a_pos[0] + rng.gen_range(-4., 4.), a_pos[1] + rng.gen_range(-4., 4.)];
+        let a_shape = random_shape(&mut rng);
+        let b_shape = random_shape(&mut rng);
+
+        match (shape_collide(&a_pos,&a_shape,&b_pos,&b_shape), shape_collide(&b_pos,&b_shape,&a_pos,&a_shape)) {
+            (Some(ab), Some(ba)) => {
+                assert!((ab.delta_x + ba.delta_x).abs() < 1e-4 && (ab.delta_y + ba.delta_y).abs() < 1e-4,
+                        "{:?} {:?} {:?} {:?}", a_pos, a_shape, b_pos, b_shape);
+            },
+            (None, None) => (),
+            _ => panic!("asymmetric collision {:?} {:?} {:?} {:?}", a_pos, a_shape, b_pos, b_shape),
+        }
+    }
+}
+
+#[test]
+fn property_collision_resolution_reduces_penetration() {
+    use rand::Rng;
+
+    let (_seed, mut rng) = property_rng();
+    for _ in 0..PROPERTY_CASES {
+        let a_pos = random_position(&mut rng);
+        let b_pos = [a_pos[0] + rng.gen_range(-4., 4.), a_pos[1] + rng.gen_range(-4., 4.)];
+        let a_shape = random_shape(&mut rng);
+        let b_shape = random_shape(&mut rng);
+
+        if let Some(collision) = shape_collide(&a_pos,&a_shape,&b_pos,&b_shape) {
+            let before = (collision.delta_x.powi(2) + collision.delta_y.powi(2)).sqrt();
+            let resolved = [a_pos[0] + collision.delta_x, a_pos[1] + collision.delta_y];
+            let after = shape_collide(&resolved,&a_shape,&b_pos,&b_shape)
+                .map(|c| (c.delta_x.powi(2) + c.delta_y.powi(2)).sqrt())
+                .unwrap_or(0.);
+            assert!(after < before || after < 1e-4, "{:?} {:?} {:?} {:?}: {} -> {}", a_pos, a_shape, b_pos, b_shape, before, after);
+        }
+    }
+}
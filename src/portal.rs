@@ -17,6 +17,9 @@ impl Portal {
             destination: destination,
         }
     }
+    pub fn destination(&self) -> &levels::Level {
+        &self.destination
+    }
 }
 
 pub struct PortalSystem;
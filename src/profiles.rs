@@ -0,0 +1,185 @@
+//! the profiles of the players, not to mistake for the controls profiles: each profile
+//! has its own save.toml, controls profiles, stats and achievements in
+//! `profiles/<name>` of the settings directory. `profiles.toml` there lists the
+//! profiles and the one selected.
+//!
+//! the profile of a session is the one of the `--profile <name>` argument, created if
+//! it doesn't exist, or the one selected. its save.toml is loaded with the
+//! configuration so a profile selected in the menu is used from the next start.
+//! `--remove-profile <name>` removes a profile but the selected one.
+
+use configuration::{self, FromToml, IntoToml};
+use settings;
+use toml;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub type VecString = Vec<String>;
+
+const FILE: &'static str = "profiles.toml";
+pub const DEFAULT: &'static str = "default";
+
+lazy_static! {
+    /// the profile of the session
+    pub static ref SESSION: String = session();
+}
+
+fn session() -> String {
+    let mut profiles = Profiles::load(&settings::dir());
+    let mut args = env::args().skip_while(|arg| arg != "--profile").skip(1);
+    if let Some(name) = args.next() {
+        let result = if profiles.names().contains(&name) {
+            Ok(())
+        } else {
+            profiles.create(&name)
+        };
+        if let Err(err) = result.and_then(|_| profiles.select(&name)) {
+            println!("WARNING: profile {} not used: {}", name, err);
+        }
+    }
+    profiles.current().into()
+}
+
+/// the profiles.toml file
+struct ProfilesFile {
+    names: VecString,
+    current: String,
+}
+impl_from_into_toml_for_struct!( ProfilesFile {
+    names: VecString,
+    current: String,
+});
+
+pub struct Profiles {
+    root: PathBuf,
+    names: Vec<String>,
+    current: String,
+}
+
+impl Profiles {
+    /// the profiles in root, only the default one if there is none
+    pub fn load(root: &Path) -> Self {
+        let read = |path: &Path| {
+            let mut string = String::new();
+            try!(try!(fs::File::open(path).map_err(|e| format!("open profiles failed: {}",e)))
+                 .read_to_string(&mut string)
+                 .map_err(|_| String::from("profiles invalid: not valid UTF-8")));
+            let mut parser = toml::Parser::new(&*string);
+            let table = try!(parser.parse().ok_or(String::from("profiles invalid: toml parsing failed")));
+            let file = try!(ProfilesFile::from_toml(&toml::Value::Table(table)).map_err(|e| format!("profiles invalid:{}",e)));
+            if !file.names.contains(&file.current) {
+                return Err(format!("profiles invalid: {} is not a profile", file.current));
+            }
+            Ok(file)
+        };
+        let file = configuration::read_recovering(&root.join(FILE), read).unwrap_or(ProfilesFile {
+            names: vec!(DEFAULT.into()),
+            current: DEFAULT.into(),
+        });
+        Profiles {
+            root: root.into(),
+            names: file.names,
+            current: file.current,
+        }
+    }
+
+    fn save(&self) -> Result<(),String> {
+        let file = ProfilesFile {
+            names: self.names.clone(),
+            current: self.current.clone(),
+        };
+        configuration::write_atomic(&self.root.join(FILE), &*format!("{}",IntoToml::into_toml(file)))
+            .map_err(|e| format!("write profiles failed: {}",e))
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// the profile selected
+    pub fn current(&self) -> &str {
+        &*self.current
+    }
+
+    /// the directory of the files of the profile
+    pub fn dir(&self, name: &str) -> PathBuf {
+        dir(&self.root, name)
+    }
+
+    pub fn select(&mut self, name: &str) -> Result<(),String> {
+        if !self.names.iter().any(|n| n == name) {
+            return Err(format!("{} is not a profile", name));
+        }
+        self.current = name.into();
+        self.save()
+    }
+
+    /// the name is made of letters, digits, '-' and '_'
+    pub fn create(&mut self, name: &str) -> Result<(),String> {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("{} is not a valid name: expect letters, digits, - or _", name));
+        }
+        if self.names.iter().any(|n| n == name) {
+            return Err(format!("{} is already a profile", name));
+        }
+        try!(fs::create_dir_all(self.dir(name)).map_err(|e| format!("create profile {} failed: {}",name,e)));
+        self.names.push(name.into());
+        self.save()
+    }
+
+    /// remove the profile and its files, but the selected one
+    pub fn remove(&mut self, name: &str) -> Result<(),String> {
+        if name == self.current {
+            return Err(format!("{} is the profile selected", name));
+        }
+        let index = try!(self.names.iter().position(|n| n == name).ok_or(format!("{} is not a profile", name)));
+        let dir = self.dir(name);
+        if dir.exists() {
+            try!(fs::remove_dir_all(&dir).map_err(|e| format!("remove profile {} failed: {}",name,e)));
+        }
+        self.names.remove(index);
+        self.save()
+    }
+
+    /// select the next profile in the order of the names, or the previous one
+    pub fn cycle(&mut self, offset: isize) -> Result<(),String> {
+        let count = self.names.len() as isize;
+        let index = self.names.iter().position(|n| *n == self.current).unwrap_or(0) as isize;
+        let name = self.names[((index + offset)%count + count) as usize % count as usize].clone();
+        self.select(&*name)
+    }
+}
+
+fn dir(root: &Path, name: &str) -> PathBuf {
+    root.join("profiles").join(name)
+}
+
+/// the path of the file of the profile of the session
+pub fn file(relative: &Path) -> PathBuf {
+    dir(&settings::dir(), &*SESSION).join(relative)
+}
+
+#[test]
+fn test_profiles() {
+    let root = env::temp_dir().join("ruga_test_profiles");
+    let _ = fs::remove_dir_all(&root);
+
+    let mut profiles = Profiles::load(&root);
+    assert_eq!(profiles.names(), &[String::from(DEFAULT)]);
+    assert!(profiles.create("bad name").is_err());
+    profiles.create("second").unwrap();
+    assert!(profiles.create("second").is_err());
+    profiles.cycle(1).unwrap();
+    assert_eq!(profiles.current(), "second");
+    assert!(profiles.remove("second").is_err());
+
+    let mut profiles = Profiles::load(&root);
+    assert_eq!(profiles.current(), "second");
+    assert!(profiles.dir("second").is_dir());
+    profiles.select(DEFAULT).unwrap();
+    profiles.remove("second").unwrap();
+    assert_eq!(profiles.names(), &[String::from(DEFAULT)]);
+    let _ = fs::remove_dir_all(&root);
+}
@@ -0,0 +1,209 @@
+//! experience and stats of the entities.
+//!
+//! entities with Experience gain the xp of the kind of each entity they kill and level
+//! up at the thresholds of progression.levels. the stats of entities with Attributes
+//! are their base value plus modifiers of their level, of the items they hold, of
+//! status effects and of the bodies they grab; health is the maximum health of their life, damage the amount of
+//! their hits and speed the factor of their velocity.
+
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use components::*;
+use specs::Join;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Stat {
+    Health,
+    Damage,
+    Speed,
+}
+impl_from_into_toml_for_enum!(Stat {
+    Health,
+    Damage,
+    Speed,
+});
+impl Stat {
+    fn index(&self) -> usize {
+        match *self {
+            Stat::Health => 0,
+            Stat::Damage => 1,
+            Stat::Speed => 2,
+        }
+    }
+}
+
+/// * add: added to the base value
+/// * multiply: factor of the value, factors of modifiers multiply together
+#[derive(Debug,Clone,PartialEq)]
+pub struct Modifier {
+    pub stat: Stat,
+    pub add: f32,
+    pub multiply: f32,
+}
+impl_from_into_toml_for_struct!( Modifier {
+    stat: Stat,
+    add: f32,
+    multiply: f32,
+});
+
+/// what a modifier comes from
+#[derive(Debug,Clone,PartialEq)]
+pub enum Source {
+    Level,
+    /// index of the item in items.definitions
+    Item(usize),
+    /// name of the status in abilities.statuses
+    Status(String),
+    /// the body held by a grabber
+    Grab,
+    /// the phase of a boss
+    Boss,
+}
+
+pub struct Attributes {
+    /// base values by stat
+    base: [f32;3],
+    modifiers: Vec<(Source,Modifier)>,
+    /// force of the physic type without the speed factor
+    base_force: Option<f32>,
+}
+impl specs::Component for Attributes {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Attributes {
+    /// the base speed factor is 1
+    pub fn new(health: f32, damage: f32) -> Self {
+        Attributes {
+            base: [health, damage, 1.],
+            modifiers: vec!(),
+            base_force: None,
+        }
+    }
+
+    /// base value plus the adds of modifiers, multiplied by their factors
+    pub fn value(&self, stat: Stat) -> f32 {
+        let (add, multiply) = self.modifiers.iter()
+            .filter(|&&(_, ref modifier)| modifier.stat == stat)
+            .fold((0., 1.), |(add, multiply), &(_, ref modifier)| (add + modifier.add, multiply*modifier.multiply));
+        (self.base[stat.index()] + add)*multiply
+    }
+
+    /// replace the modifiers of the source
+    pub fn set_modifiers(&mut self, source: Source, modifiers: Vec<Modifier>) {
+        self.modifiers.retain(|&(ref s, _)| *s != source);
+        self.modifiers.extend(modifiers.into_iter().map(|modifier| (source.clone(), modifier)));
+    }
+}
+
+pub struct Experience {
+    pub xp: f32,
+    pub level: usize,
+}
+impl specs::Component for Experience {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Experience {
+    pub fn new() -> Self {
+        Experience {
+            xp: 0.,
+            level: 1,
+        }
+    }
+
+    /// add xp, return whether the entity leveled up
+    pub fn gain(&mut self, xp: f32) -> bool {
+        self.xp += xp;
+        let level = config.progression.levels.iter().filter(|&&threshold| self.xp >= threshold).count().max(1);
+        let leveled_up = level > self.level;
+        self.level = level;
+        leveled_up
+    }
+}
+
+/// the damage dealt by an attacker, 1 without attributes
+pub fn damage(attributes: Option<&Attributes>) -> f32 {
+    attributes.map(|a| a.value(Stat::Damage)).unwrap_or(1.)
+}
+
+/// the modifiers of progression.level_modifiers applied once per level above the first
+fn level_modifiers(level: usize) -> Vec<Modifier> {
+    let levels = level.saturating_sub(1) as i32;
+    config.progression.level_modifiers.iter().map(|modifier| Modifier {
+        stat: modifier.stat,
+        add: modifier.add*levels as f32,
+        multiply: modifier.multiply.powi(levels),
+    }).collect()
+}
+
+/// resolve the modifiers of levels, items and statuses and apply the stats to life and physic
+pub struct ProgressionSystem;
+impl specs::System<::app::UpdateContext> for ProgressionSystem {
+    fn run(&mut self, arg: specs::RunArg, _context: ::app::UpdateContext) {
+        let (mut attributes, experiences, inventories, statuses, mut lives, mut types, entities) = arg.fetch(|world| {
+            (
+                world.write::<Attributes>(),
+                world.read::<Experience>(),
+                world.read::<Inventory>(),
+                world.read::<Statuses>(),
+                world.write::<Life>(),
+                world.write::<PhysicType>(),
+                world.entities(),
+            )
+        });
+
+        for (attributes, entity) in (&mut attributes, &entities).iter() {
+            if let Some(experience) = experiences.get(entity) {
+                attributes.set_modifiers(Source::Level, level_modifiers(experience.level));
+            }
+            if let Some(inventory) = inventories.get(entity) {
+                for (item, definition) in config.items.definitions.iter().enumerate() {
+                    let held = inventory.slots().iter().any(|slot| slot.map(|stack| stack.item == item).unwrap_or(false));
+                    let modifiers = if held { definition.modifiers.clone() } else { vec!() };
+                    attributes.set_modifiers(Source::Item(item), modifiers);
+                }
+            }
+            if let Some(statuses) = statuses.get(entity) {
+                for (status, definition) in config.abilities.statuses.iter().enumerate() {
+                    let modifiers = if statuses.is_active(status) { definition.modifiers.clone() } else { vec!() };
+                    attributes.set_modifiers(Source::Status(definition.name.clone()), modifiers);
+                }
+            }
+
+            if let Some(life) = lives.get_mut(entity) {
+                life.set_max_health(attributes.value(Stat::Health));
+            }
+            if let Some(typ) = types.get_mut(entity) {
+                let base_force = *attributes.base_force.get_or_insert(typ.force);
+                typ.force = base_force*attributes.value(Stat::Speed);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_attributes() {
+    let mut attributes = Attributes::new(10., 1.);
+    assert_eq!(attributes.value(Stat::Speed), 1.);
+
+    attributes.set_modifiers(Source::Level, vec!(
+        Modifier { stat: Stat::Health, add: 5., multiply: 1. },
+        Modifier { stat: Stat::Damage, add: 0., multiply: 2. },
+    ));
+    attributes.set_modifiers(Source::Item(0), vec!(Modifier { stat: Stat::Health, add: 0., multiply: 2. }));
+    assert_eq!(attributes.value(Stat::Health), 30.);
+    assert_eq!(attributes.value(Stat::Damage), 2.);
+
+    attributes.set_modifiers(Source::Level, vec!());
+    assert_eq!(attributes.value(Stat::Health), 20.);
+    assert_eq!(attributes.value(Stat::Damage), 1.);
+
+    let modifiers = level_modifiers(1);
+    assert!(modifiers.iter().all(|m| m.add == 0. && m.multiply == 1.));
+
+    let mut experience = Experience::new();
+    assert!(!experience.gain(0.));
+    assert!(experience.gain(config.progression.levels[1]));
+    assert_eq!(experience.level, 2);
+}
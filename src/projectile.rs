@@ -0,0 +1,182 @@
+//! motion profiles of the projectiles defined in projectiles.profiles of the
+//! configuration, each weapon fires projectiles of a profile.
+//!
+//! the direction of a projectile turns toward its target at most at the turn rate of
+//! its profile, and is offset by a wave and a spiral. its intensity follows the
+//! acceleration curve and it detonates when an entity it kills comes close.
+
+use app;
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use baal;
+use components::*;
+use resource::*;
+use specs::Join;
+use utils::{minus_pi_pi, Into3D};
+use combat::{Damage, DamageType};
+use progression;
+use entity_ref::EntityRef;
+use std::f32::consts::PI;
+
+pub type VecArray2F32 = Vec<[f32;2]>;
+
+/// * homing_turn_rate: degrees per second the projectile turns toward its target,
+///   0 to go straight
+/// * wave_amplitude: degrees the direction oscillates by
+/// * wave_frequency: oscillations per second
+/// * spiral_rate: degrees per second the direction rotates by
+/// * acceleration: [time, intensity] points of the intensity of the force over time,
+///   linear between them, full intensity if empty
+/// * detonation_radius: distance to an entity it kills the projectile detonates at,
+///   0 to detonate only on contact
+pub struct MotionProfile {
+    pub name: String,
+    pub homing_turn_rate: f32,
+    pub wave_amplitude: f32,
+    pub wave_frequency: f32,
+    pub spiral_rate: f32,
+    pub acceleration: VecArray2F32,
+    pub detonation_radius: f32,
+}
+impl_from_into_toml_for_struct!( MotionProfile {
+    name: String,
+    homing_turn_rate: f32,
+    wave_amplitude: f32,
+    wave_frequency: f32,
+    spiral_rate: f32,
+    acceleration: VecArray2F32,
+    detonation_radius: f32,
+});
+
+impl MotionProfile {
+    /// the intensity of the force at time
+    fn intensity(&self, time: f32) -> f32 {
+        let curve = &self.acceleration;
+        match curve.iter().position(|point| point[0] > time) {
+            None => curve.last().map(|point| point[1]).unwrap_or(1.),
+            Some(0) => curve[0][1],
+            Some(i) => {
+                let (a, b) = (curve[i-1], curve[i]);
+                a[1] + (b[1] - a[1])*(time - a[0])/(b[0] - a[0])
+            },
+        }
+    }
+
+    /// the offset of the direction at time
+    fn offset(&self, time: f32) -> f32 {
+        let wave = self.wave_amplitude*(2.*PI*self.wave_frequency*time).sin();
+        (wave + self.spiral_rate*time).to_radians()
+    }
+}
+
+/// the index of the profile named name
+pub fn profile(name: &str) -> Option<usize> {
+    config.projectiles.profiles.iter().position(|p| p.name == name)
+}
+
+pub struct Motion {
+    profile: usize,
+    /// the direction without the offsets
+    heading: f32,
+    time: f32,
+    pub target: EntityRef,
+}
+impl specs::Component for Motion {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Motion {
+    pub fn new(profile: usize, heading: f32, target: Option<specs::Entity>) -> Self {
+        Motion {
+            profile: profile,
+            heading: heading,
+            time: 0.,
+            target: EntityRef::new(target),
+        }
+    }
+    pub fn heading(&self) -> f32 {
+        self.heading
+    }
+    /// turn the heading by angle, when refracted
+    pub fn turn(&mut self, angle: f32) {
+        self.heading += angle;
+    }
+}
+
+pub struct MotionSystem;
+impl specs::System<app::UpdateContext> for MotionSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut motions, mut forces, mut lives, states, killers, factions, attributes, physic_world, relations, entities) = arg.fetch(|world| {
+            (
+                world.write::<Motion>(),
+                world.write::<PhysicForce>(),
+                world.write::<Life>(),
+                world.read::<PhysicState>(),
+                world.read::<Killer>(),
+                world.read::<Faction>(),
+                world.read::<Attributes>(),
+                world.read_resource::<PhysicWorld>(),
+                world.read_resource::<Relations>(),
+                world.entities(),
+            )
+        });
+
+        for (motion, force, state, entity) in (&mut motions, &mut forces, &states, &entities).iter() {
+            let profile = &config.projectiles.profiles[motion.profile];
+            motion.time += context.dt;
+
+            let target = motion.target.check(|target| lives.get(target).map(|life| life.is_alive()).unwrap_or(false));
+            if let Some(target_state) = target.and_then(|target| states.get(target)) {
+                let pos = state.position;
+                let angle = (target_state.position[1] - pos[1]).atan2(target_state.position[0] - pos[0]);
+                let max_turn = profile.homing_turn_rate.to_radians()*context.dt;
+                motion.heading += minus_pi_pi(angle - motion.heading).max(-max_turn).min(max_turn);
+            }
+            force.direction = motion.heading + profile.offset(motion.time);
+            force.intensity = profile.intensity(motion.time);
+
+            if profile.detonation_radius <= 0. { continue }
+            let killer = match killers.get(entity) {
+                Some(killer) => killer,
+                None => continue,
+            };
+            let mut victims = vec!();
+            physic_world.apply_on_shape_except(&[entity], &state.position, killer.mask, &Shape::Circle(profile.detonation_radius), &mut |other_entity,_| {
+                if lives.get(*other_entity).is_some() && relations.harms(factions.get(entity), factions.get(*other_entity)) {
+                    victims.push(*other_entity);
+                }
+            });
+            if victims.is_empty() { continue }
+
+            baal::effect::short::play(killer.kill_snd,state.position.into_3d());
+            let amount = progression::damage(attributes.get(entity));
+            for victim in victims {
+                lives.get_mut(victim).unwrap().damage(amount, entity);
+                let position = states.get(victim).map(|s| s.position).unwrap_or(state.position);
+                context.event_tx.send(app::GameEvent::Damaged(Damage::new(entity, victim, position, amount, DamageType::Explosion))).unwrap();
+            }
+            if let Some(life) = lives.get_mut(entity) {
+                life.kill();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_motion_profile() {
+    let profile = MotionProfile {
+        name: "test".into(),
+        homing_turn_rate: 0.,
+        wave_amplitude: 90.,
+        wave_frequency: 1.,
+        spiral_rate: 180.,
+        acceleration: vec!([0.5, 0.], [1.5, 1.]),
+        detonation_radius: 0.,
+    };
+    assert_eq!(profile.intensity(0.), 0.);
+    assert_eq!(profile.intensity(1.), 0.5);
+    assert_eq!(profile.intensity(2.), 1.);
+    assert!((profile.offset(0.25) - (PI/2. + PI/4.)).abs() < 1e-4);
+    assert!((profile.offset(1.) - PI).abs() < 1e-4);
+}
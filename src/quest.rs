@@ -0,0 +1,123 @@
+use app::GameEvent;
+use life::Kind;
+use config;
+use configuration::{self, FromToml};
+use toml;
+
+pub type VecStageDefinition = Vec<StageDefinition>;
+
+/// a stage is completed when its objective progress reach count:
+/// * `kill`: entities of kind target killed by the player
+/// * `pickup`: items named target picked up by the player
+/// * `reach`: checkpoint reached if target is `checkpoint` otherwise room or region named target entered
+/// * `survive`: seconds without the player dying, target is ignored
+/// * `defeat`: boss named target defeated
+pub struct StageDefinition {
+    pub description: String,
+    pub objective: String,
+    pub target: String,
+    pub count: f32,
+}
+impl_from_into_toml_for_struct!( StageDefinition {
+    description: String,
+    objective: String,
+    target: String,
+    count: f32,
+});
+
+/// stages are completed one after the other,
+/// the reward is given to the player on completion of the last one
+pub struct QuestDefinition {
+    pub name: String,
+    pub stages: VecStageDefinition,
+    pub reward_item: usize,
+    pub reward_count: usize,
+}
+impl_from_into_toml_for_struct!( QuestDefinition {
+    name: String,
+    stages: VecStageDefinition,
+    reward_item: usize,
+    reward_count: usize,
+});
+
+struct QuestState {
+    stage: usize,
+    progress: f32,
+}
+
+/// progress of the quests defined in configuration
+pub struct Journal {
+    quests: Vec<QuestState>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal {
+            quests: config.quests.definitions.iter().map(|_| QuestState { stage: 0, progress: 0. }).collect(),
+        }
+    }
+
+    /// a line for each quest in progress
+    pub fn entries(&self) -> Vec<String> {
+        let mut entries = vec!();
+        for (state, definition) in self.quests.iter().zip(config.quests.definitions.iter()) {
+            if let Some(stage) = definition.stages.get(state.stage) {
+                entries.push(format!("{}: {} ({}/{})",definition.name,stage.description,state.progress.floor(),stage.count));
+            }
+        }
+        entries
+    }
+
+    /// add value to the current stages with this objective and target and
+    /// return the quest events produced
+    pub fn progress(&mut self, objective: &str, target: &str, value: f32) -> Vec<GameEvent> {
+        let mut events = vec!();
+        for (quest, (state, definition)) in self.quests.iter_mut().zip(config.quests.definitions.iter()).enumerate() {
+            let completed = match definition.stages.get(state.stage) {
+                Some(stage) if stage.objective == objective && (objective == "survive" || stage.target == target) => {
+                    state.progress += value;
+                    state.progress >= stage.count
+                },
+                _ => false,
+            };
+
+            if completed {
+                events.push(GameEvent::QuestStageCompleted(quest,state.stage));
+                state.stage += 1;
+                state.progress = 0.;
+                if state.stage == definition.stages.len() {
+                    events.push(GameEvent::QuestCompleted(quest));
+                }
+            }
+        }
+        events
+    }
+
+    pub fn update(&mut self, dt: f32) -> Vec<GameEvent> {
+        self.progress("survive", "", dt)
+    }
+
+    /// update the stages from a gameplay event and
+    /// return the quest events produced
+    pub fn handle(&mut self, event: &GameEvent, is_player: bool) -> Vec<GameEvent> {
+        match *event {
+            GameEvent::Died(_,Kind::Character,_) if is_player => {
+                for (state, definition) in self.quests.iter_mut().zip(config.quests.definitions.iter()) {
+                    if definition.stages.get(state.stage).map(|s| s.objective == "survive").unwrap_or(false) {
+                        state.progress = 0.;
+                    }
+                }
+                vec!()
+            },
+            GameEvent::Killed(_,_,kind) if is_player => self.progress("kill", kind.name(), 1.),
+            GameEvent::ItemPickedUp(_,stack) if is_player => {
+                let name = config.items.definitions.get(stack.item).map(|d| &*d.name).unwrap_or("?");
+                self.progress("pickup", name, stack.count as f32)
+            },
+            GameEvent::CheckpointReached(_,_) if is_player => self.progress("reach", "checkpoint", 1.),
+            GameEvent::RegionEntered(_,ref name) if is_player => self.progress("reach", name, 1.),
+            GameEvent::BossEncounterEnded(boss,true) => self.progress("defeat", &config.bosses.definitions[boss].name, 1.),
+            _ => vec!(),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! named regions of a level, the region entities with a RegionTracker are in is
+//! followed and RegionEntered and RegionExited events are sent when it changes.
+
+use app;
+use specs;
+use configuration::{self, FromToml};
+use toml;
+use components::*;
+use specs::Join;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub type Array2Isize = [isize;2];
+
+/// a rectangle of size tiles from position (map rows are negative),
+/// rectangles of the same name make a region of any shape
+pub struct RegionDefinition {
+    pub name: String,
+    pub position: Array2Isize,
+    pub size: Array2Isize,
+}
+impl_from_into_toml_for_struct!( RegionDefinition {
+    name: String,
+    position: Array2Isize,
+    size: Array2Isize,
+});
+
+/// the rectangles of the regions: name, lowest and highest corners
+pub struct Regions {
+    rectangles: Vec<(String,[f32;2],[f32;2])>,
+}
+
+impl Regions {
+    pub fn new() -> Self {
+        Regions {
+            rectangles: vec!(),
+        }
+    }
+
+    /// tiles are centred on integer positions
+    fn add(&mut self, definition: RegionDefinition) {
+        let min = [definition.position[0] as f32 - 0.5, (definition.position[1] - definition.size[1]) as f32 + 0.5];
+        let max = [min[0] + definition.size[0] as f32, min[1] + definition.size[1] as f32];
+        self.rectangles.push((definition.name, min, max));
+    }
+
+    /// the name of the region at position, the first defined if they overlap
+    pub fn region_at(&self, position: [f32;2]) -> Option<&str> {
+        self.rectangles.iter()
+            .find(|&&(_, min, max)| {
+                position[0] >= min[0] && position[0] < max[0]
+                    && position[1] >= min[1] && position[1] < max[1]
+            })
+            .map(|&(ref name, _, _)| &**name)
+    }
+}
+
+/// the regions defined in the file if it exists:
+/// a toml file with an array of tables `regions`
+pub fn load(path: &Path) -> Result<Regions,String> {
+    let mut regions = Regions::new();
+    if !path.is_file() {
+        return Ok(regions);
+    }
+
+    let mut string = String::new();
+    try!(try!(fs::File::open(&path).map_err(|e| format!("open regions failed: {}",e)))
+         .read_to_string(&mut string)
+         .map_err(|_| String::from("regions invalid: not valid UTF-8")));
+
+    let mut parser = toml::Parser::new(&*string);
+    let table = try!(parser.parse().ok_or(format!("regions {:?} invalid: toml parsing failed",path)));
+    let definitions = try!(table.get("regions").ok_or(format!("regions {:?} invalid: expect regions",path)));
+    let definitions: Vec<RegionDefinition> = try!(FromToml::from_toml(definitions).map_err(|e| format!("regions {:?} invalid: regions{}",path,e)));
+
+    for (i, definition) in definitions.into_iter().enumerate() {
+        if definition.size[0] <= 0 || definition.size[1] <= 0 {
+            return Err(format!("regions {:?} invalid: regions[{}].size must be positive",path,i));
+        }
+        regions.add(definition);
+    }
+    Ok(regions)
+}
+
+/// an entity whose region is followed
+pub struct RegionTracker {
+    region: Option<String>,
+}
+impl specs::Component for RegionTracker {
+    type Storage = specs::VecStorage<Self>;
+}
+impl RegionTracker {
+    pub fn new() -> Self {
+        RegionTracker {
+            region: None,
+        }
+    }
+}
+
+pub struct RegionSystem;
+impl specs::System<app::UpdateContext> for RegionSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (regions, mut trackers, states, entities) = arg.fetch(|world| {
+            (
+                world.read_resource::<Regions>(),
+                world.write::<RegionTracker>(),
+                world.read::<PhysicState>(),
+                world.entities(),
+            )
+        });
+
+        for (tracker, state, entity) in (&mut trackers, &states, &entities).iter() {
+            let region = regions.region_at(state.position);
+            if region == tracker.region.as_ref().map(|r| &**r) {
+                continue;
+            }
+            if let Some(previous) = tracker.region.take() {
+                context.event_tx.send(app::GameEvent::RegionExited(entity, previous)).unwrap();
+            }
+            if let Some(region) = region {
+                context.event_tx.send(app::GameEvent::RegionEntered(entity, region.into())).unwrap();
+                tracker.region = Some(region.into());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_region_at() {
+    let mut regions = Regions::new();
+    regions.add(RegionDefinition { name: "hall".into(), position: [0, 0], size: [2, 3] });
+    regions.add(RegionDefinition { name: "hall".into(), position: [2, 0], size: [1, 1] });
+    regions.add(RegionDefinition { name: "cellar".into(), position: [0, -3], size: [4, 1] });
+
+    // tile [x,y] is centered on [x,y]
+    assert_eq!(regions.region_at([0., 0.]), Some("hall"));
+    assert_eq!(regions.region_at([1., -2.]), Some("hall"));
+    assert_eq!(regions.region_at([2., 0.]), Some("hall"));
+    assert_eq!(regions.region_at([2., -1.]), None);
+    assert_eq!(regions.region_at([3., -3.]), Some("cellar"));
+
+    // near the edges of the tiles
+    assert_eq!(regions.region_at([-0.49, 0.49]), Some("hall"));
+    assert_eq!(regions.region_at([-0.51, 0.]), None);
+    assert_eq!(regions.region_at([0., 0.51]), None);
+    assert_eq!(regions.region_at([1.49, -2.49]), Some("hall"));
+    assert_eq!(regions.region_at([1., -2.51]), None);
+}
@@ -0,0 +1,615 @@
+//! server authoritative replication
+//!
+//! the server simulates the world and sends at tick rate the state of replicated
+//! entities to each client, as a delta from the last snapshot acknowledged by the
+//! client. clients render remote entities interpolated between snapshots and
+//! predict their own character from their inputs.
+
+use specs;
+use config;
+use entities;
+use levels::Level;
+use life::Kind;
+use components::*;
+use specs::Join;
+use net::{self, Input, NetError};
+use resource::PhysicWorld;
+use ids::IdAllocator;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const HELLO: u8 = 0;
+const INPUT: u8 = 1;
+const SNAPSHOT: u8 = 2;
+const ENTITY_SIZE: usize = 26;
+const MAX_PACKET_SIZE: usize = 65507;
+
+/// entities which state is sent by the server
+#[derive(Default)]
+pub struct Replicated;
+impl specs::Component for Replicated {
+    type Storage = specs::NullStorage<Self>;
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct EntityState {
+    pub kind: Kind,
+    pub position: [f32;2],
+    pub velocity: [f32;2],
+    pub health: f32,
+    pub alive: bool,
+}
+
+/// replicated entities by replication id
+pub type EntityStates = BTreeMap<u32,EntityState>;
+
+fn kind_to_u8(kind: Kind) -> u8 {
+    match kind {
+        Kind::Character => 0,
+        Kind::Monster => 1,
+        Kind::Ball => 2,
+    }
+}
+
+fn kind_from_u8(kind: u8) -> Result<Kind,NetError> {
+    match kind {
+        0 => Ok(Kind::Character),
+        1 => Ok(Kind::Monster),
+        2 => Ok(Kind::Ball),
+        _ => Err(NetError::InvalidPacket),
+    }
+}
+
+fn write_level(buf: &mut Vec<u8>, level: &Level) {
+    let (tag, a, b, c) = match *level {
+        Level::Entry => (0, 0, 0, 0),
+        Level::Corridor { castle } => (1, castle, 0, 0),
+        Level::Room { castle, dungeon, room } => (2, castle, dungeon, room),
+    };
+    buf.push(tag);
+    net::write_u32(buf, a as u32);
+    net::write_u32(buf, b as u32);
+    net::write_u32(buf, c as u32);
+}
+
+fn read_level(buf: &[u8]) -> Result<Level,NetError> {
+    let a = net::read_u32(&buf[1..]) as usize;
+    let b = net::read_u32(&buf[5..]) as usize;
+    let c = net::read_u32(&buf[9..]) as usize;
+    match buf[0] {
+        0 => Ok(Level::Entry),
+        1 => Ok(Level::Corridor { castle: a }),
+        2 => Ok(Level::Room { castle: a, dungeon: b, room: c }),
+        _ => Err(NetError::InvalidPacket),
+    }
+}
+
+/// states changed since base and ids removed since base
+struct Snapshot {
+    tick: u32,
+    base: Option<u32>,
+    local_id: Option<u32>,
+    level: Level,
+    changed: Vec<(u32,EntityState)>,
+    removed: Vec<u32>,
+}
+
+impl Snapshot {
+    fn delta(tick: u32, base: Option<(u32,&EntityStates)>, states: &EntityStates, local_id: Option<u32>, level: Level) -> Self {
+        let empty = BTreeMap::new();
+        let base_states = base.map(|(_,s)| s).unwrap_or(&empty);
+        Snapshot {
+            tick: tick,
+            base: base.map(|(t,_)| t),
+            local_id: local_id,
+            level: level,
+            changed: states.iter()
+                .filter(|&(id,state)| base_states.get(id) != Some(state))
+                .map(|(&id,&state)| (id,state))
+                .collect(),
+            removed: base_states.keys().cloned().filter(|id| !states.contains_key(id)).collect(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = vec!(SNAPSHOT);
+        net::write_u32(&mut buf, self.tick);
+        net::write_u32(&mut buf, self.base.map(|b| b + 1).unwrap_or(0));
+        net::write_u32(&mut buf, self.local_id.map(|id| id + 1).unwrap_or(0));
+        write_level(&mut buf, &self.level);
+        net::write_u32(&mut buf, self.changed.len() as u32);
+        for &(id, ref state) in &self.changed {
+            net::write_u32(&mut buf, id);
+            buf.push(kind_to_u8(state.kind));
+            for v in state.position.iter().chain(state.velocity.iter()).chain(Some(&state.health)) {
+                net::write_u32(&mut buf, v.to_bits());
+            }
+            buf.push(state.alive as u8);
+        }
+        net::write_u32(&mut buf, self.removed.len() as u32);
+        for &id in &self.removed {
+            net::write_u32(&mut buf, id);
+        }
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Result<Snapshot,NetError> {
+        if buf.len() < 30 || buf[0] != SNAPSHOT {
+            return Err(NetError::InvalidPacket);
+        }
+        let tick = net::read_u32(&buf[1..]);
+        let base = net::read_u32(&buf[5..]).checked_sub(1);
+        let local_id = net::read_u32(&buf[9..]).checked_sub(1);
+        let level = try!(read_level(&buf[13..]));
+
+        let count = net::read_u32(&buf[26..]) as usize;
+        let end = 30 + count*ENTITY_SIZE;
+        if buf.len() < end + 4 {
+            return Err(NetError::InvalidPacket);
+        }
+        let mut changed = vec!();
+        for chunk in buf[30..end].chunks(ENTITY_SIZE) {
+            let f = |i: usize| f32::from_bits(net::read_u32(&chunk[5+4*i..]));
+            changed.push((net::read_u32(chunk), EntityState {
+                kind: try!(kind_from_u8(chunk[4])),
+                position: [f(0),f(1)],
+                velocity: [f(2),f(3)],
+                health: f(4),
+                alive: chunk[25] != 0,
+            }));
+        }
+
+        let count = net::read_u32(&buf[end..]) as usize;
+        if buf.len() != end + 4 + count*4 {
+            return Err(NetError::InvalidPacket);
+        }
+        let removed = buf[end+4..].chunks(4).map(|chunk| net::read_u32(chunk)).collect();
+
+        Ok(Snapshot {
+            tick: tick,
+            base: base,
+            local_id: local_id,
+            level: level,
+            changed: changed,
+            removed: removed,
+        })
+    }
+}
+
+/// the state of all replicated entities of the world that have an id
+pub fn capture(world: &specs::World, ids: &HashMap<specs::Entity,u32>) -> EntityStates {
+    let replicateds = world.read::<Replicated>();
+    let states = world.read::<PhysicState>();
+    let lives = world.read::<Life>();
+    let entities = world.entities();
+
+    let mut captured = BTreeMap::new();
+    for (_, state, life, entity) in (&replicateds, &states, &lives, &entities).iter() {
+        let id = match ids.get(&entity) {
+            Some(&id) => id,
+            None => continue,
+        };
+        captured.insert(id, EntityState {
+            kind: life.kind(),
+            position: state.position,
+            velocity: state.velocity,
+            health: life.health(),
+            alive: life.is_alive(),
+        });
+    }
+    captured
+}
+
+fn timeout() -> Duration {
+    Duration::from_millis((config.net.timeout*1000.) as u64)
+}
+
+struct ClientState {
+    address: SocketAddr,
+    entity: Option<specs::Entity>,
+    ack: Option<u32>,
+    input: Input,
+    last_received: Instant,
+}
+
+pub struct Server {
+    socket: UdpSocket,
+    clients: Vec<ClientState>,
+    tick: u32,
+    accumulator: f32,
+    history: BTreeMap<u32,EntityStates>,
+    id_allocator: IdAllocator,
+    /// the replication id of each replicated entity
+    ids: HashMap<specs::Entity,u32>,
+}
+
+impl Server {
+    pub fn bind(port: u16) -> Result<Server,NetError> {
+        let socket = try!(UdpSocket::bind(("0.0.0.0",port)));
+        try!(socket.set_nonblocking(true));
+        Ok(Server {
+            socket: socket,
+            clients: vec!(),
+            tick: 0,
+            accumulator: 0.,
+            history: BTreeMap::new(),
+            id_allocator: IdAllocator::new(),
+            ids: HashMap::new(),
+        })
+    }
+
+    /// free the ids of the entities gone and give one to the new replicated entities,
+    /// in the order of the ids and of the entities so the same world gives the same ids
+    fn assign_ids(&mut self, world: &specs::World) {
+        let replicateds = world.read::<Replicated>();
+        let entities = world.entities();
+        let replicated = (&replicateds, &entities).iter().map(|(_, entity)| entity).collect::<Vec<_>>();
+        let current = replicated.iter().cloned().collect::<HashSet<_>>();
+
+        let mut gone = self.ids.iter()
+            .filter(|&(entity, _)| !current.contains(entity))
+            .map(|(&entity, &id)| (entity, id))
+            .collect::<Vec<_>>();
+        gone.sort_by_key(|&(_, id)| id);
+        for (entity, id) in gone {
+            self.ids.remove(&entity);
+            self.id_allocator.free(id);
+        }
+
+        for entity in replicated {
+            if !self.ids.contains_key(&entity) {
+                if let Some(id) = self.id_allocator.alloc() {
+                    self.ids.insert(entity, id);
+                }
+            }
+        }
+    }
+
+    /// free the ids of the entities of the previous level, the entities of the world of
+    /// the new level get the next generations
+    pub fn reset_ids(&mut self) {
+        let mut ids = self.ids.drain().map(|(_, id)| id).collect::<Vec<_>>();
+        ids.sort();
+        for id in ids {
+            self.id_allocator.free(id);
+        }
+    }
+
+    /// the characters controlled by clients
+    pub fn client_entities(&self) -> Vec<specs::Entity> {
+        self.clients.iter().filter_map(|c| c.entity).collect()
+    }
+
+    /// receive hello and inputs, drop clients timed out
+    pub fn receive(&mut self, world: &mut specs::World) -> Result<(),NetError> {
+        let mut buf = vec!(0u8;MAX_PACKET_SIZE);
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, address)) => {
+                    let index = self.clients.iter().position(|c| c.address == address);
+                    match (buf.get(0).cloned(), index) {
+                        (Some(HELLO), None) | (Some(INPUT), None) => self.clients.push(ClientState {
+                            address: address,
+                            entity: None,
+                            ack: None,
+                            input: Input::none(),
+                            last_received: Instant::now(),
+                        }),
+                        (Some(INPUT), Some(index)) if len == 14 => {
+                            let client = &mut self.clients[index];
+                            client.ack = net::read_u32(&buf[1..]).checked_sub(1);
+                            client.input = Input {
+                                direction: f32::from_bits(net::read_u32(&buf[5..])),
+                                intensity: f32::from_bits(net::read_u32(&buf[9..])),
+                                action: buf[13] != 0,
+                            };
+                            client.last_received = Instant::now();
+                        },
+                        _ => (),
+                    }
+                },
+                Err(ref e) if e.kind() == ::std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(NetError::Io(e)),
+            }
+        }
+
+        for client in self.clients.iter().filter(|c| c.last_received.elapsed() > timeout()) {
+            if let Some(entity) = client.entity {
+                world.delete_later(entity);
+            }
+        }
+        world.maintain();
+        self.clients.retain(|c| c.last_received.elapsed() <= timeout());
+        Ok(())
+    }
+
+    /// spawn characters of clients next to the first player,
+    /// if all then even clients that already have one (their character is destroyed on level load)
+    pub fn spawn_clients(&mut self, world: &mut specs::World, all: bool) {
+        let position = (&world.read::<PlayerControl>(), &world.read::<PhysicState>()).iter().next().map(|(_,s)| s.position);
+        let position = match position {
+            Some(position) => position,
+            None => return,
+        };
+
+        // next to the player but not inside walls, monsters or it
+        let position = world.read_resource::<PhysicWorld>().find_free_position(
+            &Shape::Circle(config.entities.char_radius),
+            position,
+            config.replication.spawn_radius,
+            mask!("char", "monster", "laser", "column", "wall", "chest")).unwrap_or(position);
+
+        for client in &mut self.clients {
+            let alive = client.entity.map(|e| world.read::<PhysicState>().get(e).is_some()).unwrap_or(false);
+            if all || !alive {
+                client.entity = Some(entities::add_peer_character(world, position));
+            }
+        }
+    }
+
+    /// set the force of clients characters from their last input
+    pub fn apply_inputs(&mut self, world: &mut specs::World) {
+        let mut forces = world.write::<PhysicForce>();
+        let mut interactors = world.write::<Interactor>();
+        for client in &mut self.clients {
+            if let Some(entity) = client.entity {
+                if let Some(force) = forces.get_mut(entity) {
+                    if client.input.intensity != 0. {
+                        force.direction = client.input.direction;
+                    }
+                    force.intensity = client.input.intensity;
+                }
+                if client.input.action {
+                    client.input.action = false;
+                    if let Some(interactor) = interactors.get_mut(entity) {
+                        interactor.requested = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// send a snapshot to each client if a tick elapsed
+    pub fn update(&mut self, dt: f32, world: &specs::World, level: &Level) -> Result<(),NetError> {
+        self.accumulator += dt;
+        if self.accumulator < 1. / config.replication.tick_rate {
+            return Ok(());
+        }
+        self.accumulator = 0.;
+        self.tick += 1;
+
+        self.assign_ids(world);
+        let states = capture(world, &self.ids);
+        for client in &self.clients {
+            let base = client.ack.and_then(|ack| self.history.get(&ack).map(|s| (ack,s)));
+            let local_id = client.entity.and_then(|e| self.ids.get(&e).cloned());
+            let snapshot = Snapshot::delta(self.tick, base, &states, local_id, level.clone());
+            try!(self.socket.send_to(&*snapshot.encode(), client.address));
+        }
+
+        self.history.insert(self.tick, states);
+        let oldest = self.tick.saturating_sub(config.replication.history);
+        let old: Vec<u32> = self.history.keys().cloned().filter(|&t| t < oldest).collect();
+        for tick in old {
+            self.history.remove(&tick);
+        }
+        Ok(())
+    }
+}
+
+pub struct Client {
+    socket: UdpSocket,
+    history: BTreeMap<u32,EntityStates>,
+    render_tick: f32,
+    last_sampled: Option<u32>,
+    local_id: Option<u32>,
+    entities: BTreeMap<u32,specs::Entity>,
+    last_received: Instant,
+}
+
+impl Client {
+    pub fn connect(address: &str) -> Result<Client,NetError> {
+        let socket = try!(UdpSocket::bind("0.0.0.0:0"));
+        try!(socket.connect(address));
+        try!(socket.send(&[HELLO]));
+        try!(socket.set_nonblocking(true));
+        Ok(Client {
+            socket: socket,
+            history: BTreeMap::new(),
+            render_tick: 0.,
+            last_sampled: None,
+            local_id: None,
+            entities: BTreeMap::new(),
+            last_received: Instant::now(),
+        })
+    }
+
+    fn latest(&self) -> Option<u32> {
+        self.history.keys().next_back().cloned()
+    }
+
+    /// send the input and the acknowledgement of the latest snapshot
+    pub fn send_input(&mut self, input: Input) -> Result<(),NetError> {
+        let mut buf = vec!(INPUT);
+        net::write_u32(&mut buf, self.latest().map(|t| t + 1).unwrap_or(0));
+        net::write_u32(&mut buf, input.direction.to_bits());
+        net::write_u32(&mut buf, input.intensity.to_bits());
+        buf.push(input.action as u8);
+        try!(self.socket.send(&*buf));
+        Ok(())
+    }
+
+    /// receive snapshots and return the level of the server
+    pub fn receive(&mut self) -> Result<Option<Level>,NetError> {
+        let mut buf = vec!(0u8;MAX_PACKET_SIZE);
+        let mut level = None;
+        loop {
+            match self.socket.recv(&mut buf) {
+                Ok(len) => {
+                    let snapshot = try!(Snapshot::decode(&buf[..len]));
+                    if self.history.contains_key(&snapshot.tick) { continue }
+
+                    let mut states = match snapshot.base {
+                        Some(base) => match self.history.get(&base) {
+                            Some(states) => states.clone(),
+                            None => continue,
+                        },
+                        None => BTreeMap::new(),
+                    };
+                    for id in snapshot.removed {
+                        states.remove(&id);
+                    }
+                    for (id, state) in snapshot.changed {
+                        states.insert(id, state);
+                    }
+
+                    if self.latest().map(|t| snapshot.tick > t).unwrap_or(true) {
+                        self.local_id = snapshot.local_id;
+                        level = Some(snapshot.level);
+                    }
+                    self.history.insert(snapshot.tick, states);
+                    self.last_received = Instant::now();
+                },
+                Err(ref e) if e.kind() == ::std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(NetError::Io(e)),
+            }
+        }
+
+        let oldest = self.latest().unwrap_or(0).saturating_sub(config.replication.history);
+        let old: Vec<u32> = self.history.keys().cloned().filter(|&t| t < oldest).collect();
+        for tick in old {
+            self.history.remove(&tick);
+        }
+
+        if self.last_received.elapsed() > timeout() {
+            return Err(NetError::Timeout);
+        }
+        Ok(level)
+    }
+
+    /// forget the entities of the previous level
+    pub fn reset(&mut self) {
+        self.entities.clear();
+    }
+
+    /// the time remote entities are rendered at, late of the interpolation delay
+    pub fn render_time(&self) -> f32 {
+        (self.render_tick - config.replication.interpolation_delay).max(0.) / config.replication.tick_rate
+    }
+
+    /// create and destroy entities to match the snapshots, buffer the states of
+    /// remote entities and correct the local character if it is too far from the server one
+    pub fn apply(&mut self, dt: f32, world: &mut specs::World) {
+        let latest = match self.latest() {
+            Some(latest) => latest as f32,
+            None => return,
+        };
+        self.render_tick = (self.render_tick + dt * config.replication.tick_rate)
+            .max(latest - config.replication.interpolation_delay*2.)
+            .min(latest);
+
+        let first_unsampled = self.last_sampled.map(|t| t + 1).unwrap_or(0);
+        let unsampled: Vec<(u32,&EntityStates)> = self.history.range(first_unsampled..).map(|(&t,s)| (t,s)).collect();
+        self.last_sampled = Some(latest as u32);
+        let latest_states = &self.history[&(latest as u32)];
+
+        // remove entities not replicated anymore and the ones not from the server
+        {
+            let known: Vec<specs::Entity> = self.entities.values().cloned().collect();
+            let replicateds = world.read::<Replicated>();
+            for (_, entity) in (&replicateds, &world.entities()).iter() {
+                if !known.contains(&entity) {
+                    world.delete_later(entity);
+                }
+            }
+        }
+        let removed: Vec<u32> = self.entities.keys().cloned().filter(|id| !latest_states.contains_key(id)).collect();
+        for id in removed {
+            world.delete_later(self.entities.remove(&id).unwrap());
+        }
+        world.maintain();
+
+        for (&id, state) in latest_states {
+            let local = self.local_id == Some(id);
+            let alive = self.entities.get(&id).map(|&e| world.read::<PhysicState>().get(e).is_some()).unwrap_or(false);
+            if !alive {
+                let entity = if local {
+                    entities::add_peer_character(world, state.position)
+                } else {
+                    entities::add_puppet(world, state.kind, state.position)
+                };
+                self.entities.insert(id, entity);
+            }
+
+            let entity = self.entities[&id];
+            if !local {
+                let mut interpolateds = world.write::<Interpolated>();
+                if let Some(interpolated) = interpolateds.get_mut(entity) {
+                    for &(tick, states) in &unsampled {
+                        if let Some(state) = states.get(&id) {
+                            interpolated.push(tick as f32 / config.replication.tick_rate, state.position, state.velocity);
+                        }
+                    }
+                }
+            }
+
+            let mut physic_states = world.write::<PhysicState>();
+            let physic_state = match physic_states.get_mut(entity) {
+                Some(physic_state) => physic_state,
+                None => continue,
+            };
+
+            if local {
+                let distance = ((physic_state.position[0] - state.position[0]).powi(2) + (physic_state.position[1] - state.position[1]).powi(2)).sqrt();
+                if distance > config.replication.correction_distance {
+                    physic_state.position = state.position;
+                    physic_state.velocity = state.velocity;
+                }
+            } else {
+                physic_state.position = state.position;
+                physic_state.velocity = state.velocity;
+            }
+            if let Some(life) = world.write::<Life>().get_mut(entity) {
+                life.set_health(state.health);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_snapshot_delta() {
+    let state = EntityState {
+        kind: Kind::Monster,
+        position: [1., -2.],
+        velocity: [0.5, 0.],
+        health: 3.,
+        alive: true,
+    };
+    let mut base = BTreeMap::new();
+    base.insert(1, state);
+    base.insert(2, state);
+    let mut states = base.clone();
+    states.remove(&2);
+    states.get_mut(&1).unwrap().position = [3., 4.];
+    states.get_mut(&1).unwrap().health = 1.5;
+    states.insert(3, state);
+
+    let snapshot = Snapshot::delta(7, Some((5,&base)), &states, Some(3), Level::Entry);
+    let decoded = Snapshot::decode(&*snapshot.encode()).unwrap();
+    assert_eq!(decoded.tick, 7);
+    assert_eq!(decoded.base, Some(5));
+    assert_eq!(decoded.local_id, Some(3));
+    assert_eq!(decoded.level, Level::Entry);
+    assert_eq!(decoded.removed, vec!(2));
+    assert_eq!(decoded.changed.len(), 2);
+    assert_eq!(decoded.changed[0].1.position, [3., 4.]);
+    assert_eq!(decoded.changed[0].1.health, 1.5);
+    assert_eq!(decoded.changed[1].1, state);
+
+    // a change of health only is sent
+    let mut hurt = states.clone();
+    hurt.get_mut(&3).unwrap().health = 2.;
+    let snapshot = Snapshot::delta(8, Some((7,&states)), &hurt, None, Level::Entry);
+    assert_eq!(snapshot.changed, vec!((3, hurt[&3])));
+}
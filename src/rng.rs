@@ -0,0 +1,29 @@
+//! random number generator of the world seeded by general.seed, gameplay randomness
+//! goes through it so lockstep peers draw the same numbers.
+
+use config;
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+pub struct WorldRng {
+    rng: XorShiftRng,
+}
+
+impl WorldRng {
+    pub fn new() -> Self {
+        WorldRng::from_seed(config.general.seed)
+    }
+
+    pub fn from_seed(seed: u32) -> Self {
+        // xorshift must not be seeded with zeros only
+        WorldRng {
+            rng: XorShiftRng::from_seed([seed, 0x193a_6754, 0xa8a7_d469, 0x9783_0e05]),
+        }
+    }
+}
+
+impl Rng for WorldRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+}
+
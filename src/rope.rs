@@ -0,0 +1,236 @@
+//! ropes hold an entity within a length of an anchor, a wall point or an entity.
+//! the rope wraps around the corners of the walls between the anchor and the entity
+//! and unwraps when the previous point is visible again.
+
+use app;
+use specs;
+use components::*;
+use resource::PhysicWorld;
+use specs::Join;
+use math;
+
+/// shortest length of a rope
+const MIN_LENGTH: f32 = 0.5;
+/// distance under which a wall doesn't block the rope
+const EPSILON: f32 = 1e-3;
+/// depth in the wall hit of the point whose cell is the wall
+const DEPTH: f32 = 1e-2;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Anchor {
+    Point([f32;2]),
+    Entity(specs::Entity),
+}
+
+pub struct Rope {
+    pub length: f32,
+    /// statics of mask the rope wraps around
+    pub mask: u32,
+    anchor: Anchor,
+    /// corners the rope wraps around, from the anchor to the entity
+    wraps: Vec<[f32;2]>,
+}
+impl specs::Component for Rope {
+    type Storage = specs::VecStorage<Self>;
+}
+
+fn distance(a: [f32;2], b: [f32;2]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+/// the first static entity of mask on the segment from a to b, as the point hit
+fn first_static_hit(physic_world: &PhysicWorld, a: [f32;2], b: [f32;2], mask: u32) -> Option<[f32;2]> {
+    let length = distance(a, b);
+    if length <= EPSILON {
+        return None;
+    }
+    let angle = math::atan2(b[1] - a[1], b[0] - a[0]);
+    let ray = Ray { origin: a, angle: angle, length: length, mask: mask };
+    let options = RaycastOptions { max_hits: Some(1), static_only: true, .. RaycastOptions::default() };
+
+    let mut hit = None;
+    physic_world.raycast_with(&ray, &options, &mut |(_,min,_)| {
+        // the wall the rope is wrapped on is touched at the origin
+        if min > EPSILON && min < length - EPSILON {
+            hit = Some([a[0] + min*math::cos(angle), a[1] + min*math::sin(angle)]);
+        }
+        false
+    });
+    hit
+}
+
+impl Rope {
+    /// cast a rope from origin, it is anchored to the first entity of mask hit within
+    /// max_length: at the point hit for a static entity, to the entity for a movable one
+    pub fn cast(physic_world: &PhysicWorld, caster: specs::Entity, origin: [f32;2], angle: f32, max_length: f32, mask: u32) -> Option<Rope> {
+        let ray = Ray { origin: origin, angle: angle, length: max_length, mask: mask };
+        let exclude = [caster];
+        let first_hit = |static_only| {
+            let options = RaycastOptions { exclude: &exclude, max_hits: Some(1), static_only: static_only };
+            let mut hit = None;
+            physic_world.raycast_with(&ray, &options, &mut |(entity,min,_)| {
+                hit = Some((entity, min.max(0.)));
+                false
+            });
+            hit
+        };
+
+        let anchor = match (first_hit(false), first_hit(true)) {
+            (Some((entity, min)), Some((static_entity, _))) if entity == static_entity => {
+                Some((Anchor::Point([origin[0] + min*math::cos(angle), origin[1] + min*math::sin(angle)]), min))
+            },
+            (Some((entity, min)), _) => Some((Anchor::Entity(entity), min)),
+            (None, _) => None,
+        };
+
+        anchor.map(|(anchor, length)| Rope {
+            length: length.max(MIN_LENGTH),
+            mask: mask,
+            anchor: anchor,
+            wraps: vec!(),
+        })
+    }
+
+    pub fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+
+    /// lengthen the rope of delta, shorten it if negative
+    pub fn reel(&mut self, delta: f32) {
+        self.length = (self.length + delta).max(MIN_LENGTH);
+    }
+
+    /// the point the rope goes from to the entity and the length left from it
+    fn pivot(&self, anchor: [f32;2]) -> ([f32;2], f32) {
+        let mut pivot = anchor;
+        let mut left = self.length;
+        for &wrap in &self.wraps {
+            left -= distance(pivot, wrap);
+            pivot = wrap;
+        }
+        (pivot, left)
+    }
+
+    /// wrap around the wall corner hit between the pivot and the entity
+    /// and unwrap the last corner if the previous point is visible
+    fn update_wraps(&mut self, physic_world: &PhysicWorld, anchor: [f32;2], position: [f32;2]) {
+        let (pivot, _) = self.pivot(anchor);
+        if let Some(hit) = first_static_hit(physic_world, pivot, position, self.mask) {
+            // walls are squares of the grid centred on integer coordinates,
+            // the rope wraps on the corner of the wall hit nearest to the hit
+            let angle = math::atan2(position[1] - pivot[1], position[0] - pivot[0]);
+            let wall = [(hit[0] + DEPTH*math::cos(angle)).round(), (hit[1] + DEPTH*math::sin(angle)).round()];
+            let corner = [wall[0] + 0.5*(hit[0] - wall[0]).signum(), wall[1] + 0.5*(hit[1] - wall[1]).signum()];
+            if distance(corner, pivot) > EPSILON {
+                self.wraps.push(corner);
+            }
+        } else if let Some(_) = self.wraps.last() {
+            let previous = if self.wraps.len() >= 2 { self.wraps[self.wraps.len() - 2] } else { anchor };
+            if first_static_hit(physic_world, previous, position, self.mask).is_none() {
+                self.wraps.pop();
+            }
+        }
+    }
+}
+
+/// keep the state within length of pivot, the velocity away from the pivot is removed
+fn constrain(state: &mut PhysicState, pivot: [f32;2], length: f32) {
+    let dx = state.position[0] - pivot[0];
+    let dy = state.position[1] - pivot[1];
+    let d = (dx.powi(2) + dy.powi(2)).sqrt();
+    if d <= length || d == 0. {
+        return;
+    }
+
+    let (nx, ny) = (dx/d, dy/d);
+    state.position = [pivot[0] + nx*length, pivot[1] + ny*length];
+    let radial = state.velocity[0]*nx + state.velocity[1]*ny;
+    if radial > 0. {
+        state.velocity[0] -= radial*nx;
+        state.velocity[1] -= radial*ny;
+    }
+}
+
+/// apply the ropes constraints, ropes anchored to deleted entities are removed
+pub struct RopeSystem;
+impl specs::System<app::UpdateContext> for RopeSystem {
+    fn run(&mut self, arg: specs::RunArg, _: app::UpdateContext) {
+        let (mut ropes, mut states, physic_world, entities) = arg.fetch(|world| {
+            (
+                world.write::<Rope>(),
+                world.write::<PhysicState>(),
+                world.read_resource::<PhysicWorld>(),
+                world.entities(),
+            )
+        });
+
+        let mut broken = vec!();
+        for (rope, entity) in (&mut ropes, &entities).iter() {
+            let anchor = match rope.anchor {
+                Anchor::Point(point) => Some(point),
+                Anchor::Entity(anchor) => states.get(anchor).map(|s| s.position),
+            };
+            let anchor = match anchor {
+                Some(anchor) => anchor,
+                None => {
+                    broken.push(entity);
+                    continue;
+                },
+            };
+            let state = match states.get_mut(entity) {
+                Some(state) => state,
+                None => continue,
+            };
+
+            rope.update_wraps(&physic_world, anchor, state.position);
+            let (pivot, left) = rope.pivot(anchor);
+            constrain(state, pivot, left.max(0.));
+        }
+        for entity in broken {
+            ropes.remove(entity);
+        }
+    }
+}
+
+#[test]
+fn test_rope_constrain() {
+    let mut state = PhysicState::new([3.,4.]);
+    state.velocity = [3.,-1.];
+    constrain(&mut state, [0.,0.], 2.5);
+    assert_eq!(state.position, [1.5,2.]);
+    // the radial part of the velocity (0.6,0.8)*1 is removed
+    assert!((state.velocity[0] - 2.4).abs() < 1e-6 && (state.velocity[1] + 1.8).abs() < 1e-6);
+
+    let mut state = PhysicState::new([1.,0.]);
+    state.velocity = [1.,0.];
+    constrain(&mut state, [0.,0.], 2.);
+    assert_eq!(state.position, [1.,0.]);
+    assert_eq!(state.velocity, [1.,0.]);
+}
+
+#[test]
+fn test_rope_wraps() {
+    let mut world = specs::World::new();
+    let wall = world.create_now().build();
+    let mut physic_world = PhysicWorld::new();
+    physic_world.insert_static(wall, &[0.,0.], 1, &Shape::Square(0.5));
+
+    let anchor = [-2.,1.];
+    let mut rope = Rope { length: 10., mask: 1, anchor: Anchor::Point(anchor), wraps: vec!() };
+    rope.update_wraps(&physic_world, anchor, [2.,0.6]);
+    assert!(rope.wraps.is_empty());
+
+    // the rope goes down over the wall and wraps on its top right corner
+    rope.update_wraps(&physic_world, anchor, [2.,0.1]);
+    assert_eq!(rope.wraps, vec!([0.5,0.5]));
+    let (pivot, left) = rope.pivot(anchor);
+    assert_eq!(pivot, [0.5,0.5]);
+    assert!((left - (10. - 2.5f32.hypot(0.5))).abs() < 1e-5);
+
+    rope.update_wraps(&physic_world, anchor, [2.,-1.]);
+    assert_eq!(rope.wraps, vec!([0.5,0.5]));
+
+    // the anchor is visible again
+    rope.update_wraps(&physic_world, anchor, [2.,1.]);
+    assert!(rope.wraps.is_empty());
+}
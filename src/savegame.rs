@@ -0,0 +1,41 @@
+//! the versions of the files saved for the player, save.toml and the stats file. each
+//! file is written with its version; loading an older one applies the migrations of
+//! the versions in between, then the keys the current version doesn't know are dropped
+//! and the ones missing are set to their default. what was migrated, dropped and
+//! defaulted is reported at startup instead of the file failing to load.
+//!
+//! a change to the keys of a file bumps its version, a key renamed or whose value
+//! changed of meaning also registers a migration from the previous version.
+
+use configuration::{Migration, SaveReport};
+
+/// the version of save.toml
+pub const SAVE_VERSION: u32 = 1;
+
+/// the migrations of save.toml
+/// * 0: the files written before the versions, only keys were added since
+pub static SAVE_MIGRATIONS: &'static [Migration] = &[];
+
+/// the version of the stats file
+pub const STATS_VERSION: u32 = 1;
+
+/// the migrations of the stats file
+/// * 0: the files written before the versions
+pub static STATS_MIGRATIONS: &'static [Migration] = &[];
+
+/// print the report of the file if it isn't loaded as it is
+pub fn print_report(file: &str, report: &SaveReport) {
+    if report.is_empty() {
+        return;
+    }
+    println!("WARNING: {} of version {} loaded with changes", file, report.version);
+    for migration in &report.migrations {
+        println!("\tmigrated: {}", migration);
+    }
+    for key in &report.dropped {
+        println!("\tdropped: {}", key);
+    }
+    for key in &report.defaulted {
+        println!("\tdefaulted: {}", key);
+    }
+}
@@ -0,0 +1,85 @@
+//! the stack of the scenes of the game flow: the game at the bottom, the menu over it,
+//! the submenus, the controls and the texts over the menu. the top scene receives the
+//! inputs, is updated and rendered; escape pops it to reveal the scene under it.
+//!
+//! the owner of the stack runs the exit and enter hooks of the scenes around a push or
+//! a pop. both start the transition: a curtain drawn over the scene entered and
+//! withdrawn in menu.transition_time.
+
+use config;
+
+pub struct SceneStack<S> {
+    scenes: Vec<S>,
+    /// the time left of the transition
+    transition: f32,
+}
+
+impl<S> SceneStack<S> {
+    pub fn new(root: S) -> Self {
+        SceneStack {
+            scenes: vec!(root),
+            transition: 0.,
+        }
+    }
+
+    pub fn top(&self) -> &S {
+        self.scenes.last().unwrap()
+    }
+
+    pub fn top_mut(&mut self) -> &mut S {
+        self.scenes.last_mut().unwrap()
+    }
+
+    pub fn push(&mut self, scene: S) {
+        self.scenes.push(scene);
+        self.transition = config.menu.transition_time;
+    }
+
+    /// the top scene, none if it is the root
+    pub fn pop(&mut self) -> Option<S> {
+        if self.scenes.len() == 1 {
+            return None;
+        }
+        self.transition = config.menu.transition_time;
+        self.scenes.pop()
+    }
+
+    /// replace the top scene without transition
+    pub fn replace(&mut self, scene: S) -> S {
+        ::std::mem::replace(self.top_mut(), scene)
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.transition = (self.transition - dt).max(0.);
+    }
+
+    /// the fraction of the transition left, none if there is none
+    pub fn transition(&self) -> Option<f32> {
+        if self.transition > 0. {
+            Some(self.transition / config.menu.transition_time)
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_scene_stack() {
+    let mut scenes = SceneStack::new(0);
+    assert_eq!(scenes.pop(), None);
+    scenes.push(1);
+    scenes.push(2);
+    assert_eq!(*scenes.top(), 2);
+    assert_eq!(scenes.replace(3), 2);
+    assert_eq!(scenes.pop(), Some(3));
+    assert_eq!(scenes.pop(), Some(1));
+    assert_eq!(scenes.pop(), None);
+    assert_eq!(*scenes.top(), 0);
+
+    scenes.push(1);
+    if config.menu.transition_time > 0. {
+        assert_eq!(scenes.transition(), Some(1.));
+    }
+    scenes.update(config.menu.transition_time);
+    assert_eq!(scenes.transition(), None);
+}
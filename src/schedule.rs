@@ -0,0 +1,150 @@
+//! the phases the systems are run in, declared by name with the phases they run
+//! after and before and sorted into the priorities of the planner.
+//!
+//! the built-in phases, in order:
+//! * `pre_step`: before the physic step, custom forces
+//! * `physic`: move entities and resolve collisions
+//! * `post_step`: after the physic step, recording of the resolved states
+//! * `validate`: check the physic world, with the validate feature
+//! * `control`: controllers setting the forces of the entities and platforms moved on their path
+//! * `gameplay`: gameplay systems reading the resolved positions and the forces of the frame
+//! * `follow`: entities following others, riders on their mount, held bodies and roped entities
+//! * `sound`: sounds following entities before they are removed
+//! * `life`: remove dead entities
+//!
+//! systems of the same phase may run in parallel so they must not depend on each other.
+
+use specs;
+use app::UpdateContext;
+use std::collections::HashMap;
+
+struct PhaseDefinition {
+    name: String,
+    after: Vec<String>,
+    before: Vec<String>,
+}
+
+pub struct Schedule {
+    phases: Vec<PhaseDefinition>,
+    priorities: HashMap<String,specs::Priority>,
+}
+
+impl Schedule {
+    /// a schedule of the built-in phases
+    pub fn new() -> Self {
+        let mut schedule = Schedule {
+            phases: vec!(),
+            priorities: HashMap::new(),
+        };
+        let phases: &[(&str, &[&str])] = &[
+            ("pre_step", &[]),
+            ("physic", &["pre_step"]),
+            ("post_step", &["physic"]),
+            ("control", &["post_step"]),
+            ("gameplay", &["control"]),
+            ("follow", &["gameplay"]),
+            ("sound", &["follow"]),
+            ("life", &["sound"]),
+        ];
+        for &(name, after) in phases {
+            schedule.declare(name, after, &[]).expect("built-in phases must be ordered");
+        }
+        #[cfg(feature = "validate")]
+        schedule.declare("validate", &["post_step"], &["control"]).expect("built-in phases must be ordered");
+        schedule
+    }
+
+    /// declare the phase run after the phases of after and before the phases of before,
+    /// the schedule is unchanged if they are not declared or if they contradict the order
+    pub fn declare(&mut self, name: &str, after: &[&str], before: &[&str]) -> Result<(),String> {
+        if self.phases.iter().any(|phase| phase.name == name) {
+            return Err(format!("phase {} is already declared", name));
+        }
+        for other in after.iter().chain(before.iter()) {
+            if !self.phases.iter().any(|phase| phase.name == *other) {
+                return Err(format!("phase {} is not declared", other));
+            }
+        }
+        self.phases.push(PhaseDefinition {
+            name: name.into(),
+            after: after.iter().map(|&p| p.into()).collect(),
+            before: before.iter().map(|&p| p.into()).collect(),
+        });
+        match self.sort() {
+            Ok(priorities) => {
+                self.priorities = priorities;
+                Ok(())
+            },
+            Err(err) => {
+                self.phases.pop();
+                Err(err)
+            },
+        }
+    }
+
+    /// the priority of the phase, the phases run first have the highest
+    pub fn priority(&self, phase: &str) -> Option<specs::Priority> {
+        self.priorities.get(phase).cloned()
+    }
+
+    /// add the system to the planner in the phase
+    pub fn add_system<S: specs::System<UpdateContext> + 'static>(&self, planner: &mut specs::Planner<UpdateContext>, system: S, name: &str, phase: &str) {
+        let priority = self.priority(phase).expect("the phase of the system must be declared");
+        planner.add_system(system, name, priority);
+    }
+
+    /// topological sort of the phases, among the phases ready to run the first declared
+    /// goes first so the order doesn't depend on the hash of the names
+    fn sort(&self) -> Result<HashMap<String,specs::Priority>,String> {
+        let index = |name: &str| self.phases.iter().position(|phase| phase.name == name).unwrap();
+        let mut successors = vec!(vec!(); self.phases.len());
+        let mut predecessors = vec!(0; self.phases.len());
+        for (i, phase) in self.phases.iter().enumerate() {
+            for after in &phase.after {
+                successors[index(after)].push(i);
+                predecessors[i] += 1;
+            }
+            for before in &phase.before {
+                successors[i].push(index(before));
+                predecessors[index(before)] += 1;
+            }
+        }
+
+        let mut order = vec!();
+        while order.len() < self.phases.len() {
+            let ready = match (0..self.phases.len()).find(|&i| predecessors[i] == 0 && !order.contains(&i)) {
+                Some(ready) => ready,
+                None => return Err(String::from("phases are ordered in a cycle")),
+            };
+            for &successor in &successors[ready] {
+                predecessors[successor] -= 1;
+            }
+            order.push(ready);
+        }
+
+        let count = order.len();
+        Ok(order.into_iter()
+           .enumerate()
+           .map(|(rank, i)| (self.phases[i].name.clone(), (count - rank) as specs::Priority))
+           .collect())
+    }
+}
+
+#[test]
+fn test_declared_phases() {
+    let mut schedule = Schedule::new();
+    let priority = |schedule: &Schedule, phase: &str| schedule.priority(phase).unwrap();
+    assert!(priority(&schedule, "pre_step") > priority(&schedule, "physic"));
+    assert!(priority(&schedule, "gameplay") > priority(&schedule, "life"));
+
+    // between the physic step and the controllers
+    schedule.declare("ragdoll", &["physic"], &["control"]).unwrap();
+    assert!(priority(&schedule, "physic") > priority(&schedule, "ragdoll"));
+    assert!(priority(&schedule, "ragdoll") > priority(&schedule, "control"));
+    assert!(priority(&schedule, "pre_step") > priority(&schedule, "physic"));
+
+    assert!(schedule.declare("loop", &["life"], &["physic"]).is_err());
+    assert!(schedule.priority("loop").is_none());
+    assert!(schedule.declare("orphan", &["render"], &[]).is_err());
+    assert!(schedule.declare("ragdoll", &[], &[]).is_err());
+}
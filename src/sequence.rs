@@ -0,0 +1,306 @@
+use specs;
+use config;
+use entities;
+use levels;
+use physic;
+use weather;
+use faction::{self, Relation};
+use configuration::FromToml;
+use toml;
+use graphics::Color;
+use components::*;
+use specs::Join;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub const SPAWNABLE: &'static [&'static str] = &["wall", "laser", "monster", "column", "chest", "checkpoint", "pickup", "foliage", "low_wall"];
+
+/// a step of a scripted sequence,
+/// positions are in world coordinates (map rows are negative)
+pub enum Step {
+    LockInput(bool),
+    Wait(f32),
+    MoveCamera {
+        position: [f32;2],
+        time: f32,
+    },
+    ReleaseCamera,
+    /// wait for the action key
+    Dialogue {
+        speaker: String,
+        portrait: Color,
+        line: String,
+    },
+    Spawn {
+        entity: String,
+        position: [isize;2],
+    },
+    WaitPlayerNear {
+        position: [f32;2],
+        radius: f32,
+    },
+    /// index of the weather state to change to
+    Weather(usize),
+    /// the new relation of a faction toward another, by index
+    Relation {
+        faction: usize,
+        toward: usize,
+        relation: Relation,
+    },
+}
+
+impl FromToml for Step {
+    fn from_toml(val: &toml::Value) -> Result<Self,String> {
+        let table = try!(val.as_table().ok_or(String::from(" expect table")));
+        let get = |key: &str| table.get(key).ok_or(format!(" expect key: {}",key));
+        let typ = try!(try!(get("type")).as_str().ok_or(String::from(".type expect string")));
+
+        match typ {
+            "lock_input" => Ok(Step::LockInput(try!(FromToml::from_toml(try!(get("lock")))))),
+            "wait" => Ok(Step::Wait(try!(FromToml::from_toml(try!(get("time")))))),
+            "move_camera" => Ok(Step::MoveCamera {
+                position: try!(FromToml::from_toml(try!(get("position")))),
+                time: try!(FromToml::from_toml(try!(get("time")))),
+            }),
+            "release_camera" => Ok(Step::ReleaseCamera),
+            "dialogue" => Ok(Step::Dialogue {
+                speaker: try!(FromToml::from_toml(try!(get("speaker")))),
+                portrait: try!(FromToml::from_toml(try!(get("portrait")))),
+                line: try!(FromToml::from_toml(try!(get("line")))),
+            }),
+            "spawn" => {
+                let entity: String = try!(FromToml::from_toml(try!(get("entity"))));
+                if !SPAWNABLE.contains(&&*entity) {
+                    return Err(format!(" unexpected entity to spawn: {}",entity));
+                }
+                Ok(Step::Spawn {
+                    entity: entity,
+                    position: try!(FromToml::from_toml(try!(get("position")))),
+                })
+            },
+            "wait_player_near" => Ok(Step::WaitPlayerNear {
+                position: try!(FromToml::from_toml(try!(get("position")))),
+                radius: try!(FromToml::from_toml(try!(get("radius")))),
+            }),
+            "weather" => {
+                let name: String = try!(FromToml::from_toml(try!(get("state"))));
+                Ok(Step::Weather(try!(weather::state(&*name).ok_or(format!(" unexpected weather state: {}",name)))))
+            },
+            "relation" => {
+                let faction = |key: &str| -> Result<usize,String> {
+                    let name: String = try!(FromToml::from_toml(try!(get(key))));
+                    faction::id(&*name).ok_or(format!(" unexpected faction: {}",name))
+                };
+                Ok(Step::Relation {
+                    faction: try!(faction("faction")),
+                    toward: try!(faction("toward")),
+                    relation: try!(FromToml::from_toml(try!(get("relation")))),
+                })
+            },
+            _ => Err(format!(" unexpected step type: {}",typ)),
+        }
+    }
+}
+
+/// load the sequence of a room if the castle defines one:
+/// a toml file with the name of the room in the sequences directory of the castle
+pub fn load(level: &levels::Level, castles: &Vec<levels::Castle>) -> Result<Option<Sequence>,String> {
+    let path = if let levels::Level::Room { castle: castle_id, dungeon: dungeon_id, room: room_id } = *level {
+        let castle = try!(castles.get(castle_id).ok_or(String::from("castle id out of bounds")));
+        let room = try!(castle.dungeons.get(dungeon_id)
+            .and_then(|dungeon| dungeon.rooms.get(room_id))
+            .ok_or(String::from("room id out of bounds")));
+
+        PathBuf::new()
+            .join(Path::new(&*config.levels.dir.val))
+            .join(Path::new(&*castle.name))
+            .join(Path::new("sequences"))
+            .join(Path::new(&*room).with_extension("toml"))
+    } else {
+        return Ok(None);
+    };
+
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let mut string = String::new();
+    try!(try!(fs::File::open(&path).map_err(|e| format!("open sequence failed: {}",e)))
+         .read_to_string(&mut string)
+         .map_err(|_| String::from("sequence invalid: not valid UTF-8")));
+
+    let mut parser = toml::Parser::new(&*string);
+    let table = try!(parser.parse().ok_or(format!("sequence {:?} invalid: toml parsing failed",path)));
+    let steps = try!(table.get("steps").ok_or(format!("sequence {:?} invalid: expect steps",path)));
+    let steps: Vec<Step> = try!(FromToml::from_toml(steps).map_err(|e| format!("sequence {:?} invalid: steps{}",path,e)));
+
+    Ok(Some(Sequence::new(steps)))
+}
+
+/// a sequence is run step by step each update,
+/// a step either complete instantly or hold the sequence until it is done
+pub struct Sequence {
+    steps: Vec<Step>,
+    current: usize,
+    elapsed: f32,
+    camera_start: Option<[f32;2]>,
+    camera: Option<[f32;2]>,
+    input_locked: bool,
+    advance: bool,
+}
+
+impl Sequence {
+    pub fn new(steps: Vec<Step>) -> Self {
+        Sequence {
+            steps: steps,
+            current: 0,
+            elapsed: 0.,
+            camera_start: None,
+            camera: None,
+            input_locked: false,
+            advance: false,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current >= self.steps.len()
+    }
+
+    pub fn input_locked(&self) -> bool {
+        !self.is_finished() && self.input_locked
+    }
+
+    /// the position the camera is forced to if any
+    pub fn camera(&self) -> Option<[f32;2]> {
+        if self.is_finished() { None } else { self.camera }
+    }
+
+    /// the speaker, the portrait and the line of the current dialogue if any
+    pub fn dialogue(&self) -> Option<(&str,Color,&str)> {
+        match self.steps.get(self.current) {
+            Some(&Step::Dialogue { ref speaker, portrait, ref line }) => Some((&*speaker,portrait,&*line)),
+            _ => None,
+        }
+    }
+
+    /// go to the next dialogue line
+    pub fn advance(&mut self) {
+        self.advance = true;
+    }
+
+    /// run all the remaining spawns and end the sequence
+    pub fn skip(&mut self, world: &mut specs::World) {
+        let mut spawned = false;
+        while self.current < self.steps.len() {
+            if let Step::Spawn { ref entity, position } = self.steps[self.current] {
+                spawn(world, entity, position);
+                spawned = true;
+            }
+            self.current += 1;
+        }
+        if spawned {
+            refill_physic_world(world);
+        }
+    }
+
+    pub fn update(&mut self, dt: f32, world: &mut specs::World, camera: [f32;2]) {
+        let mut spawned = false;
+
+        while self.current < self.steps.len() {
+            let done = match self.steps[self.current] {
+                Step::LockInput(lock) => {
+                    self.input_locked = lock;
+                    true
+                },
+                Step::Wait(time) => {
+                    self.elapsed += dt;
+                    self.elapsed >= time
+                },
+                Step::MoveCamera { position, time } => {
+                    if self.camera_start.is_none() {
+                        self.camera_start = Some(self.camera.unwrap_or(camera));
+                    }
+                    let start = self.camera_start.unwrap();
+                    self.elapsed += dt;
+                    let rate = if time > 0. { (self.elapsed/time).min(1.) } else { 1. };
+                    self.camera = Some([
+                        start[0] + (position[0] - start[0])*rate,
+                        start[1] + (position[1] - start[1])*rate,
+                    ]);
+                    rate >= 1.
+                },
+                Step::ReleaseCamera => {
+                    self.camera = None;
+                    true
+                },
+                Step::Dialogue { .. } => {
+                    let advance = self.advance;
+                    self.advance = false;
+                    advance
+                },
+                Step::Spawn { ref entity, position } => {
+                    spawn(world, entity, position);
+                    spawned = true;
+                    true
+                },
+                Step::Weather(state) => {
+                    world.write_resource::<weather::Weather>().change(state);
+                    true
+                },
+                Step::Relation { faction, toward, relation } => {
+                    world.write_resource::<faction::Relations>().set(faction, toward, relation);
+                    true
+                },
+                Step::WaitPlayerNear { position, radius } => {
+                    let characters = world.read::<PlayerControl>();
+                    let states = world.read::<PhysicState>();
+                    (&characters, &states).iter().any(|(_, state)| {
+                        (state.position[0] - position[0]).powi(2) + (state.position[1] - position[1]).powi(2) < radius.powi(2)
+                    })
+                },
+            };
+
+            if done {
+                self.current += 1;
+                self.elapsed = 0.;
+                self.camera_start = None;
+            } else {
+                break;
+            }
+        }
+
+        if spawned {
+            refill_physic_world(world);
+        }
+    }
+}
+
+fn spawn(world: &mut specs::World, entity: &str, pos: [isize;2]) {
+    use inventory::Stack;
+
+    match entity {
+        "wall" => entities::add_wall(world, pos),
+        "laser" => entities::add_laser(world, pos),
+        "monster" => entities::add_monster(world, pos),
+        "column" => entities::add_column(world, pos),
+        "chest" => entities::add_chest(world, pos),
+        "checkpoint" => entities::add_checkpoint(world, pos),
+        "pickup" => entities::add_pickup(world, pos, [0.,0.], Stack { item: 0, count: 1 }),
+        "foliage" => entities::add_foliage(world, pos),
+        "low_wall" => entities::add_low_wall(world, pos),
+        _ => unreachable!(),
+    }
+}
+
+/// spawn an entity of SPAWNABLE at a tile outside of a sequence
+pub fn spawn_now(world: &mut specs::World, entity: &str, pos: [isize;2]) {
+    spawn(world, entity, pos);
+    refill_physic_world(world);
+}
+
+/// static entities are only hashed when the physic world is filled
+fn refill_physic_world(world: &mut specs::World) {
+    let mut physic_world = world.write_resource::<physic::PhysicWorld>();
+    physic_world.fill(&world);
+}
@@ -0,0 +1,71 @@
+//! the settings of the player are kept in the configuration directory of the platform,
+//! in the directory of the profile of the session, so they survive the updates of the
+//! game and don't need a writable game directory:
+//! * save.toml: the options of the menu, the volumes and the display
+//! * the controls profiles of controls.dir
+//! * the stats file, with the achievements
+//!
+//! the directory is `$XDG_CONFIG_HOME/ruga` or `~/.config/ruga` on linux,
+//! `~/Library/Application Support/ruga` on macos and `%APPDATA%\ruga` on windows, the
+//! working directory if the variable isn't set. a settings file written by a previous
+//! version in the working directory or in the directory itself is copied to the
+//! default profile the first time.
+//!
+//! the files are written atomically with a backup of their previous content, a file
+//! that fails to load is moved aside and replaced by its backup or the defaults.
+//! save.toml is loaded with the configuration, before the window and the world.
+
+use profiles;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const NAME: &'static str = "ruga";
+
+lazy_static! {
+    /// the path of save.toml
+    pub static ref SAVE_PATH: PathBuf = file(Path::new("save.toml"));
+}
+
+#[cfg(target_os = "windows")]
+fn platform_dir() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(PathBuf::from)
+}
+
+#[cfg(target_os = "macos")]
+fn platform_dir() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Application Support"))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+/// the directory of the settings
+pub fn dir() -> PathBuf {
+    platform_dir().map(|dir| dir.join(NAME)).unwrap_or(PathBuf::from("."))
+}
+
+/// the path of the settings file relative to the directory of the profile, the file
+/// of a previous version is copied there if it is missing
+pub fn file(relative: &Path) -> PathBuf {
+    let path = profiles::file(relative);
+    if path.exists() || *profiles::SESSION != profiles::DEFAULT {
+        return path;
+    }
+    let legacy = [dir().join(relative), relative.into()];
+    if let Some(previous) = legacy.iter().find(|previous| previous.is_file()) {
+        let copied = path.parent().map(|parent| fs::create_dir_all(parent)).unwrap_or(Ok(()))
+            .and_then(|_| fs::copy(previous, &path));
+        match copied {
+            Ok(_) => println!("settings {:?} copied to {:?}", previous, path),
+            Err(err) => {
+                println!("WARNING: copy settings {:?} to {:?} failed: {}", previous, path, err);
+                return previous.clone();
+            },
+        }
+    }
+    path
+}
@@ -0,0 +1,118 @@
+//! the custom shaders, loaded from the fragment shaders `<name>.frag` of shaders.dir:
+//! * the materials of shaders.materials draw the entities whose graphic selects them,
+//!   the hit entities flash with shaders.flash_material and are outlined with
+//!   shaders.outline_material
+//! * the post passes of shaders.post_passes are applied to the screen in order when
+//!   the post effects are on
+//! * the color grading lookup tables `<name>.png` of shaders.luts_dir graded on the
+//!   screen in the regions of shaders.region_luts
+//!
+//! a shader that can't be read or compiled is reported and the default material is
+//! drawn instead, or the pass is skipped. a lookup table that can't be loaded keeps
+//! the colors.
+
+use config;
+use png;
+use graphics::{Graphics, Material, PostPass, Grading};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+fn read_shader(name: &str) -> Result<String,String> {
+    let path = PathBuf::from(&*config.shaders.dir.val).join(name).with_extension("frag");
+    let mut source = String::new();
+    try!(try!(fs::File::open(&path).map_err(|e| format!("open shader {:?} failed: {}",path,e)))
+         .read_to_string(&mut source)
+         .map_err(|_| format!("shader {:?} invalid: not valid UTF-8",path)));
+    Ok(source)
+}
+
+/// the rgb rows and the size of the lookup table
+fn read_lut(name: &str) -> Result<(Vec<u8>,u32),String> {
+    let path = PathBuf::from(&*config.shaders.luts_dir.val).join(name).with_extension("png");
+    let decoder = png::Decoder::new(try!(fs::File::open(&path).map_err(|e| format!("open lut {:?} failed: {}",path,e))));
+    let (info, mut reader) = try!(decoder.read_info().map_err(|e| format!("lut {:?} invalid: {}",path,e)));
+    if info.color_type != png::ColorType::RGB || info.bit_depth != png::BitDepth::Eight {
+        return Err(format!("lut {:?} invalid: expect 8 bits rgb",path));
+    }
+    if info.height == 0 || info.width != info.height * info.height {
+        return Err(format!("lut {:?} invalid: expect width the square of height",path));
+    }
+    let mut data = vec![0; 3 * info.width as usize * info.height as usize];
+    try!(reader.next_frame(&mut data).map_err(|e| format!("lut {:?} invalid: {}",path,e)));
+    Ok((data, info.height))
+}
+
+pub struct Shaders {
+    materials: HashMap<String,Material>,
+    post_passes: Vec<PostPass>,
+    gradings: HashMap<String,Grading>,
+    pub post_effects: bool,
+}
+impl Shaders {
+    pub fn load(graphics: &mut Graphics) -> Self {
+        let mut materials = HashMap::new();
+        for name in &config.shaders.materials {
+            match read_shader(name).and_then(|source| graphics.add_material(&*source).map_err(|e| format!("{}",e))) {
+                Ok(material) => { materials.insert(name.clone(), material); },
+                Err(err) => println!("WARNING: material {} replaced by the default one: {}", name, err),
+            }
+        }
+        let mut post_passes = vec!();
+        for name in &config.shaders.post_passes {
+            match read_shader(name).and_then(|source| graphics.add_post_pass(&*source).map_err(|e| format!("{}",e))) {
+                Ok(pass) => post_passes.push(pass),
+                Err(err) => println!("WARNING: post pass {} skipped: {}", name, err),
+            }
+        }
+        let mut gradings = HashMap::new();
+        for name in &config.shaders.luts {
+            match read_lut(name).and_then(|(lut, size)| graphics.add_grading(lut, size).map_err(|e| format!("{}",e))) {
+                Ok(grading) => { gradings.insert(name.clone(), grading); },
+                Err(err) => println!("WARNING: lut {} keeps the colors: {}", name, err),
+            }
+        }
+        let shaders = Shaders {
+            materials: materials,
+            post_passes: post_passes,
+            gradings: gradings,
+            post_effects: config.shaders.post_effects,
+        };
+        graphics.set_post_passes(shaders.active_post_passes());
+        shaders
+    }
+
+    /// the material of the name, the default one if it failed
+    pub fn material(&self, name: &str) -> Material {
+        self.materials.get(name).cloned().unwrap_or(Material::default())
+    }
+
+    /// the grading of the region, the default one if it has no lut or it failed
+    pub fn region_grading(&self, region: Option<&str>) -> Grading {
+        region.and_then(|region| config.shaders.region_luts.iter().find(|r| r[0] == region))
+            .and_then(|r| self.gradings.get(&r[1]).cloned())
+            .unwrap_or(Grading::default())
+    }
+
+    /// the post passes to apply
+    pub fn active_post_passes(&self) -> Vec<PostPass> {
+        if self.post_effects {
+            self.post_passes.clone()
+        } else {
+            vec!()
+        }
+    }
+}
+
+#[test]
+fn test_shaders_read() {
+    for name in config.shaders.materials.iter().chain(config.shaders.post_passes.iter()) {
+        assert!(read_shader(name).unwrap().contains("#version 150"));
+    }
+    assert!(read_shader("unknown").is_err());
+    for name in &config.shaders.luts {
+        let (lut, size) = read_lut(name).unwrap();
+        assert_eq!(lut.len() as u32, size*size*size*3);
+    }
+}
@@ -0,0 +1,226 @@
+//! vendors trading items for money at the prices of a table of shop.tables.
+//!
+//! interacting with a vendor opens its shop: up and down select an entry, right buys
+//! it and left sells it, escape or leaving the vendor closes the shop. trades are
+//! sent as Control::Trade so any system can trade, the app applies them and sends
+//! Bought and Sold events.
+
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use components::*;
+use inventory::Stack;
+use locale::Locale;
+
+pub type VecPriceEntry = Vec<PriceEntry>;
+
+/// * item: index of the item in items.definitions
+/// * buy: price the vendor sells the item at, 0 if it doesn't sell it
+/// * sell: price the vendor buys the item at, 0 if it doesn't buy it
+/// * stock: count of the item the vendor starts with
+pub struct PriceEntry {
+    pub item: usize,
+    pub buy: usize,
+    pub sell: usize,
+    pub stock: usize,
+}
+impl_from_into_toml_for_struct!( PriceEntry {
+    item: usize,
+    buy: usize,
+    sell: usize,
+    stock: usize,
+});
+
+pub struct PriceTable {
+    pub name: String,
+    pub entries: VecPriceEntry,
+}
+impl_from_into_toml_for_struct!( PriceTable {
+    name: String,
+    entries: VecPriceEntry,
+});
+
+/// the index of the table named name
+pub fn table(name: &str) -> Option<usize> {
+    config.shop.tables.iter().position(|t| t.name == name)
+}
+
+/// the money of an entity
+pub struct Wallet {
+    pub money: usize,
+}
+impl specs::Component for Wallet {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Wallet {
+    pub fn new(money: usize) -> Self {
+        Wallet {
+            money: money,
+        }
+    }
+}
+
+/// an entity trading at the prices of a table, its inventory is its stock
+pub struct Vendor {
+    pub table: usize,
+}
+impl specs::Component for Vendor {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Vendor {
+    pub fn named(name: &str) -> Self {
+        Vendor {
+            table: table(name).expect("price table must be defined in shop.tables"),
+        }
+    }
+
+    /// the stock at the start
+    pub fn stock(&self) -> Inventory {
+        let mut inventory = Inventory::new(config.entities.vendor_inventory_size);
+        for entry in &config.shop.tables[self.table].entries {
+            if entry.stock > 0 {
+                inventory.insert(Stack { item: entry.item, count: entry.stock });
+            }
+        }
+        inventory
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Trade {
+    /// the customer buys from the vendor
+    Buy,
+    /// the customer sells to the vendor
+    Sell,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ShopError {
+    NotTraded,
+    NotEnoughMoney,
+    OutOfStock,
+    NotOwned,
+    InventoryFull,
+}
+impl ShopError {
+    pub fn message(&self) -> &'static str {
+        match *self {
+            ShopError::NotTraded => "not traded here",
+            ShopError::NotEnoughMoney => "not enough money",
+            ShopError::OutOfStock => "out of stock",
+            ShopError::NotOwned => "nothing to sell",
+            ShopError::InventoryFull => "inventory full",
+        }
+    }
+}
+
+/// trade one item of the entry between the stock of the vendor and the inventory of
+/// the customer, return the price paid or received. nothing changes on error and
+/// items sold to a full stock are not kept
+pub fn trade(trade: Trade, entry: &PriceEntry, wallet: &mut Wallet, stock: &mut Inventory, inventory: &mut Inventory) -> Result<usize,ShopError> {
+    let one = Stack { item: entry.item, count: 1 };
+    match trade {
+        Trade::Buy => {
+            if entry.buy == 0 { return Err(ShopError::NotTraded) }
+            if wallet.money < entry.buy { return Err(ShopError::NotEnoughMoney) }
+            if stock.count(entry.item) == 0 { return Err(ShopError::OutOfStock) }
+            if inventory.insert(one) != 0 { return Err(ShopError::InventoryFull) }
+            stock.remove(entry.item, 1);
+            wallet.money -= entry.buy;
+            Ok(entry.buy)
+        },
+        Trade::Sell => {
+            if entry.sell == 0 { return Err(ShopError::NotTraded) }
+            if inventory.remove(entry.item, 1) == 0 { return Err(ShopError::NotOwned) }
+            stock.insert(one);
+            wallet.money += entry.sell;
+            Ok(entry.sell)
+        },
+    }
+}
+
+/// the shop opened by a customer at a vendor
+pub struct ShopView {
+    pub customer: specs::Entity,
+    pub vendor: specs::Entity,
+    selected: usize,
+}
+impl ShopView {
+    pub fn new(customer: specs::Entity, vendor: specs::Entity) -> Self {
+        ShopView {
+            customer: customer,
+            vendor: vendor,
+            selected: 0,
+        }
+    }
+
+    fn entries(&self, world: &specs::World) -> &'static [PriceEntry] {
+        world.read::<Vendor>().get(self.vendor)
+            .map(|vendor| &*config.shop.tables[vendor.table].entries)
+            .unwrap_or(&[])
+    }
+
+    /// move the selection by delta entries
+    pub fn select(&mut self, world: &specs::World, delta: isize) {
+        let len = self.entries(world).len() as isize;
+        if len > 0 {
+            self.selected = (((self.selected as isize + delta) % len + len) % len) as usize;
+        }
+    }
+
+    /// the item of the selected entry
+    pub fn selected(&self, world: &specs::World) -> Option<usize> {
+        self.entries(world).get(self.selected).map(|entry| entry.item)
+    }
+
+    /// the money of the customer and the entries with their prices,
+    /// the stock of the vendor and the count owned by the customer
+    pub fn text(&self, world: &specs::World, locale: &Locale) -> String {
+        let inventories = world.read::<Inventory>();
+        let money = world.read::<Wallet>().get(self.customer).map(|w| w.money).unwrap_or(0);
+        let mut text = locale.plural("money", money, &[]);
+        text.push('\n');
+        for (i, entry) in self.entries(world).iter().enumerate() {
+            let cursor = if i == self.selected { ">" } else { " " };
+            let name = config.items.definitions.get(entry.item).map(|d| &*d.name).unwrap_or("?");
+            let stock = inventories.get(self.vendor).map(|inventory| inventory.count(entry.item)).unwrap_or(0);
+            let owned = inventories.get(self.customer).map(|inventory| inventory.count(entry.item)).unwrap_or(0);
+            text.push_str(&*locale.format("shop_entry", &[
+                ("cursor", cursor),
+                ("name", &*locale.get(name)),
+                ("buy", &*entry.buy.to_string()),
+                ("sell", &*entry.sell.to_string()),
+                ("stock", &*stock.to_string()),
+                ("owned", &*owned.to_string()),
+            ]));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[test]
+fn test_trade() {
+    let entry = PriceEntry { item: 0, buy: 3, sell: 1, stock: 1 };
+    let mut wallet = Wallet::new(4);
+    let mut stock = Inventory::new(1);
+    stock.insert(Stack { item: 0, count: 1 });
+    let mut inventory = Inventory::new(1);
+
+    assert_eq!(trade(Trade::Buy, &entry, &mut wallet, &mut stock, &mut inventory), Ok(3));
+    assert_eq!(wallet.money, 1);
+    assert_eq!(inventory.count(0), 1);
+    assert_eq!(trade(Trade::Buy, &entry, &mut wallet, &mut stock, &mut inventory), Err(ShopError::NotEnoughMoney));
+
+    wallet.money = 10;
+    assert_eq!(trade(Trade::Buy, &entry, &mut wallet, &mut stock, &mut inventory), Err(ShopError::OutOfStock));
+
+    assert_eq!(trade(Trade::Sell, &entry, &mut wallet, &mut stock, &mut inventory), Ok(1));
+    assert_eq!(wallet.money, 11);
+    assert_eq!(stock.count(0), 1);
+    assert_eq!(trade(Trade::Sell, &entry, &mut wallet, &mut stock, &mut inventory), Err(ShopError::NotOwned));
+
+    let entry = PriceEntry { item: 0, buy: 0, sell: 0, stock: 0 };
+    assert_eq!(trade(Trade::Buy, &entry, &mut wallet, &mut stock, &mut inventory), Err(ShopError::NotTraded));
+}
@@ -0,0 +1,196 @@
+use app::GameEvent;
+use life::Kind;
+use specs;
+use config;
+use configuration::{self, FromToml, IntoToml};
+use toml;
+use savegame;
+use settings;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+pub type Counters = BTreeMap<String,f32>;
+pub type VecString = Vec<String>;
+
+/// an achievement is unlocked when the counter named stat
+/// reach the threshold
+pub struct AchievementDefinition {
+    pub name: String,
+    pub stat: String,
+    pub threshold: f32,
+}
+impl_from_into_toml_for_struct!( AchievementDefinition {
+    name: String,
+    stat: String,
+    threshold: f32,
+});
+
+/// statistics accumulated from gameplay events and persisted across sessions
+///
+/// counters are named:
+/// * `time`: time played in seconds
+/// * `kill.<kind>`: number of entities of the kind killed by the player
+/// * `damage.dealt`, `damage.taken`: damage dealt and taken by the player
+/// * `death`: number of death of the player
+/// * `secret`: number of secrets found, a secret is a region whose name starts with
+///   stats.secret_prefix, `secret.<region>` is set once it is found
+/// * `pickup.<item>`: number of items picked up
+/// * `checkpoint`, `respawn`, `interact`
+pub struct Stats {
+    counters: Counters,
+    unlocked: VecString,
+}
+impl_from_into_toml_for_struct!( Stats {
+    counters: Counters,
+    unlocked: VecString,
+});
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            counters: BTreeMap::new(),
+            unlocked: vec!(),
+        }
+    }
+
+    /// load stats from the stats file, start empty stats if there is none
+    pub fn load() -> Result<Stats,String> {
+        let path = settings::file(Path::new(&*config.stats.file.val));
+        if !path.is_file() {
+            return Ok(Stats::new());
+        }
+
+        let mut string = String::new();
+        try!(try!(File::open(&path).map_err(|e| format!("open stats file failed: {}",e)))
+             .read_to_string(&mut string)
+             .map_err(|_| String::from("stats file invalid: not valid UTF-8")));
+
+        let mut parser = toml::Parser::new(&*string);
+        let mut table = try!(parser.parse().ok_or(String::from("stats file invalid: toml parsing failed")));
+        let defaults = match IntoToml::into_toml(Stats::new()) {
+            toml::Value::Table(defaults) => defaults,
+            _ => unreachable!(),
+        };
+        let report = try!(configuration::migrate_save(&mut table, savegame::STATS_VERSION, savegame::STATS_MIGRATIONS, &defaults)
+                          .map_err(|e| format!("stats file invalid:{}",e)));
+        savegame::print_report(&*config.stats.file.val, &report);
+        Stats::from_toml(&toml::Value::Table(table)).map_err(|e| format!("stats file invalid:{}",e))
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let stats = Stats {
+            counters: self.counters.clone(),
+            unlocked: self.unlocked.clone(),
+        };
+        let contents = format!("{}",configuration::versioned(IntoToml::into_toml(stats), savegame::STATS_VERSION));
+        configuration::write_atomic(&settings::file(Path::new(&*config.stats.file.val)), &*contents)
+    }
+
+    pub fn get(&self, stat: &str) -> f32 {
+        self.counters.get(stat).cloned().unwrap_or(0.)
+    }
+
+    pub fn unlocked(&self) -> &[String] {
+        &self.unlocked
+    }
+
+    /// add to the counter and return the names of the achievements unlocked
+    pub fn add(&mut self, stat: &str, value: f32) -> Vec<String> {
+        *self.counters.entry(stat.into()).or_insert(0.) += value;
+
+        let mut unlocked = vec!();
+        for achievement in &config.stats.achievements {
+            if achievement.stat == stat
+                && self.get(stat) >= achievement.threshold
+                && !self.unlocked.contains(&achievement.name)
+            {
+                self.unlocked.push(achievement.name.clone());
+                unlocked.push(achievement.name.clone());
+            }
+        }
+        unlocked
+    }
+
+    /// update counters from a gameplay event and
+    /// return the names of the achievements unlocked
+    pub fn handle<F: Fn(specs::Entity) -> bool>(&mut self, event: &GameEvent, is_player: F) -> Vec<String> {
+        match *event {
+            GameEvent::Died(entity,Kind::Character,_) if is_player(entity) => self.add("death", 1.),
+            GameEvent::Killed(killer,_,kind) if is_player(killer) => self.add(&*format!("kill.{}",kind.name()), 1.),
+            GameEvent::Damaged(ref damage) => {
+                let mut unlocked = vec!();
+                if is_player(damage.source) {
+                    unlocked.extend(self.add("damage.dealt", damage.amount));
+                }
+                if is_player(damage.target) {
+                    unlocked.extend(self.add("damage.taken", damage.amount));
+                }
+                unlocked
+            },
+            GameEvent::RegionEntered(entity,ref name) if is_player(entity) && name.starts_with(&*config.stats.secret_prefix) => {
+                let secret = format!("secret.{}",name);
+                if self.get(&*secret) > 0. {
+                    return vec!();
+                }
+                let mut unlocked = self.add(&*secret, 1.);
+                unlocked.extend(self.add("secret", 1.));
+                unlocked
+            },
+            GameEvent::ItemPickedUp(entity,stack) if is_player(entity) => {
+                let name = config.items.definitions.get(stack.item).map(|d| &*d.name).unwrap_or("?");
+                self.add(&*format!("pickup.{}",name), stack.count as f32)
+            },
+            GameEvent::CheckpointReached(entity,_) if is_player(entity) => self.add("checkpoint", 1.),
+            GameEvent::Respawned(entity) if is_player(entity) => self.add("respawn", 1.),
+            GameEvent::Interact(entity,_) if is_player(entity) => self.add("interact", 1.),
+            _ => vec!(),
+        }
+    }
+}
+
+#[test]
+fn test_kills_are_by_the_player() {
+    let mut world = specs::World::new();
+    let player = world.create_now().build();
+    let monster = world.create_now().build();
+    let is_player = |entity| entity == player;
+    let mut stats = Stats::new();
+    stats.handle(&GameEvent::Died(monster, Kind::Monster, [0.,0.]), &is_player);
+    stats.handle(&GameEvent::Killed(player, monster, Kind::Monster), &is_player);
+    stats.handle(&GameEvent::Killed(monster, monster, Kind::Monster), &is_player);
+    stats.handle(&GameEvent::Died(player, Kind::Character, [0.,0.]), &is_player);
+    stats.handle(&GameEvent::Died(monster, Kind::Character, [0.,0.]), &is_player);
+    assert_eq!(stats.get("kill.monster"), 1.);
+    assert_eq!(stats.get("death"), 1.);
+}
+
+#[test]
+fn test_damage_dealt_and_taken() {
+    use combat::{Damage, DamageType};
+
+    let mut world = specs::World::new();
+    let player = world.create_now().build();
+    let monster = world.create_now().build();
+    let is_player = |entity| entity == player;
+    let mut stats = Stats::new();
+    stats.handle(&GameEvent::Damaged(Damage::new(player, monster, [0.,0.], 2., DamageType::Contact)), &is_player);
+    stats.handle(&GameEvent::Damaged(Damage::new(monster, player, [0.,0.], 3., DamageType::Contact)), &is_player);
+    stats.handle(&GameEvent::Damaged(Damage::new(monster, monster, [0.,0.], 5., DamageType::Contact)), &is_player);
+    assert_eq!(stats.get("damage.dealt"), 2.);
+    assert_eq!(stats.get("damage.taken"), 3.);
+}
+
+#[test]
+fn test_secrets_are_found_once() {
+    let mut world = specs::World::new();
+    let player = world.create_now().build();
+    let is_player = |entity| entity == player;
+    let secret = format!("{}cellar", config.stats.secret_prefix);
+    let mut stats = Stats::new();
+    stats.handle(&GameEvent::RegionEntered(player, secret.clone()), &is_player);
+    stats.handle(&GameEvent::RegionEntered(player, secret.clone()), &is_player);
+    stats.handle(&GameEvent::RegionEntered(player, String::from("hall")), &is_player);
+    assert_eq!(stats.get("secret"), 1.);
+}
@@ -0,0 +1,120 @@
+//! choice of the target of an attacker shared by everything that attacks: the
+//! entities of targeting.mask in range are scored by distance, line of sight, threat
+//! and relation of factions with the weights of targeting in the configuration.
+//!
+//! the threat of an entity is the damage it dealt recently, it decays over time.
+
+use app;
+use specs;
+use config;
+use components::*;
+use resource::*;
+use faction::Relation;
+use specs::Join;
+
+pub struct Threat {
+    pub value: f32,
+}
+impl specs::Component for Threat {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Threat {
+    pub fn new() -> Self {
+        Threat {
+            value: 0.,
+        }
+    }
+    /// the entity dealt damage
+    pub fn hit(&mut self) {
+        self.value += config.targeting.threat_per_hit;
+    }
+}
+
+pub struct ThreatSystem;
+impl specs::System<app::UpdateContext> for ThreatSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let mut threats = arg.fetch(|world| world.write::<Threat>());
+
+        for threat in (&mut threats).iter() {
+            threat.value = (threat.value - config.targeting.threat_decay*context.dt).max(0.);
+        }
+    }
+}
+
+/// what is known of an entity that can be targeted
+pub struct Candidate<'a> {
+    pub position: [f32;2],
+    pub faction: Option<&'a Faction>,
+    pub threat: f32,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Target {
+    pub entity: specs::Entity,
+    pub position: [f32;2],
+    pub relation: Relation,
+    pub visible: bool,
+    pub score: f32,
+}
+
+/// the candidates in range of the attacker at position that are not friendly to it, the
+/// highest score first. visibility is the line of sight of vision_mask
+pub fn targets<'a, F>(attacker: specs::Entity, position: [f32;2], faction: Option<&Faction>, range: f32, vision_mask: u32,
+                      physic_world: &PhysicWorld, line_of_sight: &mut LineOfSight, relations: &Relations, candidate: F) -> Vec<Target>
+    where F: Fn(specs::Entity) -> Option<Candidate<'a>>
+{
+    let mut entities = vec!();
+    physic_world.apply_on_region(&QueryRegion::Circle { center: position, radius: range }, config.targeting.mask.val, &mut |entity| {
+        if *entity != attacker {
+            entities.push(*entity);
+        }
+    });
+    entities.sort_by_key(|e| e.get_id());
+
+    let mut targets = vec!();
+    for entity in entities {
+        let candidate = match candidate(entity) {
+            Some(candidate) => candidate,
+            None => continue,
+        };
+        let relation = relations.get(faction, candidate.faction);
+        if relation == Relation::Friendly {
+            continue;
+        }
+        let visible = line_of_sight.los(physic_world, attacker, position, entity, candidate.position, vision_mask);
+        targets.push(Target {
+            entity: entity,
+            position: candidate.position,
+            relation: relation,
+            visible: visible,
+            score: score(position, range, &candidate, relation, visible),
+        });
+    }
+    targets.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    targets
+}
+
+fn score(position: [f32;2], range: f32, candidate: &Candidate, relation: Relation, visible: bool) -> f32 {
+    let distance = ((candidate.position[0] - position[0]).powi(2) + (candidate.position[1] - position[1]).powi(2)).sqrt();
+    let mut score = config.targeting.distance_weight*(1. - distance/range).max(0.)
+        + config.targeting.threat_weight*candidate.threat;
+    if visible {
+        score += config.targeting.visibility_weight;
+    }
+    if relation == Relation::Hostile {
+        score += config.targeting.hostility_weight;
+    }
+    score
+}
+
+#[test]
+fn test_score() {
+    let near = Candidate { position: [1., 0.], faction: None, threat: 0. };
+    let far = Candidate { position: [5., 0.], faction: None, threat: 0. };
+    let threatening = Candidate { position: [5., 0.], faction: None, threat: 10. };
+
+    assert!(score([0., 0.], 10., &near, Relation::Neutral, false) > score([0., 0.], 10., &far, Relation::Neutral, false));
+    assert!(score([0., 0.], 10., &far, Relation::Neutral, true) > score([0., 0.], 10., &far, Relation::Neutral, false));
+    assert!(score([0., 0.], 10., &far, Relation::Hostile, false) > score([0., 0.], 10., &far, Relation::Neutral, false));
+    assert!(score([0., 0.], 10., &threatening, Relation::Neutral, false) > score([0., 0.], 10., &far, Relation::Neutral, false));
+}
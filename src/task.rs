@@ -0,0 +1,129 @@
+//! tasks are linear scripts run along the updates: a task is a list of steps,
+//! a step either runs instantly or waits for some time.
+//!
+//! ```ignore
+//! tasks.spawn_for(boss, Task::new()
+//!     .then(move |world| sequence::spawn_now(world, "monster", first))
+//!     .wait_seconds(config.bosses.add_interval)
+//!     .then(move |world| sequence::spawn_now(world, "monster", second)));
+//! ```
+
+use specs;
+use components::*;
+use std::collections::VecDeque;
+
+enum Step {
+    WaitSeconds(f32),
+    Run(Box<FnMut(&mut specs::World)>),
+}
+
+pub struct Task {
+    steps: VecDeque<Step>,
+    elapsed: f32,
+}
+
+impl Task {
+    pub fn new() -> Self {
+        Task {
+            steps: VecDeque::new(),
+            elapsed: 0.,
+        }
+    }
+
+    pub fn wait_seconds(mut self, time: f32) -> Self {
+        self.steps.push_back(Step::WaitSeconds(time));
+        self
+    }
+
+    pub fn then<F: FnMut(&mut specs::World) + 'static>(mut self, action: F) -> Self {
+        self.steps.push_back(Step::Run(Box::new(action)));
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// run the steps until one waits, time left when a wait ends is carried over
+    /// to the next steps so the task does not depend on the frame rate
+    fn update(&mut self, dt: f32, world: &mut specs::World) {
+        self.elapsed += dt;
+        loop {
+            let done = match self.steps.front_mut() {
+                Some(&mut Step::WaitSeconds(time)) => if self.elapsed >= time {
+                    self.elapsed -= time;
+                    true
+                } else {
+                    false
+                },
+                Some(&mut Step::Run(ref mut action)) => {
+                    action(world);
+                    true
+                },
+                None => break,
+            };
+
+            if done {
+                self.steps.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// the tasks running, a task is dropped when its entity is deleted
+pub struct Tasks {
+    tasks: Vec<(specs::Entity,Task)>,
+}
+
+impl Tasks {
+    pub fn new() -> Self {
+        Tasks {
+            tasks: vec!(),
+        }
+    }
+
+    pub fn spawn_for(&mut self, entity: specs::Entity, task: Task) {
+        self.tasks.push((entity, task));
+    }
+
+    pub fn clear(&mut self) {
+        self.tasks.clear();
+    }
+
+    pub fn update(&mut self, dt: f32, world: &mut specs::World) {
+        self.tasks.retain(|&(entity, _)| world.read::<PhysicState>().get(entity).is_some());
+        for &mut (_, ref mut task) in &mut self.tasks {
+            task.update(dt, world);
+        }
+        self.tasks.retain(|&(_, ref task)| !task.is_finished());
+    }
+}
+
+#[test]
+fn test_task_frame_rate_independent() {
+    use std::rc::Rc;
+    use std::cell::Cell;
+
+    let mut world = specs::World::new();
+
+    let count = Rc::new(Cell::new(0));
+    let c = count.clone();
+    let mut task = Task::new()
+        .wait_seconds(1.)
+        .then(move |_| c.set(c.get() + 1))
+        .wait_seconds(0.5)
+        .wait_seconds(1.)
+        .then(|_| ());
+
+    // one update of 1.5 seconds is the same as several small ones
+    task.update(1.5, &mut world);
+    assert_eq!(count.get(), 1);
+    assert!(!task.is_finished());
+
+    task.update(0.5, &mut world);
+    assert!(!task.is_finished());
+    task.update(0.5, &mut world);
+    assert!(task.is_finished());
+}
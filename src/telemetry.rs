@@ -0,0 +1,148 @@
+//! the telemetry writes gameplay events as json lines for balancing, opt in with
+//! `--telemetry <file>`. each line is an object with the time in seconds since the
+//! start of the game, the name of the event and its fields:
+//! * `death`: level, kind of the entity, x and y of its position
+//! * `damage`: level, type, amount, crit, x and y of the position of the target
+//! * `level_completed`: level left through a portal and duration spent in it
+//!
+//! `--telemetry-heatmap <file> <csv>` aggregates the deaths of characters of a telemetry
+//! file over the tiles of the maps and writes the count of each tile as csv.
+
+use combat::Damage;
+use life::Kind;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+fn escape(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub struct Telemetry {
+    export: Option<BufWriter<File>>,
+    time: f32,
+    level: String,
+    level_start: f32,
+}
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Telemetry {
+            export: None,
+            time: 0.,
+            level: String::new(),
+            level_start: 0.,
+        }
+    }
+
+    /// write the events recorded from now on to the file
+    pub fn export(&mut self, path: &Path) -> io::Result<()> {
+        self.export = Some(BufWriter::new(try!(File::create(path))));
+        Ok(())
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    fn write(&mut self, event: &str, fields: String) {
+        if let Some(ref mut writer) = self.export {
+            let result = writeln!(writer, "{{\"time\":{},\"event\":\"{}\",\"level\":\"{}\",{}}}",
+                                  self.time, event, escape(&self.level), fields);
+            if let Err(err) = result {
+                println!("WARNING: telemetry export failed: {}", err);
+            }
+        }
+    }
+
+    /// the level entered
+    pub fn level_started(&mut self, level: String) {
+        self.level = level;
+        self.level_start = self.time;
+    }
+
+    /// the level is left through a portal
+    pub fn level_completed(&mut self) {
+        let duration = self.time - self.level_start;
+        self.write("level_completed", format!("\"duration\":{}", duration));
+    }
+
+    pub fn death(&mut self, kind: Kind, position: [f32;2]) {
+        self.write("death", format!("\"kind\":\"{}\",\"x\":{},\"y\":{}", kind.name(), position[0], position[1]));
+    }
+
+    pub fn damage(&mut self, damage: &Damage) {
+        self.write("damage", format!("\"type\":\"{}\",\"amount\":{},\"crit\":{},\"x\":{},\"y\":{}",
+                                     damage.typ.name(), damage.amount, damage.crit, damage.position[0], damage.position[1]));
+    }
+}
+
+/// the raw value of the field of the json line written by the telemetry
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let pattern = format!("\"{}\":", key);
+    let start = match line.find(&*pattern) {
+        Some(start) => start + pattern.len(),
+        None => return None,
+    };
+    let rest = &line[start..];
+    if rest.starts_with('"') {
+        // the string ends at the first quote not escaped
+        let mut escaped = false;
+        for (i, c) in rest.char_indices().skip(1) {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => return Some(&rest[1..i]),
+                _ => escaped = false,
+            }
+        }
+        None
+    } else {
+        rest.find(|c: char| c == ',' || c == '}').map(|end| &rest[..end])
+    }
+}
+
+/// the number of deaths of characters of each tile of each level of the telemetry lines
+pub fn death_heatmap<R: BufRead>(reader: R) -> Result<BTreeMap<(String,[isize;2]),usize>, String> {
+    let mut heatmap = BTreeMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = try!(line.map_err(|e| format!("read failed: {}", e)));
+        if field(&line, "event") != Some("death") || field(&line, "kind") != Some(Kind::Character.name()) {
+            continue;
+        }
+        let invalid = || format!("line {} invalid: {}", i + 1, line);
+        let level = try!(field(&line, "level").ok_or_else(&invalid)).replace("\\\"", "\"").replace("\\\\", "\\");
+        let x: f32 = try!(field(&line, "x").and_then(|x| x.parse().ok()).ok_or_else(&invalid));
+        let y: f32 = try!(field(&line, "y").and_then(|y| y.parse().ok()).ok_or_else(&invalid));
+        // tiles of the maps are centered on integer positions
+        let tile = [x.round() as isize, y.round() as isize];
+        *heatmap.entry((level, tile)).or_insert(0) += 1;
+    }
+    Ok(heatmap)
+}
+
+/// write the death heatmap of the telemetry file as csv
+pub fn export_death_heatmap(telemetry: &Path, csv: &Path) -> Result<(), String> {
+    let file = try!(File::open(telemetry).map_err(|e| format!("open telemetry failed: {}", e)));
+    let heatmap = try!(death_heatmap(BufReader::new(file)));
+
+    let mut writer = BufWriter::new(try!(File::create(csv).map_err(|e| format!("create heatmap failed: {}", e))));
+    try!(writeln!(writer, "level,x,y,deaths").map_err(|e| format!("write heatmap failed: {}", e)));
+    for (&(ref level, tile), count) in &heatmap {
+        try!(writeln!(writer, "{},{},{},{}", level, tile[0], tile[1], count).map_err(|e| format!("write heatmap failed: {}", e)));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_death_heatmap() {
+    let lines = "{\"time\":1,\"event\":\"death\",\"level\":\"a/\\\"b\\\"/c\",\"kind\":\"monster\",\"x\":2.1,\"y\":-3.2}\n\
+                 {\"time\":2,\"event\":\"damage\",\"level\":\"a\",\"type\":\"melee\",\"amount\":1,\"crit\":false,\"x\":0,\"y\":0}\n\
+                 {\"time\":3,\"event\":\"death\",\"level\":\"a/\\\"b\\\"/c\",\"kind\":\"character\",\"x\":1.9,\"y\":-2.8}\n\
+                 {\"time\":4,\"event\":\"death\",\"level\":\"a/\\\"b\\\"/c\",\"kind\":\"character\",\"x\":2.2,\"y\":-3.4}\n\
+                 {\"time\":5,\"event\":\"death\",\"level\":\"a\",\"kind\":\"ball\",\"x\":0,\"y\":0}\n";
+    // only the deaths of characters are counted
+    let heatmap = death_heatmap(io::Cursor::new(lines)).unwrap();
+    assert_eq!(heatmap.len(), 1);
+    assert_eq!(heatmap.get(&(String::from("a/\"b\"/c"), [2, -3])), Some(&2));
+}
@@ -0,0 +1,218 @@
+//! regression traces: a scenario of spawns and inputs is simulated for a number of
+//! frames and the states of its bodies are written in a canonical text trace.
+//!
+//! traces are compared to the ones committed in the traces directory so changes of the
+//! physic or of the controls that alter the behavior fail `cargo test`. a missing trace
+//! fails too, set RUGA_RECORD_TRACES to record the traces, again after a wanted change.
+
+use app;
+use specs;
+use config;
+use physic;
+use components::*;
+use systems::{PhysicSystem, TowardPlayerSystem};
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+pub enum Spawn {
+    Wall([isize;2]),
+    /// a body controlled by the inputs
+    Body([f32;2]),
+    /// a body going toward the player
+    Chaser([f32;2]),
+    Player([f32;2]),
+}
+
+/// set the force of the body of index in the spawns at frame
+pub struct Input {
+    pub frame: usize,
+    pub body: usize,
+    pub direction: f32,
+    pub intensity: f32,
+}
+
+pub struct Scenario {
+    pub spawns: Vec<Spawn>,
+    pub inputs: Vec<Input>,
+    pub frames: usize,
+}
+
+/// a world with the physic and controls components and the physic world, the tests
+/// of the systems run on it too
+pub fn new_world() -> specs::World {
+    let mut world = specs::World::new();
+    world.register::<PhysicState>();
+    world.register::<PhysicType>();
+    world.register::<PhysicForce>();
+    world.register::<PhysicDynamic>();
+    world.register::<PhysicStatic>();
+    world.register::<PhysicTrigger>();
+    world.register::<PhysicTeleported>();
+    world.register::<UpdateLod>();
+    world.register::<Movement>();
+    world.register::<PlayerControl>();
+    world.register::<TowardPlayerControl>();
+    world.add_resource(physic::PhysicWorld::new());
+    world
+}
+
+pub fn new_context() -> app::UpdateContext {
+    let (effect_tx, _) = mpsc::channel();
+    let (control_tx, _) = mpsc::channel();
+    let (event_tx, _) = mpsc::channel();
+    app::UpdateContext {
+        effect_tx: effect_tx,
+        control_tx: control_tx,
+        event_tx: event_tx,
+        dt: 1. / config.event_loop.ups as f32,
+        ai_lod: 1.,
+    }
+}
+
+/// a movable body of the size of a monster
+pub fn add_body(world: &mut specs::World, position: [f32;2]) -> specs::Entity {
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new(position))
+        .with::<PhysicDynamic>(PhysicDynamic)
+        .with::<PhysicType>(PhysicType::new_movable(
+                config.entities.monster_group.val,
+                config.entities.monster_mask.val,
+                Shape::Circle(config.entities.monster_radius),
+                CollisionBehavior::Persist,
+                config.entities.monster_velocity,
+                config.entities.monster_time,
+                config.entities.monster_weight))
+        .with::<PhysicForce>(PhysicForce::new())
+        .build()
+}
+
+fn spawn(world: &mut specs::World, spawn: &Spawn) -> Option<specs::Entity> {
+    match *spawn {
+        Spawn::Wall(position) => {
+            world.create_now()
+                .with::<PhysicState>(PhysicState::new(position))
+                .with::<PhysicStatic>(PhysicStatic)
+                .with::<PhysicType>(PhysicType::new_static(
+                        config.entities.wall_group.val,
+                        config.entities.wall_mask.val,
+                        Shape::Square(config.entities.wall_radius)))
+                .build();
+            None
+        },
+        Spawn::Body(position) => Some(add_body(world, position)),
+        Spawn::Chaser(position) => {
+            let entity = add_body(world, position);
+            world.write::<TowardPlayerControl>().insert(entity, TowardPlayerControl);
+            world.write::<PhysicForce>().get_mut(entity).unwrap().intensity = 1.;
+            Some(entity)
+        },
+        Spawn::Player(position) => {
+            let entity = add_body(world, position);
+            world.write::<PlayerControl>().insert(entity, PlayerControl);
+            Some(entity)
+        },
+    }
+}
+
+/// simulate the scenario and return its trace:
+/// a line per frame and body with the index of the body, its position and velocity
+pub fn record(scenario: &Scenario) -> String {
+    let mut world = new_world();
+    let bodies: Vec<Option<specs::Entity>> = scenario.spawns.iter().map(|s| spawn(&mut world, s)).collect();
+    {
+        let mut physic_world = world.write_resource::<physic::PhysicWorld>();
+        physic_world.fill(&world);
+    }
+
+    let mut planner = specs::Planner::new(world, 1);
+    app::add_system(&mut planner, PhysicSystem, "physic", "physic");
+    app::add_system(&mut planner, TowardPlayerSystem, "toward_player", "control");
+
+    let mut trace = String::new();
+    for frame in 0..scenario.frames {
+        {
+            let world = planner.mut_world();
+            let mut forces = world.write::<PhysicForce>();
+            for input in scenario.inputs.iter().filter(|i| i.frame == frame) {
+                let entity = bodies[input.body].expect("input on a spawn that is not a body");
+                let force = forces.get_mut(entity).unwrap();
+                force.direction = input.direction;
+                force.intensity = input.intensity;
+            }
+        }
+
+        planner.dispatch(new_context());
+        planner.wait();
+
+        let world = planner.mut_world();
+        let states = world.read::<PhysicState>();
+        for (i, entity) in bodies.iter().enumerate() {
+            if let Some(state) = entity.and_then(|e| states.get(e)) {
+                write!(trace, "{} {} {:.3} {:.3} {:.3} {:.3}\n",
+                       frame, i,
+                       state.position[0], state.position[1],
+                       state.velocity[0], state.velocity[1]).unwrap();
+            }
+        }
+    }
+    trace
+}
+
+/// compare the trace to the one committed under name,
+/// record it instead with RUGA_RECORD_TRACES set
+pub fn check(name: &str, trace: &str) {
+    let path = PathBuf::from("traces").join(name).with_extension("trace");
+
+    if env::var("RUGA_RECORD_TRACES").is_ok() {
+        fs::create_dir_all("traces").unwrap();
+        fs::File::create(&path).unwrap().write_all(trace.as_bytes()).unwrap();
+        println!("trace {:?} recorded", path);
+        return;
+    }
+    assert!(path.is_file(), "trace {:?} missing, record it with RUGA_RECORD_TRACES=1 and commit it", path);
+
+    let mut expected = String::new();
+    fs::File::open(&path).unwrap().read_to_string(&mut expected).unwrap();
+    for (i, (expected, actual)) in expected.lines().zip(trace.lines()).enumerate() {
+        assert!(expected == actual, "trace {:?} differs at line {}:\n  expected: {}\n  actual:   {}", path, i+1, expected, actual);
+    }
+    assert_eq!(expected.lines().count(), trace.lines().count(), "trace {:?} length differs", path);
+}
+
+#[test]
+fn test_trace_chasers_in_room() {
+    use std::f32::consts::PI;
+
+    let mut spawns = vec!();
+    for i in -1..9 {
+        spawns.push(Spawn::Wall([i, -1]));
+        spawns.push(Spawn::Wall([i, 8]));
+        spawns.push(Spawn::Wall([-1, i]));
+        spawns.push(Spawn::Wall([8, i]));
+    }
+    let player = spawns.len();
+    spawns.push(Spawn::Player([1.5, 1.5]));
+    spawns.push(Spawn::Chaser([6.5, 6.5]));
+    spawns.push(Spawn::Chaser([6.5, 1.5]));
+    let body = spawns.len();
+    spawns.push(Spawn::Body([3.5, 5.5]));
+
+    let scenario = Scenario {
+        spawns: spawns,
+        inputs: vec!(
+            Input { frame: 0, body: player, direction: 0., intensity: 1. },
+            Input { frame: 30, body: player, direction: PI/2., intensity: 1. },
+            Input { frame: 60, body: player, direction: 0., intensity: 0. },
+            Input { frame: 10, body: body, direction: -PI/2., intensity: 1. },
+        ),
+        frames: 120,
+    };
+
+    let trace = record(&scenario);
+    assert_eq!(trace, record(&scenario));
+    check("chasers_in_room", &trace);
+}
@@ -82,6 +82,73 @@ fn test_minus_pi_pi() {
     assert!((minus_pi_pi(3.*PI)-PI).abs() < 0.001);
 }
 
+/// the center of the cell for a grid of cells of size unit, cell [0,0] goes
+/// from [0,0] to [unit,unit]
+#[inline]
+pub fn cell_center(cell: [i32;2], unit: f32) -> [f32;2] {
+    [(cell[0] as f32 + 0.5)*unit, (cell[1] as f32 + 0.5)*unit]
+}
+
+/// the cells from min included to max excluded
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct CellRect {
+    pub min: [i32;2],
+    pub max: [i32;2],
+}
+
+impl CellRect {
+    /// the cells overlapping the rectangle from min to max,
+    /// cells only touching its border are not included
+    pub fn covering(min: [f32;2], max: [f32;2], unit: f32) -> CellRect {
+        use std::f32;
+
+        CellRect {
+            min: [((min[0]+f32::EPSILON)/unit).floor() as i32, ((min[1]+f32::EPSILON)/unit).floor() as i32],
+            max: [((max[0]-f32::EPSILON)/unit).ceil() as i32, ((max[1]-f32::EPSILON)/unit).ceil() as i32],
+        }
+    }
+
+    pub fn iter(&self) -> CellRectIter {
+        CellRectIter {
+            rect: *self,
+            next: self.min,
+        }
+    }
+}
+
+/// iterate column by column
+pub struct CellRectIter {
+    rect: CellRect,
+    next: [i32;2],
+}
+
+impl Iterator for CellRectIter {
+    type Item = [i32;2];
+
+    fn next(&mut self) -> Option<[i32;2]> {
+        if self.rect.min[1] >= self.rect.max[1] || self.next[0] >= self.rect.max[0] {
+            return None;
+        }
+        let cell = self.next;
+        self.next[1] += 1;
+        if self.next[1] >= self.rect.max[1] {
+            self.next[1] = self.rect.min[1];
+            self.next[0] += 1;
+        }
+        Some(cell)
+    }
+}
+
+#[test]
+fn test_grid_helpers() {
+    assert_eq!(cell_center([-1, 0], 2.), [-1., 1.]);
+
+    let cells: Vec<[i32;2]> = CellRect::covering([-1.,-0.5], [1.,0.5], 1.).iter().collect();
+    assert_eq!(cells, vec!([-1,-1],[-1,0],[0,-1],[0,0]));
+    assert_eq!(CellRect { min: [0,0], max: [0,3] }.iter().count(), 0);
+    assert_eq!(CellRect { min: [0,0], max: [3,0] }.iter().count(), 0);
+}
+
 pub trait HorizontalVerticalAxis {
     fn is_horizontal(&self) -> bool;
     fn is_vertical(&self) -> bool;
@@ -0,0 +1,221 @@
+//! weather states defined in weather.states of the configuration.
+//!
+//! a state drops particles around the players, plays ambient loops at the listener,
+//! limits the range of the monsters vision and changes the friction of the bodies
+//! on the tiles not sheltered, the tiles of surfaces have their own friction.
+//! changes of state are blended over weather.transition_time.
+
+use app;
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use graphics::{self, Color};
+use components::*;
+use resource::PhysicWorld;
+use specs::Join;
+use rand::{self, Rng};
+
+/// bodies are on a surface when this circle around their center overlaps it
+const SURFACE_RADIUS: f32 = 0.1;
+
+pub type VecUsize = Vec<usize>;
+
+/// * particle_rate: particles per second around each player
+/// * particle_angle: direction the particles fall in
+/// * vision_range: distance further which monsters don't see
+/// * friction: factor of the damping of the bodies, below 1 they slide
+/// * ambient_snds: persistent effects played at the listener
+pub struct WeatherDefinition {
+    pub name: String,
+    pub particle_rate: f32,
+    pub particle_angle: f32,
+    pub particle_color: Color,
+    pub vision_range: f32,
+    pub friction: f32,
+    pub ambient_snds: VecUsize,
+}
+impl_from_into_toml_for_struct!( WeatherDefinition {
+    name: String,
+    particle_rate: f32,
+    particle_angle: f32,
+    particle_color: Color,
+    vision_range: f32,
+    friction: f32,
+    ambient_snds: VecUsize,
+});
+
+/// the index of the weather state named name
+pub fn state(name: &str) -> Option<usize> {
+    config.weather.states.iter().position(|s| s.name == name)
+}
+
+pub struct Weather {
+    from: usize,
+    to: usize,
+    /// from 0 at the start of the transition to 1 once in state to
+    progress: f32,
+}
+
+impl Weather {
+    pub fn new() -> Self {
+        let initial = state(&*config.weather.initial).expect("initial weather must be a weather state");
+        Weather {
+            from: initial,
+            to: initial,
+            progress: 1.,
+        }
+    }
+
+    /// start the transition to the state, from the state the most present
+    pub fn change(&mut self, state: usize) {
+        if state == self.to {
+            return;
+        }
+        self.from = self.current();
+        self.to = state;
+        self.progress = 0.;
+    }
+
+    /// the state the most present
+    pub fn current(&self) -> usize {
+        if self.progress < 0.5 { self.from } else { self.to }
+    }
+
+    fn blend<F: Fn(&WeatherDefinition) -> f32>(&self, f: F) -> f32 {
+        let from = f(&config.weather.states[self.from]);
+        let to = f(&config.weather.states[self.to]);
+        from + (to - from)*self.progress
+    }
+
+    pub fn vision_range(&self) -> f32 {
+        self.blend(|s| s.vision_range)
+    }
+
+    pub fn friction(&self) -> f32 {
+        self.blend(|s| s.friction)
+    }
+
+    /// the states dropping particles and their rates
+    fn particle_rates(&self) -> Vec<(usize,f32)> {
+        if self.from == self.to {
+            vec!((self.to, config.weather.states[self.to].particle_rate))
+        } else {
+            vec!(
+                (self.from, config.weather.states[self.from].particle_rate*(1. - self.progress)),
+                (self.to, config.weather.states[self.to].particle_rate*self.progress),
+            )
+        }
+    }
+
+    fn advance(&mut self, dt: f32) {
+        if config.weather.transition_time > 0. {
+            self.progress = (self.progress + dt/config.weather.transition_time).min(1.);
+        } else {
+            self.progress = 1.;
+        }
+        if self.progress >= 1. {
+            self.from = self.to;
+        }
+    }
+}
+
+/// advance the transition, drop particles and apply the friction
+pub struct WeatherSystem {
+    /// particles left to drop for each state
+    pending: Vec<f32>,
+}
+impl Default for WeatherSystem {
+    fn default() -> Self {
+        WeatherSystem {
+            pending: vec![0.; config.weather.states.len()],
+        }
+    }
+}
+impl specs::System<app::UpdateContext> for WeatherSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (mut weather, players, dynamics, types, surfaces, mut states, physic_world) = arg.fetch(|world| {
+            (
+                world.write_resource::<Weather>(),
+                world.read::<PlayerControl>(),
+                world.read::<PhysicDynamic>(),
+                world.read::<PhysicType>(),
+                world.read::<Surface>(),
+                world.write::<PhysicState>(),
+                world.read_resource::<PhysicWorld>(),
+            )
+        });
+
+        weather.advance(context.dt);
+
+        // the particles are cosmetic, they must not draw from the world rng
+        let mut rng = rand::thread_rng();
+        let radius = config.weather.particle_radius;
+        for (_, state) in (&players, &states).iter() {
+            for (id, rate) in weather.particle_rates() {
+                self.pending[id] += rate*context.dt;
+                while self.pending[id] >= 1. {
+                    self.pending[id] -= 1.;
+                    let definition = &config.weather.states[id];
+                    context.effect_tx.send(app::Effect::Line {
+                        origin: [
+                            state.position[0] + rng.gen_range(-radius, radius),
+                            state.position[1] + rng.gen_range(-radius, radius),
+                        ],
+                        length: config.weather.particle_length,
+                        angle: definition.particle_angle,
+                        persistance: config.weather.particle_persistance,
+                        thickness: config.weather.particle_thickness,
+                        layer: graphics::Layer::Ceil,
+                        color: definition.particle_color,
+                    }).unwrap();
+                }
+            }
+        }
+
+        // the damping of the integration is scaled by the friction of the tile under
+        // the body, the surface of lowest id when they overlap
+        let weather_friction = weather.friction();
+        for (_, typ, state) in (&dynamics, &types, &mut states).iter() {
+            let mut tile: Option<specs::Entity> = None;
+            physic_world.apply_on_shape(&state.position, config.entities.zone_group.val, &Shape::Circle(SURFACE_RADIUS), &mut |other_entity,_| {
+                if surfaces.get(*other_entity).is_some() && tile.map(|t| other_entity.get_id() < t.get_id()).unwrap_or(true) {
+                    tile = Some(*other_entity);
+                }
+            });
+            let friction = tile.map(|t| surfaces.get(t).unwrap().friction(weather_friction)).unwrap_or(weather_friction);
+            if friction != 1. {
+                let coef = (1. - friction)*typ.damping/typ.weight*context.dt;
+                state.velocity[0] += coef*state.velocity[0];
+                state.velocity[1] += coef*state.velocity[1];
+            }
+        }
+    }
+}
+
+#[test]
+fn test_weather_transition() {
+    let clear = state(&*config.weather.initial).unwrap();
+    let other = (clear + 1) % config.weather.states.len();
+    let mut weather = Weather::new();
+    assert_eq!(weather.vision_range(), config.weather.states[clear].vision_range);
+
+    weather.change(other);
+    weather.advance(config.weather.transition_time/2.);
+    let middle = (config.weather.states[clear].friction + config.weather.states[other].friction)/2.;
+    assert!((weather.friction() - middle).abs() < 1e-5);
+    assert_eq!(weather.current(), other);
+
+    weather.advance(config.weather.transition_time);
+    assert!((weather.friction() - config.weather.states[other].friction).abs() < 1e-5);
+    assert_eq!(weather.particle_rates(), vec!((other, config.weather.states[other].particle_rate)));
+}
+
+#[test]
+fn test_surface_friction() {
+    let ice = Surface { friction: 0.5, sheltered: false };
+    assert_eq!(ice.friction(1.), 0.5);
+    assert_eq!(ice.friction(0.8), 0.4);
+    let floor = Surface { friction: 1., sheltered: true };
+    assert_eq!(floor.friction(0.5), 1.);
+}
@@ -0,0 +1,83 @@
+//! creation of the worlds with every component registered.
+
+use specs;
+use components::*;
+
+/// a world with every component registered
+pub fn new_world() -> specs::World {
+    let mut world = specs::World::new();
+    world.register::<PlayerControl>();
+    world.register::<TowardPlayerControl>();
+    world.register::<MonsterControl>();
+
+    world.register::<PhysicState>();
+    world.register::<PhysicForce>();
+    world.register::<PhysicType>();
+    world.register::<PhysicDynamic>();
+    world.register::<PhysicStatic>();
+    world.register::<PhysicTrigger>();
+    world.register::<PhysicTeleported>();
+    world.register::<GridSquare>();
+
+    world.register::<Graphic>();
+    world.register::<Highlight>();
+
+    world.register::<Life>();
+    world.register::<Killer>();
+    world.register::<Ball>();
+    world.register::<Column>();
+
+    world.register::<Portal>();
+
+    world.register::<Inventory>();
+    world.register::<Pickup>();
+
+    world.register::<Interactable>();
+    world.register::<Interactor>();
+
+    world.register::<Checkpoint>();
+    world.register::<Liquid>();
+    world.register::<ForceZone>();
+    world.register::<ProjectileField>();
+    world.register::<Surface>();
+    world.register::<UpdateLod>();
+    world.register::<MeleeAttack>();
+    world.register::<Rope>();
+    world.register::<Patrol>();
+    world.register::<RegionTracker>();
+    world.register::<Faction>();
+    world.register::<Threat>();
+    world.register::<Motion>();
+    world.register::<Loot>();
+    world.register::<Attributes>();
+    world.register::<Experience>();
+    world.register::<Wallet>();
+    world.register::<Vendor>();
+    world.register::<Abilities>();
+    world.register::<Caster>();
+    world.register::<Statuses>();
+    world.register::<Dash>();
+    world.register::<Movement>();
+    world.register::<ModeZone>();
+    world.register::<Grabber>();
+    world.register::<Mount>();
+    world.register::<Rider>();
+    world.register::<Autotile>();
+    world.register::<AmbientEmitter>();
+    world.register::<Hazard>();
+    world.register::<Boss>();
+    world.register::<Arena>();
+    world.register::<ArenaGate>();
+
+    world.register::<FixedCameraText>();
+    world.register::<Text>();
+    world.register::<FixedCamera>();
+
+    world.register::<Replicated>();
+    world.register::<Interpolated>();
+    world.register::<Peer>();
+
+    world.register::<DynPersistentSnd>();
+    world.register::<StaticPersistentSnd>();
+    world
+}
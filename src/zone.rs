@@ -0,0 +1,402 @@
+use app;
+use specs;
+use config;
+use configuration::{self, FromToml};
+use toml;
+use components::*;
+use resource::PhysicWorld;
+use specs::Join;
+use movement::{MovementMode, ModeZone};
+use std::collections::HashSet;
+use std::f32::consts::PI;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub type Array2Isize = [isize;2];
+pub type Array2F32 = [f32;2];
+
+/// entities are in a zone when this circle around their center overlaps it
+const ZONE_RADIUS: f32 = 0.1;
+
+/// a rectangle of size tiles from position (map rows are negative)
+/// that pushes the dynamic entities over it:
+/// * force: accelerate the entities according to their weight (fans, currents)
+/// * velocity: carry the entities at this velocity (conveyors)
+pub struct ZoneDefinition {
+    pub position: Array2Isize,
+    pub size: Array2Isize,
+    pub force: Array2F32,
+    pub velocity: Array2F32,
+}
+impl_from_into_toml_for_struct!( ZoneDefinition {
+    position: Array2Isize,
+    size: Array2Isize,
+    force: Array2F32,
+    velocity: Array2F32,
+});
+
+/// a rectangle of size tiles from position (map rows are negative)
+/// that modifies the projectiles passing through it:
+/// * slow: drag applied to the projectiles (slow fields)
+/// * shield: number of projectiles each tile absorbs, 0 for none
+/// * refraction: angle in degrees the projectiles are turned by when they enter
+pub struct FieldDefinition {
+    pub position: Array2Isize,
+    pub size: Array2Isize,
+    pub slow: f32,
+    pub shield: usize,
+    pub refraction: f32,
+}
+impl_from_into_toml_for_struct!( FieldDefinition {
+    position: Array2Isize,
+    size: Array2Isize,
+    slow: f32,
+    shield: usize,
+    refraction: f32,
+});
+
+/// a rectangle of size tiles from position (map rows are negative)
+/// that sets the movement mode of the entities in it
+pub struct ModeZoneDefinition {
+    pub position: Array2Isize,
+    pub size: Array2Isize,
+    pub mode: MovementMode,
+}
+impl_from_into_toml_for_struct!( ModeZoneDefinition {
+    position: Array2Isize,
+    size: Array2Isize,
+    mode: MovementMode,
+});
+
+/// a rectangle of size tiles from position (map rows are negative)
+/// whose friction is the factor of the damping of the bodies over it,
+/// below 1 they slide (ice), the weather doesn't change it if sheltered
+pub struct SurfaceDefinition {
+    pub position: Array2Isize,
+    pub size: Array2Isize,
+    pub friction: f32,
+    pub sheltered: bool,
+}
+impl_from_into_toml_for_struct!( SurfaceDefinition {
+    position: Array2Isize,
+    size: Array2Isize,
+    friction: f32,
+    sheltered: bool,
+});
+
+/// a tile of a zone
+pub struct ForceZone {
+    pub zone: usize,
+    pub force: [f32;2],
+    pub velocity: [f32;2],
+}
+impl specs::Component for ForceZone {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// a tile of a field
+pub struct ProjectileField {
+    pub slow: f32,
+    pub shield: usize,
+    /// in radians
+    pub refraction: f32,
+}
+impl specs::Component for ProjectileField {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// a tile of a surface
+pub struct Surface {
+    pub friction: f32,
+    pub sheltered: bool,
+}
+impl specs::Component for Surface {
+    type Storage = specs::VecStorage<Self>;
+}
+impl Surface {
+    /// the friction of the tile with the friction of the weather
+    pub fn friction(&self, weather: f32) -> f32 {
+        if self.sheltered {
+            self.friction
+        } else {
+            self.friction*weather
+        }
+    }
+}
+
+/// create the zones, fields and surfaces defined in the file if it exists:
+/// a toml file with arrays of tables `zones`, `fields`, `modes` and `surfaces`, all optional
+pub fn load(path: &Path, world: &mut specs::World) -> Result<(),String> {
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    let mut string = String::new();
+    try!(try!(fs::File::open(&path).map_err(|e| format!("open zones failed: {}",e)))
+         .read_to_string(&mut string)
+         .map_err(|_| String::from("zones invalid: not valid UTF-8")));
+
+    let mut parser = toml::Parser::new(&*string);
+    let table = try!(parser.parse().ok_or(format!("zones {:?} invalid: toml parsing failed",path)));
+    let zones: Vec<ZoneDefinition> = match table.get("zones") {
+        Some(zones) => try!(FromToml::from_toml(zones).map_err(|e| format!("zones {:?} invalid: zones{}",path,e))),
+        None => vec!(),
+    };
+    let fields: Vec<FieldDefinition> = match table.get("fields") {
+        Some(fields) => try!(FromToml::from_toml(fields).map_err(|e| format!("zones {:?} invalid: fields{}",path,e))),
+        None => vec!(),
+    };
+    let modes: Vec<ModeZoneDefinition> = match table.get("modes") {
+        Some(modes) => try!(FromToml::from_toml(modes).map_err(|e| format!("zones {:?} invalid: modes{}",path,e))),
+        None => vec!(),
+    };
+    let surfaces: Vec<SurfaceDefinition> = match table.get("surfaces") {
+        Some(surfaces) => try!(FromToml::from_toml(surfaces).map_err(|e| format!("zones {:?} invalid: surfaces{}",path,e))),
+        None => vec!(),
+    };
+
+    for (i, zone) in zones.iter().enumerate() {
+        if zone.size[0] <= 0 || zone.size[1] <= 0 {
+            return Err(format!("zones {:?} invalid: zones[{}].size must be positive",path,i));
+        }
+        for x in 0..zone.size[0] {
+            for y in 0..zone.size[1] {
+                world.create_now()
+                    .with::<ForceZone>(ForceZone {
+                        zone: i,
+                        force: zone.force,
+                        velocity: zone.velocity,
+                    })
+                    .with::<PhysicState>(PhysicState::new([zone.position[0] + x, zone.position[1] - y]))
+                    .with::<PhysicStatic>(PhysicStatic)
+                    .with::<PhysicType>(PhysicType::new_static(
+                            config.entities.zone_group.val,
+                            0,
+                            Shape::Square(0.5)))
+                    .build();
+            }
+        }
+    }
+
+    for (i, field) in fields.iter().enumerate() {
+        if field.size[0] <= 0 || field.size[1] <= 0 {
+            return Err(format!("zones {:?} invalid: fields[{}].size must be positive",path,i));
+        }
+        for x in 0..field.size[0] {
+            for y in 0..field.size[1] {
+                world.create_now()
+                    .with::<ProjectileField>(ProjectileField {
+                        slow: field.slow,
+                        shield: field.shield,
+                        refraction: field.refraction*PI/180.,
+                    })
+                    .with::<PhysicState>(PhysicState::new([field.position[0] + x, field.position[1] - y]))
+                    .with::<PhysicStatic>(PhysicStatic)
+                    .with::<PhysicType>(PhysicType::new_static(
+                            config.entities.zone_group.val,
+                            0,
+                            Shape::Square(0.5)))
+                    .build();
+            }
+        }
+    }
+
+    for (i, mode) in modes.iter().enumerate() {
+        if mode.size[0] <= 0 || mode.size[1] <= 0 {
+            return Err(format!("zones {:?} invalid: modes[{}].size must be positive",path,i));
+        }
+        for x in 0..mode.size[0] {
+            for y in 0..mode.size[1] {
+                world.create_now()
+                    .with::<ModeZone>(ModeZone { mode: mode.mode })
+                    .with::<PhysicState>(PhysicState::new([mode.position[0] + x, mode.position[1] - y]))
+                    .with::<PhysicStatic>(PhysicStatic)
+                    .with::<PhysicType>(PhysicType::new_static(
+                            config.entities.zone_group.val,
+                            0,
+                            Shape::Square(0.5)))
+                    .build();
+            }
+        }
+    }
+
+    for (i, surface) in surfaces.iter().enumerate() {
+        if surface.size[0] <= 0 || surface.size[1] <= 0 {
+            return Err(format!("zones {:?} invalid: surfaces[{}].size must be positive",path,i));
+        }
+        if surface.friction < 0. {
+            return Err(format!("zones {:?} invalid: surfaces[{}].friction must be positive or zero",path,i));
+        }
+        for x in 0..surface.size[0] {
+            for y in 0..surface.size[1] {
+                world.create_now()
+                    .with::<Surface>(Surface { friction: surface.friction, sheltered: surface.sheltered })
+                    .with::<PhysicState>(PhysicState::new([surface.position[0] + x, surface.position[1] - y]))
+                    .with::<PhysicStatic>(PhysicStatic)
+                    .with::<PhysicType>(PhysicType::new_static(
+                            config.entities.zone_group.val,
+                            0,
+                            Shape::Square(0.5)))
+                    .build();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// push the dynamic entities in zones, the pushes of overlapping zones add up
+pub struct ForceZoneSystem;
+impl specs::System<app::UpdateContext> for ForceZoneSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (dynamics, mut states, types, zones, physic_world, entities) = arg.fetch(|world| {
+            (
+                world.read::<PhysicDynamic>(),
+                world.write::<PhysicState>(),
+                world.read::<PhysicType>(),
+                world.read::<ForceZone>(),
+                world.read_resource::<PhysicWorld>(),
+                world.entities(),
+            )
+        });
+
+        for (_, state, typ, _) in (&dynamics, &mut states, &types, &entities).iter() {
+            let mut visited = HashSet::new();
+            let mut force = [0f32;2];
+            let mut velocity = [0f32;2];
+            physic_world.apply_on_shape(&state.position, config.entities.zone_group.val, &Shape::Circle(ZONE_RADIUS), &mut |other_entity,_| {
+                if let Some(zone) = zones.get(*other_entity) {
+                    // an entity over several tiles of a zone is pushed once
+                    if visited.insert(zone.zone) {
+                        force[0] += zone.force[0];
+                        force[1] += zone.force[1];
+                        velocity[0] += zone.velocity[0];
+                        velocity[1] += zone.velocity[1];
+                    }
+                }
+            });
+
+            if typ.weight != ::std::f32::MAX {
+                state.velocity[0] += context.dt*force[0]/typ.weight;
+                state.velocity[1] += context.dt*force[1]/typ.weight;
+            }
+            state.position[0] += context.dt*velocity[0];
+            state.position[1] += context.dt*velocity[1];
+        }
+    }
+}
+
+/// turn the velocity by angle
+fn refract(velocity: [f32;2], angle: f32) -> [f32;2] {
+    let (sin, cos) = (angle.sin(), angle.cos());
+    [
+        velocity[0]*cos - velocity[1]*sin,
+        velocity[0]*sin + velocity[1]*cos,
+    ]
+}
+
+/// slow, absorb and refract the balls in fields,
+/// the heading of the balls with a motion is turned with their velocity
+#[derive(Default)]
+pub struct ProjectileFieldSystem {
+    /// the balls and the field tiles they were in
+    inside: HashSet<(specs::Entity,specs::Entity)>,
+}
+impl specs::System<app::UpdateContext> for ProjectileFieldSystem {
+    fn run(&mut self, arg: specs::RunArg, context: app::UpdateContext) {
+        let (balls, mut lives, mut states, mut motions, mut fields, physic_world, entities) = arg.fetch(|world| {
+            (
+                world.read::<Ball>(),
+                world.write::<Life>(),
+                world.write::<PhysicState>(),
+                world.write::<Motion>(),
+                world.write::<ProjectileField>(),
+                world.read_resource::<PhysicWorld>(),
+                world.entities(),
+            )
+        });
+
+        let mut inside = HashSet::new();
+        for (_, life, state, entity) in (&balls, &mut lives, &mut states, &entities).iter() {
+            let mut tiles = vec!();
+            physic_world.apply_on_shape(&state.position, config.entities.zone_group.val, &Shape::Circle(ZONE_RADIUS), &mut |other_entity,_| {
+                if fields.get(*other_entity).is_some() {
+                    tiles.push(*other_entity);
+                }
+            });
+            tiles.sort_by_key(|e| e.get_id());
+
+            for tile in tiles {
+                let field = fields.get_mut(tile).unwrap();
+                let entered = !self.inside.contains(&(entity, tile));
+                inside.insert((entity, tile));
+
+                if field.shield > 0 && life.is_alive() {
+                    field.shield -= 1;
+                    life.kill();
+                }
+                if field.slow > 0. {
+                    let rate = (-field.slow*context.dt).exp();
+                    state.velocity[0] *= rate;
+                    state.velocity[1] *= rate;
+                }
+                if entered && field.refraction != 0. {
+                    state.velocity = refract(state.velocity, field.refraction);
+                    if let Some(motion) = motions.get_mut(entity) {
+                        motion.turn(field.refraction);
+                    }
+                }
+            }
+        }
+        self.inside = inside;
+    }
+}
+
+#[test]
+fn test_refract() {
+    let velocity = refract([2.,0.], PI/2.);
+    assert!(velocity[0].abs() < 1e-6 && (velocity[1] - 2.).abs() < 1e-6);
+    let velocity = refract([1.,1.], -PI/4.);
+    assert!((velocity[0] - 2f32.sqrt()).abs() < 1e-6 && velocity[1].abs() < 1e-6);
+}
+
+#[test]
+fn test_field_refracts_ball_heading() {
+    use life::Kind;
+    use std::sync::Arc;
+    use trace;
+
+    let mut world = trace::new_world();
+    world.register::<Ball>();
+    world.register::<Life>();
+    world.register::<Motion>();
+    world.register::<ProjectileField>();
+    world.create_now()
+        .with::<PhysicState>(PhysicState::new([0.5, 0.5]))
+        .with::<PhysicStatic>(PhysicStatic)
+        .with::<PhysicType>(PhysicType::new_static(config.entities.zone_group.val, 0, Shape::Square(0.5)))
+        .with::<ProjectileField>(ProjectileField { slow: 0., shield: 0, refraction: PI/2. })
+        .build();
+    let mut state = PhysicState::new([0.5, 0.5]);
+    state.velocity = [1., 0.];
+    let ball = world.create_now()
+        .with::<PhysicState>(state)
+        .with::<Ball>(Ball::new(Arc::new(())))
+        .with::<Life>(Life::new(Kind::Ball, 0))
+        .with::<Motion>(Motion::new(0, 0., None))
+        .build();
+    world.write_resource::<PhysicWorld>().fill(&world);
+
+    let mut planner = specs::Planner::new(world, 1);
+    app::add_system(&mut planner, ProjectileFieldSystem::default(), "projectile_field", "gameplay");
+    for _ in 0..2 {
+        planner.dispatch(trace::new_context());
+        planner.wait();
+    }
+
+    // turned once when entering the field, the heading with the velocity
+    let world = planner.mut_world();
+    let velocity = world.read::<PhysicState>().get(ball).unwrap().velocity;
+    assert!(velocity[0].abs() < 1e-6 && (velocity[1] - 1.).abs() < 1e-6);
+    assert_eq!(world.read::<Motion>().get(ball).unwrap().heading(), PI/2.);
+}